@@ -0,0 +1,261 @@
+//! The checks every read of a tagged byte array goes through, before the payload is handed
+//! to `rkyv`: the header, the digest, the metadata in front of the payload and the
+//! decryption and decompression of the stored payload.
+//!
+//! The access functions in the crate root and on
+//! [ValidationConfig](crate::ValidationConfig) are thin wrappers around these, so the
+//! `no_panic` lints cover the whole read path from here.
+
+use rkyv::rancor::Source;
+use rkyv::util::AlignedVec;
+
+use crate::{
+    encryption, extensions, fingerprint, header, header_words, read_header, CompressionCodec,
+    DigestAlgorithm, EncryptionAlgorithm, EncryptionKey, RkyvVersionedError,
+    TaggedVersionedHeader, ValidationConfig, VersionedContainer, TAGGED_HEADER_SIZE,
+};
+
+/// Reads the header of a tagged byte array and checks that it holds a supported version of
+/// `T`.
+pub(crate) fn check_header<T: VersionedContainer>(
+    buf: &[u8],
+) -> Result<TaggedVersionedHeader, RkyvVersionedError> {
+    let header = read_header(buf)
+        .and_then(|header| match header.type_id == T::ARCHIVE_TYPE_ID {
+            true => Ok(header),
+            false => Err(RkyvVersionedError::UnexpectedTypeError(
+                T::ARCHIVE_TYPE_ID,
+                header.type_id,
+            )),
+        })
+        .map_err(|e| {
+            // Explain why data written before the format version was recorded can't be read
+            match header::is_legacy_format(buf, T::ARCHIVE_TYPE_ID) {
+                true => RkyvVersionedError::UnsupportedFormatError(0),
+                false => e,
+            }
+        })?;
+
+    // Ensure the version header is valid
+    if !T::is_valid_version_id(header.version_id) {
+        return Err(RkyvVersionedError::UnsupportedVersionError(
+            header.version_id,
+        ));
+    }
+    Ok(header)
+}
+
+/// Validates the header, length and digest of a tagged byte array holding a `T`, returning
+/// the header, the metadata in front of the payload and the stored payload.
+fn check_tagged_bytes<'a, T: VersionedContainer>(
+    buf: &'a [u8],
+    config: &ValidationConfig,
+) -> Result<(TaggedVersionedHeader, &'a [u8], &'a [u8]), RkyvVersionedError> {
+    let header = check_header::<T>(buf)?;
+    config.check_payload_len(header.payload_len as usize)?;
+    config.check_sunset::<T>(header.version_id)?;
+
+    // Ensure the payload is intact before handing it to rkyv
+    let body = verify_payload(buf, &header)?;
+    config.check_trailing_bytes(buf.len(), header.tagged_len()?)?;
+    fingerprint::check_fingerprint::<T>(&header, body, config.allow_missing_fingerprint)?;
+    let (metadata, payload) = split_metadata(&header, body)?;
+    Ok((header, metadata, payload))
+}
+
+/// Splits the stored body of a tagged byte array into the metadata, i.e. the header words and
+/// extension area, and the payload.
+pub(crate) fn split_metadata<'a>(
+    header: &TaggedVersionedHeader,
+    body: &'a [u8],
+) -> Result<(&'a [u8], &'a [u8]), RkyvVersionedError> {
+    let (_, rest) = header_words::split_words(header, body)?;
+    let (area, _) = extensions::split_extensions(header, rest)?;
+    body.split_at_checked(header_words::words_len(header) + area.len())
+        .ok_or(RkyvVersionedError::BufferTooSmallError)
+}
+
+/// Checks that `buf` holds the whole payload described by `header` and that it matches its
+/// digest, returning the stored payload, including any extension area.
+pub(crate) fn verify_payload<'a>(
+    buf: &'a [u8],
+    header: &TaggedVersionedHeader,
+) -> Result<&'a [u8], RkyvVersionedError> {
+    let payload_end = TAGGED_HEADER_SIZE + header.payload_len as usize;
+    let tagged_len = header.tagged_len()?;
+    if buf.len() < tagged_len {
+        return Err(RkyvVersionedError::BufferTooSmallError);
+    }
+    let digest = digest_algorithm(header)?;
+    let (Some(payload), Some(expected)) = (
+        buf.get(TAGGED_HEADER_SIZE..payload_end),
+        buf.get(payload_end..payload_end + digest.digest_len()),
+    ) else {
+        return Err(RkyvVersionedError::BufferTooSmallError);
+    };
+    digest.verify(payload, expected)?;
+    Ok(payload)
+}
+
+/// Returns the [DigestAlgorithm] recorded in `header`.
+pub(crate) fn digest_algorithm(
+    header: &TaggedVersionedHeader,
+) -> Result<DigestAlgorithm, RkyvVersionedError> {
+    DigestAlgorithm::from_id(header.digest)
+        .ok_or(RkyvVersionedError::UnsupportedDigestError(header.digest))
+}
+
+/// Returns the [EncryptionAlgorithm] recorded in `header`.
+pub(crate) fn encryption_algorithm(
+    header: &TaggedVersionedHeader,
+) -> Result<EncryptionAlgorithm, RkyvVersionedError> {
+    EncryptionAlgorithm::from_id(header.encryption).ok_or(
+        RkyvVersionedError::UnsupportedEncryptionError(header.encryption),
+    )
+}
+
+/// Returns the [CompressionCodec] recorded in `header`.
+pub(crate) fn compression_codec(
+    header: &TaggedVersionedHeader,
+) -> Result<CompressionCodec, RkyvVersionedError> {
+    CompressionCodec::from_id(header.compression).ok_or(
+        RkyvVersionedError::UnsupportedCompressionError(header.compression),
+    )
+}
+
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes) within the limits of `config`.
+pub(crate) fn access_tagged<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    config: &ValidationConfig,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_tagged_with_strategy::<T, rkyv::rancor::Error>(buf, config)
+}
+
+/// [access_from_tagged_bytes_with_strategy](crate::access_from_tagged_bytes_with_strategy) within the limits of `config`.
+pub(crate) fn access_tagged_with_strategy<'a, T: VersionedContainer + 'a, E: Source>(
+    buf: &'a [u8],
+    config: &ValidationConfig,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<rkyv::api::high::HighValidator<'b, E>>,
+{
+    let (header, _, payload) = check_tagged_bytes::<T>(buf, config)?;
+    if encryption_algorithm(&header)? != EncryptionAlgorithm::None {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    if compression_codec(&header)? != CompressionCodec::None {
+        return Err(RkyvVersionedError::CompressedPayloadError);
+    }
+
+    config.access_payload_with_strategy::<T, E>(payload)
+}
+
+/// [access_from_tagged_bytes_with_scratch](crate::access_from_tagged_bytes_with_scratch) within the limits of `config`.
+pub(crate) fn access_tagged_with_scratch<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    scratch: &'a mut AlignedVec,
+    config: &ValidationConfig,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let (header, _, payload) = check_tagged_bytes::<T>(buf, config)?;
+    if encryption_algorithm(&header)? != EncryptionAlgorithm::None {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    let payload = match compression_codec(&header)? {
+        CompressionCodec::None => payload,
+        codec => {
+            codec.decompress_limited(payload, scratch, config.payload_len_limit())?;
+            scratch.as_slice()
+        }
+    };
+
+    config.access_payload::<T>(payload)
+}
+
+/// [access_from_encrypted_tagged_bytes_in_place](crate::access_from_encrypted_tagged_bytes_in_place) within the limits of `config`.
+pub(crate) fn access_encrypted_tagged_in_place<'a, T: VersionedContainer + 'a>(
+    buf: &'a mut [u8],
+    key: &EncryptionKey,
+    config: &ValidationConfig,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let (header, metadata, payload) = check_tagged_bytes::<T>(buf, config)?;
+    if compression_codec(&header)? != CompressionCodec::None {
+        return Err(RkyvVersionedError::CompressedPayloadError);
+    }
+    let encryption = encryption_algorithm(&header)?;
+    let aad = encryption::associated_data(header.type_id, header.version_id, metadata);
+    let start = TAGGED_HEADER_SIZE + metadata.len();
+    let end = start + payload.len();
+
+    let payload = buf
+        .get_mut(start..end)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let payload = match encryption {
+        EncryptionAlgorithm::None => payload,
+        // The plaintext is moved to the start of the payload, which keeps it aligned
+        EncryptionAlgorithm::XChaCha20Poly1305 => {
+            encryption::decrypt_in_place(key, &aad, payload)?
+        }
+    };
+    config.access_payload::<T>(payload)
+}
+
+/// [access_from_encrypted_tagged_bytes](crate::access_from_encrypted_tagged_bytes) within the limits of `config`.
+pub(crate) fn access_encrypted_tagged<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    key: &EncryptionKey,
+    scratch: &'a mut AlignedVec,
+    config: &ValidationConfig,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let (header, metadata, payload) = check_tagged_bytes::<T>(buf, config)?;
+    let limit = config.payload_len_limit();
+    let payload = match encryption_algorithm(&header)? {
+        EncryptionAlgorithm::None => payload,
+        EncryptionAlgorithm::XChaCha20Poly1305 => {
+            let aad = encryption::associated_data(header.type_id, header.version_id, metadata);
+            match compression_codec(&header)? {
+                CompressionCodec::None => {
+                    encryption::decrypt_into(key, &aad, payload, scratch)?
+                }
+                codec => {
+                    let plaintext = encryption::decrypt(key, &aad, payload)?;
+                    codec.decompress_limited(&plaintext, scratch, limit)?;
+                }
+            }
+            return config.access_payload::<T>(scratch);
+        }
+    };
+    let payload = match compression_codec(&header)? {
+        CompressionCodec::None => payload,
+        codec => {
+            codec.decompress_limited(payload, scratch, limit)?;
+            scratch.as_slice()
+        }
+    };
+
+    config.access_payload::<T>(payload)
+}
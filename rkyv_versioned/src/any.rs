@@ -0,0 +1,160 @@
+//! Type-erased references to accessed records.
+//!
+//! [access_from_tagged_bytes](crate::access_from_tagged_bytes) returns a reference to the
+//! archived enum of one container type.  To hold records of several types in one collection,
+//! e.g. the results of a [TypeRegistry](crate::TypeRegistry), [access_any_from_tagged_bytes]
+//! returns a `Box<dyn AnyVersionedRef>` instead, which exposes the type and version IDs of
+//! the record, and its `downcast_ref` recovers the archived enum of its container.
+
+use alloc::boxed::Box;
+use core::any::Any;
+use core::fmt;
+
+use crate::{
+    access_from_tagged_bytes, get_type_and_version_from_tagged_bytes, RkyvVersionedError,
+    VersionedContainer,
+};
+
+/// An object-safe reference to the archived value of a record of any container type.
+pub trait AnyVersionedRef<'a> {
+    /// The [ARCHIVE_TYPE_ID](VersionedContainer::ARCHIVE_TYPE_ID) of the container.
+    fn archive_type_id(&self) -> u32;
+
+    /// The version ID of the record.
+    fn version_id(&self) -> u32;
+
+    /// The name of the variant holding the version, e.g. `V1`, if the container knows it.
+    fn version_name(&self) -> Option<&'static str>;
+
+    /// The archived enum of the container, as [Any].
+    fn as_any(&self) -> &'a dyn Any;
+}
+
+impl<'a> dyn AnyVersionedRef<'a> + 'a {
+    /// Returns whether the record holds a `T`.
+    pub fn is<T>(&self) -> bool
+    where
+        T: VersionedContainer,
+        T::Archived: Any,
+    {
+        self.as_any().is::<T::Archived>()
+    }
+
+    /// Returns the archived enum of `T` if the record holds a `T`, or `None` otherwise.
+    pub fn downcast_ref<T>(&self) -> Option<&'a T::Archived>
+    where
+        T: VersionedContainer,
+        T::Archived: Any,
+    {
+        self.as_any().downcast_ref::<T::Archived>()
+    }
+}
+
+impl fmt::Debug for dyn AnyVersionedRef<'_> + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnyVersionedRef")
+            .field("archive_type_id", &self.archive_type_id())
+            .field("version_id", &self.version_id())
+            .field("version_name", &self.version_name())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reference to the archived value of a `T`.
+struct VersionedRef<'a, T: VersionedContainer> {
+    archived: &'a T::Archived,
+    version_id: u32,
+}
+
+impl<'a, T> AnyVersionedRef<'a> for VersionedRef<'a, T>
+where
+    T: VersionedContainer,
+    T::Archived: Any,
+{
+    fn archive_type_id(&self) -> u32 {
+        T::ARCHIVE_TYPE_ID
+    }
+
+    fn version_id(&self) -> u32 {
+        self.version_id
+    }
+
+    fn version_name(&self) -> Option<&'static str> {
+        T::version_info(self.version_id).map(|info| info.name)
+    }
+
+    fn as_any(&self) -> &'a dyn Any {
+        self.archived
+    }
+}
+
+/// As [access_from_tagged_bytes], returning a type-erased reference to the archived value.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either the type-erased reference or an error if any of the checks
+/// fail.
+pub fn access_any_from_tagged_bytes<'a, T>(
+    buf: &'a [u8],
+) -> Result<Box<dyn AnyVersionedRef<'a> + 'a>, RkyvVersionedError>
+where
+    T: VersionedContainer + 'a,
+    T::Archived: Any
+        + rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let archived = access_from_tagged_bytes::<T>(buf)?;
+    let (_, version_id) = get_type_and_version_from_tagged_bytes(buf)?;
+    Ok(Box::new(VersionedRef::<T> {
+        archived,
+        version_id,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Reading {
+        V1(u32),
+        V2(u64),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Alarm {
+        V1(String),
+    }
+
+    #[test]
+    fn test_any_versioned_ref() {
+        let reading = to_tagged_bytes(&Reading::V2(42)).unwrap();
+        let alarm = to_tagged_bytes(&Alarm::V1("overheat".to_string())).unwrap();
+        let records = [
+            access_any_from_tagged_bytes::<Reading>(&reading).unwrap(),
+            access_any_from_tagged_bytes::<Alarm>(&alarm).unwrap(),
+        ];
+
+        assert_eq!(records[0].archive_type_id(), Reading::ARCHIVE_TYPE_ID);
+        assert_eq!(records[0].version_name(), Some("V2"));
+        assert!(records[0].is::<Reading>() && !records[0].is::<Alarm>());
+        match records[0].downcast_ref::<Reading>() {
+            Some(ArchivedReading::V2(value)) => assert_eq!(*value, 42),
+            _ => panic!("Expected Reading::V2"),
+        }
+        match records[1].downcast_ref::<Alarm>() {
+            Some(ArchivedAlarm::V1(message)) => assert_eq!(message, "overheat"),
+            _ => panic!("Expected Alarm::V1"),
+        }
+        assert!(records[1].downcast_ref::<Reading>().is_none());
+        assert!(format!("{:?}", records[1]).contains("AnyVersionedRef"));
+    }
+}
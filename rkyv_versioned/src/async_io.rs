@@ -0,0 +1,296 @@
+//! Reading and writing tagged byte arrays over tokio streams, enabled with the `tokio` feature.
+//!
+//! These are the async counterparts of [write_tagged](crate::write_tagged) and
+//! [read_tagged](crate::read_tagged) and use the same length-prefixed frames, so a service can
+//! stream records over TCP and a synchronous tool can read them back from a capture.
+//!
+//! [read_tagged_async] loses the part of a frame it has already read if its future is dropped,
+//! so it must not be used as a branch of `tokio::select!` that can lose the race.
+//! [AsyncTaggedReader] keeps partially read frames between calls instead, which makes its
+//! [read_tagged](AsyncTaggedReader::read_tagged) cancellation safe.
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::framing::{frame_padding, frame_prefix, parse_frame_prefix};
+use crate::io::io_error;
+use crate::{
+    check_header, to_tagged_bytes, RkyvVersionedError, VersionedContainer, FRAME_ALIGNMENT,
+    FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE,
+};
+
+/// Serializes `item` into a tagged byte array and writes it to `writer` as a length-prefixed
+/// frame, like [write_tagged](crate::write_tagged).
+///
+/// The future is not cancellation safe: if it is dropped part of the frame may have been
+/// written, leaving the stream unusable.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `writer` - The stream to write the frame to.
+///
+/// # Returns
+///
+/// A `Result` containing either the number of bytes written, including the prefix and
+/// padding of the frame, or an error if serialization or writing fails.
+pub async fn write_tagged_async<T>(
+    item: &T,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let record = to_tagged_bytes(item)?;
+    let padding = [0u8; FRAME_ALIGNMENT];
+    let padding = padding
+        .get(..frame_padding(record.len()))
+        .unwrap_or_default();
+    writer
+        .write_all(&frame_prefix(record.len())?)
+        .await
+        .map_err(io_error)?;
+    writer.write_all(&record).await.map_err(io_error)?;
+    writer.write_all(padding).await.map_err(io_error)?;
+    Ok(FRAME_PREFIX_SIZE + record.len() + padding.len())
+}
+
+/// Reads the next length-prefixed frame from `reader`, like [read_tagged](crate::read_tagged).
+///
+/// The future is not cancellation safe, use [AsyncTaggedReader] when reading in a
+/// `tokio::select!` loop.
+///
+/// # Arguments
+///
+/// * `reader` - The stream positioned at the start of a frame.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, `None` if the stream ended cleanly
+/// before the frame, or an error.  Truncated frames are reported as
+/// [RkyvVersionedError::BufferTooSmallError] and corrupt ones as
+/// [RkyvVersionedError::InvalidMessageError].
+pub async fn read_tagged_async<T: VersionedContainer>(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+    AsyncTaggedReader::new(reader).read_tagged::<T>().await
+}
+
+/// Reads length-prefixed frames from a tokio stream, keeping partially read frames between
+/// calls so that [read_tagged](Self::read_tagged) is cancellation safe.
+#[derive(Debug)]
+pub struct AsyncTaggedReader<R> {
+    reader: R,
+    prefix: [u8; FRAME_PREFIX_SIZE],
+    /// The record and padding read so far, sized to what is needed next.
+    record: AlignedVec,
+    /// The number of bytes of the current frame read so far.
+    filled: usize,
+}
+
+impl<R: AsyncRead + Unpin> AsyncTaggedReader<R> {
+    /// Creates a reader of the frames in `reader`.
+    pub fn new(reader: R) -> Self {
+        AsyncTaggedReader {
+            reader,
+            prefix: [0u8; FRAME_PREFIX_SIZE],
+            record: AlignedVec::new(),
+            filled: 0,
+        }
+    }
+
+    /// Returns the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the underlying stream mutably, reading from it directly while a frame is
+    /// partially read will corrupt the frame.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns `true` if part of a frame has been read but not returned yet.
+    pub fn has_partial_frame(&self) -> bool {
+        self.filled > 0
+    }
+
+    /// Consumes the reader, returning the underlying stream and dropping any partially read
+    /// frame.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next frame, like [read_tagged_async].
+    ///
+    /// The future is cancellation safe: if it is dropped before completing, the bytes read so
+    /// far are kept and the next call carries on with the same frame.  After an error the
+    /// partial frame is discarded, but the stream is unlikely to be usable.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the tagged byte array, `None` if the stream ended cleanly
+    /// before the frame, or an error as for [read_tagged_async].
+    pub async fn read_tagged<T: VersionedContainer>(
+        &mut self,
+    ) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+        let result = self.read_frame::<T>().await;
+        if result.is_err() {
+            self.filled = 0;
+            self.record.clear();
+        }
+        result
+    }
+
+    async fn read_frame<T: VersionedContainer>(
+        &mut self,
+    ) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+        while self.filled < FRAME_PREFIX_SIZE {
+            let read = self
+                .reader
+                .read(self.prefix.get_mut(self.filled..).unwrap_or_default())
+                .await
+                .map_err(io_error)?;
+            match read {
+                0 if self.filled == 0 => return Ok(None),
+                0 => return Err(RkyvVersionedError::BufferTooSmallError),
+                read => self.filled += read,
+            }
+        }
+        let record_len =
+            parse_frame_prefix(&self.prefix).ok_or(RkyvVersionedError::InvalidMessageError)?;
+
+        // Only trust the prefix as far as the header agrees with it, and don't allocate for a
+        // record which isn't a `T` anyway
+        self.fill_record(TAGGED_HEADER_SIZE).await?;
+        let header =
+            check_header::<T>(self.record.get(..TAGGED_HEADER_SIZE).unwrap_or_default())?;
+        if header.tagged_len()? != record_len {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        self.fill_record(record_len + frame_padding(record_len))
+            .await?;
+
+        self.filled = 0;
+        let mut record = core::mem::take(&mut self.record);
+        // Drop the padding
+        record.resize(record_len, 0);
+        Ok(Some(record))
+    }
+
+    /// Reads until the first `len` bytes of the record are available.
+    async fn fill_record(&mut self, len: usize) -> Result<(), RkyvVersionedError> {
+        if self.record.len() < len {
+            self.record.resize(len, 0);
+        }
+        while self.filled - FRAME_PREFIX_SIZE < len {
+            let start = self.filled - FRAME_PREFIX_SIZE;
+            let read = self
+                .reader
+                .read(self.record.get_mut(start..len).unwrap_or_default())
+                .await
+                .map_err(io_error)?;
+            if read == 0 {
+                return Err(RkyvVersionedError::BufferTooSmallError);
+            }
+            self.filled += read;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    #[tokio::test]
+    async fn test_write_and_read_tagged_async() {
+        let mut stream = Vec::new();
+        let written = write_tagged_async(&Event::V1("first".to_string()), &mut stream)
+            .await
+            .unwrap();
+        assert_eq!(written, stream.len());
+        write_tagged_async(&Event::V2(2), &mut stream)
+            .await
+            .unwrap();
+
+        // The frames are the same as those written synchronously
+        let mut sync_stream = Vec::new();
+        crate::write_tagged(&Event::V1("first".to_string()), &mut sync_stream).unwrap();
+        assert_eq!(&stream[..written], sync_stream.as_slice());
+
+        let mut reader = stream.as_slice();
+        let first = read_tagged_async::<Event>(&mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        match access_from_tagged_bytes::<Event>(&first).unwrap() {
+            ArchivedEvent::V1(text) => assert_eq!(text, "first"),
+            _ => panic!("Expected V1"),
+        }
+        let second = read_tagged_async::<Event>(&mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(access_from_tagged_bytes::<Event>(&second).is_ok());
+        assert!(read_tagged_async::<Event>(&mut reader)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Truncated and corrupt frames
+        assert!(matches!(
+            read_tagged_async::<Event>(&mut &stream[..written - 1]).await,
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        let mut corrupt = stream.clone();
+        corrupt[0] ^= 1;
+        assert!(matches!(
+            read_tagged_async::<Event>(&mut corrupt.as_slice()).await,
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_read_keeps_partial_frame() {
+        let mut frame = Vec::new();
+        write_tagged_async(&Event::V2(7), &mut frame).await.unwrap();
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut reader = AsyncTaggedReader::new(server);
+
+        // Cancel reads part way through the prefix and part way through the record
+        for chunk in [&frame[..10], &frame[10..40]] {
+            client.write_all(chunk).await.unwrap();
+            tokio::select! {
+                biased;
+                _ = reader.read_tagged::<Event>() => panic!("The frame is incomplete"),
+                _ = core::future::ready(()) => {}
+            }
+            assert!(reader.has_partial_frame());
+        }
+
+        client.write_all(&frame[40..]).await.unwrap();
+        let record = reader.read_tagged::<Event>().await.unwrap().unwrap();
+        match access_from_tagged_bytes::<Event>(&record).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 7),
+            _ => panic!("Expected V2"),
+        }
+        assert!(!reader.has_partial_frame());
+
+        drop(client);
+        assert!(reader.read_tagged::<Event>().await.unwrap().is_none());
+    }
+}
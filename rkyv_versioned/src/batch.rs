@@ -0,0 +1,250 @@
+//! Serializing many records into one buffer, with an offsets table for random access.
+//!
+//! [to_tagged_batch] writes the records back to back, each padded to the next multiple of
+//! [RECORD_ALIGNMENT](crate::RECORD_ALIGNMENT) bytes as with
+//! [append_tagged](crate::append_tagged), followed by a table of their offsets and a footer:
+//!
+//! `[record][zero padding]...[offset (u32, little-endian)]*[count (u32, little-endian)]["VACB"]`
+//!
+//! The serializer's arena and the buffer each record is tagged in are reused across the
+//! batch, so bulk writes allocate far less than tagging each record separately.
+//! [TaggedBatch] reads the footer and gives access to the i-th record in constant time.
+
+use alloc::vec::Vec;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::{Arena, ArenaHandle};
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::records::RECORD_ALIGNMENT;
+use crate::{
+    access_from_tagged_bytes, read_header, rkyv_error, tag_serialized, RkyvVersionedError,
+    TaggedOptions, VersionedContainer,
+};
+
+/// The magic bytes ending a batch.
+const BATCH_MAGIC: [u8; 4] = *b"VACB";
+
+/// The size of the footer after the offsets table.
+const FOOTER_SIZE: usize = 8;
+
+/// The size of each entry of the offsets table.
+const OFFSET_SIZE: usize = 4;
+
+/// Serializes `items` into a single batch with the default [TaggedOptions], see the
+/// [module](self) documentation.
+///
+/// # Returns
+///
+/// A `Result` containing either the batch or an error if serialization fails or the batch
+/// would exceed 4 GiB.
+pub fn to_tagged_batch<T>(items: &[&T]) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    to_tagged_batch_with(items, &TaggedOptions::default())
+}
+
+/// Serializes `items` into a single batch, tagging every record with `options`.
+///
+/// # Arguments
+///
+/// * `items` - The items to be serialized, in the order they are indexed in the batch.
+/// * `options` - The options controlling the header and trailer of every record.
+///
+/// # Returns
+///
+/// A `Result` containing either the batch or an error if serialization fails or the batch
+/// would exceed 4 GiB.
+pub fn to_tagged_batch_with<T>(
+    items: &[&T],
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let mut batch = AlignedVec::new();
+    let mut offsets = Vec::with_capacity(items.len());
+    let mut arena = Arena::new();
+    let mut record = AlignedVec::new();
+    for item in items {
+        record.clear();
+        record = tag_serialized::<T>(item.get_entry_version_id(), options, record, |out| {
+            rkyv::api::high::to_bytes_in_with_alloc::<_, _, rkyv::rancor::Error>(
+                *item,
+                out,
+                arena.acquire(),
+            )
+            .map_err(rkyv_error)
+        })?;
+        offsets.push(encode_offset(batch.len())?);
+        batch.extend_from_slice(&record);
+        batch.resize(batch.len().next_multiple_of(RECORD_ALIGNMENT), 0);
+    }
+
+    for offset in &offsets {
+        batch.extend_from_slice(&offset.to_le_bytes());
+    }
+    batch.extend_from_slice(&encode_offset(offsets.len())?.to_le_bytes());
+    batch.extend_from_slice(&BATCH_MAGIC);
+    encode_offset(batch.len())?;
+    Ok(batch)
+}
+
+fn encode_offset(offset: usize) -> Result<u32, RkyvVersionedError> {
+    u32::try_from(offset).map_err(|_| RkyvVersionedError::PayloadTooLargeError(offset))
+}
+
+/// A batch written by [to_tagged_batch], giving constant time access to its records.
+#[derive(Debug, Clone, Copy)]
+pub struct TaggedBatch<'a> {
+    buf: &'a [u8],
+    /// The offsets table.
+    offsets: &'a [u8],
+}
+
+impl<'a> TaggedBatch<'a> {
+    /// Reads the footer of a batch.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the batch, or [RkyvVersionedError::InvalidMessageError] if `buf`
+    /// doesn't end with a batch footer and offsets table.
+    pub fn new(buf: &'a [u8]) -> Result<Self, RkyvVersionedError> {
+        let Some((rest, footer)) = buf.split_last_chunk::<FOOTER_SIZE>() else {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        };
+        if footer[4..] != BATCH_MAGIC {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        let count = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+        let (records, offsets) = count
+            .checked_mul(OFFSET_SIZE)
+            .and_then(|table_len| rest.len().checked_sub(table_len))
+            .and_then(|table_start| rest.split_at_checked(table_start))
+            .ok_or(RkyvVersionedError::InvalidMessageError)?;
+        Ok(TaggedBatch {
+            buf: records,
+            offsets,
+        })
+    }
+
+    /// Returns the number of records in the batch.
+    pub fn len(&self) -> usize {
+        self.offsets.len() / OFFSET_SIZE
+    }
+
+    /// Returns whether the batch holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Returns the tagged byte array of the record at `index`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the record, or an error if `index` is out of range
+    /// ([RkyvVersionedError::BufferTooSmallError]), or the offsets table or record header
+    /// are corrupt.
+    pub fn record(&self, index: usize) -> Result<&'a [u8], RkyvVersionedError> {
+        let entry = self
+            .offsets
+            .get(index * OFFSET_SIZE..)
+            .and_then(|entries| entries.first_chunk::<OFFSET_SIZE>())
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let offset = u32::from_le_bytes(*entry) as usize;
+        if !offset.is_multiple_of(RECORD_ALIGNMENT) {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        let rest = self
+            .buf
+            .get(offset..)
+            .ok_or(RkyvVersionedError::InvalidMessageError)?;
+        let tagged_len = read_header(rest)?.tagged_len()?;
+        rest.get(..tagged_len)
+            .ok_or(RkyvVersionedError::InvalidMessageError)
+    }
+
+    /// Accesses the record at `index` in place, validating it like [access_from_tagged_bytes].
+    pub fn get<T: VersionedContainer + 'a>(
+        &self,
+        index: usize,
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        access_from_tagged_bytes::<T>(self.record(index)?)
+    }
+
+    /// Returns an iterator over the tagged byte arrays of the records, in order.
+    pub fn records(&self) -> impl Iterator<Item = Result<&'a [u8], RkyvVersionedError>> + '_ {
+        (0..self.len()).map(|index| self.record(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Metric {
+        V1(String),
+        V2(u64),
+    }
+
+    #[test]
+    fn test_tagged_batch() {
+        let metrics: Vec<Metric> = (0..10u64)
+            .map(|i| match i % 3 {
+                0 => Metric::V1(format!("metric-{}", i)),
+                _ => Metric::V2(i),
+            })
+            .collect();
+        let items: Vec<&Metric> = metrics.iter().collect();
+        let bytes = to_tagged_batch(&items).unwrap();
+
+        let batch = TaggedBatch::new(&bytes).unwrap();
+        assert_eq!(batch.len(), 10);
+        for (i, metric) in metrics.iter().enumerate() {
+            // Each record is the same as if it was tagged on its own
+            assert_eq!(
+                batch.record(i).unwrap(),
+                to_tagged_bytes(metric).unwrap().as_slice()
+            );
+        }
+        match batch.get::<Metric>(7).unwrap() {
+            ArchivedMetric::V2(value) => assert_eq!(*value, 7),
+            _ => panic!("Expected V2"),
+        }
+        match batch.get::<Metric>(9).unwrap() {
+            ArchivedMetric::V1(name) => assert_eq!(name, "metric-9"),
+            _ => panic!("Expected V1"),
+        }
+        assert!(matches!(
+            batch.get::<Metric>(10),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        assert_eq!(batch.records().filter(Result::is_ok).count(), 10);
+
+        let empty = to_tagged_batch::<Metric>(&[]).unwrap();
+        assert!(TaggedBatch::new(&empty).unwrap().is_empty());
+
+        // Buffers which aren't batches are rejected
+        assert!(matches!(
+            TaggedBatch::new(&to_tagged_bytes(&metrics[0]).unwrap()),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+        let mut corrupt = bytes.clone();
+        let count_offset = corrupt.len() - FOOTER_SIZE;
+        corrupt[count_offset + 3] = 0xff;
+        assert!(TaggedBatch::new(&corrupt).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Reusable `criterion` benchmarks, enabled with the `bench_support` feature.
+//!
+//! Each function benchmarks one stage of handling a tagged byte array for any versioned
+//! container, so downstream crates can track regressions of their own types without
+//! rewriting the same harness:
+//!
+//! ```no_run
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use rkyv::{Archive, Deserialize, Serialize};
+//! use rkyv_versioned::bench_support::bench_container;
+//! use rkyv_versioned::{TaggedOptions, VersionedArchiveContainer};
+//!
+//! #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+//! enum Setting {
+//!     V1(Vec<u32>),
+//! }
+//!
+//! fn benches(c: &mut Criterion) {
+//!     let setting = Setting::V1((0..1024).collect());
+//!     bench_container(c, "setting", &setting, &TaggedOptions::default());
+//! }
+//!
+//! criterion_group!(group, benches);
+//! criterion_main!(group);
+//! ```
+//!
+//! Benchmarks are grouped under the provided name, with one benchmark per stage
+//! (`serialize`, `header_parse`, `checked_access` and `migrate`).
+
+// Benchmarks panic when the record benchmarked can't be tagged or accessed
+#![allow(clippy::unwrap_used)]
+
+use criterion::{black_box, Criterion, Throughput};
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    access_from_tagged_bytes_with_scratch, get_type_and_version_from_tagged_bytes,
+    to_tagged_bytes_with, TaggedOptions, VersionedContainer,
+};
+
+/// Benchmarks serializing `item` into a tagged byte array with `options`.
+pub fn bench_serialize<T>(c: &mut Criterion, name: &str, item: &T, options: &TaggedOptions)
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let mut group = c.benchmark_group(name);
+    group.bench_function("serialize", |b| {
+        b.iter(|| to_tagged_bytes_with(black_box(item), options).unwrap())
+    });
+    group.finish();
+}
+
+/// Benchmarks peeking at the type and version of `tagged`.
+pub fn bench_header_parse(c: &mut Criterion, name: &str, tagged: &[u8]) {
+    let mut group = c.benchmark_group(name);
+    group.bench_function("header_parse", |b| {
+        b.iter(|| get_type_and_version_from_tagged_bytes(black_box(tagged)).unwrap())
+    });
+    group.finish();
+}
+
+/// Benchmarks validating and accessing `tagged` as a `T`, including verifying the digest and
+/// decompressing the payload if required.
+///
+/// # Panics
+///
+/// If `tagged` cannot be accessed as a `T`.
+pub fn bench_checked_access<T: VersionedContainer>(
+    c: &mut Criterion,
+    name: &str,
+    tagged: &[u8],
+) where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let mut scratch = AlignedVec::new();
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(tagged.len() as u64));
+    group.bench_function("checked_access", |b| {
+        b.iter(|| {
+            black_box(
+                access_from_tagged_bytes_with_scratch::<T>(black_box(tagged), &mut scratch)
+                    .unwrap(),
+            );
+        })
+    });
+    group.finish();
+}
+
+/// Benchmarks accessing `tagged` as a `T` and migrating it with `migrate`, e.g. to the latest
+/// version of the container.
+///
+/// # Panics
+///
+/// If `tagged` cannot be accessed as a `T`.
+pub fn bench_migrate<T: VersionedContainer, U>(
+    c: &mut Criterion,
+    name: &str,
+    tagged: &[u8],
+    migrate: impl Fn(&T::Archived) -> U,
+) where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let mut scratch = AlignedVec::new();
+    let mut group = c.benchmark_group(name);
+    group.bench_function("migrate", |b| {
+        b.iter(|| {
+            let archived =
+                access_from_tagged_bytes_with_scratch::<T>(black_box(tagged), &mut scratch)
+                    .unwrap();
+            migrate(archived)
+        })
+    });
+    group.finish();
+}
+
+/// Runs [bench_serialize], [bench_header_parse] and [bench_checked_access] for `item`.
+///
+/// # Panics
+///
+/// If `item` cannot be tagged with `options`.
+pub fn bench_container<T>(c: &mut Criterion, name: &str, item: &T, options: &TaggedOptions)
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let tagged = to_tagged_bytes_with(item, options).unwrap();
+    bench_serialize(c, name, item, options);
+    bench_header_parse(c, name, &tagged);
+    bench_checked_access::<T>(c, name, &tagged);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionedArchiveContainer;
+    use core::time::Duration;
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum SampleContainer {
+        V1(Vec<u32>),
+        V2(Vec<u64>),
+    }
+
+    #[test]
+    fn test_benchmarks_run() {
+        let mut c = Criterion::default()
+            .sample_size(10)
+            .warm_up_time(Duration::from_millis(1))
+            .measurement_time(Duration::from_millis(10))
+            .without_plots();
+
+        let sample = SampleContainer::V1((0..64).collect());
+        bench_container(&mut c, "sample", &sample, &TaggedOptions::default());
+
+        let tagged = to_tagged_bytes_with(&sample, &TaggedOptions::default()).unwrap();
+        bench_migrate::<SampleContainer, _>(&mut c, "sample", &tagged, |archived| {
+            match archived {
+                ArchivedSampleContainer::V1(values) => {
+                    values.iter().map(|v| u64::from(v.to_native())).collect()
+                }
+                ArchivedSampleContainer::V2(values) => {
+                    values.iter().map(|v| v.to_native()).collect::<Vec<_>>()
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,4 @@
+//! The `rkyv_versioned` command line tool, enabled with the `cli` feature, see the
+//! [cli](rkyv_versioned::cli) module.
+
+rkyv_versioned::migration_cli! {}
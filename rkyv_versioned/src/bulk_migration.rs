@@ -0,0 +1,399 @@
+//! Upgrading the stored records of a container to its latest version in bulk.
+//!
+//! Readers of a container handle every version still stored, so dropping an old version
+//! means rewriting the records holding it first.  [migrate_stream] runs a migration over
+//! every record of a container in a stream of frames and re-tags the migrated values,
+//! keeping the digest algorithm, codec, header words and extensions of each record, and
+//! [migrate_file] does so for a file.
+//!
+//! A migration is a function like the ones passed to
+//! [self_check_with_migration](crate::self_check_with_migration), returning the migrated
+//! container, or `None` to keep a record unchanged, e.g. because it already holds the latest
+//! version.  A [MigrationRegistry] names the migrations of several containers, so the
+//! `migrate` subcommand of a binary built with [migration_cli](crate::migration_cli) can run
+//! them by name.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::io::io_error;
+use crate::{
+    access_from_tagged_bytes_with_scratch, append_framed, compression_codec, framed_records,
+    get_extensions_from_tagged_bytes, get_sequence_from_tagged_bytes,
+    get_timestamp_from_tagged_bytes, get_user_word_from_tagged_bytes, read_header,
+    set_type_name, to_tagged_bytes_with, DigestAlgorithm, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, EXTENSION_KEY_VARIANT_NAME, HEADER_FLAG_SIGNED,
+};
+
+/// What [migrate_stream] migrated.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrateReport {
+    /// The number of records migrated.
+    pub migrated: usize,
+    /// The number of records of the container the migration kept unchanged.
+    pub unchanged: usize,
+    /// The number of records of other containers, copied unchanged.
+    pub other_types: usize,
+    /// The number of records of the container by the version ID they were stored with.
+    pub versions: BTreeMap<u32, usize>,
+    /// The length of the original stream.
+    pub len: usize,
+    /// The length of the migrated stream.
+    pub migrated_len: usize,
+}
+
+/// Runs `migrate` over every record of `T` in a stream of frames, copying records of other
+/// containers unchanged.
+///
+/// Migrated records are tagged with the digest algorithm, codec, header words and extensions
+/// of the record they replace, and any embedded variant name is updated.  They can't be
+/// signed again, so migrating a signed record fails.
+///
+/// # Arguments
+///
+/// * `buf` - The stream, e.g. the contents of a log file.
+/// * `migrate` - The migration, returning the migrated container or `None` to keep a record.
+///
+/// # Returns
+///
+/// A `Result` containing the migrated stream and a report, or an error if a frame is corrupt,
+/// a record of `T` can't be accessed or re-tagged, or the migration fails
+/// ([RkyvVersionedError::MigrationError]), wrapped in [RkyvVersionedError::RecordError] to
+/// locate it.
+pub fn migrate_stream<T, E>(
+    buf: &[u8],
+    migrate: impl Fn(&T::Archived) -> Result<Option<T>, E>,
+) -> Result<(AlignedVec, MigrateReport), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: Display,
+{
+    let mut migrated = AlignedVec::with_capacity(buf.len());
+    let mut report = MigrateReport {
+        len: buf.len(),
+        ..Default::default()
+    };
+    let mut scratch = AlignedVec::new();
+    let mut records = framed_records(buf);
+    let mut index = 0;
+    loop {
+        let offset = records.offset();
+        let Some(record) = records.next() else {
+            break;
+        };
+        record
+            .and_then(|record| {
+                match migrate_record(record, &migrate, &mut scratch, &mut report)? {
+                    Some(tagged) => append_framed(&mut migrated, &tagged),
+                    None => append_framed(&mut migrated, record),
+                }
+            })
+            .map_err(|e| e.at_record(index, offset))?;
+        index += 1;
+    }
+    report.migrated_len = migrated.len();
+    Ok((migrated, report))
+}
+
+/// Migrates one record of [migrate_stream], returning `None` to copy it unchanged.
+fn migrate_record<T, E>(
+    record: &[u8],
+    migrate: &impl Fn(&T::Archived) -> Result<Option<T>, E>,
+    scratch: &mut AlignedVec,
+    report: &mut MigrateReport,
+) -> Result<Option<AlignedVec>, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: Display,
+{
+    let header = read_header(record)?;
+    if header.type_id != T::ARCHIVE_TYPE_ID {
+        report.other_types += 1;
+        return Ok(None);
+    }
+    *report.versions.entry(header.version_id).or_default() += 1;
+    let archived = access_from_tagged_bytes_with_scratch::<T>(record, scratch)?;
+    let Some(item) =
+        migrate(archived).map_err(|e| RkyvVersionedError::MigrationError(e.to_string()))?
+    else {
+        report.unchanged += 1;
+        return Ok(None);
+    };
+    if header.flags & HEADER_FLAG_SIGNED != 0 {
+        return Err(RkyvVersionedError::UnsupportedHeaderFlagsError(
+            HEADER_FLAG_SIGNED,
+        ));
+    }
+
+    let mut extensions = get_extensions_from_tagged_bytes(record)?;
+    if extensions.get(EXTENSION_KEY_VARIANT_NAME).is_some() {
+        set_type_name::<T>(&mut extensions, item.get_entry_version_id())?;
+    }
+    let options = TaggedOptions {
+        digest: DigestAlgorithm::from_id(header.digest)
+            .ok_or(RkyvVersionedError::UnsupportedDigestError(header.digest))?,
+        compression: compression_codec(&header)?,
+        extensions,
+        user_word: get_user_word_from_tagged_bytes(record)?,
+        timestamp: get_timestamp_from_tagged_bytes(record)?,
+        sequence: get_sequence_from_tagged_bytes(record)?,
+        ..Default::default()
+    };
+    report.migrated += 1;
+    to_tagged_bytes_with(&item, &options).map(Some)
+}
+
+/// As [migrate_stream], reading the stream from the file at `path` and writing the migrated
+/// stream to a new file at `migrated_path`, which is synced before returning.
+///
+/// # Returns
+///
+/// A `Result` containing the report, or an error if `path` can't be read or migrated, or
+/// `migrated_path` already exists or can't be written.
+pub fn migrate_file<T, E>(
+    path: impl AsRef<Path>,
+    migrated_path: impl AsRef<Path>,
+    migrate: impl Fn(&T::Archived) -> Result<Option<T>, E>,
+) -> Result<MigrateReport, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: Display,
+{
+    let contents = std::fs::read(path).map_err(io_error)?;
+    let (migrated, report) = migrate_stream(&contents, migrate)?;
+    File::create_new(migrated_path)
+        .and_then(|mut file| {
+            file.write_all(&migrated)?;
+            file.sync_all()
+        })
+        .map_err(io_error)?;
+    Ok(report)
+}
+
+type FileMigration = Box<dyn Fn(&Path, &Path) -> Result<MigrateReport, RkyvVersionedError>>;
+
+/// Migrations of several containers by name, for the `migrate` subcommand of a binary built
+/// with [migration_cli](crate::migration_cli).
+#[derive(Default)]
+pub struct MigrationRegistry {
+    migrations: BTreeMap<String, FileMigration>,
+}
+
+impl MigrationRegistry {
+    /// Creates a registry without migrations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `migrate` for records of `T` under `name`, e.g. the path of the container,
+    /// replacing any migration previously registered under it.  Whitespace in `name` is
+    /// ignored, so names produced by `stringify!` match.
+    pub fn register<T, E>(
+        &mut self,
+        name: &str,
+        migrate: impl Fn(&T::Archived) -> Result<Option<T>, E> + 'static,
+    ) -> &mut Self
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>
+            + 'static,
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+        E: Display,
+    {
+        let name = name.split_whitespace().collect::<String>();
+        self.migrations.insert(
+            name,
+            Box::new(move |path, migrated_path| {
+                migrate_file::<T, E>(path, migrated_path, &migrate)
+            }),
+        );
+        self
+    }
+
+    /// The names migrations are registered under, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.migrations.keys().map(String::as_str)
+    }
+
+    /// Returns whether a migration is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// As [migrate_file], with the migration registered under `name`.  A name also matches
+    /// the last segment of a registered path, e.g. `MyContainer` for
+    /// `my_crate::MyContainer`, if that is unambiguous.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the report, [RkyvVersionedError::MigrationError] if no migration
+    /// is registered under `name`, or an error if the file can't be migrated.
+    pub fn migrate_file(
+        &self,
+        name: &str,
+        path: impl AsRef<Path>,
+        migrated_path: impl AsRef<Path>,
+    ) -> Result<MigrateReport, RkyvVersionedError> {
+        let migration = self.find(name).ok_or_else(|| {
+            RkyvVersionedError::MigrationError(format!("no migration registered for {name}"))
+        })?;
+        migration(path.as_ref(), migrated_path.as_ref())
+    }
+
+    fn find(&self, name: &str) -> Option<&FileMigration> {
+        let name = name.split_whitespace().collect::<String>();
+        if let Some(migration) = self.migrations.get(&name) {
+            return Some(migration);
+        }
+        let mut matches = self
+            .migrations
+            .iter()
+            .filter(|(registered, _)| registered.rsplit("::").next() == Some(name.as_str()));
+        match (matches.next(), matches.next()) {
+            (Some((_, migration)), None) => Some(migration),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, get_type_name_from_tagged_bytes, to_tagged_bytes,
+        to_tagged_bytes_named, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct SettingV2 {
+        name: String,
+        enabled: bool,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Setting {
+        V1(String),
+        V2(SettingV2),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u32),
+    }
+
+    fn upgrade(archived: &ArchivedSetting) -> Result<Option<Setting>, String> {
+        match archived {
+            ArchivedSetting::V1(name) if name.is_empty() => Err("unnamed setting".to_string()),
+            ArchivedSetting::V1(name) => Ok(Some(Setting::V2(SettingV2 {
+                name: name.to_string(),
+                enabled: true,
+            }))),
+            ArchivedSetting::V2(_) => Ok(None),
+        }
+    }
+
+    #[test]
+    fn test_migrate_file() {
+        let options = TaggedOptions {
+            user_word: Some(9),
+            ..Default::default()
+        };
+        let mut stream = AlignedVec::<16>::new();
+        let records = [
+            to_tagged_bytes_named(&Setting::V1("verbose".to_string()), &options).unwrap(),
+            to_tagged_bytes(&Other::V1(3)).unwrap(),
+            to_tagged_bytes(&Setting::V2(SettingV2 {
+                name: "color".to_string(),
+                enabled: false,
+            }))
+            .unwrap(),
+        ];
+        for record in &records {
+            append_framed(&mut stream, record).unwrap();
+        }
+
+        let dir = std::env::temp_dir()
+            .join(format!("rkyv_versioned_migrate_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (path, migrated_path) = (dir.join("settings.log"), dir.join("settings.migrated"));
+        std::fs::write(&path, &stream).unwrap();
+
+        let mut registry = MigrationRegistry::new();
+        registry.register::<Setting, _>("my_crate :: Setting", upgrade);
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["my_crate::Setting"]);
+        assert!(registry.contains("Setting"));
+        assert!(registry
+            .migrate_file("Unknown", &path, &migrated_path)
+            .is_err());
+
+        let report = registry
+            .migrate_file("my_crate::Setting", &path, &migrated_path)
+            .unwrap();
+        assert_eq!(
+            (report.migrated, report.unchanged, report.other_types),
+            (1, 1, 1)
+        );
+        assert_eq!(report.versions, BTreeMap::from([(0, 1), (1, 1)]));
+        let migrated = std::fs::read(&migrated_path).unwrap();
+        assert_eq!(migrated.len(), report.migrated_len);
+        let migrated = framed_records(&migrated)
+            .map(|record| record.unwrap().to_vec())
+            .collect::<Vec<_>>();
+        match access_from_tagged_bytes::<Setting>(&migrated[0]).unwrap() {
+            ArchivedSetting::V2(setting) => assert_eq!(setting.name, "verbose"),
+            ArchivedSetting::V1(_) => panic!("record not migrated"),
+        }
+        assert_eq!(
+            get_user_word_from_tagged_bytes(&migrated[0]).unwrap(),
+            Some(9)
+        );
+        let names = get_type_name_from_tagged_bytes(&migrated[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(names.variant_name.as_deref(), Some("V2"));
+        assert_eq!(migrated[1], records[1].as_slice());
+        assert_eq!(migrated[2], records[2].as_slice());
+
+        // Failed migrations are located
+        let mut failing = stream.clone();
+        append_framed(
+            &mut failing,
+            &to_tagged_bytes(&Setting::V1(String::new())).unwrap(),
+        )
+        .unwrap();
+        let Err(e) = migrate_stream::<Setting, _>(&failing, upgrade) else {
+            panic!("failed migration succeeded");
+        };
+        assert_eq!(e.record_location().map(|(index, _)| index), Some(3));
+        assert!(matches!(e.root(), RkyvVersionedError::MigrationError(_)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! A codec trait for plugging containers into generic framing and codec frameworks.
+//!
+//! Frameworks usually extend through a pair of encode and decode hooks working on byte
+//! buffers.  [BytesCodec] is that pair for the length-prefixed frames of
+//! [write_tagged](crate::write_tagged): every [VersionedContainer] implements it, encoding a
+//! container as a frame and decoding the next complete frame at the front of a buffer into
+//! its tagged byte array, so an adapter written once against [BytesCodec] serves every
+//! container.  Other message types, e.g. an enum of several containers, can implement it too.
+//!
+//! With the `codec` feature, `BytesCodecAdapter` is such an adapter for tokio_util's
+//! `Encoder` and `Decoder`, which actix-codec re-exports, so it also serves actix.
+
+use std::io::Write;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::{
+    check_header, write_tagged, RkyvVersionedError, VersionedContainer, FRAME_PREFIX_SIZE,
+    TAGGED_HEADER_SIZE,
+};
+
+/// Encodes values as frames and decodes them from the front of a buffer.
+pub trait BytesCodec {
+    /// What decoding a frame yields.
+    type Decoded;
+
+    /// Encodes `self` as a frame, writing it to `dst`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the number of bytes written, or an error if encoding or
+    /// writing fails.
+    fn encode_frame<W: Write>(&self, dst: &mut W) -> Result<usize, RkyvVersionedError>;
+
+    /// Decodes the frame at the front of `src`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the decoded value and the number of bytes of `src` it
+    /// took up, `None` if `src` doesn't hold the whole frame yet, or an error if the frame is
+    /// corrupt or doesn't hold the expected type.
+    fn decode_frame(src: &[u8]) -> Result<Option<(Self::Decoded, usize)>, RkyvVersionedError>;
+}
+
+impl<T> BytesCodec for T
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    /// The tagged byte array of the frame, copied into an [AlignedVec] so that it can be
+    /// accessed in place.  The header is checked to hold a supported version of `T`, the
+    /// digest and payload are validated when it is accessed.
+    type Decoded = AlignedVec;
+
+    fn encode_frame<W: Write>(&self, dst: &mut W) -> Result<usize, RkyvVersionedError> {
+        write_tagged(self, dst)
+    }
+
+    fn decode_frame(src: &[u8]) -> Result<Option<(AlignedVec, usize)>, RkyvVersionedError> {
+        let Some(prefix) = src.first_chunk::<FRAME_PREFIX_SIZE>() else {
+            return Ok(None);
+        };
+        let record_len =
+            parse_frame_prefix(prefix).ok_or(RkyvVersionedError::InvalidMessageError)?;
+        let Some(header) = src.get(FRAME_PREFIX_SIZE..FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE)
+        else {
+            return Ok(None);
+        };
+        if check_header::<T>(header)?.tagged_len()? != record_len {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        if src.len() < frame_len {
+            return Ok(None);
+        }
+        let mut record = AlignedVec::with_capacity(record_len);
+        record.extend_from_slice(
+            src.get(FRAME_PREFIX_SIZE..FRAME_PREFIX_SIZE + record_len)
+                .unwrap_or_default(),
+        );
+        Ok(Some((record, frame_len)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Command {
+        V1(String),
+        V2((String, u32)),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u32),
+    }
+
+    /// Stands in for a framework driving any codec through the trait
+    fn decode_all<C: BytesCodec>(mut src: &[u8]) -> Vec<C::Decoded> {
+        let mut decoded = Vec::new();
+        while let Some((value, len)) = C::decode_frame(src).unwrap() {
+            decoded.push(value);
+            src = &src[len..];
+        }
+        decoded
+    }
+
+    #[test]
+    fn test_bytes_codec() {
+        let mut buf = Vec::new();
+        let first = Command::V1("start".to_string());
+        let written = first.encode_frame(&mut buf).unwrap();
+        assert_eq!(written, buf.len());
+        Command::V2(("stop".to_string(), 3))
+            .encode_frame(&mut buf)
+            .unwrap();
+
+        let decoded = decode_all::<Command>(&buf);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(
+            decoded[0].as_slice(),
+            to_tagged_bytes(&first).unwrap().as_slice()
+        );
+        match access_from_tagged_bytes::<Command>(&decoded[1]).unwrap() {
+            ArchivedCommand::V2(command) => assert_eq!(command.1, 3),
+            _ => panic!("Expected V2"),
+        }
+
+        // Incomplete frames wait for more bytes, frames of another type are rejected
+        assert!(Command::decode_frame(&buf[..written - 1])
+            .unwrap()
+            .is_none());
+        assert!(Command::decode_frame(&buf[..8]).unwrap().is_none());
+        assert!(matches!(
+            Other::decode_frame(&buf),
+            Err(RkyvVersionedError::UnexpectedTypeError(..))
+        ));
+    }
+}
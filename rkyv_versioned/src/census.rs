@@ -0,0 +1,214 @@
+//! Tallying the versions held by a store of records.
+//!
+//! Retiring an old version of a container is only safe once nothing still holds records of
+//! it.  [scan_versions] walks the files of a store, e.g. the segments of a
+//! [VersionedLog](crate::VersionedLog), sealed archives or [LogWriter](crate::LogWriter)
+//! files, and counts the records of each type and version ID, reading only the frame
+//! prefixes and headers so even large stores are cheap to scan.  [write_version_report]
+//! saves the tally as JSON for planning dashboards.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::io::io_error;
+use crate::{read_header, RkyvVersionedError, FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE};
+
+/// The records of one type and version ID found by [scan_versions].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VersionCount {
+    /// The number of records.
+    pub entries: u64,
+    /// The total length of the records, excluding their frame prefixes and padding.
+    pub bytes: u64,
+    /// The number of files holding at least one of the records.
+    pub files: u64,
+}
+
+/// Counts the records of each `(type_id, version_id)` in a store.
+///
+/// Each file is walked as a stream of frames from its start until the end of the file or the
+/// first bytes which aren't a frame, such as the index of a
+/// [VersionedFile](crate::VersionedFile) or a seal, so files which aren't streams of frames
+/// contribute nothing.  Payloads aren't read or verified.
+///
+/// # Arguments
+///
+/// * `store` - A file, or a directory whose files are walked recursively.
+///
+/// # Returns
+///
+/// A `Result` containing the counts, or an error if a file or directory can't be read.
+pub fn scan_versions(
+    store: impl AsRef<Path>,
+) -> Result<BTreeMap<(u32, u32), VersionCount>, RkyvVersionedError> {
+    let mut counts = BTreeMap::new();
+    let mut pending = vec![store.as_ref().to_path_buf()];
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(&path)
+                .map_err(io_error)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<PathBuf>, _>>()
+                .map_err(io_error)?;
+            // Visit in name order, e.g. oldest segment first
+            entries.sort_unstable_by(|a, b| b.cmp(a));
+            pending.extend(entries);
+        } else {
+            scan_file(&path, &mut counts)?;
+        }
+    }
+    Ok(counts)
+}
+
+/// Adds the records in the stream of frames in the file at `path` to `counts`.
+fn scan_file(
+    path: &Path,
+    counts: &mut BTreeMap<(u32, u32), VersionCount>,
+) -> Result<(), RkyvVersionedError> {
+    let mut file = File::open(path).map_err(io_error)?;
+    let file_len = file.metadata().map_err(io_error)?.len();
+    let mut file_counts = BTreeMap::<(u32, u32), VersionCount>::new();
+    let mut position = 0u64;
+    let mut start = [0u8; FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE];
+    while position + start.len() as u64 <= file_len {
+        file.read_exact(&mut start).map_err(io_error)?;
+        let Some((prefix, header)) = start.split_first_chunk() else {
+            break;
+        };
+        let Some(record_len) = parse_frame_prefix(prefix) else {
+            break;
+        };
+        let Ok(header) = read_header(header) else {
+            break;
+        };
+        let offset = position + FRAME_PREFIX_SIZE as u64;
+        if header.tagged_len().ok() != Some(record_len)
+            || offset + record_len as u64 > file_len
+        {
+            break;
+        }
+        let count = file_counts
+            .entry((header.type_id, header.version_id))
+            .or_default();
+        count.entries += 1;
+        count.bytes += record_len as u64;
+        position = offset + (record_len + frame_padding(record_len)) as u64;
+        file.seek(SeekFrom::Start(position)).map_err(io_error)?;
+    }
+
+    for (key, file_count) in file_counts {
+        let count = counts.entry(key).or_default();
+        count.entries += file_count.entries;
+        count.bytes += file_count.bytes;
+        count.files += 1;
+    }
+    Ok(())
+}
+
+/// Writes the counts returned by [scan_versions] to `path` as JSON, as an array of objects
+/// with the `type_id`, `version_id`, `entries`, `bytes` and `files` of each version.
+///
+/// # Returns
+///
+/// A `Result` which is an error if the file can't be written.
+pub fn write_version_report(
+    path: impl AsRef<Path>,
+    counts: &BTreeMap<(u32, u32), VersionCount>,
+) -> Result<(), RkyvVersionedError> {
+    let rows: Vec<String> = counts
+        .iter()
+        .map(|((type_id, version_id), count)| {
+            format!(
+                "  {{ \"type_id\": {}, \"version_id\": {}, \"entries\": {}, \"bytes\": {}, \
+                 \"files\": {} }}",
+                type_id, version_id, count.entries, count.bytes, count.files
+            )
+        })
+        .collect();
+    let json = match rows.is_empty() {
+        true => "[]\n".to_string(),
+        false => format!("[\n{}\n]\n", rows.join(",\n")),
+    };
+    fs::write(path, json).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        append_framed, to_tagged_bytes, VersionedArchiveContainer, VersionedContainer,
+    };
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+        V2(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Command {
+        V1(u8),
+    }
+
+    #[test]
+    fn test_scan_versions() {
+        let dir =
+            std::env::temp_dir().join(format!("rkyv_versioned_census_{}", std::process::id()));
+        fs::create_dir_all(dir.join("archive")).unwrap();
+
+        let mut old = AlignedVec::<16>::new();
+        for i in 0..3 {
+            append_framed(&mut old, &to_tagged_bytes(&Event::V1(i)).unwrap()).unwrap();
+        }
+        fs::write(dir.join("archive").join("old.vlog"), &old).unwrap();
+
+        let mut current = AlignedVec::<16>::new();
+        append_framed(&mut current, &to_tagged_bytes(&Event::V1(3)).unwrap()).unwrap();
+        let v2 = to_tagged_bytes(&Event::V2("hello".into())).unwrap();
+        append_framed(&mut current, &v2).unwrap();
+        append_framed(&mut current, &to_tagged_bytes(&Command::V1(1)).unwrap()).unwrap();
+        let mut torn = current.to_vec();
+        torn.extend_from_slice(&[7; 40]);
+        fs::write(dir.join("current.vlog"), &torn).unwrap();
+        fs::write(dir.join("notes.txt"), b"not a stream of frames").unwrap();
+
+        let counts = scan_versions(&dir).unwrap();
+        assert_eq!(counts.len(), 3);
+        let v1_id = Event::V1(0).get_entry_version_id();
+        let v2_id = Event::V2(String::new()).get_entry_version_id();
+        let v1 = counts[&(Event::ARCHIVE_TYPE_ID, v1_id)];
+        assert_eq!((v1.entries, v1.files), (4, 2));
+        assert_eq!(
+            counts[&(Event::ARCHIVE_TYPE_ID, v2_id)],
+            VersionCount {
+                entries: 1,
+                bytes: v2.len() as u64,
+                files: 1,
+            }
+        );
+        assert_eq!(
+            counts[&(
+                Command::ARCHIVE_TYPE_ID,
+                Command::V1(0).get_entry_version_id()
+            )]
+                .entries,
+            1
+        );
+
+        let report = dir.join("report.json");
+        write_version_report(&report, &counts).unwrap();
+        let json = fs::read_to_string(&report).unwrap();
+        assert!(json.contains(&format!(
+            "{{ \"type_id\": {}, \"version_id\": {}, \"entries\": 1, \"bytes\": {}, \"files\": 1 }}",
+            Event::ARCHIVE_TYPE_ID,
+            v2_id,
+            v2.len()
+        )));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+//! Uncompressed regions of common fields in front of compressed payloads.
+//!
+//! Filtering compressed records, e.g. by region or amount, would otherwise mean decompressing
+//! every payload to read a couple of fields.  A container implementing [ClearRegion]
+//! declares a struct of its common fields, which [to_tagged_bytes_with_clear_region]
+//! serializes as its own small `rkyv` archive into the [HeaderExtensions] of the record, under
+//! [EXTENSION_KEY_CLEAR_REGION].  The extensions are never compressed, so
+//! [access_clear_region] evaluates the fields without touching the payload, which is
+//! compressed (and encrypted) as set in the [TaggedOptions] as usual.
+//!
+//! Unlike [CleartextFields](crate::CleartextFields), which stores raw bytes under
+//! application defined keys, the region is typed and accessed through `rkyv`, so filters can
+//! compare numbers and strings in place.
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Serialize};
+
+use crate::{
+    check_header, get_extensions_from_tagged_bytes, rkyv_error, to_tagged_bytes_with,
+    RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// The extension key holding the archived [ClearRegion::Fields] of a record.
+pub const EXTENSION_KEY_CLEAR_REGION: u16 = 0xff05;
+
+/// A versioned container with common fields stored uncompressed in front of its payload.
+pub trait ClearRegion {
+    /// The common fields, usually a small struct shared by every version.
+    type Fields: Archive
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>;
+
+    /// Returns the common fields of this version.
+    fn clear_fields(&self) -> Self::Fields;
+}
+
+/// As [to_tagged_bytes_with], additionally storing the [ClearRegion::Fields] of `item`
+/// uncompressed in the header extensions.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `options` - The options to tag the item with, usually with a
+///   [compression](TaggedOptions::compression) codec set.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, or an error if serialization fails
+/// or the archived fields are longer than an extension value can be.
+pub fn to_tagged_bytes_with_clear_region<T>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + ClearRegion
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let region =
+        rkyv::to_bytes::<rkyv::rancor::Error>(&item.clear_fields()).map_err(rkyv_error)?;
+    let mut extensions = options.extensions.clone();
+    extensions.insert(EXTENSION_KEY_CLEAR_REGION, &region)?;
+    let options = TaggedOptions {
+        extensions,
+        ..options.clone()
+    };
+    to_tagged_bytes_with(item, &options)
+}
+
+/// Zero-copy accesses the clear region of a tagged byte array holding a `T`, without
+/// decompressing, decrypting or validating the payload.
+///
+/// The region is copied into `scratch` to align it.  Like other header extensions it is not
+/// verified against the payload digest here, so a filter's decision should be confirmed when
+/// the record is accessed in full.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+/// * `scratch` - A buffer to copy the region into.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the archived fields, `None` if the record was
+/// written without a clear region, or an error if the header doesn't hold a supported version
+/// of `T` or the region fails validation.
+pub fn access_clear_region<'a, T>(
+    buf: &[u8],
+    scratch: &'a mut AlignedVec,
+) -> Result<Option<&'a <T::Fields as Archive>::Archived>, RkyvVersionedError>
+where
+    T: VersionedContainer + ClearRegion,
+    <T::Fields as Archive>::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    check_header::<T>(buf)?;
+    let extensions = get_extensions_from_tagged_bytes(buf)?;
+    let Some(region) = extensions.get(EXTENSION_KEY_CLEAR_REGION) else {
+        return Ok(None);
+    };
+    scratch.clear();
+    scratch.extend_from_slice(region);
+    rkyv::access::<<T::Fields as Archive>::Archived, rkyv::rancor::Error>(scratch)
+        .map(Some)
+        .map_err(rkyv_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes_with_scratch, CompressionCodec, VersionedArchiveContainer,
+    };
+    use rkyv::Deserialize;
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct OrderSummary {
+        region: String,
+        amount: u64,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Order {
+        V1((String, u64, Vec<String>)),
+    }
+
+    impl ClearRegion for Order {
+        type Fields = OrderSummary;
+
+        fn clear_fields(&self) -> OrderSummary {
+            match self {
+                Order::V1((region, amount, _)) => OrderSummary {
+                    region: region.clone(),
+                    amount: *amount,
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_clear_region() {
+        let order = Order::V1(("eu-west".to_string(), 250, vec!["widget".repeat(50); 20]));
+        let compression = match CompressionCodec::Lz4.is_available() {
+            true => CompressionCodec::Lz4,
+            false => CompressionCodec::None,
+        };
+        let options = TaggedOptions {
+            compression,
+            ..Default::default()
+        };
+        let bytes = to_tagged_bytes_with_clear_region(&order, &options).unwrap();
+
+        let mut scratch = AlignedVec::new();
+        let summary = access_clear_region::<Order>(&bytes, &mut scratch)
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.region, "eu-west");
+        assert_eq!(summary.amount, 250);
+
+        // The payload is still compressed and readable in full
+        let mut payload_scratch = AlignedVec::new();
+        match access_from_tagged_bytes_with_scratch::<Order>(&bytes, &mut payload_scratch)
+            .unwrap()
+        {
+            ArchivedOrder::V1(fields) => assert_eq!(fields.2.len(), 20),
+        }
+
+        // Records without a region have none to access
+        let plain = to_tagged_bytes_with(&order, &options).unwrap();
+        assert!(access_clear_region::<Order>(&plain, &mut scratch)
+            .unwrap()
+            .is_none());
+    }
+}
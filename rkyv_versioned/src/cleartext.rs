@@ -0,0 +1,178 @@
+//! Exposing selected fields of encrypted records in cleartext.
+//!
+//! A broker routing encrypted records, e.g. by tenant or partition key, shouldn't need the
+//! key to find out where a record goes.  A container implementing [CleartextFields] declares
+//! which of its fields are exempt from encryption, and [to_tagged_bytes_with_cleartext] copies
+//! them into the [HeaderExtensions] of the record, which are never encrypted, while the
+//! payload is encrypted as usual.  The broker reads them back with
+//! [get_cleartext_field_from_tagged_bytes], without knowing the container type.
+//!
+//! The extensions are bound to the ciphertext as associated data, so a consumer holding the
+//! key detects cleartext fields tampered with in transit when it decrypts the payload.  The
+//! fields are still part of the payload too, only copied, so consumers don't need to read
+//! them from the extensions.
+
+use alloc::vec::Vec;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    get_extensions_from_tagged_bytes, to_tagged_bytes_with, HeaderExtensions,
+    RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// A versioned container with fields to be stored in cleartext when its payload is
+/// encrypted.
+pub trait CleartextFields {
+    /// Inserts the cleartext fields of this version into `extensions`, each under an
+    /// application defined key, e.g. the routing key of a message.
+    ///
+    /// Fails if a field can't be stored, e.g. with
+    /// [RkyvVersionedError::InvalidExtensionError] if its value is too long.
+    fn cleartext_fields(
+        &self,
+        extensions: &mut HeaderExtensions,
+    ) -> Result<(), RkyvVersionedError>;
+}
+
+/// As [to_tagged_bytes_with], additionally storing the [CleartextFields] of `item` in the
+/// header extensions, alongside any already set in `options`.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `options` - The options to tag the item with, usually with an
+///   [encryption](TaggedOptions::encryption) key set.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, or an error if a cleartext field can't
+/// be stored or serialization fails.
+pub fn to_tagged_bytes_with_cleartext<T>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + CleartextFields
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let mut extensions = options.extensions.clone();
+    item.cleartext_fields(&mut extensions)?;
+    let options = TaggedOptions {
+        extensions,
+        ..options.clone()
+    };
+    to_tagged_bytes_with(item, &options)
+}
+
+/// Reads the cleartext field stored under `key` without accessing or decrypting the payload.
+///
+/// The field is not authenticated until the payload is decrypted, so it should only be used
+/// for decisions such as routing that a forged value can't turn into a security issue.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+/// * `key` - The extension key the field was stored under.
+///
+/// # Returns
+///
+/// A `Result` containing the value of the field, `None` if it wasn't stored, or an error if
+/// the buffer is truncated or the extension area is malformed.
+pub fn get_cleartext_field_from_tagged_bytes(
+    buf: &[u8],
+    key: u16,
+) -> Result<Option<Vec<u8>>, RkyvVersionedError> {
+    Ok(get_extensions_from_tagged_bytes(buf)?.remove(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_encrypted_tagged_bytes, EncryptionAlgorithm, EncryptionKey,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    const ROUTING_KEY: u16 = 1;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Order {
+        V1(String),
+        V2((String, u64)),
+    }
+
+    impl CleartextFields for Order {
+        fn cleartext_fields(
+            &self,
+            extensions: &mut HeaderExtensions,
+        ) -> Result<(), RkyvVersionedError> {
+            match self {
+                Order::V1(_) => Ok(()),
+                Order::V2((region, _)) => extensions.insert(ROUTING_KEY, region.as_bytes()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cleartext_fields() {
+        let order = Order::V2(("eu-west".to_string(), 1200));
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(2, b"tenant-7").unwrap();
+        let options = TaggedOptions {
+            extensions,
+            ..Default::default()
+        };
+
+        let bytes = to_tagged_bytes_with_cleartext(&order, &options).unwrap();
+        assert_eq!(
+            get_cleartext_field_from_tagged_bytes(&bytes, ROUTING_KEY).unwrap(),
+            Some(b"eu-west".to_vec())
+        );
+        assert_eq!(
+            get_cleartext_field_from_tagged_bytes(&bytes, 2).unwrap(),
+            Some(b"tenant-7".to_vec())
+        );
+        let v1 = to_tagged_bytes_with_cleartext(&Order::V1("a".into()), &options).unwrap();
+        assert_eq!(
+            get_cleartext_field_from_tagged_bytes(&v1, ROUTING_KEY).unwrap(),
+            None
+        );
+
+        if EncryptionAlgorithm::XChaCha20Poly1305.is_available() {
+            let key = EncryptionKey::from_bytes([3; 32]);
+            let options = TaggedOptions {
+                encryption: Some(key.clone()),
+                ..options
+            };
+            let mut bytes = to_tagged_bytes_with_cleartext(&order, &options).unwrap();
+            assert_eq!(
+                get_cleartext_field_from_tagged_bytes(&bytes, ROUTING_KEY).unwrap(),
+                Some(b"eu-west".to_vec())
+            );
+            // Only the cleartext copy of the routing key is readable
+            assert_eq!(bytes.windows(7).filter(|w| *w == b"eu-west").count(), 1);
+
+            let mut scratch = AlignedVec::new();
+            match access_from_encrypted_tagged_bytes::<Order>(&bytes, &key, &mut scratch)
+                .unwrap()
+            {
+                ArchivedOrder::V2(archived) => assert_eq!(archived.1, 1200),
+                _ => panic!("Expected V2"),
+            }
+
+            // Tampering with the cleartext field is detected
+            let at = bytes.windows(7).position(|w| w == b"eu-west").unwrap();
+            bytes[at] = b'u';
+            assert!(
+                access_from_encrypted_tagged_bytes::<Order>(&bytes, &key, &mut scratch)
+                    .is_err()
+            );
+        }
+    }
+}
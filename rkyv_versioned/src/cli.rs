@@ -0,0 +1,302 @@
+//! The `rkyv_versioned` command line tool for stores of tagged records, enabled with the `cli`
+//! feature.
+//!
+//! ```text
+//! rkyv_versioned stats <path>
+//! rkyv_versioned repair <path> [<repaired path>]
+//! rkyv_versioned convert <in> <out> [--compress <codec>] [--digest <algorithm>] [--header <profile>]
+//! rkyv_versioned migrate <in> <out> --container <name>
+//! ```
+//!
+//! The `rkyv_versioned` binary only knows the envelope of records, so it can't migrate them.
+//! [migration_cli](crate::migration_cli) builds a binary running the same commands with the
+//! containers and migrations of a crate registered for `migrate`, see
+//! [MigrationRegistry](crate::MigrationRegistry):
+//!
+//! ```ignore
+//! // src/bin/store_tool.rs
+//! rkyv_versioned::migration_cli! {
+//!     my_crate::Settings => my_crate::migrations::upgrade_settings,
+//!     my_crate::Event => my_crate::migrations::upgrade_event,
+//! }
+//! ```
+
+use std::process::ExitCode;
+
+use crate::{
+    convert_file, repair_file, stream_stats_file, CompressionCodec, ConvertOptions,
+    DigestAlgorithm, HeaderProfile, MigrateReport, MigrationRegistry, RepairReport,
+    StreamStats,
+};
+
+const USAGE: &str = "\
+Usage: rkyv_versioned <command> [args]
+
+Commands:
+  stats <path>    Prints the records of each type and version in a stream of frames,
+                  their sizes, compression ratios and any corruption
+  repair <path> [<repaired path>]
+                  Copies the intact records of a stream of frames to a new file, by default
+                  <path>.repaired, and prints the ranges dropped
+  convert <in> <out> [--compress none|zstd|lz4] [--digest none|crc32|crc32c|xxh3|blake3]
+                     [--header standard|compact|extended]
+                  Re-envelopes the records of a stream of frames into a new file, keeping
+                  each record's codec and digest unless given
+  migrate <in> <out> --container <name>
+                  Migrates the records of a registered container in a stream of frames into
+                  a new file, copying the records of other containers";
+
+/// Builds a `main` function running the `rkyv_versioned` commands, with migrations of the
+/// given containers registered for the `migrate` subcommand under their paths.
+///
+/// Each entry names a container type and a migration returning `Result<Option<T>, E>`, see
+/// [migrate_stream](crate::migrate_stream).  Requires the `cli` feature.
+#[macro_export]
+macro_rules! migration_cli {
+    ($($container:ty => $migrate:expr),* $(,)?) => {
+        fn main() -> ::std::process::ExitCode {
+            #[allow(unused_mut)]
+            let mut migrations = $crate::MigrationRegistry::new();
+            $(migrations.register::<$container, _>(stringify!($container), $migrate);)*
+            $crate::cli::main(&migrations)
+        }
+    };
+}
+
+/// Runs the command given by the arguments of the process, see [run].
+pub fn main(migrations: &MigrationRegistry) -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    run(&args, migrations)
+}
+
+/// Runs the command given by `args`, not including the name of the program, printing its
+/// output or errors.
+///
+/// # Returns
+///
+/// The exit code: success, `2` if the arguments are invalid, or failure if the command
+/// failed, or `stats` found corruption.
+pub fn run(args: &[&str], migrations: &MigrationRegistry) -> ExitCode {
+    let result = match args {
+        ["stats", path] => stats(path),
+        ["repair", path] => repair(path, &format!("{path}.repaired")),
+        ["repair", path, repaired_path] => repair(path, repaired_path),
+        ["convert", path, converted_path, flags @ ..] => match convert_options(flags) {
+            Some(options) => convert(path, converted_path, &options),
+            None => {
+                eprintln!("{USAGE}");
+                return ExitCode::from(2);
+            }
+        },
+        ["migrate", path, migrated_path, "--container", name] => {
+            migrate(path, migrated_path, name, migrations)
+        }
+        ["help" | "--help" | "-h"] => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints the [StreamStats] of the file at `path`, failing if it isn't clean.
+fn stats(path: &str) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let stats = stream_stats_file(path)?;
+    print!("{}", format_stats(path, &stats));
+    Ok(match stats.is_clean() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
+/// Writes the intact records of the file at `path` to `repaired_path` and prints what was
+/// dropped.
+fn repair(path: &str, repaired_path: &str) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let report = repair_file(path, repaired_path)?;
+    print!("{}", format_repair(path, repaired_path, &report));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_repair(path: &str, repaired_path: &str, report: &RepairReport) -> String {
+    let mut out = format!(
+        "{path}: {} bytes, {} intact records\n",
+        report.len, report.records
+    );
+    for range in &report.dropped {
+        out += &format!(
+            "Dropped bytes {}..{} ({} bytes)\n",
+            range.start,
+            range.end,
+            range.len()
+        );
+    }
+    match report.is_intact() {
+        true => out += "Nothing dropped\n",
+        false => {
+            out += &format!(
+                "Dropped {} bytes in {} ranges\n",
+                report.dropped_bytes(),
+                report.dropped.len()
+            )
+        }
+    }
+    out += &format!("Wrote {} bytes to {repaired_path}\n", report.repaired_len);
+    out
+}
+
+/// Parses the flags of the `convert` subcommand, or returns `None` if they are invalid.
+fn convert_options(mut flags: &[&str]) -> Option<ConvertOptions> {
+    let mut options = ConvertOptions::default();
+    while let [flag, value, rest @ ..] = flags {
+        match *flag {
+            "--compress" => {
+                options.compression = Some(match *value {
+                    "none" => CompressionCodec::None,
+                    "zstd" => CompressionCodec::Zstd,
+                    "lz4" => CompressionCodec::Lz4,
+                    _ => return None,
+                })
+            }
+            "--digest" => {
+                options.digest = Some(match *value {
+                    "none" => DigestAlgorithm::None,
+                    "crc32" => DigestAlgorithm::Crc32,
+                    "crc32c" => DigestAlgorithm::Crc32c,
+                    "xxh3" => DigestAlgorithm::Xxh3,
+                    "blake3" => DigestAlgorithm::Blake3,
+                    _ => return None,
+                })
+            }
+            "--header" => {
+                options.profile = match *value {
+                    "standard" => HeaderProfile::Standard,
+                    "compact" => HeaderProfile::Compact,
+                    "extended" => HeaderProfile::Extended,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+        flags = rest;
+    }
+    flags.is_empty().then_some(options)
+}
+
+/// Re-envelopes the records of the file at `path` into `converted_path` and prints how many
+/// were converted.
+fn convert(
+    path: &str,
+    converted_path: &str,
+    options: &ConvertOptions,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let report = convert_file(path, converted_path, options)?;
+    println!(
+        "{path}: converted {} records, copied {} signed or encrypted records unchanged",
+        report.records, report.unchanged
+    );
+    println!(
+        "Wrote {} bytes to {converted_path} ({} bytes before)",
+        report.converted_len, report.len
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Migrates the records of the container registered under `name` in the file at `path` into
+/// `migrated_path` and prints how many were migrated.
+fn migrate(
+    path: &str,
+    migrated_path: &str,
+    name: &str,
+    migrations: &MigrationRegistry,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if !migrations.contains(name) {
+        let names = migrations.names().collect::<Vec<_>>();
+        return Err(match names.is_empty() {
+            true => format!(
+                "no migration registered for {name}, build a binary with migration_cli! to \
+                 register them"
+            ),
+            false => format!(
+                "no migration registered for {name}, expected one of: {}",
+                names.join(", ")
+            ),
+        }
+        .into());
+    }
+    let report = migrations.migrate_file(name, path, migrated_path)?;
+    print!("{}", format_migrate(path, migrated_path, &report));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_migrate(path: &str, migrated_path: &str, report: &MigrateReport) -> String {
+    let mut out = format!(
+        "{path}: migrated {} records, kept {} unchanged, copied {} of other types\n",
+        report.migrated, report.unchanged, report.other_types
+    );
+    for (version_id, records) in &report.versions {
+        out += &format!("  version {version_id}: {records} records\n");
+    }
+    out += &format!(
+        "Wrote {} bytes to {migrated_path} ({} bytes before)\n",
+        report.migrated_len, report.len
+    );
+    out
+}
+
+fn format_stats(path: &str, stats: &StreamStats) -> String {
+    let mut out = format!(
+        "{path}: {} bytes, {} records\n\n",
+        stats.len,
+        stats.records()
+    );
+    out += &format!(
+        "{:<10}  {:<20}  {:>7}  {:>9}  {:>12}  {:>12}  {:>6}  {:>8}\n",
+        "type_id", "name", "version", "records", "bytes", "payload", "ratio", "corrupt"
+    );
+    for ((type_id, version_id), version) in &stats.versions {
+        let name = stats.type_names.get(type_id).map_or("?", String::as_str);
+        let ratio = version
+            .compression_ratio()
+            .map_or("-".to_string(), |ratio| format!("{ratio:.2}"));
+        out += &format!(
+            "{:<#10x}  {:<20}  {:>7}  {:>9}  {:>12}  {:>12}  {:>6}  {:>8}\n",
+            type_id,
+            name,
+            version_id,
+            version.records,
+            version.bytes,
+            version.stored_payload_bytes,
+            ratio,
+            version.checksum_failures
+        );
+    }
+    out += "\n";
+    if let Some(offset) = stats.truncated_at {
+        out += &format!("Truncated frame at offset {offset}\n");
+    }
+    if let Some(offset) = stats.corrupt_at {
+        out += &format!(
+            "Corrupt frame at offset {offset}, {} bytes not walked\n",
+            stats.len - offset
+        );
+    }
+    match stats.checksum_failures() {
+        0 => {}
+        failures => out += &format!("{failures} records failed their digest\n"),
+    }
+    if stats.is_clean() {
+        out += "Clean\n";
+    }
+    out
+}
@@ -0,0 +1,383 @@
+//! A tokio_util codec for tagged byte arrays, enabled with the `codec` feature.
+//!
+//! [VersionedCodec] encodes and decodes the length-prefixed frames written by
+//! [write_tagged](crate::write_tagged), so a `Framed` transport can exchange versioned
+//! containers with a peer using the synchronous or async helpers:
+//!
+//! ```
+//! use bytes::BytesMut;
+//! use rkyv::{Archive, Deserialize, Serialize};
+//! use rkyv_versioned::{access_from_tagged_bytes, VersionedArchiveContainer, VersionedCodec};
+//! use tokio_util::codec::{Decoder, Encoder};
+//!
+//! #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+//! enum Event {
+//!     V1(String),
+//! }
+//!
+//! let mut codec = VersionedCodec::<Event>::new();
+//! let mut buf = BytesMut::new();
+//! codec.encode(&Event::V1("hello".to_string()), &mut buf).unwrap();
+//!
+//! let record = codec.decode(&mut buf).unwrap().unwrap();
+//! assert!(access_from_tagged_bytes::<Event>(&record).is_ok());
+//! ```
+//!
+//! Decoded records are the tagged byte arrays, copied into an [AlignedVec] so that they can be
+//! accessed in place.
+//!
+//! For pipelines working with `futures` streams and sinks, [into_record_stream] and
+//! [into_record_sink] wrap a tokio reader or writer in the codec, yielding an owned record
+//! buffer per frame which can be handed to another task.
+//!
+//! [BytesCodecAdapter] implements the same traits for any [BytesCodec], e.g. a message type
+//! wrapping several containers.  actix-codec re-exports the tokio_util traits, so both codecs
+//! also work with actix.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use bytes::{Buf, BufMut, BytesMut};
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+use crate::framing::{frame_padding, frame_prefix, parse_frame_prefix};
+use crate::validation::ValidationLimit;
+use crate::{
+    check_header, to_tagged_bytes_with, BytesCodec, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, FRAME_ALIGNMENT, FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE,
+};
+
+/// A tokio_util [Encoder] and [Decoder] of length-prefixed frames holding tagged byte arrays
+/// of `T`.
+pub struct VersionedCodec<T> {
+    options: TaggedOptions,
+    max_record_len: Option<usize>,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for VersionedCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionedCodec")
+            .field("options", &self.options)
+            .field("max_record_len", &self.max_record_len)
+            .finish()
+    }
+}
+
+impl<T> Clone for VersionedCodec<T> {
+    fn clone(&self) -> Self {
+        VersionedCodec {
+            options: self.options.clone(),
+            max_record_len: self.max_record_len,
+            _container: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for VersionedCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> VersionedCodec<T> {
+    /// Creates a codec tagging containers with the default [TaggedOptions].
+    pub fn new() -> Self {
+        Self::with_options(TaggedOptions::default())
+    }
+
+    /// Creates a codec tagging containers with `options`.
+    pub fn with_options(options: TaggedOptions) -> Self {
+        VersionedCodec {
+            options,
+            max_record_len: None,
+            _container: PhantomData,
+        }
+    }
+
+    /// Limits the length of decoded records, failing with
+    /// [RkyvVersionedError::LimitExceededError] as soon as the header of a longer one has been
+    /// received instead of buffering it.
+    pub fn with_max_record_len(mut self, max_record_len: usize) -> Self {
+        self.max_record_len = Some(max_record_len);
+        self
+    }
+}
+
+impl<T> Encoder<&T> for VersionedCodec<T>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    type Error = RkyvVersionedError;
+
+    fn encode(&mut self, item: &T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let record = to_tagged_bytes_with(item, &self.options)?;
+        let padding = [0u8; FRAME_ALIGNMENT];
+        let padding = padding
+            .get(..frame_padding(record.len()))
+            .unwrap_or_default();
+        dst.reserve(FRAME_PREFIX_SIZE + record.len() + padding.len());
+        dst.extend_from_slice(&frame_prefix(record.len())?);
+        dst.extend_from_slice(&record);
+        dst.extend_from_slice(padding);
+        Ok(())
+    }
+}
+
+impl<T> Encoder<T> for VersionedCodec<T>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    type Error = RkyvVersionedError;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
+
+impl<T: VersionedContainer> Decoder for VersionedCodec<T> {
+    type Item = AlignedVec;
+    type Error = RkyvVersionedError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(prefix) = src.first_chunk::<FRAME_PREFIX_SIZE>() else {
+            return Ok(None);
+        };
+        let record_len =
+            parse_frame_prefix(prefix).ok_or(RkyvVersionedError::InvalidMessageError)?;
+
+        // Only trust the prefix as far as the header agrees with it, and don't buffer a record
+        // which isn't a `T` anyway
+        let Some(header_bytes) =
+            src.get(FRAME_PREFIX_SIZE..FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE)
+        else {
+            src.reserve(FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE - src.len());
+            return Ok(None);
+        };
+        let header = check_header::<T>(header_bytes)?;
+        if header.tagged_len()? != record_len {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        if let Some(max) = self.max_record_len {
+            if record_len > max {
+                return Err(RkyvVersionedError::LimitExceededError(
+                    ValidationLimit::PayloadLen,
+                    max,
+                ));
+            }
+        }
+
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        let mut record = AlignedVec::with_capacity(record_len);
+        record.extend_from_slice(
+            src.get(FRAME_PREFIX_SIZE..FRAME_PREFIX_SIZE + record_len)
+                .unwrap_or_default(),
+        );
+        src.advance(frame_len);
+        Ok(Some(record))
+    }
+}
+
+/// A tokio_util [Encoder] and [Decoder] of the frames of any [BytesCodec] `C`.
+pub struct BytesCodecAdapter<C> {
+    _codec: PhantomData<fn() -> C>,
+}
+
+impl<C> fmt::Debug for BytesCodecAdapter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BytesCodecAdapter").finish()
+    }
+}
+
+impl<C> Clone for BytesCodecAdapter<C> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Default for BytesCodecAdapter<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> BytesCodecAdapter<C> {
+    /// Creates an adapter for `C`.
+    pub fn new() -> Self {
+        BytesCodecAdapter {
+            _codec: PhantomData,
+        }
+    }
+}
+
+impl<C: BytesCodec> Encoder<&C> for BytesCodecAdapter<C> {
+    type Error = RkyvVersionedError;
+
+    fn encode(&mut self, item: &C, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        item.encode_frame(&mut dst.writer())?;
+        Ok(())
+    }
+}
+
+impl<C: BytesCodec> Decoder for BytesCodecAdapter<C> {
+    type Item = C::Decoded;
+    type Error = RkyvVersionedError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match C::decode_frame(src)? {
+            Some((item, len)) => {
+                src.advance(len);
+                Ok(Some(item))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// A `Stream` of the tagged records read from `R`, see [into_record_stream].
+pub type RecordStream<R, T> = FramedRead<R, VersionedCodec<T>>;
+
+/// A `Sink` of containers written to `W` as tagged records, see [into_record_sink].
+pub type RecordSink<W, T> = FramedWrite<W, VersionedCodec<T>>;
+
+/// Wraps `reader` in a `Stream` yielding the tagged byte array of each frame of `T`.
+///
+/// Each item is an owned [AlignedVec], so records can be fanned out to workers and accessed
+/// there with e.g. [access_from_tagged_bytes](crate::access_from_tagged_bytes).  The stream
+/// ends when `reader` does, with an error if it ends part way through a frame.
+pub fn into_record_stream<R: AsyncRead, T: VersionedContainer>(
+    reader: R,
+) -> RecordStream<R, T> {
+    FramedRead::new(reader, VersionedCodec::new())
+}
+
+/// Wraps `writer` in a `Sink` accepting containers of `T`, by value or by reference, and
+/// writing each one as a tagged frame with the default [TaggedOptions].
+///
+/// Use [FramedWrite::new] with [VersionedCodec::with_options] for other options.
+pub fn into_record_sink<W: AsyncWrite, T: VersionedContainer>(writer: W) -> RecordSink<W, T> {
+    FramedWrite::new(writer, VersionedCodec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, VersionedArchiveContainer};
+    use futures::{SinkExt, StreamExt};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    #[test]
+    fn test_codec() {
+        let mut codec = VersionedCodec::<Event>::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(&Event::V1("first".to_string()), &mut buf)
+            .unwrap();
+        codec.encode(Event::V2(2), &mut buf).unwrap();
+
+        // The frames are the same as those written by `write_tagged`
+        let mut stream = Vec::new();
+        crate::write_tagged(&Event::V1("first".to_string()), &mut stream).unwrap();
+        crate::write_tagged(&Event::V2(2), &mut stream).unwrap();
+        assert_eq!(&buf[..], stream.as_slice());
+
+        // Frames arriving a byte at a time are only decoded once complete
+        let mut received = BytesMut::new();
+        let mut records = Vec::new();
+        for byte in stream.iter() {
+            received.extend_from_slice(&[*byte]);
+            if let Some(record) = codec.decode(&mut received).unwrap() {
+                records.push(record);
+            }
+        }
+        assert!(received.is_empty());
+        assert_eq!(records.len(), 2);
+
+        // The adapter encodes and decodes the same frames through `BytesCodec`
+        let mut adapter = BytesCodecAdapter::<Event>::new();
+        let mut adapted = BytesMut::new();
+        adapter
+            .encode(&Event::V1("first".to_string()), &mut adapted)
+            .unwrap();
+        adapter.encode(&Event::V2(2), &mut adapted).unwrap();
+        assert_eq!(&adapted[..], stream.as_slice());
+        let record = adapter.decode(&mut adapted).unwrap().unwrap();
+        assert_eq!(record.as_slice(), records[0].as_slice());
+        assert_eq!(
+            adapter.decode(&mut adapted).unwrap().unwrap().len(),
+            records[1].len()
+        );
+        assert!(adapted.is_empty());
+        match access_from_tagged_bytes::<Event>(&records[0]).unwrap() {
+            ArchivedEvent::V1(text) => assert_eq!(text, "first"),
+            _ => panic!("Expected V1"),
+        }
+        match access_from_tagged_bytes::<Event>(&records[1]).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 2),
+            _ => panic!("Expected V2"),
+        }
+
+        // Corrupt and oversized frames
+        let mut corrupt = BytesMut::from(stream.as_slice());
+        corrupt[4] = 1;
+        assert!(matches!(
+            codec.decode(&mut corrupt),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+        let mut limited = VersionedCodec::<Event>::new().with_max_record_len(8);
+        assert!(matches!(
+            limited.decode(&mut BytesMut::from(stream.as_slice())),
+            Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::PayloadLen,
+                8
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_stream_and_sink() {
+        let (client, server) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            let mut sink = into_record_sink::<_, Event>(client);
+            for value in 0..10 {
+                sink.send(Event::V2(value)).await.unwrap();
+            }
+            sink.send(&Event::V1("last".to_string())).await.unwrap();
+        });
+
+        let records: Vec<AlignedVec> = into_record_stream::<_, Event>(server)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        writer.await.unwrap();
+
+        assert_eq!(records.len(), 11);
+        for (value, record) in records[..10].iter().enumerate() {
+            match access_from_tagged_bytes::<Event>(record).unwrap() {
+                ArchivedEvent::V2(v) => assert_eq!(*v, value as u64),
+                _ => panic!("Expected V2"),
+            }
+        }
+        assert!(matches!(
+            access_from_tagged_bytes::<Event>(&records[10]).unwrap(),
+            ArchivedEvent::V1(_)
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+//! Optional payload compression.
+//!
+//! When a [CompressionCodec] other than [CompressionCodec::None] is chosen, the payload of a
+//! tagged byte array is stored as the little-endian `u32` length of the uncompressed payload
+//! followed by the compressed payload, and the codec is recorded in the header.  The digest
+//! covers the stored (compressed) bytes so corruption is caught before decompressing.
+//!
+//! Compressed payloads cannot be accessed in place, see
+//! [access_from_tagged_bytes_with_scratch](crate::access_from_tagged_bytes_with_scratch).
+
+use alloc::vec::Vec;
+
+use rkyv::util::AlignedVec;
+
+use crate::{RkyvVersionedError, ValidationLimit};
+
+/// The size of the uncompressed length prefix of a compressed payload.
+const UNCOMPRESSED_LEN_SIZE: usize = 4;
+
+/// The codec used to compress the payload of a tagged byte array.
+///
+/// The discriminant of each variant is written into the header and MUST NOT CHANGE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum CompressionCodec {
+    /// The payload is stored uncompressed and can be accessed in place.
+    #[default]
+    None = 0,
+    /// Zstandard at the default compression level.  Requires the `zstd` feature.
+    Zstd = 1,
+    /// LZ4 block compression, which trades ratio for speed.  Requires the `lz4` feature.
+    Lz4 = 2,
+}
+
+impl CompressionCodec {
+    /// Returns the identifier of the codec as written in the header.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the codec for an identifier read from a header, or `None` if the identifier is
+    /// unknown.
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(CompressionCodec::None),
+            1 => Some(CompressionCodec::Zstd),
+            2 => Some(CompressionCodec::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Returns whether support for this codec has been compiled in.
+    pub const fn is_available(self) -> bool {
+        match self {
+            CompressionCodec::None => true,
+            CompressionCodec::Zstd => cfg!(feature = "zstd"),
+            CompressionCodec::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Compresses `payload`, appending the stored form to `out`.
+    pub(crate) fn compress(
+        self,
+        payload: &[u8],
+        out: &mut AlignedVec,
+    ) -> Result<(), RkyvVersionedError> {
+        if self == CompressionCodec::None {
+            out.extend_from_slice(payload);
+            return Ok(());
+        }
+
+        let uncompressed_len = u32::try_from(payload.len())
+            .map_err(|_| RkyvVersionedError::PayloadTooLargeError(payload.len()))?;
+
+        let compressed: Vec<u8> = match self {
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => {
+                zstd::bulk::compress(payload, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .map_err(|e| RkyvVersionedError::CompressionError(Box::new(e)))
+            }
+            #[cfg(feature = "lz4")]
+            CompressionCodec::Lz4 => Ok(lz4_flex::block::compress(payload)),
+            #[allow(unreachable_patterns)]
+            _ => Err(RkyvVersionedError::UnsupportedCompressionError(self.id())),
+        }?;
+
+        out.extend_from_slice(&uncompressed_len.to_le_bytes());
+        out.extend_from_slice(&compressed);
+        Ok(())
+    }
+
+    /// The longest stored form [CompressionCodec::compress] can produce for a payload of
+    /// `payload_len` bytes.
+    pub(crate) fn max_stored_len(
+        self,
+        payload_len: usize,
+    ) -> Result<usize, RkyvVersionedError> {
+        if self == CompressionCodec::None {
+            return Ok(payload_len);
+        }
+
+        let compressed_len: usize = match self {
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => Ok(zstd::compress_bound(payload_len)),
+            #[cfg(feature = "lz4")]
+            CompressionCodec::Lz4 => Ok(lz4_flex::block::get_maximum_output_size(payload_len)),
+            #[allow(unreachable_patterns)]
+            _ => Err(RkyvVersionedError::UnsupportedCompressionError(self.id())),
+        }?;
+        Ok(UNCOMPRESSED_LEN_SIZE + compressed_len)
+    }
+
+    /// Decompresses a stored payload into `out`, replacing its contents.
+    pub(crate) fn decompress(
+        self,
+        stored: &[u8],
+        out: &mut AlignedVec,
+    ) -> Result<(), RkyvVersionedError> {
+        self.decompress_limited(stored, out, usize::MAX)
+    }
+
+    /// Decompresses `stored` into `out` like [CompressionCodec::decompress], failing before
+    /// allocating if the uncompressed payload would be longer than `max_len`.
+    pub(crate) fn decompress_limited(
+        self,
+        stored: &[u8],
+        out: &mut AlignedVec,
+        max_len: usize,
+    ) -> Result<(), RkyvVersionedError> {
+        out.clear();
+        if self == CompressionCodec::None {
+            out.extend_from_slice(stored);
+            return Ok(());
+        }
+
+        let (len, _) = stored
+            .split_first_chunk::<UNCOMPRESSED_LEN_SIZE>()
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let uncompressed_len = u32::from_le_bytes(*len) as usize;
+        if uncompressed_len > max_len {
+            return Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::PayloadLen,
+                max_len,
+            ));
+        }
+        out.resize(uncompressed_len, 0);
+
+        let written: usize = match self {
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => zstd::bulk::decompress_to_buffer(
+                stored.get(UNCOMPRESSED_LEN_SIZE..).unwrap_or_default(),
+                out.as_mut_slice(),
+            )
+            .map_err(|e| RkyvVersionedError::CompressionError(Box::new(e))),
+            #[cfg(feature = "lz4")]
+            CompressionCodec::Lz4 => lz4_flex::block::decompress_into(
+                stored.get(UNCOMPRESSED_LEN_SIZE..).unwrap_or_default(),
+                out.as_mut_slice(),
+            )
+            .map_err(|e| RkyvVersionedError::CompressionError(e.into())),
+            #[allow(unreachable_patterns)]
+            _ => Err(RkyvVersionedError::UnsupportedCompressionError(self.id())),
+        }?;
+
+        if written != uncompressed_len {
+            return Err(RkyvVersionedError::BufferTooSmallError);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() {
+        let payload: Vec<u8> = (0..4096u32).flat_map(|i| (i % 7).to_le_bytes()).collect();
+
+        for codec in [
+            CompressionCodec::None,
+            CompressionCodec::Zstd,
+            CompressionCodec::Lz4,
+        ] {
+            assert_eq!(CompressionCodec::from_id(codec.id()), Some(codec));
+
+            let mut stored = AlignedVec::new();
+            let result = codec.compress(&payload, &mut stored);
+            if !codec.is_available() {
+                assert!(matches!(
+                    result,
+                    Err(RkyvVersionedError::UnsupportedCompressionError(id)) if id == codec.id()
+                ));
+                continue;
+            }
+            result.unwrap();
+            if codec != CompressionCodec::None {
+                assert!(stored.len() < payload.len());
+            }
+
+            let mut decompressed = AlignedVec::new();
+            codec.decompress(&stored, &mut decompressed).unwrap();
+            assert_eq!(decompressed.as_slice(), payload.as_slice());
+
+            // Truncated compressed data must fail rather than yield a short payload
+            if codec != CompressionCodec::None {
+                assert!(codec
+                    .decompress(&stored[..stored.len() / 2], &mut decompressed)
+                    .is_err());
+            }
+        }
+        assert_eq!(CompressionCodec::from_id(0xff), None);
+    }
+}
@@ -0,0 +1,195 @@
+//! Hashing of the logical content of archived values.
+//!
+//! The raw bytes of a tagged byte array depend on more than the value it holds: the digest,
+//! compression codec, padding and the exact layout chosen by the `rkyv` serializer can all
+//! differ between two encodings of an equal value.  [payload_content_hash] instead walks the
+//! archived value through its [Hash] implementation, feeding a [ContentHasher] whose output
+//! is stable across platforms and releases of this crate.
+//!
+//! Archived types opt in with `#[rkyv(derive(Hash))]`.
+
+use core::hash::{Hash, Hasher};
+
+use rkyv::util::AlignedVec;
+
+use crate::{access_from_tagged_bytes_with_scratch, RkyvVersionedError, VersionedContainer};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A [Hasher] computing the 64-bit FNV-1a hash of the values written to it.
+///
+/// Unlike `std`'s default hasher the algorithm is fixed, and integers are always written
+/// little-endian with `usize`/`isize` widened to 64 bits, so the same value hashes the same
+/// on every platform.
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        ContentHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+/// Hashes the logical content of the versioned container held in a tagged byte array.
+///
+/// The hash covers the type ID and the archived value (including which version it is), but not
+/// how it was encoded, so equal values compare equal regardless of digest, compression or
+/// layout differences.  The payload is validated as by
+/// [access_from_tagged_bytes_with_scratch] first, and encrypted payloads are rejected.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either the 64-bit content hash or an error.
+pub fn payload_content_hash<T: VersionedContainer>(
+    buf: &[u8],
+) -> Result<u64, RkyvVersionedError>
+where
+    T::Archived: Hash
+        + rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let mut scratch = AlignedVec::new();
+    let archived = access_from_tagged_bytes_with_scratch::<T>(buf, &mut scratch)?;
+
+    let mut hasher = ContentHasher::default();
+    T::ARCHIVE_TYPE_ID.hash(&mut hasher);
+    archived.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        to_tagged_bytes, to_tagged_bytes_with, CompressionCodec, DigestAlgorithm,
+        TaggedOptions, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    #[rkyv(derive(Hash))]
+    struct Document {
+        pub title: String,
+        pub tags: Vec<String>,
+        pub revision: u64,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[rkyv(derive(Hash))]
+    enum DocumentContainer {
+        V1(Document),
+    }
+
+    #[test]
+    fn test_payload_content_hash() {
+        let document = Document {
+            title: "Hashing".to_owned(),
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            revision: 7,
+        };
+        let container = DocumentContainer::V1(document);
+        let plain = to_tagged_bytes(&container).unwrap();
+        let hash = payload_content_hash::<DocumentContainer>(&plain).unwrap();
+
+        // The same value encoded differently hashes the same
+        let mut options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            ..Default::default()
+        };
+        if CompressionCodec::Lz4.is_available() {
+            options.compression = CompressionCodec::Lz4;
+        }
+        let other = to_tagged_bytes_with(&container, &options).unwrap();
+        assert_ne!(plain.as_slice(), other.as_slice());
+        assert_eq!(
+            payload_content_hash::<DocumentContainer>(&other).unwrap(),
+            hash
+        );
+
+        // A different value doesn't
+        let DocumentContainer::V1(mut changed) = container;
+        changed.revision = 8;
+        let changed = to_tagged_bytes(&DocumentContainer::V1(changed)).unwrap();
+        assert_ne!(
+            payload_content_hash::<DocumentContainer>(&changed).unwrap(),
+            hash
+        );
+    }
+
+    #[test]
+    fn test_content_hasher_is_stable() {
+        let mut hasher = ContentHasher::default();
+        hasher.write(b"");
+        assert_eq!(hasher.finish(), FNV_OFFSET_BASIS);
+
+        // Reference FNV-1a values, these MUST NOT CHANGE
+        let mut hasher = ContentHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = ContentHasher::default();
+        hasher.write_usize(1);
+        let mut expected = ContentHasher::default();
+        expected.write(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(hasher.finish(), expected.finish());
+    }
+}
@@ -0,0 +1,315 @@
+//! Re-enveloping the records of existing streams, e.g. to recompress an archive or upgrade its
+//! digests and headers in bulk.
+//!
+//! [convert_record] rewrites the envelope of one tagged byte array of any type with a new
+//! digest algorithm and compression codec, keeping its type and version IDs, header words,
+//! extensions and payload.  [convert_stream] does so for every record of a stream of frames,
+//! optionally framing them with another [HeaderProfile], and [convert_file] for a file, e.g.
+//! with the `convert` subcommand of the `rkyv_versioned` binary.
+//!
+//! Signed and encrypted records can't be rewritten without their keys, since the signature
+//! covers the header and the ciphertext the compressed payload, so they are copied unchanged
+//! and counted in [ConvertReport::unchanged].
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use rkyv::util::AlignedVec;
+
+use crate::framing::frame_prefix;
+use crate::io::io_error;
+use crate::profile::reframe_from_standard;
+use crate::{
+    append_framed, compression_codec, framed_records, read_header, split_metadata,
+    verify_payload, write_header, CompressionCodec, DigestAlgorithm, EncryptionAlgorithm,
+    HeaderProfile, RkyvVersionedError, TaggedVersionedHeader, FRAME_ALIGNMENT,
+    HEADER_FLAG_SIGNED, TAGGED_HEADER_SIZE,
+};
+
+/// How [convert_stream] re-envelopes each record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConvertOptions {
+    /// The digest algorithm to recompute digests with, or `None` to keep each record's.
+    pub digest: Option<DigestAlgorithm>,
+    /// The codec to recompress payloads with, or `None` to keep each record's.
+    pub compression: Option<CompressionCodec>,
+    /// The header profile to frame the converted records with.  Records framed in a profile
+    /// other than [HeaderProfile::Standard] have to be read with
+    /// [EntryView](crate::EntryView) rather than [framed_records].
+    pub profile: HeaderProfile,
+}
+
+/// What [convert_stream] converted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConvertReport {
+    /// The number of records converted.
+    pub records: usize,
+    /// The number of signed or encrypted records copied without re-enveloping them.
+    pub unchanged: usize,
+    /// The length of the original stream.
+    pub len: usize,
+    /// The length of the converted stream.
+    pub converted_len: usize,
+}
+
+/// Rewrites the envelope of a tagged byte array of any type with `digest` and `compression`.
+///
+/// # Arguments
+///
+/// * `record` - The tagged byte array, which must be neither signed nor encrypted.
+/// * `digest` - The digest algorithm to protect the payload with.
+/// * `compression` - The codec to compress the payload with.
+///
+/// # Returns
+///
+/// A `Result` containing the converted tagged byte array, or an error if `record` is
+/// invalid or doesn't match its digest, is encrypted
+/// ([RkyvVersionedError::EncryptedPayloadError]) or signed
+/// ([RkyvVersionedError::UnsupportedHeaderFlagsError]), or an algorithm isn't enabled.
+pub fn convert_record(
+    record: &[u8],
+    digest: DigestAlgorithm,
+    compression: CompressionCodec,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    let header = read_header(record)?;
+    if header.encryption != EncryptionAlgorithm::None.id() {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    if header.flags & HEADER_FLAG_SIGNED != 0 {
+        return Err(RkyvVersionedError::UnsupportedHeaderFlagsError(
+            HEADER_FLAG_SIGNED,
+        ));
+    }
+    let body = verify_payload(record, &header)?;
+    let (metadata, stored) = split_metadata(&header, body)?;
+
+    let mut payload = AlignedVec::new();
+    compression_codec(&header)?.decompress(stored, &mut payload)?;
+    let mut converted = AlignedVec::with_capacity(record.len());
+    converted.resize(TAGGED_HEADER_SIZE, 0);
+    converted.extend_from_slice(metadata);
+    match compression {
+        CompressionCodec::None => converted.extend_from_slice(&payload),
+        codec => codec.compress(&payload, &mut converted)?,
+    }
+
+    let body = converted.get(TAGGED_HEADER_SIZE..).unwrap_or_default();
+    let payload_len = u32::try_from(body.len())
+        .map_err(|_| RkyvVersionedError::PayloadTooLargeError(body.len()))?;
+    let digest_bytes = digest.compute(body)?;
+    let header = TaggedVersionedHeader {
+        payload_len,
+        digest: digest.id(),
+        compression: compression.id(),
+        ..header
+    };
+    write_header(&mut converted, &header)?;
+    converted.extend_from_slice(digest_bytes.as_bytes());
+    Ok(converted)
+}
+
+/// Converts every record of a stream of frames of any types with [convert_record], keeping
+/// the digest algorithm or codec of each record where `options` doesn't set one.
+///
+/// # Arguments
+///
+/// * `buf` - The stream, e.g. the contents of a log file.
+/// * `options` - How to re-envelope the records.
+///
+/// # Returns
+///
+/// A `Result` containing the converted stream and a report, or an error if a frame is
+/// corrupt or a record can't be converted, wrapped in [RkyvVersionedError::RecordError] to
+/// locate it.
+pub fn convert_stream(
+    buf: &[u8],
+    options: &ConvertOptions,
+) -> Result<(AlignedVec, ConvertReport), RkyvVersionedError> {
+    let mut converted = AlignedVec::with_capacity(buf.len());
+    let mut report = ConvertReport {
+        len: buf.len(),
+        ..Default::default()
+    };
+    let mut records = framed_records(buf);
+    let mut index = 0;
+    loop {
+        let offset = records.offset();
+        let Some(record) = records.next() else {
+            break;
+        };
+        convert_frame(record, options, &mut converted, &mut report)
+            .map_err(|e| e.at_record(index, offset))?;
+        index += 1;
+    }
+    report.converted_len = converted.len();
+    Ok((converted, report))
+}
+
+/// Converts one record of [convert_stream] and appends it to `out` in a frame.
+fn convert_frame(
+    record: Result<&[u8], RkyvVersionedError>,
+    options: &ConvertOptions,
+    out: &mut AlignedVec,
+    report: &mut ConvertReport,
+) -> Result<(), RkyvVersionedError> {
+    let record = record?;
+    let header = read_header(record)?;
+    let converted;
+    let record = match header.encryption != EncryptionAlgorithm::None.id()
+        || header.flags & HEADER_FLAG_SIGNED != 0
+    {
+        true => {
+            verify_payload(record, &header)?;
+            report.unchanged += 1;
+            record
+        }
+        false => {
+            let digest = options
+                .digest
+                .or(DigestAlgorithm::from_id(header.digest))
+                .ok_or(RkyvVersionedError::UnsupportedDigestError(header.digest))?;
+            let compression = match options.compression {
+                Some(compression) => compression,
+                None => compression_codec(&header)?,
+            };
+            report.records += 1;
+            converted = convert_record(record, digest, compression)?;
+            &converted
+        }
+    };
+
+    match options.profile {
+        HeaderProfile::Standard => append_framed(out, record),
+        profile => {
+            let mut entry = AlignedVec::new();
+            reframe_from_standard(record, profile, &mut entry)?;
+            out.extend_from_slice(&frame_prefix(entry.len())?);
+            out.extend_from_slice(&entry);
+            out.resize(out.len().next_multiple_of(FRAME_ALIGNMENT), 0);
+            Ok(())
+        }
+    }
+}
+
+/// As [convert_stream], reading the stream from the file at `path` and writing the converted
+/// stream to a new file at `converted_path`, which is synced before returning.
+///
+/// # Returns
+///
+/// A `Result` containing the report, or an error if `path` can't be read or converted, or
+/// `converted_path` already exists or can't be written.
+pub fn convert_file(
+    path: impl AsRef<Path>,
+    converted_path: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<ConvertReport, RkyvVersionedError> {
+    let contents = std::fs::read(path).map_err(io_error)?;
+    let (converted, report) = convert_stream(&contents, options)?;
+    File::create_new(converted_path)
+        .and_then(|mut file| {
+            file.write_all(&converted)?;
+            file.sync_all()
+        })
+        .map_err(io_error)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, get_extensions_from_tagged_bytes, to_tagged_bytes_with,
+        EntryView, TaggedOptions, VersionedArchiveContainer, FRAME_PREFIX_SIZE,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Document {
+        V1(String),
+    }
+
+    #[test]
+    fn test_convert_stream() {
+        let mut options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            user_word: Some(7),
+            ..Default::default()
+        };
+        options.extensions.insert(0x10, b"origin").unwrap();
+        let mut stream = AlignedVec::<16>::new();
+        for i in 0..3 {
+            let document = Document::V1(format!("document {i} ").repeat(50));
+            append_framed(
+                &mut stream,
+                &to_tagged_bytes_with(&document, &options).unwrap(),
+            )
+            .unwrap();
+        }
+
+        // Upgrade the digests, keeping the payloads uncompressed
+        let upgrade = ConvertOptions {
+            digest: Some(DigestAlgorithm::Crc32),
+            ..Default::default()
+        };
+        let (converted, report) = convert_stream(&stream, &upgrade).unwrap();
+        assert_eq!(report.records, 3);
+        assert_eq!(report.unchanged, 0);
+        let records = framed_records(&converted)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        let header = read_header(records[2]).unwrap();
+        assert_eq!(header.digest, DigestAlgorithm::Crc32.id());
+        assert_eq!(
+            crate::get_user_word_from_tagged_bytes(records[2]).unwrap(),
+            Some(7)
+        );
+        assert_eq!(
+            get_extensions_from_tagged_bytes(records[2])
+                .unwrap()
+                .get(0x10),
+            Some(&b"origin"[..])
+        );
+        match access_from_tagged_bytes::<Document>(records[2]).unwrap() {
+            ArchivedDocument::V1(text) => assert!(text.starts_with("document 2")),
+        }
+
+        // Recompressing round trips
+        if CompressionCodec::Lz4.is_available() {
+            let lz4 = ConvertOptions {
+                compression: Some(CompressionCodec::Lz4),
+                ..upgrade
+            };
+            let (compressed, _) = convert_stream(&converted, &lz4).unwrap();
+            assert!(compressed.len() < converted.len());
+            let uncompressed = ConvertOptions {
+                compression: Some(CompressionCodec::None),
+                ..upgrade
+            };
+            let (round_tripped, _) = convert_stream(&compressed, &uncompressed).unwrap();
+            assert_eq!(round_tripped.as_slice(), converted.as_slice());
+        }
+
+        // Frame with the extended header, which has to be read with an EntryView
+        let extended = ConvertOptions {
+            profile: HeaderProfile::Extended,
+            ..upgrade
+        };
+        let (framed, _) = convert_stream(&converted, &extended).unwrap();
+        assert!(framed_records(&framed).next().unwrap().is_err());
+        let entry_len = u32::from_le_bytes(framed[..4].try_into().unwrap()) as usize;
+        assert_eq!(entry_len, records[0].len() + 16);
+        let entry = &framed[FRAME_PREFIX_SIZE..FRAME_PREFIX_SIZE + entry_len];
+        let entry = EntryView::new(entry, HeaderProfile::Extended).unwrap();
+        assert_eq!(entry.type_id(), read_header(records[0]).unwrap().type_id);
+
+        // Corrupt records are located
+        let mut corrupt = converted.clone();
+        let last = corrupt.len() - 1 - crate::framing::frame_padding(records[2].len());
+        corrupt[last] ^= 1;
+        let Err(e) = convert_stream(&corrupt, &upgrade) else {
+            panic!("corrupt record converted");
+        };
+        assert_eq!(e.record_location().map(|(index, _)| index), Some(2));
+    }
+}
@@ -0,0 +1,367 @@
+//! Support for sending tagged byte arrays over unreliable datagram transports such as QUIC
+//! datagrams (RFC 9221).
+//!
+//! Datagrams are delivered whole or not at all, but are limited in size by the path MTU
+//! (e.g. `quinn::Connection::max_datagram_size()`), so a [DatagramProfile] budgets tagged byte
+//! arrays against that limit and, if enabled, splits oversized ones into fragments which are
+//! put back together by a [DatagramReassembler].
+//!
+//! When fragmentation is enabled, every datagram carries a small fragment header:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | `message_id` (little-endian)            |
+//! | 4      | 2    | `fragment_index` (little-endian)        |
+//! | 6      | 2    | `fragment_count` (little-endian)        |
+//!
+//! When fragmentation is disabled, datagrams contain the tagged byte array as-is.
+
+use std::collections::{HashMap, VecDeque};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    to_tagged_bytes_with, DigestAlgorithm, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, TAGGED_HEADER_SIZE,
+};
+
+/// The size of the fragment header prepended to each datagram when fragmentation is enabled.
+pub const FRAGMENT_HEADER_SIZE: usize = 8;
+
+/// The size budget and fragmentation policy for sending tagged byte arrays as datagrams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramProfile {
+    /// The largest datagram the transport will accept.
+    pub max_datagram_size: usize,
+    /// Whether tagged byte arrays larger than a single datagram are split into fragments.
+    pub fragmentation: bool,
+}
+
+impl DatagramProfile {
+    /// A conservative profile for QUIC, whose minimum supported datagram size is 1200 bytes.
+    /// Prefer the actual value reported by the connection when available.
+    pub const QUIC_MINIMUM: DatagramProfile = DatagramProfile::new(1200);
+
+    /// Creates a profile for the given maximum datagram size with fragmentation disabled.
+    pub const fn new(max_datagram_size: usize) -> Self {
+        DatagramProfile {
+            max_datagram_size,
+            fragmentation: false,
+        }
+    }
+
+    /// Returns a copy of this profile with fragmentation enabled or disabled.
+    pub const fn with_fragmentation(self, fragmentation: bool) -> Self {
+        DatagramProfile {
+            fragmentation,
+            ..self
+        }
+    }
+
+    /// The number of bytes of tagged data that can be carried by a single datagram.
+    pub const fn datagram_capacity(&self) -> usize {
+        if self.fragmentation {
+            self.max_datagram_size.saturating_sub(FRAGMENT_HEADER_SIZE)
+        } else {
+            self.max_datagram_size
+        }
+    }
+
+    /// The largest tagged byte array that can be sent with this profile.
+    pub const fn max_tagged_len(&self) -> usize {
+        if self.fragmentation {
+            self.datagram_capacity().saturating_mul(u16::MAX as usize)
+        } else {
+            self.datagram_capacity()
+        }
+    }
+
+    /// The largest payload that can be sent with this profile when protected by `digest`.
+    /// This is useful for sizing containers up front, e.g. when choosing the length of an
+    /// inline array in a new version.
+    pub const fn payload_budget(&self, digest: DigestAlgorithm) -> usize {
+        self.max_tagged_len()
+            .saturating_sub(TAGGED_HEADER_SIZE + digest.digest_len())
+    }
+
+    /// Checks that a tagged byte array can be sent with this profile, returning the number of
+    /// datagrams it will occupy.
+    pub fn check_fits(&self, tagged: &[u8]) -> Result<usize, RkyvVersionedError> {
+        let capacity = self.datagram_capacity();
+        if tagged.len() > self.max_tagged_len() || capacity == 0 {
+            return Err(RkyvVersionedError::DatagramTooLargeError(
+                tagged.len(),
+                self.max_tagged_len(),
+            ));
+        }
+        Ok(tagged.len().div_ceil(capacity).max(1))
+    }
+
+    /// Serializes `item` with the given options and checks that the result can be sent with
+    /// this profile.  This can be used to validate that every version of a container fits
+    /// the transport, e.g. in a test that serializes a worst-case value of each version.
+    pub fn to_tagged_bytes_checked<T>(
+        &self,
+        item: &T,
+        options: &TaggedOptions,
+    ) -> Result<AlignedVec, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let bytes = to_tagged_bytes_with(item, options)?;
+        self.check_fits(&bytes)?;
+        Ok(bytes)
+    }
+
+    /// Splits a tagged byte array into datagrams.  With fragmentation disabled this yields the
+    /// tagged byte array itself, otherwise each datagram is prefixed with a fragment header
+    /// carrying `message_id`, which should be unique among the messages in flight.
+    pub fn to_datagrams(
+        &self,
+        message_id: u32,
+        tagged: &[u8],
+    ) -> Result<Vec<Vec<u8>>, RkyvVersionedError> {
+        let count = self.check_fits(tagged)?;
+        if !self.fragmentation {
+            return Ok(vec![tagged.to_vec()]);
+        }
+
+        let chunks = tagged.chunks(self.datagram_capacity());
+        let chunks: Vec<&[u8]> = if tagged.is_empty() {
+            vec![&[]]
+        } else {
+            chunks.collect()
+        };
+
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut datagram = Vec::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+                datagram.extend_from_slice(&message_id.to_le_bytes());
+                datagram.extend_from_slice(&(index as u16).to_le_bytes());
+                datagram.extend_from_slice(&(count as u16).to_le_bytes());
+                datagram.extend_from_slice(chunk);
+                datagram
+            })
+            .collect())
+    }
+}
+
+/// A partially received message
+struct PendingMessage {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Reassembles datagrams produced by [DatagramProfile::to_datagrams] into aligned tagged byte
+/// arrays.
+///
+/// Since the transport is unreliable, fragments of a message may never arrive.  At most
+/// `max_pending` incomplete messages are kept, after which the oldest is dropped.
+pub struct DatagramReassembler {
+    profile: DatagramProfile,
+    max_pending: usize,
+    pending: HashMap<u32, PendingMessage>,
+    order: VecDeque<u32>,
+}
+
+impl DatagramReassembler {
+    /// Creates a reassembler for datagrams sent with `profile`.
+    pub fn new(profile: DatagramProfile, max_pending: usize) -> Self {
+        DatagramReassembler {
+            profile,
+            max_pending,
+            pending: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The number of incomplete messages currently held.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Feeds a received datagram in, returning the complete tagged byte array once all of
+    /// its fragments have arrived.  Duplicate fragments are ignored.
+    pub fn push(&mut self, datagram: &[u8]) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+        if !self.profile.fragmentation {
+            let mut bytes = AlignedVec::with_capacity(datagram.len());
+            bytes.extend_from_slice(datagram);
+            return Ok(Some(bytes));
+        }
+
+        let Some((&[m0, m1, m2, m3, i0, i1, c0, c1], chunk)) =
+            datagram.split_first_chunk::<FRAGMENT_HEADER_SIZE>()
+        else {
+            return Err(RkyvVersionedError::BufferTooSmallError);
+        };
+        let message_id = u32::from_le_bytes([m0, m1, m2, m3]);
+        let index = u16::from_le_bytes([i0, i1]) as usize;
+        let count = u16::from_le_bytes([c0, c1]) as usize;
+        if count == 0 || index >= count {
+            return Err(RkyvVersionedError::InvalidFragmentError(message_id));
+        }
+
+        if !self.pending.contains_key(&message_id) {
+            if self.pending.len() >= self.max_pending {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.order.push_back(message_id);
+        }
+
+        let message = self
+            .pending
+            .entry(message_id)
+            .or_insert_with(|| PendingMessage {
+                fragments: vec![None; count],
+                received: 0,
+            });
+        if message.fragments.len() != count {
+            return Err(RkyvVersionedError::InvalidFragmentError(message_id));
+        }
+        let Some(fragment) = message.fragments.get_mut(index) else {
+            return Err(RkyvVersionedError::InvalidFragmentError(message_id));
+        };
+        if fragment.is_none() {
+            *fragment = Some(chunk.to_vec());
+            message.received += 1;
+        }
+        if message.received < count {
+            return Ok(None);
+        }
+
+        let Some(message) = self.pending.remove(&message_id) else {
+            return Ok(None);
+        };
+        self.order.retain(|id| *id != message_id);
+
+        let mut bytes = AlignedVec::new();
+        for fragment in message.fragments.into_iter().flatten() {
+            bytes.extend_from_slice(&fragment);
+        }
+        Ok(Some(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Telemetry {
+        pub samples: Vec<u32>,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum TelemetryContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Telemetry),
+    }
+
+    #[test]
+    fn test_datagram_budget() {
+        let small = Telemetry {
+            samples: vec![1, 2, 3],
+        };
+        let large = Telemetry {
+            samples: (0..1000).collect(),
+        };
+        let profile = DatagramProfile::QUIC_MINIMUM;
+
+        let bytes = profile
+            .to_tagged_bytes_checked(
+                &TelemetryContainer::V1(&small),
+                &TaggedOptions::default(),
+            )
+            .unwrap();
+        assert!(bytes.len() <= profile.max_datagram_size);
+        assert_eq!(
+            profile.to_datagrams(7, &bytes).unwrap(),
+            vec![bytes.to_vec()]
+        );
+
+        match profile.to_tagged_bytes_checked(
+            &TelemetryContainer::V1(&large),
+            &TaggedOptions::default(),
+        ) {
+            Err(RkyvVersionedError::DatagramTooLargeError(len, max)) => {
+                assert!(len > max);
+                assert_eq!(max, 1200);
+            }
+            _ => panic!("Expected RkyvVersionedError::DatagramTooLargeError"),
+        }
+
+        assert_eq!(
+            profile.payload_budget(DigestAlgorithm::Crc32),
+            1200 - TAGGED_HEADER_SIZE - 4
+        );
+    }
+
+    #[test]
+    fn test_datagram_fragmentation() {
+        let large = Telemetry {
+            samples: (0..1000).collect(),
+        };
+        let container = TelemetryContainer::V1(&large);
+        let bytes = to_tagged_bytes(&container).unwrap();
+
+        let profile = DatagramProfile::QUIC_MINIMUM.with_fragmentation(true);
+        let datagrams = profile.to_datagrams(42, &bytes).unwrap();
+        assert_eq!(datagrams.len(), profile.check_fits(&bytes).unwrap());
+        assert!(datagrams.len() > 1);
+        assert!(datagrams
+            .iter()
+            .all(|d| d.len() <= profile.max_datagram_size));
+
+        // Deliver out of order, with a duplicate and an interleaved message which never
+        // completes
+        let mut reassembler = DatagramReassembler::new(profile, 4);
+        let stray = profile.to_datagrams(43, &bytes).unwrap();
+        assert!(reassembler.push(&stray[0]).unwrap().is_none());
+
+        let mut result = None;
+        for datagram in datagrams.last().into_iter().chain(datagrams.iter().rev()) {
+            if let Some(bytes) = reassembler.push(datagram).unwrap() {
+                result = Some(bytes);
+            }
+        }
+        let reassembled = result.unwrap();
+        assert_eq!(reassembled.as_slice(), bytes.as_slice());
+        assert_eq!(reassembler.pending_len(), 1);
+
+        match access_from_tagged_bytes::<TelemetryContainer>(&reassembled).unwrap() {
+            ArchivedTelemetryContainer::V1(telemetry) => {
+                assert_eq!(telemetry.samples.len(), 1000)
+            }
+        }
+
+        // Malformed fragment headers are rejected
+        let mut malformed = datagrams[0].clone();
+        malformed[4..6].copy_from_slice(&100u16.to_le_bytes());
+        assert!(matches!(
+            reassembler.push(&malformed),
+            Err(RkyvVersionedError::InvalidFragmentError(42))
+        ));
+    }
+
+    #[test]
+    fn test_datagram_reassembler_eviction() {
+        let profile = DatagramProfile::new(16).with_fragmentation(true);
+        let mut reassembler = DatagramReassembler::new(profile, 2);
+        let payload = [0u8; 32];
+        for message_id in 0..5 {
+            let datagrams = profile.to_datagrams(message_id, &payload).unwrap();
+            assert!(reassembler.push(&datagrams[0]).unwrap().is_none());
+        }
+        assert_eq!(reassembler.pending_len(), 2);
+    }
+}
@@ -0,0 +1,119 @@
+//! Rendering archived payloads as JSON for triage, enabled with the `debug_json` feature.
+//!
+//! Records pulled from production are opaque bytes.  Containers deriving
+//! [VersionedArchiveContainer](crate::VersionedArchiveContainer) with `#[vac(debug_json)]`
+//! implement [DebugJson], which deserializes the archived value of any version and renders it
+//! with the `serde::Serialize` implementation of its variant type, and [to_debug_json] wraps
+//! it with the type and version of the record:
+//!
+//! ```json
+//! {"type": "Invoice", "type_id": 3405691582, "version_id": 1, "version": "V2", "payload": {...}}
+//! ```
+//!
+//! Every variant type must implement `serde::Serialize`.  This is meant for debugging, the
+//! JSON layout of a payload is whatever its `Serialize` implementation produces and isn't
+//! versioned.
+
+use rkyv::api::high::HighDeserializer;
+use rkyv::util::AlignedVec;
+use serde_json::{json, Value};
+
+use crate::{
+    access_from_tagged_bytes_with_scratch, read_header, rkyv_error, RkyvVersionedError,
+    VersionedContainer,
+};
+
+/// Renders the archived values of a container as JSON, implemented by `#[vac(debug_json)]`.
+pub trait DebugJson: VersionedContainer {
+    /// Deserializes `archived` and renders the value of its variant as JSON.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the JSON value, or an error if deserialization or rendering
+    /// fails, wrapped in [RkyvVersionedError::RkyvError].
+    fn archived_to_debug_json(archived: &Self::Archived) -> Result<Value, RkyvVersionedError>;
+}
+
+/// Accesses a tagged byte array holding any version of `T` and renders it as JSON, along with
+/// the type and version of the record.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data, which may
+///   be compressed.
+///
+/// # Returns
+///
+/// A `Result` containing the JSON value, or an error if the record can't be accessed like
+/// with [access_from_tagged_bytes_with_scratch], or rendered.
+pub fn to_debug_json<T: DebugJson>(buf: &[u8]) -> Result<Value, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let mut scratch = AlignedVec::new();
+    let archived = access_from_tagged_bytes_with_scratch::<T>(buf, &mut scratch)?;
+    let version_id = read_header(buf)?.version_id;
+    Ok(json!({
+        "type": T::TYPE_NAME,
+        "type_id": T::ARCHIVE_TYPE_ID,
+        "version_id": version_id,
+        "version": T::version_info(version_id).map(|info| info.name),
+        "payload": T::archived_to_debug_json(archived)?,
+    }))
+}
+
+/// Deserializes `archived` into a `V` and renders it as JSON, for the code generated by
+/// `#[vac(debug_json)]`.
+#[doc(hidden)]
+pub fn archived_variant_to_json<A, V>(archived: &A) -> Result<Value, RkyvVersionedError>
+where
+    A: rkyv::Deserialize<V, HighDeserializer<rkyv::rancor::Error>>,
+    V: serde::Serialize,
+{
+    let value = rkyv::deserialize::<V, rkyv::rancor::Error>(archived).map_err(rkyv_error)?;
+    serde_json::to_value(&value).map_err(|e| RkyvVersionedError::RkyvError(Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, serde::Serialize)]
+    struct Line {
+        sku: String,
+        quantity: u32,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(debug_json)]
+    enum Order<'a> {
+        V1(u64),
+        V2(#[rkyv(with = InlineAsBox)] &'a Line),
+    }
+
+    #[test]
+    fn test_to_debug_json() {
+        let json = to_debug_json::<Order>(&to_tagged_bytes(&Order::V1(42)).unwrap()).unwrap();
+        assert_eq!(json["type"], "Order");
+        assert_eq!(json["version"], "V1");
+        assert_eq!(json["payload"], 42);
+
+        let line = Line {
+            sku: "A-1".to_string(),
+            quantity: 3,
+        };
+        let json =
+            to_debug_json::<Order>(&to_tagged_bytes(&Order::V2(&line)).unwrap()).unwrap();
+        assert_eq!(json["type_id"], Order::ARCHIVE_TYPE_ID);
+        assert_eq!(json["version_id"], 1);
+        assert_eq!(json["payload"], json!({"sku": "A-1", "quantity": 3}));
+
+        assert!(to_debug_json::<Order>(&[0u8; 8]).is_err());
+    }
+}
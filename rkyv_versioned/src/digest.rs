@@ -0,0 +1,226 @@
+//! Payload integrity digests.
+//!
+//! Every tagged byte array carries a digest of its payload in a trailer directly after the
+//! payload, and the algorithm used is recorded in the header so that readers can verify it
+//! without any out-of-band configuration.  CRC32 is always available and is the default,
+//! the other algorithms are enabled through the `crc32c`, `xxh3` and `blake3` features.
+
+use crate::RkyvVersionedError;
+
+/// The length of the longest digest we can produce (BLAKE3).
+pub(crate) const MAX_DIGEST_LEN: usize = 32;
+
+/// The algorithm used to compute the integrity digest of a tagged payload.
+///
+/// The discriminant of each variant is written into the header and MUST NOT CHANGE.
+/// Algorithms which are not compiled in (see [DigestAlgorithm::is_available]) can still be
+/// named, but attempting to write or verify a digest with them will fail with
+/// [RkyvVersionedError::UnsupportedDigestError].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DigestAlgorithm {
+    /// No digest is written and nothing is verified on read.
+    None = 0,
+    /// CRC32 (IEEE), always available.
+    #[default]
+    Crc32 = 1,
+    /// CRC32C (Castagnoli), hardware accelerated on most platforms.  Requires the `crc32c`
+    /// feature.
+    Crc32c = 2,
+    /// 64-bit XXH3, a very fast non-cryptographic hash.  Requires the `xxh3` feature.
+    Xxh3 = 3,
+    /// 256-bit BLAKE3, a cryptographic hash.  Requires the `blake3` feature.
+    Blake3 = 4,
+}
+
+impl DigestAlgorithm {
+    /// Returns the identifier of the algorithm as written in the header.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the algorithm for an identifier read from a header, or `None` if the
+    /// identifier is unknown.
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(DigestAlgorithm::None),
+            1 => Some(DigestAlgorithm::Crc32),
+            2 => Some(DigestAlgorithm::Crc32c),
+            3 => Some(DigestAlgorithm::Xxh3),
+            4 => Some(DigestAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bytes the digest occupies in the trailer.
+    pub const fn digest_len(self) -> usize {
+        match self {
+            DigestAlgorithm::None => 0,
+            DigestAlgorithm::Crc32 | DigestAlgorithm::Crc32c => 4,
+            DigestAlgorithm::Xxh3 => 8,
+            DigestAlgorithm::Blake3 => 32,
+        }
+    }
+
+    /// Returns whether support for this algorithm has been compiled in.
+    pub const fn is_available(self) -> bool {
+        match self {
+            DigestAlgorithm::None | DigestAlgorithm::Crc32 => true,
+            DigestAlgorithm::Crc32c => cfg!(feature = "crc32c"),
+            DigestAlgorithm::Xxh3 => cfg!(feature = "xxh3"),
+            DigestAlgorithm::Blake3 => cfg!(feature = "blake3"),
+        }
+    }
+
+    /// Computes the digest of `data`.  Integer digests are stored little-endian.
+    pub(crate) fn compute(self, data: &[u8]) -> Result<Digest, RkyvVersionedError> {
+        let digest = match self {
+            DigestAlgorithm::None => Digest::from_slice(&[]),
+            DigestAlgorithm::Crc32 => Digest::from_slice(&crc32fast::hash(data).to_le_bytes()),
+            #[cfg(feature = "crc32c")]
+            DigestAlgorithm::Crc32c => Digest::from_slice(&crc32c::crc32c(data).to_le_bytes()),
+            #[cfg(feature = "xxh3")]
+            DigestAlgorithm::Xxh3 => {
+                Digest::from_slice(&xxhash_rust::xxh3::xxh3_64(data).to_le_bytes())
+            }
+            #[cfg(feature = "blake3")]
+            DigestAlgorithm::Blake3 => Digest::from_slice(blake3::hash(data).as_bytes()),
+            #[allow(unreachable_patterns)]
+            _ => return Err(RkyvVersionedError::UnsupportedDigestError(self.id())),
+        };
+        Ok(digest)
+    }
+
+    /// Computes the digest of the concatenation of `parts`, without copying them into one
+    /// buffer.
+    pub(crate) fn compute_parts(self, parts: &[&[u8]]) -> Result<Digest, RkyvVersionedError> {
+        if let [data] = parts {
+            return self.compute(data);
+        }
+        let digest = match self {
+            DigestAlgorithm::None => Digest::from_slice(&[]),
+            DigestAlgorithm::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new();
+                parts.iter().for_each(|part| hasher.update(part));
+                Digest::from_slice(&hasher.finalize().to_le_bytes())
+            }
+            #[cfg(feature = "crc32c")]
+            DigestAlgorithm::Crc32c => {
+                let crc = parts
+                    .iter()
+                    .fold(0, |crc, part| crc32c::crc32c_append(crc, part));
+                Digest::from_slice(&crc.to_le_bytes())
+            }
+            #[cfg(feature = "xxh3")]
+            DigestAlgorithm::Xxh3 => {
+                let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+                parts.iter().for_each(|part| hasher.update(part));
+                Digest::from_slice(&hasher.digest().to_le_bytes())
+            }
+            #[cfg(feature = "blake3")]
+            DigestAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                parts.iter().for_each(|part| {
+                    hasher.update(part);
+                });
+                Digest::from_slice(hasher.finalize().as_bytes())
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(RkyvVersionedError::UnsupportedDigestError(self.id())),
+        };
+        Ok(digest)
+    }
+
+    /// Computes the digest of `data` and compares it against `expected`.
+    pub(crate) fn verify(
+        self,
+        data: &[u8],
+        expected: &[u8],
+    ) -> Result<(), RkyvVersionedError> {
+        if self.compute(data)?.as_bytes() == expected {
+            Ok(())
+        } else {
+            Err(RkyvVersionedError::ChecksumMismatchError)
+        }
+    }
+}
+
+/// A computed digest, stored inline to avoid an allocation per record.
+pub(crate) struct Digest {
+    bytes: [u8; MAX_DIGEST_LEN],
+    len: usize,
+}
+
+impl Digest {
+    fn from_slice(digest: &[u8]) -> Self {
+        let mut bytes = [0u8; MAX_DIGEST_LEN];
+        let len = digest.len().min(MAX_DIGEST_LEN);
+        bytes
+            .iter_mut()
+            .zip(digest)
+            .for_each(|(byte, digest)| *byte = *digest);
+        Digest { bytes, len }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.bytes.get(..self.len).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [DigestAlgorithm; 5] = [
+        DigestAlgorithm::None,
+        DigestAlgorithm::Crc32,
+        DigestAlgorithm::Crc32c,
+        DigestAlgorithm::Xxh3,
+        DigestAlgorithm::Blake3,
+    ];
+
+    #[test]
+    fn test_digest_ids_round_trip() {
+        for algorithm in ALL {
+            assert_eq!(DigestAlgorithm::from_id(algorithm.id()), Some(algorithm));
+            assert!(algorithm.digest_len() <= MAX_DIGEST_LEN);
+        }
+        assert_eq!(DigestAlgorithm::from_id(0xff), None);
+    }
+
+    #[test]
+    fn test_digest_compute_and_verify() {
+        let data = b"The quick brown fox jumps over the lazy dog";
+
+        // Known answer for CRC32 (IEEE)
+        let crc = DigestAlgorithm::Crc32.compute(data).unwrap();
+        assert_eq!(crc.as_bytes(), 0x414fa339_u32.to_le_bytes());
+
+        for algorithm in ALL {
+            if !algorithm.is_available() {
+                assert!(matches!(
+                    algorithm.compute(data),
+                    Err(RkyvVersionedError::UnsupportedDigestError(id)) if id == algorithm.id()
+                ));
+                continue;
+            }
+
+            let digest = algorithm.compute(data).unwrap();
+            assert_eq!(digest.as_bytes().len(), algorithm.digest_len());
+            let (head, tail) = data.split_at(10);
+            let parts = algorithm.compute_parts(&[head, &[], tail]).unwrap();
+            assert_eq!(parts.as_bytes(), digest.as_bytes());
+            algorithm.verify(data, digest.as_bytes()).unwrap();
+
+            if algorithm != DigestAlgorithm::None {
+                assert!(matches!(
+                    algorithm.verify(
+                        b"The quick brown fox jumps over the lazy cat",
+                        digest.as_bytes()
+                    ),
+                    Err(RkyvVersionedError::ChecksumMismatchError)
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Dual-version records for rollback safety.
+//!
+//! Deploying a new version of a container is risky when the deployment may be rolled back:
+//! records written by the new build can't be read by the old one.  [to_tagged_bytes_dual]
+//! writes a record which both can read.  It is an ordinary tagged byte array holding a
+//! downgraded copy of the item, in a version the old build supports, with the complete
+//! tagged byte array of the latest version stored in its [HeaderExtensions] under
+//! [EXTENSION_KEY_DUAL_LATEST].
+//!
+//! Old builds read the downgraded payload with [access_from_tagged_bytes] as usual and never
+//! look at the extension, while [select_dual_tagged_bytes] (or
+//! [access_dual_from_tagged_bytes]) picks the latest version if the reader supports it.  Like
+//! any extension value, the latest version is limited to `u16::MAX` bytes.
+//!
+//! Old builds access the archived enum of the container as they define it, so adding the new
+//! version must not change the size of the archived enum, e.g. by giving it a larger payload
+//! than the existing versions.
+//!
+//! [HeaderExtensions]: crate::HeaderExtensions
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    access_from_tagged_bytes, check_header, get_extensions_from_tagged_bytes, read_header,
+    to_tagged_bytes_with, HeaderExtensions, RkyvVersionedError, TaggedOptions,
+    VersionedContainer,
+};
+
+/// The extension key holding the tagged byte array of the latest version in a dual-version
+/// record.
+pub const EXTENSION_KEY_DUAL_LATEST: u16 = 0xff06;
+
+/// Serializes `item` together with `downgraded`, the same item in an older version, into a
+/// dual-version record, see the [module](self) documentation.
+///
+/// The latest version is stored in a header extension, so its tagged byte array, header and
+/// digest included, can be at most `u16::MAX` bytes long.  Items which may be larger can't be
+/// written as dual-version records.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item in its latest version.
+/// * `downgraded` - A reference to the item in the version older readers support.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, an
+/// [RkyvVersionedError::InvalidMessageError] if both are the same version, an
+/// [RkyvVersionedError::InvalidExtensionError] with [EXTENSION_KEY_DUAL_LATEST] if the latest
+/// version is longer than `u16::MAX` bytes, or an error if serialization fails.
+pub fn to_tagged_bytes_dual<T>(
+    item: &T,
+    downgraded: &T,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    to_tagged_bytes_dual_with(item, downgraded, &TaggedOptions::default())
+}
+
+/// As [to_tagged_bytes_dual], tagging both versions with `options`.  The header extensions
+/// in `options` are only stored on the downgraded record.
+pub fn to_tagged_bytes_dual_with<T>(
+    item: &T,
+    downgraded: &T,
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    if item.get_entry_version_id() == downgraded.get_entry_version_id() {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+    let latest = to_tagged_bytes_with(
+        item,
+        &TaggedOptions {
+            extensions: HeaderExtensions::new(),
+            ..options.clone()
+        },
+    )?;
+    let mut extensions = options.extensions.clone();
+    extensions.insert(EXTENSION_KEY_DUAL_LATEST, &latest)?;
+    to_tagged_bytes_with(
+        downgraded,
+        &TaggedOptions {
+            extensions,
+            ..options.clone()
+        },
+    )
+}
+
+/// Selects the tagged byte array of the newest version of a record `T` supports: the latest
+/// version stored in a dual-version record if `T` supports it, or `buf` itself otherwise.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data, which
+///   needn't be a dual-version record.
+/// * `scratch` - A buffer to copy the latest version into to align it.
+///
+/// # Returns
+///
+/// A `Result` containing either the selected tagged byte array, to be accessed with any of
+/// the access functions, or an error if the header of `buf` can't be read.
+pub fn select_dual_tagged_bytes<'a, T: VersionedContainer>(
+    buf: &'a [u8],
+    scratch: &'a mut AlignedVec,
+) -> Result<&'a [u8], RkyvVersionedError> {
+    read_header(buf)?;
+    let extensions = get_extensions_from_tagged_bytes(buf)?;
+    let Some(latest) = extensions.get(EXTENSION_KEY_DUAL_LATEST) else {
+        return Ok(buf);
+    };
+    if check_header::<T>(latest).is_err() {
+        return Ok(buf);
+    }
+    scratch.clear();
+    scratch.extend_from_slice(latest);
+    Ok(scratch)
+}
+
+/// Zero-copy accesses the newest version of a record `T` supports, see
+/// [select_dual_tagged_bytes].  Compressed or encrypted records need the matching access
+/// function to be called on the selected bytes instead.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the archived value or an error if any of the
+/// checks of [access_from_tagged_bytes] fail.
+pub fn access_dual_from_tagged_bytes<'a, T>(
+    buf: &'a [u8],
+    scratch: &'a mut AlignedVec,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T: VersionedContainer + 'a,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_from_tagged_bytes::<T>(select_dual_tagged_bytes::<T>(buf, scratch)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionedArchiveContainer;
+    use rkyv::{Archive, Deserialize};
+
+    // The container as known to the build being deployed
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Price {
+        V1(u64),
+        V2((u32, u32)),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Note {
+        V1(u64),
+        V2(Vec<u8>),
+    }
+
+    mod rollback {
+        use super::*;
+
+        // The container as known to the build rolled back to
+        #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+        pub enum Price {
+            V1(u64),
+        }
+    }
+
+    #[test]
+    fn test_dual_version_record() {
+        let latest = Price::V2((1999, 978));
+        let bytes = to_tagged_bytes_dual(&latest, &Price::V1(1999)).unwrap();
+
+        // The old build reads the downgraded payload, with or without knowing about dual
+        // records
+        match access_from_tagged_bytes::<rollback::Price>(&bytes).unwrap() {
+            rollback::ArchivedPrice::V1(cents) => assert_eq!(*cents, 1999),
+        }
+        let mut scratch = AlignedVec::new();
+        assert!(
+            access_dual_from_tagged_bytes::<rollback::Price>(&bytes, &mut scratch).is_ok()
+        );
+
+        // The new build picks the latest version
+        match access_dual_from_tagged_bytes::<Price>(&bytes, &mut scratch).unwrap() {
+            ArchivedPrice::V2(price) => assert_eq!(price.1, 978),
+            _ => panic!("Expected V2"),
+        }
+
+        // Plain records are selected as they are
+        let plain = crate::to_tagged_bytes(&latest).unwrap();
+        let selected = select_dual_tagged_bytes::<Price>(&plain, &mut scratch).unwrap();
+        assert_eq!(selected, plain.as_slice());
+
+        assert!(matches!(
+            to_tagged_bytes_dual(&latest, &latest),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+
+        // The latest version can take up to u16::MAX bytes
+        let tagged_len = |len| {
+            crate::to_tagged_bytes(&Note::V2(vec![7; len]))
+                .unwrap()
+                .len()
+        };
+        let largest = (0..u16::MAX as usize)
+            .rev()
+            .find(|len| tagged_len(*len) <= u16::MAX as usize)
+            .unwrap();
+        let bytes = to_tagged_bytes_dual(&Note::V2(vec![7; largest]), &Note::V1(1)).unwrap();
+        match access_dual_from_tagged_bytes::<Note>(&bytes, &mut scratch).unwrap() {
+            ArchivedNote::V2(note) => assert_eq!(note.len(), largest),
+            _ => panic!("Expected V2"),
+        }
+        assert!(matches!(
+            to_tagged_bytes_dual(&Note::V2(vec![7; largest + 1]), &Note::V1(1)),
+            Err(RkyvVersionedError::InvalidExtensionError(
+                EXTENSION_KEY_DUAL_LATEST
+            ))
+        ));
+    }
+}
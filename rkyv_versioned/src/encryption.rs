@@ -0,0 +1,312 @@
+//! Optional authenticated encryption of payloads.
+//!
+//! When an [EncryptionKey] is provided in [TaggedOptions](crate::TaggedOptions), the payload
+//! (after compression, if any) is encrypted with XChaCha20-Poly1305 and stored as the random
+//! 24 byte nonce followed by the ciphertext and its 16 byte authentication tag.  The header
+//! stays in plaintext so that records can still be routed by type and version, and the type
+//! and version IDs and any header extensions are bound to the ciphertext as associated data
+//! so they cannot be swapped without detection.
+//!
+//! Encryption requires the `encryption` feature.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use rkyv::util::AlignedVec;
+
+use crate::{RkyvVersionedError, Rng};
+
+/// The length of an XChaCha20-Poly1305 nonce.
+pub(crate) const NONCE_LEN: usize = 24;
+
+/// The length of a Poly1305 authentication tag.
+pub(crate) const TAG_LEN: usize = 16;
+
+/// The algorithm used to encrypt the payload of a tagged byte array.
+///
+/// The discriminant of each variant is written into the header and MUST NOT CHANGE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum EncryptionAlgorithm {
+    /// The payload is stored in plaintext.
+    #[default]
+    None = 0,
+    /// XChaCha20-Poly1305 with a random nonce per payload.  Requires the `encryption`
+    /// feature.
+    XChaCha20Poly1305 = 1,
+}
+
+impl EncryptionAlgorithm {
+    /// Returns the identifier of the algorithm as written in the header.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the algorithm for an identifier read from a header, or `None` if the
+    /// identifier is unknown.
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(EncryptionAlgorithm::None),
+            1 => Some(EncryptionAlgorithm::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// Returns whether support for this algorithm has been compiled in.
+    pub const fn is_available(self) -> bool {
+        match self {
+            EncryptionAlgorithm::None => true,
+            EncryptionAlgorithm::XChaCha20Poly1305 => cfg!(feature = "encryption"),
+        }
+    }
+
+    /// The number of bytes encryption adds to a payload.
+    pub const fn overhead(self) -> usize {
+        match self {
+            EncryptionAlgorithm::None => 0,
+            EncryptionAlgorithm::XChaCha20Poly1305 => NONCE_LEN + TAG_LEN,
+        }
+    }
+}
+
+/// A 256-bit key used to encrypt and decrypt payloads.
+///
+/// The key material is never printed by the `Debug` implementation.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Creates a key from raw key material.
+    pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+        EncryptionKey(bytes)
+    }
+
+    /// Returns the raw key material.
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Returns the associated data binding a ciphertext to its header and the metadata (header words
+/// and extension area) stored in front of it.
+pub(crate) fn associated_data(type_id: u32, version_id: u32, metadata: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(8 + metadata.len());
+    aad.extend_from_slice(&type_id.to_le_bytes());
+    aad.extend_from_slice(&version_id.to_le_bytes());
+    aad.extend_from_slice(metadata);
+    aad
+}
+
+/// Encrypts `plaintext` with a fresh random nonce from `rng`, or the operating system's
+/// entropy source, appending the nonce and ciphertext to `out`.
+#[cfg(feature = "encryption")]
+pub(crate) fn encrypt(
+    key: &EncryptionKey,
+    rng: Option<&dyn Rng>,
+    aad: &[u8],
+    plaintext: &[u8],
+    out: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
+    let mut nonce = XNonce::default();
+    rng.unwrap_or(&crate::SystemRng).fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| RkyvVersionedError::EncryptedPayloadError)?;
+
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn encrypt(
+    _key: &EncryptionKey,
+    _rng: Option<&dyn Rng>,
+    _aad: &[u8],
+    _plaintext: &[u8],
+    _out: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError> {
+    Err(RkyvVersionedError::UnsupportedEncryptionError(
+        EncryptionAlgorithm::XChaCha20Poly1305.id(),
+    ))
+}
+
+/// Decrypts a stored payload produced by [encrypt], returning the plaintext.  Fails with
+/// [RkyvVersionedError::EncryptedPayloadError] if the key is wrong or the payload or its
+/// associated data has been tampered with.
+#[cfg(feature = "encryption")]
+pub(crate) fn decrypt(
+    key: &EncryptionKey,
+    aad: &[u8],
+    stored: &[u8],
+) -> Result<Vec<u8>, RkyvVersionedError> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    if stored.len() < NONCE_LEN + TAG_LEN {
+        return Err(RkyvVersionedError::BufferTooSmallError);
+    }
+    let (nonce, ciphertext) = stored
+        .split_first_chunk::<NONCE_LEN>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+
+    let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| RkyvVersionedError::EncryptedPayloadError)
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn decrypt(
+    _key: &EncryptionKey,
+    _aad: &[u8],
+    _stored: &[u8],
+) -> Result<Vec<u8>, RkyvVersionedError> {
+    Err(RkyvVersionedError::UnsupportedEncryptionError(
+        EncryptionAlgorithm::XChaCha20Poly1305.id(),
+    ))
+}
+
+/// Decrypts a stored payload produced by [encrypt] in place, moving the plaintext to the
+/// start of `stored` and returning it.  The nonce is overwritten either way, so `stored` no
+/// longer holds the payload afterwards.  Fails like [decrypt].
+#[cfg(feature = "encryption")]
+pub(crate) fn decrypt_in_place<'a>(
+    key: &EncryptionKey,
+    aad: &[u8],
+    stored: &'a mut [u8],
+) -> Result<&'a mut [u8], RkyvVersionedError> {
+    use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+    use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+
+    let len = stored
+        .len()
+        .checked_sub(NONCE_LEN + TAG_LEN)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let (nonce, rest) = stored
+        .split_first_chunk::<NONCE_LEN>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let (_, tag) = rest
+        .split_last_chunk::<TAG_LEN>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let nonce = XNonce::from(*nonce);
+    let tag = Tag::from(*tag);
+    stored.copy_within(NONCE_LEN..NONCE_LEN + len, 0);
+    let plaintext = stored
+        .get_mut(..len)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+
+    let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
+    cipher
+        .decrypt_in_place_detached(&nonce, aad, plaintext, &tag)
+        .map_err(|_| RkyvVersionedError::EncryptedPayloadError)?;
+    Ok(plaintext)
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn decrypt_in_place<'a>(
+    _key: &EncryptionKey,
+    _aad: &[u8],
+    _stored: &'a mut [u8],
+) -> Result<&'a mut [u8], RkyvVersionedError> {
+    Err(RkyvVersionedError::UnsupportedEncryptionError(
+        EncryptionAlgorithm::XChaCha20Poly1305.id(),
+    ))
+}
+
+/// Decrypts a stored payload produced by [encrypt] into `out`, replacing its contents, without
+/// allocating if `out` already has the capacity.  Fails like [decrypt].
+pub(crate) fn decrypt_into(
+    key: &EncryptionKey,
+    aad: &[u8],
+    stored: &[u8],
+    out: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError> {
+    out.clear();
+    out.extend_from_slice(stored);
+    let len = decrypt_in_place(key, aad, out.as_mut_slice())?.len();
+    out.resize(len, 0);
+    Ok(())
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = EncryptionKey::from_bytes([7; 32]);
+        let aad = associated_data(1, 2, &[]);
+        let plaintext = b"attack at dawn";
+
+        let mut stored = AlignedVec::new();
+        encrypt(&key, None, &aad, plaintext, &mut stored).unwrap();
+        assert_eq!(
+            stored.len(),
+            plaintext.len() + EncryptionAlgorithm::XChaCha20Poly1305.overhead()
+        );
+        assert_eq!(decrypt(&key, &aad, &stored).unwrap(), plaintext);
+        let mut out = AlignedVec::new();
+        decrypt_into(&key, &aad, &stored, &mut out).unwrap();
+        assert_eq!(out.as_slice(), plaintext);
+        let mut in_place = stored.clone();
+        assert_eq!(
+            decrypt_in_place(&key, &aad, &mut in_place).unwrap(),
+            plaintext
+        );
+
+        // Nonces are random, so encrypting twice yields different ciphertexts
+        let mut again = AlignedVec::new();
+        encrypt(&key, None, &aad, plaintext, &mut again).unwrap();
+        assert_ne!(stored.as_slice(), again.as_slice());
+
+        // Unless they come from a deterministic generator
+        let mut seeded = [AlignedVec::new(), AlignedVec::new()];
+        for out in seeded.iter_mut() {
+            let rng = crate::SplitMixRng::new(1);
+            encrypt(&key, Some(&rng), &aad, plaintext, out).unwrap();
+        }
+        assert_eq!(seeded[0].as_slice(), seeded[1].as_slice());
+        assert_eq!(decrypt(&key, &aad, &seeded[0]).unwrap(), plaintext);
+
+        // Wrong key, wrong associated data and tampering are all rejected
+        let wrong_key = EncryptionKey::from_bytes([8; 32]);
+        assert!(matches!(
+            decrypt(&wrong_key, &aad, &stored),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+        assert!(matches!(
+            decrypt(&key, &associated_data(1, 3, &[]), &stored),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+        stored[NONCE_LEN] ^= 1;
+        assert!(matches!(
+            decrypt(&key, &aad, &stored),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+    }
+}
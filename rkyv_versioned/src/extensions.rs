@@ -0,0 +1,244 @@
+//! Optional key/value metadata stored between the header and the payload.
+//!
+//! When [TaggedOptions::extensions](crate::TaggedOptions::extensions) is not empty, the
+//! header flags the presence of an extension area at the start of the stored payload, after
+//! the header words if there are any:
+//!
+//! `[area length (u32, little-endian)][entry]*[zero padding]`
+//!
+//! where each entry is a little-endian `u16` key, a little-endian `u16` value length and the
+//! value bytes.  The area is padded to a multiple of 16 bytes to keep the payload after it
+//! aligned, and key `0` is reserved so that the padding terminates the entries.
+//!
+//! The area is covered by the payload digest (and signature, if any), and bound to the
+//! ciphertext of encrypted payloads, but is itself never compressed or encrypted so it can be
+//! read with [get_extensions_from_tagged_bytes] without knowing the container type.
+
+use alloc::vec::Vec;
+
+use rkyv::ser::Writer;
+
+use crate::{
+    header_words, read_header, rkyv_error, RkyvVersionedError, TaggedVersionedHeader,
+    TAGGED_HEADER_SIZE,
+};
+
+/// Set in the header flags when an extension area precedes the payload.
+pub const HEADER_FLAG_EXTENSIONS: u8 = 1 << 1;
+
+/// The size of the length prefix of the extension area.
+const AREA_LEN_SIZE: usize = 4;
+
+/// The size of the key and length in front of each entry.
+const ENTRY_HEADER_SIZE: usize = 4;
+
+/// The alignment of the extension area, keeping the payload after it aligned.
+const AREA_ALIGNMENT: usize = 16;
+
+/// Key/value metadata attached to a tagged byte array, e.g. a tenant or trace ID.
+///
+/// Keys are application defined, except for `0` which is reserved and keys from `0xff00`
+/// which are used by this crate, e.g. for the [WriterIdentity](crate::WriterIdentity) and
+/// idempotency keys.  Values are limited to
+/// `u16::MAX` bytes, though they are intended to be small since they are copied when read.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeaderExtensions {
+    entries: Vec<(u16, Vec<u8>)>,
+}
+
+impl HeaderExtensions {
+    /// Creates an empty set of extensions.
+    pub fn new() -> Self {
+        HeaderExtensions::default()
+    }
+
+    /// Sets the value of `key`, replacing any previous value.
+    ///
+    /// Fails with [RkyvVersionedError::InvalidExtensionError] if `key` is `0` or the value is
+    /// longer than `u16::MAX` bytes.
+    pub fn insert(&mut self, key: u16, value: &[u8]) -> Result<(), RkyvVersionedError> {
+        if key == 0 || value.len() > u16::MAX as usize {
+            return Err(RkyvVersionedError::InvalidExtensionError(key));
+        }
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value.to_vec(),
+            None => self.entries.push((key, value.to_vec())),
+        }
+        Ok(())
+    }
+
+    /// Returns the value of `key`, if set.
+    pub fn get(&self, key: u16) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Removes `key`, returning its value if it was set.
+    pub fn remove(&mut self, key: u16) -> Option<Vec<u8>> {
+        let index = self.entries.iter().position(|(k, _)| *k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Iterates over the entries in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (*key, value.as_slice()))
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The size of the encoded extension area, including padding, or 0 if there are no
+    /// entries.
+    pub(crate) fn encoded_len(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let unpadded = AREA_LEN_SIZE
+            + self
+                .entries
+                .iter()
+                .map(|(_, value)| ENTRY_HEADER_SIZE + value.len())
+                .sum::<usize>();
+        unpadded.next_multiple_of(AREA_ALIGNMENT)
+    }
+
+    /// Appends the encoded extension area to `out`, writing nothing if there are no entries.
+    pub(crate) fn encode<W: Writer<rkyv::rancor::Error>>(
+        &self,
+        out: &mut W,
+    ) -> Result<(), RkyvVersionedError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let area_len = self.encoded_len();
+        let area_len_u32 = u32::try_from(area_len)
+            .map_err(|_| RkyvVersionedError::PayloadTooLargeError(area_len))?;
+
+        let end = out.pos() + area_len;
+        out.write(&area_len_u32.to_le_bytes()).map_err(rkyv_error)?;
+        for (key, value) in &self.entries {
+            out.write(&key.to_le_bytes()).map_err(rkyv_error)?;
+            out.write(&(value.len() as u16).to_le_bytes())
+                .map_err(rkyv_error)?;
+            out.write(value).map_err(rkyv_error)?;
+        }
+        let padding = [0u8; AREA_ALIGNMENT];
+        out.write(padding.get(..end - out.pos()).unwrap_or_default())
+            .map_err(rkyv_error)
+    }
+
+    /// Decodes an extension area previously split off by [split_extensions].
+    pub(crate) fn decode(area: &[u8]) -> Result<Self, RkyvVersionedError> {
+        let mut extensions = HeaderExtensions::new();
+        let mut entries = area.get(AREA_LEN_SIZE..).unwrap_or_default();
+        while let Some((entry_header, rest)) = entries.split_first_chunk::<ENTRY_HEADER_SIZE>()
+        {
+            let key = u16::from_le_bytes([entry_header[0], entry_header[1]]);
+            if key == 0 {
+                // The remainder is padding
+                break;
+            }
+            let len = u16::from_le_bytes([entry_header[2], entry_header[3]]) as usize;
+            let value = rest
+                .get(..len)
+                .ok_or(RkyvVersionedError::InvalidExtensionError(key))?;
+            extensions.insert(key, value)?;
+            entries = rest.get(len..).unwrap_or_default();
+        }
+        Ok(extensions)
+    }
+}
+
+/// Splits the stored body of a tagged byte array, after the header words if any, into the
+/// extension area, which is empty if there is none, and the payload.
+pub(crate) fn split_extensions<'a>(
+    header: &TaggedVersionedHeader,
+    body: &'a [u8],
+) -> Result<(&'a [u8], &'a [u8]), RkyvVersionedError> {
+    if header.flags & HEADER_FLAG_EXTENSIONS == 0 {
+        return Ok((&[], body));
+    }
+    let (area_len, _) = body
+        .split_first_chunk::<AREA_LEN_SIZE>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let area_len = u32::from_le_bytes(*area_len) as usize;
+    if area_len < AREA_LEN_SIZE {
+        return Err(RkyvVersionedError::BufferTooSmallError);
+    }
+    body.split_at_checked(area_len)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)
+}
+
+/// Reads the [HeaderExtensions] of a tagged byte array without accessing the payload.
+///
+/// Like [get_type_and_version_from_tagged_bytes](crate::get_type_and_version_from_tagged_bytes)
+/// this does not require knowing the container type, and the payload digest is not verified.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the extensions, which are empty if none were written, or an error if
+/// the buffer is truncated or the extension area is malformed.
+pub fn get_extensions_from_tagged_bytes(
+    buf: &[u8],
+) -> Result<HeaderExtensions, RkyvVersionedError> {
+    let header = read_header(buf)?;
+    let body = buf
+        .get(TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + header.payload_len as usize)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let (_, body) = header_words::split_words(&header, body)?;
+    let (area, _) = split_extensions(&header, body)?;
+    HeaderExtensions::decode(area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rkyv::util::AlignedVec;
+
+    #[test]
+    fn test_extensions_encoding() {
+        let mut extensions = HeaderExtensions::new();
+        assert_eq!(extensions.encoded_len(), 0);
+        extensions.insert(1, b"tenant-42").unwrap();
+        extensions.insert(2, &[0xab; 16]).unwrap();
+        extensions.insert(1, b"tenant-7").unwrap();
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get(1), Some(&b"tenant-7"[..]));
+
+        let mut area = AlignedVec::<16>::new();
+        extensions.encode(&mut area).unwrap();
+        assert_eq!(area.len(), extensions.encoded_len());
+        assert_eq!(area.len() % AREA_ALIGNMENT, 0);
+        assert_eq!(HeaderExtensions::decode(&area).unwrap(), extensions);
+
+        assert!(matches!(
+            extensions.insert(0, b"reserved"),
+            Err(RkyvVersionedError::InvalidExtensionError(0))
+        ));
+        assert!(extensions.insert(3, &vec![0; 0x1_0000]).is_err());
+        assert_eq!(extensions.remove(2), Some(vec![0xab; 16]));
+        assert_eq!(extensions.get(2), None);
+
+        // A value running past the end of the area is rejected
+        let truncated = [16, 0, 0, 0, 5, 0, 100, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(matches!(
+            HeaderExtensions::decode(&truncated),
+            Err(RkyvVersionedError::InvalidExtensionError(5))
+        ));
+    }
+}
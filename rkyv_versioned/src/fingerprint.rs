@@ -0,0 +1,382 @@
+//! Layout fingerprints of payload types, catching edits to a version's type without a new
+//! version.
+//!
+//! The [schema_hash](crate::VersionInfo::schema_hash) of a version only covers the name and
+//! declared type of its variant, so adding a field to `TestStructV2` in place goes unnoticed
+//! until its records fail to validate, or worse, validate as something else.  A
+//! [SchemaFingerprint] is a CRC32 of the structure of a type computed at compile time: the
+//! kinds, order and fingerprints of its fields, recursively, along with any `rkyv` wrappers.
+//! Renaming fields or types keeps the fingerprint, since it doesn't change the archived
+//! layout.
+//!
+//! Payload types derive it with `#[derive(SchemaFingerprint)]`, and containers deriving
+//! [VersionedArchiveContainer](crate::VersionedArchiveContainer) with `#[vac(fingerprint)]`
+//! record the fingerprint of each variant in
+//! [VersionInfo::fingerprint](crate::VersionInfo::fingerprint).  Records of those containers
+//! carry the fingerprint of their version in the [EXTENSION_KEY_SCHEMA_FINGERPRINT]
+//! extension, and the access functions fail with [RkyvVersionedError::SchemaMismatchError]
+//! when it doesn't match the fingerprint the reader was built with.  Records without the
+//! extension are rejected the same way, with a found fingerprint of 0, since nothing
+//! vouches for their layout.  Records written before the container was fingerprinted can be
+//! read with [ValidationConfig::allow_missing_fingerprint](crate::ValidationConfig::allow_missing_fingerprint).
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use const_crc32::{crc32, crc32_seed};
+
+use crate::extensions::split_extensions;
+use crate::header_words::split_words;
+use crate::{HeaderExtensions, RkyvVersionedError, TaggedVersionedHeader, VersionedContainer};
+
+/// The extension key holding the [SchemaFingerprint] of the version of a record, as a
+/// little-endian `u32`.
+pub const EXTENSION_KEY_SCHEMA_FINGERPRINT: u16 = 0xff0c;
+
+/// A fingerprint of the archived layout of a type, recorded in
+/// [VersionInfo::fingerprint](crate::VersionInfo::fingerprint) by `#[vac(fingerprint)]` and
+/// checked when records are read.
+///
+/// Implemented for primitives, strings, boxes, options, vectors, arrays, tuples and maps, and
+/// derived for structs and enums with `#[derive(SchemaFingerprint)]`.
+pub trait SchemaFingerprint {
+    /// The fingerprint, computed at compile time.
+    const FINGERPRINT: u32;
+}
+
+/// Combines the fingerprints of the parts of a type of the given kind, e.g. the fields of a
+/// struct, in order.
+pub const fn combine_fingerprints(kind: &str, parts: &[u32]) -> u32 {
+    let mut fingerprint = crc32(kind.as_bytes());
+    let mut rest = parts;
+    while let Some((part, tail)) = rest.split_first() {
+        fingerprint = crc32_seed(&part.to_le_bytes(), fingerprint);
+        rest = tail;
+    }
+    fingerprint
+}
+
+macro_rules! impl_primitive_fingerprint {
+    ($($ty:ty),*) => {
+        $(impl SchemaFingerprint for $ty {
+            const FINGERPRINT: u32 = crc32(stringify!($ty).as_bytes());
+        })*
+    };
+}
+
+impl_primitive_fingerprint!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    f32,
+    f64,
+    usize,
+    isize,
+    String
+);
+
+macro_rules! impl_wrapper_fingerprint {
+    ($($kind:literal => $ty:ty),*) => {
+        $(impl<T: SchemaFingerprint> SchemaFingerprint for $ty {
+            const FINGERPRINT: u32 = combine_fingerprints($kind, &[T::FINGERPRINT]);
+        })*
+    };
+}
+
+impl_wrapper_fingerprint!(
+    "Option" => Option<T>,
+    "Vec" => Vec<T>,
+    "BTreeSet" => BTreeSet<T>,
+    "[]" => [T]
+);
+
+impl<T: SchemaFingerprint + ?Sized> SchemaFingerprint for Box<T> {
+    const FINGERPRINT: u32 = combine_fingerprints("Box", &[T::FINGERPRINT]);
+}
+
+impl SchemaFingerprint for str {
+    const FINGERPRINT: u32 = String::FINGERPRINT;
+}
+
+/// References, e.g. the variants of containers archived with `InlineAsBox`, have the
+/// fingerprint of what they refer to.
+impl<T: SchemaFingerprint + ?Sized> SchemaFingerprint for &T {
+    const FINGERPRINT: u32 = T::FINGERPRINT;
+}
+
+impl<T: SchemaFingerprint, const N: usize> SchemaFingerprint for [T; N] {
+    const FINGERPRINT: u32 = combine_fingerprints("[;]", &[T::FINGERPRINT, N as u32]);
+}
+
+impl<K: SchemaFingerprint, V: SchemaFingerprint> SchemaFingerprint for BTreeMap<K, V> {
+    const FINGERPRINT: u32 =
+        combine_fingerprints("BTreeMap", &[K::FINGERPRINT, V::FINGERPRINT]);
+}
+
+#[cfg(feature = "std")]
+impl<K: SchemaFingerprint, V: SchemaFingerprint, S> SchemaFingerprint
+    for std::collections::HashMap<K, V, S>
+{
+    const FINGERPRINT: u32 =
+        combine_fingerprints("HashMap", &[K::FINGERPRINT, V::FINGERPRINT]);
+}
+
+#[cfg(feature = "std")]
+impl<T: SchemaFingerprint, S> SchemaFingerprint for std::collections::HashSet<T, S> {
+    const FINGERPRINT: u32 = combine_fingerprints("HashSet", &[T::FINGERPRINT]);
+}
+
+macro_rules! impl_tuple_fingerprint {
+    ($(($($name:ident),+)),*) => {
+        $(impl<$($name: SchemaFingerprint),+> SchemaFingerprint for ($($name,)+) {
+            const FINGERPRINT: u32 = combine_fingerprints("()", &[$($name::FINGERPRINT),+]);
+        })*
+    };
+}
+
+impl_tuple_fingerprint!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
+
+/// Returns the fingerprint of version `version_id` of `T`, if it has one.
+pub(crate) fn expected_fingerprint<T: VersionedContainer>(version_id: u32) -> Option<u32> {
+    T::version_info(version_id).and_then(|info| info.fingerprint)
+}
+
+/// The size of an extension area holding only a fingerprint.
+pub(crate) const FINGERPRINT_AREA_SIZE: usize = 16;
+
+/// Encodes the extension area of a record whose only extension is `fingerprint`, the same as
+/// [HeaderExtensions] would, but without allocating, for the slice serializers.
+pub(crate) fn fingerprint_area(fingerprint: u32) -> [u8; FINGERPRINT_AREA_SIZE] {
+    let [l0, l1, l2, l3] = (FINGERPRINT_AREA_SIZE as u32).to_le_bytes();
+    let [k0, k1] = EXTENSION_KEY_SCHEMA_FINGERPRINT.to_le_bytes();
+    let [n0, n1] = 4u16.to_le_bytes();
+    let [f0, f1, f2, f3] = fingerprint.to_le_bytes();
+    [l0, l1, l2, l3, k0, k1, n0, n1, f0, f1, f2, f3, 0, 0, 0, 0]
+}
+
+/// Checks the fingerprint stored in the body of a record holding version
+/// `header.version_id` of `T` against the one `T` was built with, if it has one.  A record
+/// without a fingerprint is a mismatch with a found fingerprint of 0, unless `allow_missing`
+/// is set.
+pub(crate) fn check_fingerprint<T: VersionedContainer>(
+    header: &TaggedVersionedHeader,
+    body: &[u8],
+    allow_missing: bool,
+) -> Result<(), RkyvVersionedError> {
+    let Some(expected) = expected_fingerprint::<T>(header.version_id) else {
+        return Ok(());
+    };
+    let (_, rest) = split_words(header, body)?;
+    let (area, _) = split_extensions(header, rest)?;
+    let stored = match area.is_empty() {
+        true => None,
+        false => HeaderExtensions::decode(area)?
+            .get(EXTENSION_KEY_SCHEMA_FINGERPRINT)
+            .map(|value| {
+                <[u8; 4]>::try_from(value)
+                    .map(u32::from_le_bytes)
+                    .map_err(|_| {
+                        RkyvVersionedError::InvalidExtensionError(
+                            EXTENSION_KEY_SCHEMA_FINGERPRINT,
+                        )
+                    })
+            })
+            .transpose()?,
+    };
+    match stored {
+        Some(stored) if stored == expected => Ok(()),
+        None if allow_missing => Ok(()),
+        stored => Err(RkyvVersionedError::SchemaMismatchError(
+            expected,
+            stored.unwrap_or_default(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, get_extensions_from_tagged_bytes, serialized_tagged_size,
+        to_tagged_bytes, to_tagged_bytes_in_fixed, SchemaFingerprint, ValidationConfig,
+        VersionedArchiveContainer,
+    };
+    #[cfg(feature = "std")]
+    use crate::{framed_records, write_tagged_vectored};
+    use core::mem::MaybeUninit;
+    use rkyv::util::AlignedVec;
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, SchemaFingerprint)]
+    struct ProfileV1 {
+        name: String,
+        age: u32,
+    }
+
+    // The same layout under other names
+    #[derive(SchemaFingerprint)]
+    struct RenamedProfile {
+        _full_name: String,
+        _years: u32,
+    }
+
+    // ProfileV1 edited in place
+    #[derive(Debug, Archive, Serialize, Deserialize, SchemaFingerprint)]
+    struct EditedProfile {
+        name: String,
+        age: u64,
+    }
+
+    #[derive(SchemaFingerprint)]
+    enum _Status {
+        Active,
+        Away(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(fingerprint)]
+    enum Profile<'a> {
+        V1(#[rkyv(with = InlineAsBox)] &'a ProfileV1),
+    }
+
+    // The container as written before it was fingerprinted
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum UnfingerprintedProfile<'a> {
+        V1(#[rkyv(with = InlineAsBox)] &'a ProfileV1),
+    }
+
+    // The container as built by a reader with the edited type
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(fingerprint)]
+    enum EditedContainer<'a> {
+        V1(#[rkyv(with = InlineAsBox)] &'a EditedProfile),
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        assert_eq!(ProfileV1::FINGERPRINT, RenamedProfile::FINGERPRINT);
+        assert_ne!(ProfileV1::FINGERPRINT, EditedProfile::FINGERPRINT);
+        assert_ne!(<(String, u32)>::FINGERPRINT, <(u32, String)>::FINGERPRINT);
+        assert_ne!(<Vec<u8>>::FINGERPRINT, <Option<u8>>::FINGERPRINT);
+        assert_eq!(
+            Profile::VERSION_TABLE[0].fingerprint,
+            Some(ProfileV1::FINGERPRINT)
+        );
+
+        let profile = ProfileV1 {
+            name: "Ada".to_string(),
+            age: 36,
+        };
+        let bytes = to_tagged_bytes(&Profile::V1(&profile)).unwrap();
+        let stored = get_extensions_from_tagged_bytes(&bytes).unwrap();
+        assert_eq!(
+            stored.get(EXTENSION_KEY_SCHEMA_FINGERPRINT),
+            Some(&ProfileV1::FINGERPRINT.to_le_bytes()[..])
+        );
+        assert!(access_from_tagged_bytes::<Profile>(&bytes).is_ok());
+
+        assert_eq!(
+            serialized_tagged_size(&Profile::V1(&profile)).unwrap(),
+            bytes.len()
+        );
+
+        // The slice serializers write the same extension without allocating it
+        let mut extensions = HeaderExtensions::new();
+        extensions
+            .insert(
+                EXTENSION_KEY_SCHEMA_FINGERPRINT,
+                &ProfileV1::FINGERPRINT.to_le_bytes(),
+            )
+            .unwrap();
+        let mut area = AlignedVec::<16>::new();
+        extensions.encode(&mut area).unwrap();
+        assert_eq!(
+            fingerprint_area(ProfileV1::FINGERPRINT).as_slice(),
+            area.as_slice()
+        );
+        let mut fixed = AlignedVec::<16>::new();
+        fixed.resize(bytes.len(), 0);
+        let mut scratch = [MaybeUninit::uninit(); 256];
+        let len = to_tagged_bytes_in_fixed(&Profile::V1(&profile), &mut fixed, &mut scratch)
+            .unwrap();
+        assert_eq!(&fixed[..len], bytes.as_slice());
+
+        // A reader built with the edited type has the same type ID, but rejects the record
+        for record in [&bytes[..], &fixed[..len]] {
+            let mut edited = AlignedVec::<16>::new();
+            edited.extend_from_slice(record);
+            edited[..4].copy_from_slice(&EditedContainer::ARCHIVE_TYPE_ID.to_le_bytes());
+            assert!(matches!(
+                access_from_tagged_bytes::<EditedContainer>(&edited),
+                Err(RkyvVersionedError::SchemaMismatchError(expected, found))
+                    if expected == EditedProfile::FINGERPRINT && found == ProfileV1::FINGERPRINT
+            ));
+        }
+    }
+
+    #[test]
+    fn test_missing_fingerprint_is_a_mismatch() {
+        let profile = ProfileV1 {
+            name: "Grace".to_string(),
+            age: 45,
+        };
+        let bytes = to_tagged_bytes(&UnfingerprintedProfile::V1(&profile)).unwrap();
+        let mut unfingerprinted = AlignedVec::<16>::new();
+        unfingerprinted.extend_from_slice(&bytes);
+        unfingerprinted[..4].copy_from_slice(&Profile::ARCHIVE_TYPE_ID.to_le_bytes());
+
+        assert!(matches!(
+            access_from_tagged_bytes::<Profile>(&unfingerprinted),
+            Err(RkyvVersionedError::SchemaMismatchError(expected, 0))
+                if expected == ProfileV1::FINGERPRINT
+        ));
+        let config = ValidationConfig {
+            allow_missing_fingerprint: true,
+            ..Default::default()
+        };
+        assert!(config
+            .access_from_tagged_bytes::<Profile>(&unfingerprinted)
+            .is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_vectored_writer_records_fingerprint() {
+        let profile = ProfileV1 {
+            name: "Ada".to_string(),
+            age: 36,
+        };
+        let container = Profile::V1(&profile);
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&container).unwrap();
+
+        let mut stream = Vec::new();
+        write_tagged_vectored::<Profile>(&mut stream, 0, &payload).unwrap();
+        let mut record = AlignedVec::<16>::new();
+        record.extend_from_slice(framed_records(&stream).next().unwrap().unwrap());
+        assert_eq!(
+            record.as_slice(),
+            to_tagged_bytes(&container).unwrap().as_slice()
+        );
+        assert!(access_from_tagged_bytes::<Profile>(&record).is_ok());
+    }
+}
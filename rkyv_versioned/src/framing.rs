@@ -0,0 +1,213 @@
+//! Length-prefixed framing for concatenating tagged byte arrays in one stream.
+//!
+//! [access_from_tagged_bytes](crate::access_from_tagged_bytes) expects a slice starting with
+//! a single record.  To store many records back to back, [append_framed] writes each one as a
+//! frame:
+//!
+//! `[prefix: record length (u32, little-endian) + 12 zero bytes][record][zero padding]`
+//!
+//! The prefix and padding keep every frame a multiple of [FRAME_ALIGNMENT] bytes, so the
+//! records of a stream held in an [AlignedVec] stay aligned and can be accessed in place, and
+//! [FramedRecords] splits the stream back apart.
+
+use rkyv::util::AlignedVec;
+
+use crate::{read_header, RkyvVersionedError};
+
+/// The size of the length prefix in front of every frame.
+pub const FRAME_PREFIX_SIZE: usize = 16;
+
+/// The alignment of every frame in a stream.
+pub const FRAME_ALIGNMENT: usize = 16;
+
+/// Appends `tagged` to `out` as a length-prefixed frame.
+///
+/// `out` should either be empty or hold previously appended frames for the record to stay
+/// aligned.
+///
+/// # Arguments
+///
+/// * `out` - The stream to append the frame to.
+/// * `tagged` - A tagged byte array generated by e.g. [to_tagged_bytes](crate::to_tagged_bytes).
+///
+/// # Returns
+///
+/// A `Result` which is an error if `tagged` is not a complete tagged byte array.
+pub fn append_framed(out: &mut AlignedVec, tagged: &[u8]) -> Result<(), RkyvVersionedError> {
+    // Only frame whole records, a truncated one would poison the rest of the stream
+    let tagged_len = read_header(tagged)?.tagged_len()?;
+    let record = tagged
+        .get(..tagged_len)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    out.extend_from_slice(&frame_prefix(record.len())?);
+    out.extend_from_slice(record);
+    out.resize(out.len().next_multiple_of(FRAME_ALIGNMENT), 0);
+    Ok(())
+}
+
+/// Encodes the prefix of a frame holding a record of `record_len` bytes.
+pub(crate) fn frame_prefix(
+    record_len: usize,
+) -> Result<[u8; FRAME_PREFIX_SIZE], RkyvVersionedError> {
+    let encoded_len = u32::try_from(record_len)
+        .map_err(|_| RkyvVersionedError::PayloadTooLargeError(record_len))?;
+    let mut prefix = [0u8; FRAME_PREFIX_SIZE];
+    prefix[..4].copy_from_slice(&encoded_len.to_le_bytes());
+    Ok(prefix)
+}
+
+/// Decodes the record length from the prefix of a frame, or `None` if the prefix is corrupt.
+pub(crate) fn parse_frame_prefix(prefix: &[u8; FRAME_PREFIX_SIZE]) -> Option<usize> {
+    if prefix[4..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize)
+}
+
+/// The number of padding bytes following a record of `record_len` bytes in its frame.
+pub(crate) fn frame_padding(record_len: usize) -> usize {
+    (FRAME_PREFIX_SIZE + record_len).next_multiple_of(FRAME_ALIGNMENT)
+        - FRAME_PREFIX_SIZE
+        - record_len
+}
+
+/// Returns an iterator over the records of a stream written by [append_framed].
+pub fn framed_records(buf: &[u8]) -> FramedRecords<'_> {
+    FramedRecords { buf, offset: 0 }
+}
+
+/// An iterator over the records of a length-prefixed stream, see [framed_records].
+///
+/// Each item is a tagged byte array which can be passed to
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes).  Frames that are truncated,
+/// have a corrupt prefix, or whose length doesn't match the header of the record are reported
+/// as [RkyvVersionedError::InvalidFrameError] with the offset of the frame, after which
+/// iteration stops.
+#[derive(Debug, Clone)]
+pub struct FramedRecords<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> FramedRecords<'a> {
+    /// The offset of the next frame in the stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn next_record(&self) -> Result<(&'a [u8], usize), RkyvVersionedError> {
+        let invalid = RkyvVersionedError::InvalidFrameError(self.offset);
+        let frame = self.buf.get(self.offset..).unwrap_or_default();
+        let Some((prefix, rest)) = frame.split_first_chunk::<FRAME_PREFIX_SIZE>() else {
+            return Err(invalid);
+        };
+        let Some(record_len) = parse_frame_prefix(prefix) else {
+            return Err(invalid);
+        };
+        let Some(record) = rest.get(..record_len) else {
+            return Err(invalid);
+        };
+        match read_header(record).and_then(|header| header.tagged_len()) {
+            Ok(tagged_len) if tagged_len == record_len => {}
+            _ => return Err(invalid),
+        }
+
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        Ok((record, frame_len.min(frame.len())))
+    }
+}
+
+impl<'a> Iterator for FramedRecords<'a> {
+    type Item = Result<&'a [u8], RkyvVersionedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+        match self.next_record() {
+            Ok((record, frame_len)) => {
+                self.offset += frame_len;
+                Some(Ok(record))
+            }
+            Err(e) => {
+                self.offset = self.buf.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Event {
+        pub id: u64,
+        pub name: String,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum EventContainer {
+        V1(Event),
+    }
+
+    #[test]
+    fn test_framed_round_trip() {
+        let mut stream = AlignedVec::<16>::new();
+        for id in 0..5u64 {
+            let event = EventContainer::V1(Event {
+                id,
+                name: "x".repeat(id as usize * 3),
+            });
+            append_framed(&mut stream, &to_tagged_bytes(&event).unwrap()).unwrap();
+            assert_eq!(stream.len() % FRAME_ALIGNMENT, 0);
+        }
+
+        let mut records = framed_records(&stream);
+        for id in 0..5u64 {
+            let record = records.next().unwrap().unwrap();
+            match access_from_tagged_bytes::<EventContainer>(record).unwrap() {
+                ArchivedEventContainer::V1(event) => {
+                    assert_eq!(event.id, id);
+                    assert_eq!(event.name.len(), id as usize * 3);
+                }
+            }
+        }
+        assert!(records.next().is_none());
+        assert!(framed_records(&[]).next().is_none());
+    }
+
+    #[test]
+    fn test_framed_corruption() {
+        let tagged = to_tagged_bytes(&EventContainer::V1(Event {
+            id: 1,
+            name: "one".to_owned(),
+        }))
+        .unwrap();
+        let mut stream = AlignedVec::<16>::new();
+        append_framed(&mut stream, &tagged).unwrap();
+        let second_frame = stream.len();
+        append_framed(&mut stream, &tagged).unwrap();
+
+        // Truncating the second frame fails after yielding the first record
+        let truncated = &stream[..stream.len() - 20];
+        let results = framed_records(truncated).collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(RkyvVersionedError::InvalidFrameError(offset)) if offset == second_frame
+        ));
+
+        // A length that disagrees with the record header is rejected
+        let mut corrupt = stream.clone();
+        corrupt[second_frame] = corrupt[second_frame].wrapping_add(16);
+        assert!(framed_records(&corrupt).nth(1).unwrap().is_err());
+
+        // Only complete tagged byte arrays can be framed
+        assert!(append_framed(&mut stream, &tagged[..tagged.len() - 1]).is_err());
+    }
+}
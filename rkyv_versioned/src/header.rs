@@ -0,0 +1,192 @@
+//! The fixed header written in front of every tagged payload.
+
+use crate::extensions::HEADER_FLAG_EXTENSIONS;
+use crate::header_words::{
+    HEADER_FLAG_SEQUENCE, HEADER_FLAG_TIMESTAMP, HEADER_FLAG_USER_WORD,
+};
+use crate::signature::SIGNATURE_LEN;
+use crate::{digest_algorithm, RkyvVersionedError};
+
+/// The size of the encoded [TaggedVersionedHeader].  This is a multiple of the alignment of
+/// [AlignedVec](rkyv::util::AlignedVec) so that the payload following the header remains
+/// correctly aligned.
+pub const TAGGED_HEADER_SIZE: usize = 16;
+
+/// The header written in front of every tagged payload.
+///
+/// A tagged byte array is laid out as `[header][body][digest][signature]`, where the body is
+/// `payload_len` bytes long and holds the optional header words and extension area followed
+/// by the payload, a plain `rkyv` archive of the versioned container (possibly compressed or
+/// encrypted).  The header can be accessed on its own to "peek" at the type and version
+/// without touching the payload.
+///
+/// The header has a canonical encoding which doesn't depend on the architecture or the `rkyv`
+/// features in use, so tagged byte arrays written on one machine can be peeked at on any
+/// other:
+///
+/// | Offset | Size | Field                                                      |
+/// |--------|------|------------------------------------------------------------|
+/// | 0      | 4    | `type_id`, little-endian                                   |
+/// | 4      | 4    | `version_id`, little-endian                                |
+/// | 8      | 4    | `payload_len`, little-endian                               |
+/// | 12     | 1    | `digest`                                                   |
+/// | 13     | 1    | `compression` in the low nibble, `encryption` in the high nibble |
+/// | 14     | 1    | The format version, [TAGGED_FORMAT_VERSION]                |
+/// | 15     | 1    | `flags`                                                    |
+///
+/// Headers holding any other format version are rejected with
+/// [RkyvVersionedError::UnsupportedFormatError].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggedVersionedHeader {
+    /// The [ARCHIVE_TYPE_ID](crate::VersionedContainer::ARCHIVE_TYPE_ID) of the container
+    pub type_id: u32,
+    /// The version ID of the variant held in the payload
+    pub version_id: u32,
+    /// The length of the body between the header and the digest trailer
+    pub payload_len: u32,
+    /// The [DigestAlgorithm](crate::DigestAlgorithm) id of the digest trailer
+    pub digest: u8,
+    /// The [CompressionCodec](crate::CompressionCodec) id of the payload
+    pub compression: u8,
+    /// The [EncryptionAlgorithm](crate::EncryptionAlgorithm) id of the payload
+    pub encryption: u8,
+    /// Bit flags describing optional sections, see [HEADER_FLAG_SIGNED],
+    /// [HEADER_FLAG_EXTENSIONS], [HEADER_FLAG_USER_WORD], [HEADER_FLAG_TIMESTAMP] and
+    /// [HEADER_FLAG_SEQUENCE]
+    pub flags: u8,
+}
+
+/// The version of the tagged format written by this crate, stored in byte 14 of the header.
+///
+/// Format 0 is the layout written by releases before the header was introduced: an `rkyv`
+/// archive of the container followed by a root holding the type and version IDs.  It is not
+/// read by this version of the crate, and the access functions reject it with
+/// [RkyvVersionedError::UnsupportedFormatError] holding 0, so such data must be rewritten
+/// with the release that wrote it before upgrading.
+pub const TAGGED_FORMAT_VERSION: u8 = 1;
+
+/// The size of the root of a format 0 archive: the type and version IDs followed by a
+/// relative pointer to the container.
+const LEGACY_ROOT_SIZE: usize = 12;
+
+/// Set in [TaggedVersionedHeader::flags] when an ed25519 signature follows the digest trailer.
+pub const HEADER_FLAG_SIGNED: u8 = 1 << 0;
+
+/// All of the header flags understood by this version of the crate.
+pub const KNOWN_HEADER_FLAGS: u8 = HEADER_FLAG_SIGNED
+    | HEADER_FLAG_EXTENSIONS
+    | HEADER_FLAG_USER_WORD
+    | HEADER_FLAG_TIMESTAMP
+    | HEADER_FLAG_SEQUENCE;
+
+impl TaggedVersionedHeader {
+    /// Encodes the header in its canonical layout, with the current [TAGGED_FORMAT_VERSION].
+    /// Only the low nibbles of `compression` and `encryption` are kept, [write_header] rejects
+    /// headers whose IDs don't fit.
+    pub fn encode(&self) -> [u8; TAGGED_HEADER_SIZE] {
+        let mut bytes = [0u8; TAGGED_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.type_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.version_id.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes[12] = self.digest;
+        bytes[13] = (self.compression & 0x0f) | (self.encryption & 0x0f) << 4;
+        bytes[14] = TAGGED_FORMAT_VERSION;
+        bytes[15] = self.flags;
+        bytes
+    }
+
+    /// Decodes a header from its canonical layout, without validating it or its format
+    /// version.
+    pub fn decode(bytes: &[u8; TAGGED_HEADER_SIZE]) -> Self {
+        let [t0, t1, t2, t3, v0, v1, v2, v3, l0, l1, l2, l3, digest, algorithms, _, flags] =
+            *bytes;
+        TaggedVersionedHeader {
+            type_id: u32::from_le_bytes([t0, t1, t2, t3]),
+            version_id: u32::from_le_bytes([v0, v1, v2, v3]),
+            payload_len: u32::from_le_bytes([l0, l1, l2, l3]),
+            digest,
+            compression: algorithms & 0x0f,
+            encryption: algorithms >> 4,
+            flags,
+        }
+    }
+
+    /// The total length of the tagged byte array described by this header, including the
+    /// header itself and the trailers.
+    pub fn tagged_len(&self) -> Result<usize, RkyvVersionedError> {
+        Ok(TAGGED_HEADER_SIZE + self.payload_len as usize + self.trailer_len()?)
+    }
+
+    /// The length of the digest and signature trailers following the payload.
+    pub fn trailer_len(&self) -> Result<usize, RkyvVersionedError> {
+        let mut len = digest_algorithm(self)?.digest_len();
+        if self.flags & HEADER_FLAG_SIGNED != 0 {
+            len += SIGNATURE_LEN;
+        }
+        Ok(len)
+    }
+}
+
+/// Writes `header` into the first [TAGGED_HEADER_SIZE] bytes of `buf`.
+pub fn write_header(
+    buf: &mut [u8],
+    header: &TaggedVersionedHeader,
+) -> Result<(), RkyvVersionedError> {
+    if header.compression > 0x0f {
+        return Err(RkyvVersionedError::UnsupportedCompressionError(
+            header.compression,
+        ));
+    }
+    if header.encryption > 0x0f {
+        return Err(RkyvVersionedError::UnsupportedEncryptionError(
+            header.encryption,
+        ));
+    }
+    buf.get_mut(..TAGGED_HEADER_SIZE)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?
+        .copy_from_slice(&header.encode());
+    Ok(())
+}
+
+/// Reads the header from the front of a tagged byte array.
+pub fn read_header(buf: &[u8]) -> Result<TaggedVersionedHeader, RkyvVersionedError> {
+    let (header_bytes, _) = buf
+        .split_first_chunk::<TAGGED_HEADER_SIZE>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let [.., format, _] = *header_bytes;
+    if format != TAGGED_FORMAT_VERSION {
+        return Err(RkyvVersionedError::UnsupportedFormatError(format));
+    }
+    let header = TaggedVersionedHeader::decode(header_bytes);
+
+    if header.flags & !KNOWN_HEADER_FLAGS != 0 {
+        return Err(RkyvVersionedError::UnsupportedHeaderFlagsError(
+            header.flags,
+        ));
+    }
+    Ok(header)
+}
+
+/// Returns whether `buf` looks like a format 0 archive of the container with `type_id`, i.e.
+/// its root at the end of the buffer holds `type_id` and points back into the buffer.  Used
+/// to explain why data written before [TAGGED_FORMAT_VERSION] was introduced can't be read.
+pub(crate) fn is_legacy_format(buf: &[u8], type_id: u32) -> bool {
+    let Some((archive, root)) = buf.split_last_chunk::<LEGACY_ROOT_SIZE>() else {
+        return false;
+    };
+    let [t0, t1, t2, t3, _, _, _, _, o0, o1, o2, o3] = *root;
+    // The root was archived with the `rkyv` layout of the writer, assumed to be ours
+    let (found, offset) = match cfg!(feature = "big_endian") {
+        true => (
+            u32::from_be_bytes([t0, t1, t2, t3]),
+            i32::from_be_bytes([o0, o1, o2, o3]),
+        ),
+        false => (
+            u32::from_le_bytes([t0, t1, t2, t3]),
+            i32::from_le_bytes([o0, o1, o2, o3]),
+        ),
+    };
+    // The relative pointer is the last field of the root, and is relative to itself
+    let target = (archive.len() + 8) as isize + offset as isize;
+    found == type_id && (0..archive.len() as isize).contains(&target)
+}
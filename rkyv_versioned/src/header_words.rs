@@ -0,0 +1,544 @@
+//! Fixed-size metadata words stored right after the header.
+//!
+//! When [TaggedOptions::user_word](crate::TaggedOptions::user_word) or
+//! [TaggedOptions::timestamp](crate::TaggedOptions::timestamp) is set, the header flags the
+//! presence of a 16 byte block at the start of the stored payload, ahead of any
+//! [HeaderExtensions](crate::HeaderExtensions) area:
+//!
+//! `[user word (u64, little-endian)][timestamp (u64, little-endian)]`
+//!
+//! with a separate flag for each word, and the words which are not set left as zeros.  When
+//! [TaggedOptions::sequence](crate::TaggedOptions::sequence) is set the block is extended to
+//! 32 bytes:
+//!
+//! `[user word][timestamp][sequence number (u64, little-endian)][8 reserved zero bytes]`
+//!
+//! The block keeps the payload after it aligned, and like the extension area it is covered by
+//! the digest and signature and bound to the ciphertext of encrypted payloads.  Since it sits
+//! at a fixed offset, [get_user_word_from_tagged_bytes], [get_timestamp_from_tagged_bytes] and
+//! [get_sequence_from_tagged_bytes] can read it with a single bounds check, without validating
+//! the payload or knowing the container type, e.g. to route records by shard ID, seek through
+//! a log by time or deduplicate replicated records.
+//!
+//! The sequence number flag doesn't fit in the flags nibble of the
+//! [HeaderProfile::Compact](crate::HeaderProfile::Compact) header, so sequenced records can
+//! only be framed with the standard header.
+
+use alloc::vec::Vec;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::ser::Writer;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    read_header, rkyv_error, to_tagged_bytes_with, RkyvVersionedError, TaggedOptions,
+    TaggedVersionedHeader, VersionedContainer, TAGGED_HEADER_SIZE,
+};
+
+/// Set in the header flags when the block holds a user word.
+pub const HEADER_FLAG_USER_WORD: u8 = 1 << 2;
+
+/// Set in the header flags when the block holds a timestamp.
+pub const HEADER_FLAG_TIMESTAMP: u8 = 1 << 3;
+
+/// Set in the header flags when the block holds a sequence number.
+pub const HEADER_FLAG_SEQUENCE: u8 = 1 << 4;
+
+/// The size of the block holding the user word and timestamp.
+const WORDS_SIZE: usize = 16;
+
+/// The size of the block when it also holds a sequence number.
+const SEQUENCED_WORDS_SIZE: usize = 32;
+
+/// The words stored in front of the payload.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HeaderWords {
+    pub user_word: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub sequence: Option<u64>,
+}
+
+impl HeaderWords {
+    /// The header flags recording which words are set.
+    pub(crate) fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.user_word.is_some() {
+            flags |= HEADER_FLAG_USER_WORD;
+        }
+        if self.timestamp.is_some() {
+            flags |= HEADER_FLAG_TIMESTAMP;
+        }
+        if self.sequence.is_some() {
+            flags |= HEADER_FLAG_SEQUENCE;
+        }
+        flags
+    }
+
+    /// The length of the block [HeaderWords::encode] writes.
+    pub(crate) fn encoded_len(&self) -> usize {
+        words_len_for_flags(self.flags())
+    }
+
+    /// Appends the block holding the words to `out`, writing nothing if neither is set.
+    pub(crate) fn encode<W: Writer<rkyv::rancor::Error>>(
+        &self,
+        out: &mut W,
+    ) -> Result<(), RkyvVersionedError> {
+        let len = self.encoded_len();
+        if len == 0 {
+            return Ok(());
+        }
+        let mut words = [0u8; SEQUENCED_WORDS_SIZE];
+        words[..8].copy_from_slice(&self.user_word.unwrap_or_default().to_le_bytes());
+        words[8..16].copy_from_slice(&self.timestamp.unwrap_or_default().to_le_bytes());
+        words[16..24].copy_from_slice(&self.sequence.unwrap_or_default().to_le_bytes());
+        out.write(words.get(..len).unwrap_or_default())
+            .map_err(rkyv_error)
+    }
+}
+
+/// The length of the block described by `header`, or 0 if there is none.
+pub(crate) fn words_len(header: &TaggedVersionedHeader) -> usize {
+    words_len_for_flags(header.flags)
+}
+
+fn words_len_for_flags(flags: u8) -> usize {
+    if flags & HEADER_FLAG_SEQUENCE != 0 {
+        SEQUENCED_WORDS_SIZE
+    } else if flags & (HEADER_FLAG_USER_WORD | HEADER_FLAG_TIMESTAMP) != 0 {
+        WORDS_SIZE
+    } else {
+        0
+    }
+}
+
+/// Splits the stored body of a tagged byte array into the words, if any, and the rest of the
+/// body.
+pub(crate) fn split_words<'a>(
+    header: &TaggedVersionedHeader,
+    body: &'a [u8],
+) -> Result<(HeaderWords, &'a [u8]), RkyvVersionedError> {
+    let len = words_len(header);
+    if len == 0 {
+        return Ok((HeaderWords::default(), body));
+    }
+    let (words, rest) = body
+        .split_at_checked(len)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let word = |index: usize, flag: u8| {
+        if header.flags & flag == 0 {
+            return None;
+        }
+        let word = words.get(index * 8..index * 8 + 8)?;
+        Some(u64::from_le_bytes(word.try_into().ok()?))
+    };
+    let words = HeaderWords {
+        user_word: word(0, HEADER_FLAG_USER_WORD),
+        timestamp: word(1, HEADER_FLAG_TIMESTAMP),
+        sequence: word(2, HEADER_FLAG_SEQUENCE),
+    };
+    Ok((words, rest))
+}
+
+/// Reads the words from the front of a tagged byte array.
+fn peek_words(buf: &[u8]) -> Result<HeaderWords, RkyvVersionedError> {
+    let header = read_header(buf)?;
+    let body = buf
+        .get(TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + words_len(&header))
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    Ok(split_words(&header, body)?.0)
+}
+
+/// "Peeks" at the user word of a tagged byte array written with
+/// [TaggedOptions::user_word](crate::TaggedOptions::user_word).
+///
+/// Like [get_type_and_version_from_tagged_bytes](crate::get_type_and_version_from_tagged_bytes)
+/// this only reads the front of the buffer, so neither the payload nor its digest are
+/// validated.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the user word, or `None` if none was written, or an error if the
+/// buffer is undersized.
+pub fn get_user_word_from_tagged_bytes(buf: &[u8]) -> Result<Option<u64>, RkyvVersionedError> {
+    Ok(peek_words(buf)?.user_word)
+}
+
+/// "Peeks" at the timestamp of a tagged byte array written with
+/// [TaggedOptions::timestamp](crate::TaggedOptions::timestamp).
+///
+/// Like [get_user_word_from_tagged_bytes] this only reads the front of the buffer, so neither
+/// the payload nor its digest are validated.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the timestamp, or `None` if none was written, or an error if the
+/// buffer is undersized.
+pub fn get_timestamp_from_tagged_bytes(buf: &[u8]) -> Result<Option<u64>, RkyvVersionedError> {
+    Ok(peek_words(buf)?.timestamp)
+}
+
+/// "Peeks" at the sequence number of a tagged byte array written with
+/// [TaggedOptions::sequence](crate::TaggedOptions::sequence), e.g. by a [Sequencer].
+///
+/// Like [get_user_word_from_tagged_bytes] this only reads the front of the buffer, so neither
+/// the payload nor its digest are validated.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the sequence number, or `None` if none was written, or an error if
+/// the buffer is undersized.
+pub fn get_sequence_from_tagged_bytes(buf: &[u8]) -> Result<Option<u64>, RkyvVersionedError> {
+    Ok(peek_words(buf)?.sequence)
+}
+
+/// Stamps the records it serializes with consecutive sequence numbers.
+///
+/// The sequence number is only consumed when a record is serialized successfully, so a
+/// reader seeing a gap knows a record is missing, e.g. with a [SequenceTracker].  To continue
+/// a log after a restart, create the sequencer with the number following the last record
+/// written.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sequencer {
+    next: u64,
+}
+
+impl Sequencer {
+    /// Creates a sequencer whose first record is stamped with `next`.
+    pub fn new(next: u64) -> Self {
+        Sequencer { next }
+    }
+
+    /// The sequence number the next record will be stamped with.
+    pub fn next_sequence(&self) -> u64 {
+        self.next
+    }
+
+    /// Serializes `item` like [to_tagged_bytes_with](crate::to_tagged_bytes_with), with
+    /// [TaggedOptions::sequence](crate::TaggedOptions::sequence) set to the next sequence
+    /// number, then advances it.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - A reference to the item to be serialized.
+    /// * `options` - The options controlling the header and trailer, whose `sequence` is
+    ///   overridden.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the serialized byte array or an error if serialization
+    /// fails.
+    pub fn to_tagged_bytes_with<T>(
+        &mut self,
+        item: &T,
+        options: &TaggedOptions,
+    ) -> Result<AlignedVec, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let options = TaggedOptions {
+            sequence: Some(self.next),
+            ..options.clone()
+        };
+        let bytes = to_tagged_bytes_with(item, &options)?;
+        self.next = self.next.wrapping_add(1);
+        Ok(bytes)
+    }
+}
+
+/// What a [SequenceTracker] made of a record's sequence number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// The record is the first one seen or follows the previous one.
+    InOrder,
+    /// The record skips ahead, the range holds the sequence numbers which are missing.
+    Gap(core::ops::Range<u64>),
+    /// The record fills part of an earlier gap, i.e. it was reordered in transit.
+    Late(u64),
+    /// The record was seen before, or is older than the gaps still being tracked.
+    Duplicate(u64),
+}
+
+/// Detects gaps, reordering and duplicates in the sequence numbers stamped by a [Sequencer],
+/// when records travel over lossy or reordering transports.
+///
+/// Use one tracker per stream, i.e. per [Sequencer] on the writing side.  Gaps stay
+/// outstanding until filled by [Late](SequenceEvent::Late) records, up to a bounded number of
+/// gaps after which the oldest ones are forgotten.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SequenceTracker {
+    next: Option<u64>,
+    missing: Vec<core::ops::Range<u64>>,
+}
+
+impl SequenceTracker {
+    /// The number of gaps remembered to tell late records from duplicates.
+    const MAX_GAPS: usize = 64;
+
+    /// Creates a tracker expecting the stream to start with any sequence number.
+    pub fn new() -> Self {
+        SequenceTracker::default()
+    }
+
+    /// Creates a tracker expecting the stream to start with `next`, e.g. when resuming after
+    /// the last record processed.
+    pub fn starting_at(next: u64) -> Self {
+        SequenceTracker {
+            next: Some(next),
+            missing: Vec::new(),
+        }
+    }
+
+    /// The sequence number expected next, if any record has been seen.
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.next
+    }
+
+    /// Returns the sequence numbers which are still missing, oldest first.
+    pub fn missing(&self) -> &[core::ops::Range<u64>] {
+        &self.missing
+    }
+
+    /// Records `sequence` as received.
+    pub fn observe(&mut self, sequence: u64) -> SequenceEvent {
+        let Some(next) = self.next else {
+            self.next = Some(sequence.wrapping_add(1));
+            return SequenceEvent::InOrder;
+        };
+        if sequence == next {
+            self.next = Some(next.wrapping_add(1));
+            return SequenceEvent::InOrder;
+        }
+        if sequence > next {
+            self.missing.push(next..sequence);
+            if self.missing.len() > Self::MAX_GAPS {
+                self.missing.remove(0);
+            }
+            self.next = Some(sequence.wrapping_add(1));
+            return SequenceEvent::Gap(next..sequence);
+        }
+
+        let Some(index) = self.missing.iter().position(|gap| gap.contains(&sequence)) else {
+            return SequenceEvent::Duplicate(sequence);
+        };
+        // Split the gap around the late record
+        let gap = self.missing.remove(index);
+        let after = sequence + 1..gap.end;
+        let before = gap.start..sequence;
+        for part in [after, before] {
+            if !part.is_empty() {
+                self.missing.insert(index, part);
+            }
+        }
+        SequenceEvent::Late(sequence)
+    }
+
+    /// Records the sequence number of a tagged byte array, read like
+    /// [get_sequence_from_tagged_bytes].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the [SequenceEvent], `None` if the record has no sequence number,
+    /// or an error if the buffer is undersized.
+    pub fn check(&mut self, buf: &[u8]) -> Result<Option<SequenceEvent>, RkyvVersionedError> {
+        Ok(get_sequence_from_tagged_bytes(buf)?.map(|sequence| self.observe(sequence)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, get_extensions_from_tagged_bytes, to_tagged_bytes,
+        HeaderExtensions, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum OrderContainer {
+        V1(String),
+    }
+
+    #[test]
+    fn test_user_word() {
+        let order = OrderContainer::V1("order-1".to_owned());
+        let plain = to_tagged_bytes(&order).unwrap();
+        assert_eq!(get_user_word_from_tagged_bytes(&plain).unwrap(), None);
+
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(1, b"tenant-42").unwrap();
+        let options = TaggedOptions {
+            user_word: Some(0x0123_4567_89ab_cdef),
+            extensions: extensions.clone(),
+            ..Default::default()
+        };
+        let tagged = to_tagged_bytes_with(&order, &options).unwrap();
+
+        // Only the header and the user word are needed to read it
+        let peeked = &tagged[..TAGGED_HEADER_SIZE + WORDS_SIZE];
+        assert_eq!(
+            get_user_word_from_tagged_bytes(peeked).unwrap(),
+            Some(0x0123_4567_89ab_cdef)
+        );
+        assert!(matches!(
+            get_user_word_from_tagged_bytes(&peeked[..TAGGED_HEADER_SIZE + 4]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+
+        assert_eq!(
+            get_extensions_from_tagged_bytes(&tagged).unwrap(),
+            extensions
+        );
+        match access_from_tagged_bytes::<OrderContainer>(&tagged).unwrap() {
+            ArchivedOrderContainer::V1(order) => assert_eq!(order, "order-1"),
+        }
+
+        // The user word is covered by the digest
+        let mut tampered = tagged.clone();
+        tampered[TAGGED_HEADER_SIZE] ^= 1;
+        assert!(matches!(
+            access_from_tagged_bytes::<OrderContainer>(&tampered),
+            Err(RkyvVersionedError::ChecksumMismatchError)
+        ));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let order = OrderContainer::V1("order-2".to_owned());
+        let options = TaggedOptions {
+            timestamp: Some(1_700_000_000_000),
+            ..Default::default()
+        };
+        let tagged = to_tagged_bytes_with(&order, &options).unwrap();
+        assert_eq!(
+            get_timestamp_from_tagged_bytes(&tagged).unwrap(),
+            Some(1_700_000_000_000)
+        );
+        assert_eq!(get_user_word_from_tagged_bytes(&tagged).unwrap(), None);
+        assert_eq!(
+            get_timestamp_from_tagged_bytes(&to_tagged_bytes(&order).unwrap()).unwrap(),
+            None
+        );
+
+        // Both words share the same block
+        let options = TaggedOptions {
+            user_word: Some(7),
+            ..options
+        };
+        let both = to_tagged_bytes_with(&order, &options).unwrap();
+        assert_eq!(both.len(), tagged.len());
+        assert_eq!(get_user_word_from_tagged_bytes(&both).unwrap(), Some(7));
+        assert_eq!(
+            get_timestamp_from_tagged_bytes(&both).unwrap(),
+            Some(1_700_000_000_000)
+        );
+        match access_from_tagged_bytes::<OrderContainer>(&both).unwrap() {
+            ArchivedOrderContainer::V1(order) => assert_eq!(order, "order-2"),
+        }
+    }
+
+    #[test]
+    fn test_sequencer() {
+        let mut sequencer = Sequencer::new(41);
+        let options = TaggedOptions {
+            timestamp: Some(5),
+            ..Default::default()
+        };
+        let first = sequencer
+            .to_tagged_bytes_with(&OrderContainer::V1("first".to_owned()), &options)
+            .unwrap();
+        let second = sequencer
+            .to_tagged_bytes_with(&OrderContainer::V1("second".to_owned()), &options)
+            .unwrap();
+        assert_eq!(sequencer.next_sequence(), 43);
+
+        assert_eq!(get_sequence_from_tagged_bytes(&first).unwrap(), Some(41));
+        assert_eq!(get_sequence_from_tagged_bytes(&second).unwrap(), Some(42));
+        assert_eq!(get_timestamp_from_tagged_bytes(&second).unwrap(), Some(5));
+        assert_eq!(get_user_word_from_tagged_bytes(&second).unwrap(), None);
+        assert_eq!(
+            get_sequence_from_tagged_bytes(
+                &to_tagged_bytes(&OrderContainer::V1(String::new())).unwrap()
+            )
+            .unwrap(),
+            None
+        );
+        match access_from_tagged_bytes::<OrderContainer>(&second).unwrap() {
+            ArchivedOrderContainer::V1(order) => assert_eq!(order, "second"),
+        }
+
+        // The sequence number is read from the front of the record alone
+        assert!(matches!(
+            get_sequence_from_tagged_bytes(&first[..TAGGED_HEADER_SIZE + WORDS_SIZE]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        assert_eq!(
+            get_sequence_from_tagged_bytes(
+                &first[..TAGGED_HEADER_SIZE + SEQUENCED_WORDS_SIZE]
+            )
+            .unwrap(),
+            Some(41)
+        );
+    }
+
+    #[test]
+    fn test_sequence_tracker() {
+        let mut sequencer = Sequencer::new(10);
+        let records: Vec<AlignedVec> = (0..6)
+            .map(|i| {
+                sequencer
+                    .to_tagged_bytes_with(
+                        &OrderContainer::V1(format!("order-{}", i)),
+                        &TaggedOptions::default(),
+                    )
+                    .unwrap()
+            })
+            .collect();
+
+        // 12 and 13 are delayed, 14 is delivered twice
+        let mut tracker = SequenceTracker::new();
+        let mut events = Vec::new();
+        for index in [0, 1, 4, 3, 4, 5, 2] {
+            events.push(tracker.check(&records[index]).unwrap().unwrap());
+        }
+        assert_eq!(
+            events,
+            [
+                SequenceEvent::InOrder,
+                SequenceEvent::InOrder,
+                SequenceEvent::Gap(12..14),
+                SequenceEvent::Late(13),
+                SequenceEvent::Duplicate(14),
+                SequenceEvent::InOrder,
+                SequenceEvent::Late(12),
+            ]
+        );
+        assert!(tracker.missing().is_empty());
+        assert_eq!(tracker.next_sequence(), Some(16));
+
+        // Losses in the middle of a gap leave both sides missing
+        let mut tracker = SequenceTracker::starting_at(0);
+        assert_eq!(tracker.observe(10), SequenceEvent::Gap(0..10));
+        assert_eq!(tracker.observe(4), SequenceEvent::Late(4));
+        assert_eq!(tracker.missing(), [0..4, 5..10]);
+        assert_eq!(tracker.observe(4), SequenceEvent::Duplicate(4));
+
+        let unsequenced = to_tagged_bytes(&OrderContainer::V1(String::new())).unwrap();
+        assert_eq!(tracker.check(&unsequenced).unwrap(), None);
+    }
+}
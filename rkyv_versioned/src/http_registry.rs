@@ -0,0 +1,395 @@
+//! A [SchemaResolver] backed by an HTTP schema registry, enabled with the `schema_registry`
+//! feature.
+//!
+//! [HttpSchemaRegistry] speaks a minimal protocol over plain HTTP, which an organization's
+//! registry service (or a proxy in front of it) implements:
+//!
+//! - `GET {base}/types/{type_id}` returns `200` with the [TypeSchema] of the type as JSON, or
+//!   `404` if the registry doesn't know it.
+//! - `PUT {base}/types/{type_id}` with the JSON of a [TypeSchema] as the body registers it,
+//!   answering with any `2xx` status.
+//!
+//! where a schema is encoded as
+//!
+//! `{"type_id": 1, "name": "Event", "versions": [{"version_id": 1, "name": "V1",`
+//! `"schema_hash": 2, "deprecated": false}]}`
+//!
+//! Resolved schemas are cached for a configurable time, so checks on hot paths don't make a
+//! request per record.  Requests are made with HTTP/1.0 and one connection each, TLS is left
+//! to a local proxy or sidecar.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::{
+    RkyvVersionedError, SchemaResolver, SchemaVersion, TypeSchema, VersionedContainer,
+};
+
+/// The time resolved schemas are cached for by default.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// A [SchemaResolver] fetching schemas from an HTTP schema registry, see the
+/// [module](self) documentation for the protocol.
+#[derive(Debug)]
+pub struct HttpSchemaRegistry {
+    host: String,
+    base_path: String,
+    timeout: Option<Duration>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<u32, (Instant, TypeSchema)>>,
+}
+
+impl HttpSchemaRegistry {
+    /// Creates a client of the registry at `url`, e.g. `http://registry.internal:8080/schemas`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the client, or [RkyvVersionedError::InvalidMessageError] if `url`
+    /// isn't an `http://` URL.
+    pub fn new(url: &str) -> Result<Self, RkyvVersionedError> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or(RkyvVersionedError::InvalidMessageError)?;
+        let (host, base_path) = match rest.find('/') {
+            Some(index) => rest.split_at_checked(index).unwrap_or((rest, "")),
+            None => (rest, ""),
+        };
+        if host.is_empty() {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        let host = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{}:80", host),
+        };
+        Ok(HttpSchemaRegistry {
+            host,
+            base_path: base_path.trim_end_matches('/').to_string(),
+            timeout: Some(Duration::from_secs(5)),
+            cache_ttl: DEFAULT_CACHE_TTL,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets the timeout for connecting to the registry and for each read and write, or `None`
+    /// to wait indefinitely.  Defaults to 5 seconds.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how long resolved schemas are cached for, or [Duration::ZERO] to always ask the
+    /// registry.  Defaults to 5 minutes.
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Forgets every cached schema.
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// Registers `schema` with the registry, replacing any cached schema of its type ID.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is a [RkyvVersionedError::TransportError] if the registry can't be
+    /// reached or refuses the schema.
+    pub fn register(&self, schema: &TypeSchema) -> Result<(), RkyvVersionedError> {
+        let body = schema_to_json(schema).to_string();
+        let (status, _) = self.request("PUT", schema.type_id, Some(&body))?;
+        if !(200..300).contains(&status) {
+            return Err(transport_error(format!(
+                "registry answered {} registering type {:08x}",
+                status, schema.type_id
+            )));
+        }
+        self.cache()
+            .insert(schema.type_id, (Instant::now(), schema.clone()));
+        Ok(())
+    }
+
+    /// Registers `T` under `name`, with the versions of its
+    /// [VERSION_TABLE](VersionedContainer::VERSION_TABLE).
+    pub fn register_container<T: VersionedContainer>(
+        &self,
+        name: &str,
+    ) -> Result<(), RkyvVersionedError> {
+        self.register(&TypeSchema {
+            type_id: T::ARCHIVE_TYPE_ID,
+            name: name.to_string(),
+            versions: T::VERSION_TABLE.iter().map(SchemaVersion::from).collect(),
+        })
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<u32, (Instant, TypeSchema)>> {
+        // A panic while holding the lock can't leave the cache inconsistent
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Makes a request for the type `type_id`, returning the status and body of the response.
+    fn request(
+        &self,
+        method: &str,
+        type_id: u32,
+        body: Option<&str>,
+    ) -> Result<(u16, Vec<u8>), RkyvVersionedError> {
+        let mut stream = match self.timeout {
+            Some(timeout) => {
+                let addr = self
+                    .host
+                    .to_socket_addrs()
+                    .map_err(|e| RkyvVersionedError::TransportError(Box::new(e)))?
+                    .next()
+                    .ok_or_else(|| transport_error(format!("can't resolve {}", self.host)))?;
+                TcpStream::connect_timeout(&addr, timeout)
+            }
+            None => TcpStream::connect(&self.host),
+        }
+        .map_err(|e| RkyvVersionedError::TransportError(Box::new(e)))?;
+
+        let result = (|| -> io::Result<Vec<u8>> {
+            stream.set_read_timeout(self.timeout)?;
+            stream.set_write_timeout(self.timeout)?;
+            let body = body.unwrap_or_default();
+            write!(
+                stream,
+                "{} {}/types/{} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\
+                 Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                method,
+                self.base_path,
+                type_id,
+                self.host,
+                body.len(),
+                body
+            )?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            Ok(response)
+        })();
+        let response = result.map_err(|e| RkyvVersionedError::TransportError(Box::new(e)))?;
+        parse_response(&response)
+    }
+}
+
+impl SchemaResolver for HttpSchemaRegistry {
+    fn resolve(&self, type_id: u32) -> Result<Option<TypeSchema>, RkyvVersionedError> {
+        if let Some((fetched, schema)) = self.cache().get(&type_id) {
+            if fetched.elapsed() < self.cache_ttl {
+                return Ok(Some(schema.clone()));
+            }
+        }
+
+        let (status, body) = self.request("GET", type_id, None)?;
+        let schema = match status {
+            200 => schema_from_json(&body)?,
+            404 => return Ok(None),
+            _ => {
+                return Err(transport_error(format!(
+                    "registry answered {} fetching type {:08x}",
+                    status, type_id
+                )))
+            }
+        };
+        if schema.type_id != type_id {
+            return Err(transport_error(format!(
+                "registry answered type {:08x} fetching type {:08x}",
+                schema.type_id, type_id
+            )));
+        }
+        self.cache()
+            .insert(type_id, (Instant::now(), schema.clone()));
+        Ok(Some(schema))
+    }
+}
+
+/// Wraps a message describing a misbehaving registry in a
+/// [RkyvVersionedError::TransportError].
+fn transport_error(message: String) -> RkyvVersionedError {
+    RkyvVersionedError::TransportError(message.into())
+}
+
+/// Splits an HTTP response into its status code and body.
+fn parse_response(response: &[u8]) -> Result<(u16, Vec<u8>), RkyvVersionedError> {
+    let malformed = || transport_error("malformed response from registry".to_string());
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let (head, body) = response
+        .split_at_checked(header_end)
+        .ok_or_else(malformed)?;
+    let status_line = head
+        .split(|byte| *byte == b'\n')
+        .next()
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .ok_or_else(malformed)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(malformed)?;
+    Ok((status, body.get(4..).unwrap_or_default().to_vec()))
+}
+
+/// Encodes `schema` as JSON.
+fn schema_to_json(schema: &TypeSchema) -> Value {
+    json!({
+        "type_id": schema.type_id,
+        "name": schema.name,
+        "versions": schema.versions.iter().map(|version| json!({
+            "version_id": version.version_id,
+            "name": version.name,
+            "schema_hash": version.schema_hash,
+            "deprecated": version.deprecated,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// Decodes a schema encoded by [schema_to_json].
+fn schema_from_json(body: &[u8]) -> Result<TypeSchema, RkyvVersionedError> {
+    let malformed = || transport_error("malformed schema from registry".to_string());
+    let value: Value = serde_json::from_slice(body).map_err(|_| malformed())?;
+    let read_u32 = |value: &Value, key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_u64)
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(malformed)
+    };
+    let read_str = |value: &Value, key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(malformed)
+    };
+
+    let versions = value
+        .get("versions")
+        .and_then(Value::as_array)
+        .ok_or_else(malformed)?
+        .iter()
+        .map(|version| {
+            Ok(SchemaVersion {
+                version_id: read_u32(version, "version_id")?,
+                name: read_str(version, "name")?,
+                schema_hash: read_u32(version, "schema_hash")?,
+                deprecated: version
+                    .get("deprecated")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            })
+        })
+        .collect::<Result<_, RkyvVersionedError>>()?;
+    Ok(TypeSchema {
+        type_id: read_u32(&value, "type_id")?,
+        name: read_str(&value, "name")?,
+        versions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionedArchiveContainer;
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Invoice {
+        V1(u64),
+        V2(String),
+    }
+
+    /// Serves the registry protocol from memory, counting the requests made.
+    fn serve(listener: TcpListener, requests: Arc<AtomicUsize>) {
+        let mut schemas = HashMap::<String, Vec<u8>>::new();
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            requests.fetch_add(1, Ordering::SeqCst);
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            let (head, body) = loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    let head = String::from_utf8(request[..end].to_vec()).unwrap();
+                    let len: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    while request.len() < end + 4 + len {
+                        let n = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    break (head, request[end + 4..].to_vec());
+                }
+            };
+            let mut parts = head.split_whitespace();
+            let (method, path) = (parts.next().unwrap(), parts.next().unwrap().to_string());
+            let response = match (method, schemas.get(&path)) {
+                ("PUT", _) => {
+                    schemas.insert(path, body);
+                    "HTTP/1.0 204 No Content\r\n\r\n".as_bytes().to_vec()
+                }
+                ("GET", Some(schema)) => {
+                    let mut response = b"HTTP/1.0 200 OK\r\n\r\n".to_vec();
+                    response.extend_from_slice(schema);
+                    response
+                }
+                _ => b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec(),
+            };
+            stream.write_all(&response).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_http_schema_registry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/registry/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server_requests = requests.clone();
+        std::thread::spawn(move || serve(listener, server_requests));
+
+        let publisher = HttpSchemaRegistry::new(&url).unwrap();
+        publisher.register_container::<Invoice>("Invoice").unwrap();
+
+        let reader = HttpSchemaRegistry::new(&url).unwrap();
+        let schema = reader.resolve(Invoice::ARCHIVE_TYPE_ID).unwrap().unwrap();
+        assert_eq!(schema.name, "Invoice");
+        assert_eq!(
+            schema.versions,
+            Invoice::VERSION_TABLE
+                .iter()
+                .map(SchemaVersion::from)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Served from the cache, then fetched again once cleared
+        assert_eq!(
+            reader
+                .type_name(Invoice::ARCHIVE_TYPE_ID)
+                .unwrap()
+                .as_deref(),
+            Some("Invoice")
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        reader.clear_cache();
+        reader.resolve(Invoice::ARCHIVE_TYPE_ID).unwrap().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        assert!(reader.resolve(0xdead_beef).unwrap().is_none());
+        assert!(HttpSchemaRegistry::new("https://registry.internal").is_err());
+    }
+}
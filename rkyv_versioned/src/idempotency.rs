@@ -0,0 +1,160 @@
+//! Idempotency keys for dropping redelivered records.
+//!
+//! At-least-once transports redeliver records after a timeout or a reconnect.  A producer
+//! sets a key identifying the logical operation with [set_idempotency_key], stored in the
+//! [HeaderExtensions] under [EXTENSION_KEY_IDEMPOTENCY], and a consumer keeps a
+//! [DedupWindow] of the keys it has processed recently, so that it can drop repeats without
+//! an external store.  Keys are read without knowing the container type and before the
+//! payload is validated.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{get_extensions_from_tagged_bytes, HeaderExtensions, RkyvVersionedError};
+
+/// The extension key holding the idempotency key of a record.
+pub const EXTENSION_KEY_IDEMPOTENCY: u16 = 0xff04;
+
+/// Sets the idempotency key stored in `extensions`, e.g. a request ID or the hash of a
+/// client-generated operation ID.
+///
+/// Fails with [RkyvVersionedError::InvalidExtensionError] if `key` is longer than
+/// `u16::MAX` bytes.
+pub fn set_idempotency_key(
+    extensions: &mut HeaderExtensions,
+    key: &[u8],
+) -> Result<(), RkyvVersionedError> {
+    extensions.insert(EXTENSION_KEY_IDEMPOTENCY, key)
+}
+
+/// Reads the idempotency key of a tagged byte array without accessing the payload.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the key, `None` if none was written, or an error if the buffer is
+/// truncated or the extension area is malformed.
+pub fn get_idempotency_key_from_tagged_bytes(
+    buf: &[u8],
+) -> Result<Option<Vec<u8>>, RkyvVersionedError> {
+    Ok(get_extensions_from_tagged_bytes(buf)?.remove(EXTENSION_KEY_IDEMPOTENCY))
+}
+
+/// The idempotency keys most recently processed by a consumer.
+///
+/// The window holds a bounded number of keys and forgets the oldest first, so it must cover
+/// at least as many records as can be redelivered, e.g. the consumer's in-flight limit or the
+/// records received within the transport's redelivery timeout.
+#[derive(Debug, Clone)]
+pub struct DedupWindow {
+    capacity: usize,
+    keys: HashSet<Vec<u8>>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl DedupWindow {
+    /// Creates a window remembering up to `capacity` keys.
+    pub fn new(capacity: usize) -> Self {
+        DedupWindow {
+            capacity,
+            keys: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of keys remembered.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns whether no key is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Records `key` as processed.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key is new and the record should be processed, or `false` if it is in
+    /// the window and the record should be dropped.
+    pub fn insert(&mut self, key: &[u8]) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.keys.contains(key) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        self.keys.insert(key.to_vec());
+        self.order.push_back(key.to_vec());
+        true
+    }
+
+    /// Records the idempotency key of a tagged byte array as processed, see
+    /// [insert](Self::insert).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `true` if the record should be processed, including when it has no
+    /// idempotency key, or `false` if it is a redelivery, or an error if the buffer is
+    /// truncated or the extension area is malformed.
+    pub fn check(&mut self, buf: &[u8]) -> Result<bool, RkyvVersionedError> {
+        Ok(match get_idempotency_key_from_tagged_bytes(buf)? {
+            Some(key) => self.insert(&key),
+            None => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        to_tagged_bytes, to_tagged_bytes_with, TaggedOptions, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Payment {
+        V1(u64),
+    }
+
+    fn payment(request_id: &str, amount: u64) -> rkyv::util::AlignedVec {
+        let mut options = TaggedOptions::default();
+        set_idempotency_key(&mut options.extensions, request_id.as_bytes()).unwrap();
+        to_tagged_bytes_with(&Payment::V1(amount), &options).unwrap()
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let first = payment("req-1", 10);
+        assert_eq!(
+            get_idempotency_key_from_tagged_bytes(&first).unwrap(),
+            Some(b"req-1".to_vec())
+        );
+
+        let mut window = DedupWindow::new(2);
+        assert!(window.check(&first).unwrap());
+        assert!(!window.check(&first).unwrap());
+        assert!(window.check(&payment("req-2", 20)).unwrap());
+        assert!(!window.check(&payment("req-1", 10)).unwrap());
+
+        // The oldest key is forgotten once the window is full
+        assert!(window.check(&payment("req-3", 30)).unwrap());
+        assert_eq!(window.len(), 2);
+        assert!(window.check(&first).unwrap());
+
+        // Records without a key are always processed
+        let plain = to_tagged_bytes(&Payment::V1(1)).unwrap();
+        assert_eq!(get_idempotency_key_from_tagged_bytes(&plain).unwrap(), None);
+        assert!(window.check(&plain).unwrap());
+        assert!(window.check(&plain).unwrap());
+    }
+}
@@ -0,0 +1,705 @@
+//! Checking derived IDs against a checked-in manifest, enabled with the `ids` feature.
+//!
+//! Type and version IDs are derived from names and declaration order, so renaming a container
+//! or reordering its variants silently changes what is written to disk.  In a workspace of
+//! many crates it's also easy for two containers to end up with the same type ID.  Recording
+//! the IDs in a manifest and verifying it from a test catches both:
+//!
+//! ```no_run
+//! use rkyv::{Archive, Deserialize, Serialize};
+//! use rkyv_versioned::ids::{verify_manifest, ContainerIds};
+//! use rkyv_versioned::VersionedArchiveContainer;
+//!
+//! #[derive(Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+//! enum Setting {
+//!     V1(u32),
+//!     V2(String),
+//! }
+//!
+//! #[test]
+//! fn ids_are_stable() {
+//!     verify_manifest("ids.toml", &[ContainerIds::of::<Setting>()]).unwrap();
+//! }
+//! ```
+//!
+//! The manifest has a table per container, keyed by its name:
+//!
+//! ```toml
+//! [Setting]
+//! type_id = 0x5ec1b2c4
+//! versions = [
+//!     { id = 0, name = "V1", schema_hash = 0x3d5e2a0b },
+//!     { id = 1, name = "V2", schema_hash = 0x8a1c9f47 },
+//! ]
+//! ```
+//!
+//! [render_manifest] generates it.  Crates sharing a manifest only need to pass their own
+//! containers, entries for other containers are used to detect type ID collisions.
+//!
+//! Versions of containers deriving with `#[vac(fingerprint)]` also record their
+//! `fingerprint`.
+//!
+//! A manifest can also describe what is in a store rather than what the code defines, in which
+//! case [assert_readable_by] checks at startup that the running binary can read all of it.
+//!
+//! Release automation can compare the manifests of two builds with [wire_semver_check], which
+//! classifies the change to the wire format as a [WireChange], e.g. to block a release bumping
+//! the minor version which breaks reading stored data.
+
+use core::fmt;
+use std::path::Path;
+
+use crate::{VersionInfo, VersionedContainer};
+
+/// The IDs of one versioned container, as checked by [verify_manifest].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerIds {
+    /// The name of the container, without its module path or generics.
+    pub name: &'static str,
+    /// The [ARCHIVE_TYPE_ID](VersionedContainer::ARCHIVE_TYPE_ID) of the container.
+    pub type_id: u32,
+    /// The [VERSION_TABLE](VersionedContainer::VERSION_TABLE) of the container.
+    pub versions: &'static [VersionInfo],
+}
+
+impl ContainerIds {
+    /// Returns the IDs of `T`.
+    pub fn of<T: VersionedContainer>() -> Self {
+        let full_name = core::any::type_name::<T>();
+        let path = full_name.split('<').next().unwrap_or(full_name);
+        ContainerIds {
+            name: path.rsplit("::").next().unwrap_or(path),
+            type_id: T::ARCHIVE_TYPE_ID,
+            versions: T::VERSION_TABLE,
+        }
+    }
+}
+
+/// The errors returned by [verify_manifest].
+#[derive(Debug)]
+pub enum IdManifestError {
+    /// The manifest could not be read.
+    Io(std::io::Error),
+    /// The manifest is not valid TOML or doesn't have the expected structure.
+    Parse(String),
+    /// The IDs don't match the manifest, with one message per difference.
+    Mismatch(Vec<String>),
+}
+
+impl std::error::Error for IdManifestError {}
+impl fmt::Display for IdManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdManifestError::Io(e) => write!(f, "Could not read the ID manifest: {}", e),
+            IdManifestError::Parse(e) => write!(f, "Invalid ID manifest: {}", e),
+            IdManifestError::Mismatch(differences) => {
+                write!(f, "IDs do not match the manifest:")?;
+                for difference in differences {
+                    write!(f, "\n- {}", difference)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// One container as recorded in a manifest.
+struct ManifestEntry {
+    name: String,
+    type_id: u32,
+    versions: Vec<ManifestVersion>,
+}
+
+/// One version of a container as recorded in a manifest.
+struct ManifestVersion {
+    id: u32,
+    name: String,
+    schema_hash: u32,
+    fingerprint: Option<u32>,
+}
+
+/// Reads a `u32` field from a manifest table.
+fn read_u32(table: &toml::Table, key: &str, context: &str) -> Result<u32, IdManifestError> {
+    table
+        .get(key)
+        .and_then(toml::Value::as_integer)
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or_else(|| IdManifestError::Parse(format!("{} has no valid `{}`", context, key)))
+}
+
+/// Parses the entries of a manifest.
+fn parse_manifest(manifest: &str) -> Result<Vec<ManifestEntry>, IdManifestError> {
+    let table: toml::Table = manifest
+        .parse()
+        .map_err(|e: toml::de::Error| IdManifestError::Parse(e.to_string()))?;
+
+    let mut entries = Vec::with_capacity(table.len());
+    for (name, value) in table {
+        let container = value
+            .as_table()
+            .ok_or_else(|| IdManifestError::Parse(format!("{} is not a table", name)))?;
+        let type_id = read_u32(container, "type_id", &name)?;
+        let versions = container
+            .get("versions")
+            .and_then(toml::Value::as_array)
+            .ok_or_else(|| IdManifestError::Parse(format!("{} has no `versions`", name)))?
+            .iter()
+            .map(|version| {
+                let context = format!("a version of {}", name);
+                let version = version.as_table().ok_or_else(|| {
+                    IdManifestError::Parse(format!("{} is not a table", context))
+                })?;
+                let version_name = version
+                    .get("name")
+                    .and_then(toml::Value::as_str)
+                    .ok_or_else(|| {
+                        IdManifestError::Parse(format!("{} has no `name`", context))
+                    })?;
+                Ok(ManifestVersion {
+                    id: read_u32(version, "id", &context)?,
+                    name: version_name.to_string(),
+                    schema_hash: read_u32(version, "schema_hash", &context)?,
+                    fingerprint: match version.contains_key("fingerprint") {
+                        true => Some(read_u32(version, "fingerprint", &context)?),
+                        false => None,
+                    },
+                })
+            })
+            .collect::<Result<_, IdManifestError>>()?;
+        entries.push(ManifestEntry {
+            name,
+            type_id,
+            versions,
+        });
+    }
+    Ok(entries)
+}
+
+/// Checks that `container` can still read every version recorded in `entry`.
+fn check_recorded_versions(
+    entry: &ManifestEntry,
+    container: &ContainerIds,
+    differences: &mut Vec<String>,
+) {
+    let name = container.name;
+    if entry.type_id != container.type_id {
+        differences.push(format!(
+            "{} type_id changed from {:#010x} to {:#010x}",
+            name, entry.type_id, container.type_id
+        ));
+    }
+
+    for recorded in &entry.versions {
+        let (id, version_name) = (recorded.id, &recorded.name);
+        match container.versions.iter().find(|v| v.version_id == id) {
+            None => differences.push(format!(
+                "{} version {} ({}) was removed",
+                name, id, version_name
+            )),
+            Some(version) if version.name != version_name => differences.push(format!(
+                "{} version {} was renamed from {} to {}, or variants were reordered",
+                name, id, version_name, version.name
+            )),
+            Some(version) if version.schema_hash != recorded.schema_hash => differences.push(
+                format!("{} version {} ({}) changed type", name, id, version_name),
+            ),
+            Some(version)
+                if matches!(
+                    (version.fingerprint, recorded.fingerprint),
+                    (Some(current), Some(recorded)) if current != recorded
+                ) =>
+            {
+                differences.push(format!(
+                    "{} version {} ({}) changed layout",
+                    name, id, version_name
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Renders a manifest recording the IDs of `containers`, to be checked in and verified with
+/// [verify_manifest].
+pub fn render_manifest(containers: &[ContainerIds]) -> String {
+    let mut manifest = String::new();
+    for (index, container) in containers.iter().enumerate() {
+        if index > 0 {
+            manifest.push('\n');
+        }
+        manifest.push_str(&format!(
+            "[{}]\ntype_id = {:#010x}\nversions = [\n",
+            container.name, container.type_id
+        ));
+        for version in container.versions {
+            manifest.push_str(&format!(
+                "    {{ id = {}, name = \"{}\", schema_hash = {:#010x}",
+                version.version_id, version.name, version.schema_hash
+            ));
+            if let Some(fingerprint) = version.fingerprint {
+                manifest.push_str(&format!(", fingerprint = {:#010x}", fingerprint));
+            }
+            manifest.push_str(" },\n");
+        }
+        manifest.push_str("]\n");
+    }
+    manifest
+}
+
+/// Checks the IDs of `containers` against the manifest at `path`, see the [module](self)
+/// documentation.
+///
+/// Every container must be in the manifest with the same type ID, every version recorded in
+/// the manifest must still exist with the same name and schema hash, new versions must have
+/// been added to the manifest, and no two containers may share a type ID.
+///
+/// # Arguments
+///
+/// * `path` - The path of the manifest, relative to the working directory (which is the
+///   crate root when running tests).
+/// * `containers` - The containers to check, see [ContainerIds::of].
+///
+/// # Returns
+///
+/// A `Result` which is [IdManifestError::Mismatch] listing every difference if the IDs don't
+/// match the manifest.
+pub fn verify_manifest(
+    path: impl AsRef<Path>,
+    containers: &[ContainerIds],
+) -> Result<(), IdManifestError> {
+    let manifest = std::fs::read_to_string(path).map_err(IdManifestError::Io)?;
+    verify_manifest_str(&manifest, containers)
+}
+
+/// Checks the IDs of `containers` against the contents of a manifest, like
+/// [verify_manifest].
+pub fn verify_manifest_str(
+    manifest: &str,
+    containers: &[ContainerIds],
+) -> Result<(), IdManifestError> {
+    let entries = parse_manifest(manifest)?;
+    let mut differences = Vec::new();
+
+    for (index, container) in containers.iter().enumerate() {
+        let name = container.name;
+
+        // Collisions with other containers, whether they're recorded or being checked
+        let colliding = entries
+            .iter()
+            .filter(|entry| entry.name != name && entry.type_id == container.type_id)
+            .map(|entry| entry.name.as_str())
+            .chain(
+                containers
+                    .iter()
+                    .take(index)
+                    .filter(|other| other.name != name && other.type_id == container.type_id)
+                    .map(|other| other.name),
+            );
+        for other in colliding {
+            differences.push(format!(
+                "{} and {} share type_id {:#010x}",
+                name, other, container.type_id
+            ));
+        }
+
+        let Some(entry) = entries.iter().find(|entry| entry.name == name) else {
+            differences.push(format!("{} is not in the manifest", name));
+            continue;
+        };
+        check_recorded_versions(entry, container, &mut differences);
+        for version in container.versions {
+            if !entry
+                .versions
+                .iter()
+                .any(|recorded| recorded.id == version.version_id)
+            {
+                differences.push(format!(
+                    "{} version {} ({}) is not in the manifest",
+                    name, version.version_id, version.name
+                ));
+            }
+        }
+    }
+
+    match differences.is_empty() {
+        true => Ok(()),
+        false => Err(IdManifestError::Mismatch(differences)),
+    }
+}
+
+/// Checks that `T`, as compiled into the running binary, can read every version of it
+/// recorded in the manifest at `path`.
+///
+/// This is the read side of [verify_manifest], for a service which keeps a manifest of what
+/// is in its store and wants to verify at startup that it was built with container
+/// definitions able to read all of it.  Versions `T` has added since the manifest was written
+/// are fine, as are other containers in the manifest, and a manifest without `T` means there
+/// is nothing of it to read.
+///
+/// # Arguments
+///
+/// * `path` - The path of the store's manifest.
+///
+/// # Returns
+///
+/// A `Result` which is [IdManifestError::Mismatch] listing every recorded version `T` can't
+/// read, because it was removed, renamed or changed type, or because the type ID changed.
+pub fn check_readable_by<T: VersionedContainer>(
+    path: impl AsRef<Path>,
+) -> Result<(), IdManifestError> {
+    let manifest = std::fs::read_to_string(path).map_err(IdManifestError::Io)?;
+    check_readable_by_str::<T>(&manifest)
+}
+
+/// Checks that `T` can read every version of it recorded in the contents of a manifest, like
+/// [check_readable_by].
+pub fn check_readable_by_str<T: VersionedContainer>(
+    manifest: &str,
+) -> Result<(), IdManifestError> {
+    let container = ContainerIds::of::<T>();
+    let entries = parse_manifest(manifest)?;
+    let mut differences = Vec::new();
+    if let Some(entry) = entries.iter().find(|entry| entry.name == container.name) {
+        check_recorded_versions(entry, &container, &mut differences);
+    }
+
+    match differences.is_empty() {
+        true => Ok(()),
+        false => Err(IdManifestError::Mismatch(differences)),
+    }
+}
+
+/// Panics unless `T` can read every version of it recorded in the manifest at `path`, see
+/// [check_readable_by].
+///
+/// Meant to be called at startup, so that a service built with incompatible container
+/// definitions fails before reading anything.
+#[track_caller]
+#[allow(clippy::panic)]
+pub fn assert_readable_by<T: VersionedContainer>(path: impl AsRef<Path>) {
+    if let Err(e) = check_readable_by::<T>(path) {
+        panic!("{}", e);
+    }
+}
+
+/// How much the wire format changed between two manifests, ordered from the least to the
+/// most severe, see [wire_semver_check].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WireChange {
+    /// Nothing stored data depends on changed.
+    #[default]
+    Patch,
+    /// Containers or versions were added, or started or stopped recording fingerprints.
+    /// Everything written before can still be read, but records of the new versions can't be
+    /// read by the old build.
+    Minor,
+    /// Data written by the old build can't be read by the new one, because a container or
+    /// version was removed, a type ID changed, variants were renamed or reordered, a version
+    /// changed type or layout, or two containers now share a type ID.
+    Major,
+}
+
+impl fmt::Display for WireChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireChange::Patch => write!(f, "patch"),
+            WireChange::Minor => write!(f, "minor"),
+            WireChange::Major => write!(f, "major"),
+        }
+    }
+}
+
+/// The changes to the wire format between two manifests, see [wire_semver_check].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WireSemverReport {
+    /// The most severe change, [WireChange::Patch] if nothing changed.
+    pub change: WireChange,
+    /// Every change found, with its classification.
+    pub changes: Vec<(WireChange, String)>,
+}
+
+impl WireSemverReport {
+    /// Returns whether a release bumping the version by `bump` covers the changes, e.g.
+    /// `report.allows(WireChange::Minor)` for a minor release.
+    pub fn allows(&self, bump: WireChange) -> bool {
+        self.change <= bump
+    }
+
+    fn push(&mut self, change: WireChange, description: String) {
+        self.change = self.change.max(change);
+        self.changes.push((change, description));
+    }
+}
+
+/// Compares the manifests of two builds, rendered with [render_manifest], and classifies the
+/// change to the wire format like a semver bump.
+///
+/// # Arguments
+///
+/// * `old_manifest` - The path of the manifest of the previous release.
+/// * `new_manifest` - The path of the manifest of the build to release.
+///
+/// # Returns
+///
+/// A `Result` containing a report of the changes, or an error if a manifest can't be read or
+/// parsed.
+pub fn wire_semver_check(
+    old_manifest: impl AsRef<Path>,
+    new_manifest: impl AsRef<Path>,
+) -> Result<WireSemverReport, IdManifestError> {
+    let old_manifest = std::fs::read_to_string(old_manifest).map_err(IdManifestError::Io)?;
+    let new_manifest = std::fs::read_to_string(new_manifest).map_err(IdManifestError::Io)?;
+    wire_semver_check_str(&old_manifest, &new_manifest)
+}
+
+/// Compares the contents of two manifests, like [wire_semver_check].
+pub fn wire_semver_check_str(
+    old_manifest: &str,
+    new_manifest: &str,
+) -> Result<WireSemverReport, IdManifestError> {
+    let (old, new) = (parse_manifest(old_manifest)?, parse_manifest(new_manifest)?);
+    let mut report = WireSemverReport::default();
+
+    for old_entry in &old {
+        let name = &old_entry.name;
+        let Some(new_entry) = new.iter().find(|entry| entry.name == *name) else {
+            report.push(WireChange::Major, format!("{} was removed", name));
+            continue;
+        };
+        if old_entry.type_id != new_entry.type_id {
+            report.push(
+                WireChange::Major,
+                format!(
+                    "{} type_id changed from {:#010x} to {:#010x}",
+                    name, old_entry.type_id, new_entry.type_id
+                ),
+            );
+        }
+        for old_version in &old_entry.versions {
+            let (id, version_name) = (old_version.id, &old_version.name);
+            let Some(new_version) = new_entry.versions.iter().find(|v| v.id == id) else {
+                report.push(
+                    WireChange::Major,
+                    format!("{} version {} ({}) was removed", name, id, version_name),
+                );
+                continue;
+            };
+            if new_version.name != *version_name {
+                report.push(
+                    WireChange::Major,
+                    format!(
+                        "{} version {} was renamed from {} to {}, or variants were reordered",
+                        name, id, version_name, new_version.name
+                    ),
+                );
+            } else if new_version.schema_hash != old_version.schema_hash {
+                report.push(
+                    WireChange::Major,
+                    format!("{} version {} ({}) changed type", name, id, version_name),
+                );
+            }
+            match (old_version.fingerprint, new_version.fingerprint) {
+                (Some(old), Some(new)) if old != new => report.push(
+                    WireChange::Major,
+                    format!("{} version {} ({}) changed layout", name, id, version_name),
+                ),
+                (None, Some(_)) => report.push(
+                    WireChange::Minor,
+                    format!(
+                        "{} version {} ({}) started recording its fingerprint",
+                        name, id, version_name
+                    ),
+                ),
+                (Some(_), None) => report.push(
+                    WireChange::Minor,
+                    format!(
+                        "{} version {} ({}) stopped recording its fingerprint",
+                        name, id, version_name
+                    ),
+                ),
+                _ => {}
+            }
+        }
+        for new_version in &new_entry.versions {
+            if !old_entry.versions.iter().any(|v| v.id == new_version.id) {
+                report.push(
+                    WireChange::Minor,
+                    format!(
+                        "{} version {} ({}) was added",
+                        name, new_version.id, new_version.name
+                    ),
+                );
+            }
+        }
+    }
+
+    for (index, new_entry) in new.iter().enumerate() {
+        if !old.iter().any(|entry| entry.name == new_entry.name) {
+            report.push(WireChange::Minor, format!("{} was added", new_entry.name));
+        }
+        for other in new
+            .iter()
+            .take(index)
+            .filter(|other| other.type_id == new_entry.type_id)
+        {
+            report.push(
+                WireChange::Major,
+                format!(
+                    "{} and {} share type_id {:#010x}",
+                    other.name, new_entry.name, new_entry.type_id
+                ),
+            );
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionedArchiveContainer;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Setting {
+        V1(u32),
+        V2(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Profile {
+        V1(String),
+    }
+
+    fn mismatches(manifest: &str, containers: &[ContainerIds]) -> Vec<String> {
+        match verify_manifest_str(manifest, containers) {
+            Err(IdManifestError::Mismatch(differences)) => differences,
+            other => panic!("Expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let containers = [ContainerIds::of::<Setting>(), ContainerIds::of::<Profile>()];
+        assert_eq!(containers[0].name, "Setting");
+
+        let manifest = render_manifest(&containers);
+        verify_manifest_str(&manifest, &containers).unwrap();
+        // Crates sharing the manifest only check their own containers
+        verify_manifest_str(&manifest, &containers[1..]).unwrap();
+
+        // A version was appended without updating the manifest
+        let outdated = manifest.replace(
+            &format!(
+                "    {{ id = 1, name = \"V2\", schema_hash = {:#010x} }},\n",
+                Setting::VERSION_TABLE[1].schema_hash
+            ),
+            "",
+        );
+        assert_eq!(
+            mismatches(&outdated, &containers),
+            ["Setting version 1 (V2) is not in the manifest"]
+        );
+
+        // Variants were reordered
+        let reordered = manifest.replace("\"V1\"", "\"V0\"");
+        assert_eq!(mismatches(&reordered, &containers).len(), 2);
+
+        // The container was renamed, and its old ID is still recorded under the new name
+        let renamed = manifest.replace("[Profile]", "[OldProfile]");
+        assert_eq!(
+            mismatches(&renamed, &containers),
+            [
+                format!(
+                    "Profile and OldProfile share type_id {:#010x}",
+                    Profile::ARCHIVE_TYPE_ID
+                ),
+                "Profile is not in the manifest".to_string(),
+            ]
+        );
+
+        assert!(matches!(
+            verify_manifest_str("[Setting]\ntype_id = \"nope\"", &containers),
+            Err(IdManifestError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_wire_semver_check() {
+        let old = render_manifest(&[ContainerIds {
+            versions: &Setting::VERSION_TABLE[..1],
+            ..ContainerIds::of::<Setting>()
+        }]);
+        let report = wire_semver_check_str(&old, &old).unwrap();
+        assert_eq!(report, WireSemverReport::default());
+        assert!(report.allows(WireChange::Patch));
+
+        // Adding a version and a container is backwards compatible
+        let new =
+            render_manifest(&[ContainerIds::of::<Setting>(), ContainerIds::of::<Profile>()]);
+        let report = wire_semver_check_str(&old, &new).unwrap();
+        assert_eq!(report.change, WireChange::Minor);
+        assert_eq!(
+            report.changes,
+            [
+                (
+                    WireChange::Minor,
+                    "Setting version 1 (V2) was added".to_string()
+                ),
+                (WireChange::Minor, "Profile was added".to_string()),
+            ]
+        );
+        assert!(report.allows(WireChange::Minor) && !report.allows(WireChange::Patch));
+
+        // Going back removes them, and changing a layout breaks stored data
+        let report = wire_semver_check_str(&new, &old).unwrap();
+        assert_eq!(report.change, WireChange::Major);
+        assert_eq!(report.changes.len(), 2);
+        let fingerprinted = new.replacen(
+            "name = \"V1\", schema_hash = ",
+            "name = \"V1\", fingerprint = 1, schema_hash = ",
+            1,
+        );
+        let report = wire_semver_check_str(&new, &fingerprinted).unwrap();
+        assert_eq!(report.change, WireChange::Minor);
+        let relaid = fingerprinted.replace("fingerprint = 1", "fingerprint = 2");
+        let report = wire_semver_check_str(&fingerprinted, &relaid).unwrap();
+        assert_eq!(
+            report.changes,
+            [(
+                WireChange::Major,
+                "Setting version 0 (V1) changed layout".to_string()
+            )]
+        );
+        assert_eq!(WireChange::Major.to_string(), "major");
+    }
+
+    #[test]
+    fn test_readable_by() {
+        // The store has only seen V1 of Setting, and no Profile at all
+        let store = render_manifest(&[ContainerIds {
+            versions: &Setting::VERSION_TABLE[..1],
+            ..ContainerIds::of::<Setting>()
+        }]);
+        check_readable_by_str::<Setting>(&store).unwrap();
+        check_readable_by_str::<Profile>(&store).unwrap();
+
+        // An older definition of Setting, with V1 holding a different type
+        mod old {
+            use crate::VersionedArchiveContainer;
+            use rkyv::{Archive, Deserialize, Serialize};
+
+            #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+            pub enum Setting {
+                V1(u64),
+            }
+        }
+        assert_eq!(
+            match check_readable_by_str::<old::Setting>(&store) {
+                Err(IdManifestError::Mismatch(differences)) => differences,
+                other => panic!("Expected a mismatch, got {:?}", other),
+            },
+            ["Setting version 0 (V1) changed type"]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Pretty-printing tagged byte arrays for debugging.
+//!
+//! [inspect_tagged] decodes everything that can be learned about a tagged byte array without
+//! knowing its container type into a [TaggedInspection]: the raw header, the type and version
+//! IDs along with any names embedded with [to_tagged_bytes_named](crate::to_tagged_bytes_named),
+//! the digest, compression and encryption algorithms, the header flags, whether the payload
+//! matches its digest, and the first bytes of the stored payload.  Unlike the access functions
+//! it doesn't stop at the first problem, so it also describes damaged records, and its
+//! `Display` output is meant to be read by a person, e.g. in a debugger or REPL.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::extensions::HEADER_FLAG_EXTENSIONS;
+use crate::header::KNOWN_HEADER_FLAGS;
+use crate::header_words::{
+    HEADER_FLAG_SEQUENCE, HEADER_FLAG_TIMESTAMP, HEADER_FLAG_USER_WORD,
+};
+use crate::{
+    get_type_name_from_tagged_bytes, split_metadata, verify_payload, CompressionCodec,
+    DigestAlgorithm, EmbeddedTypeName, EncryptionAlgorithm, RkyvVersionedError,
+    TaggedVersionedHeader, HEADER_FLAG_SIGNED, TAGGED_HEADER_SIZE,
+};
+
+/// The most bytes of the stored payload kept in [TaggedInspection::preview].
+pub const INSPECTION_PREVIEW_LEN: usize = 64;
+
+/// Whether the payload of an inspected record matches its digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    /// The payload matches its digest.
+    Valid,
+    /// The payload doesn't match its digest.
+    Mismatch,
+    /// The record was written without a digest, so nothing could be checked.
+    Unchecked,
+    /// The buffer ends before the digest trailer.
+    Truncated,
+    /// The digest algorithm is unknown, or known but not compiled in.
+    Unsupported(u8),
+}
+
+/// A description of a tagged byte array, see [inspect_tagged].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedInspection {
+    /// The header as stored.  Tagged byte arrays have no magic number, the type ID at the
+    /// front of the header identifies them.
+    pub header_bytes: [u8; TAGGED_HEADER_SIZE],
+    /// The type ID of the record.
+    pub type_id: u32,
+    /// The version ID of the record.
+    pub version_id: u32,
+    /// The length of the body between the header and the digest trailer.
+    pub payload_len: u32,
+    /// The [DigestAlgorithm] ID of the digest trailer.
+    pub digest: u8,
+    /// The [CompressionCodec] ID of the payload.
+    pub compression: u8,
+    /// The [EncryptionAlgorithm] ID of the payload.
+    pub encryption: u8,
+    /// The header flags, including any this version of the crate doesn't understand.
+    pub flags: u8,
+    /// The names embedded in the record, if it was written with them and they can be read.
+    pub type_name: Option<EmbeddedTypeName>,
+    /// The length of the buffer inspected.
+    pub len: usize,
+    /// The length of the tagged byte array described by the header, `None` if its digest
+    /// algorithm is unknown.
+    pub tagged_len: Option<usize>,
+    /// Whether the payload matches its digest.
+    pub checksum: ChecksumStatus,
+    /// The length of the header words and extension area in front of the payload, `None` if
+    /// they are truncated or malformed.
+    pub metadata_len: Option<usize>,
+    /// The first [INSPECTION_PREVIEW_LEN] bytes of the stored payload, i.e. after any
+    /// compression or encryption.
+    pub preview: Vec<u8>,
+}
+
+impl TaggedInspection {
+    /// The header flags this version of the crate doesn't understand.
+    pub fn unknown_flags(&self) -> u8 {
+        self.flags & !KNOWN_HEADER_FLAGS
+    }
+}
+
+/// Describes a tagged byte array without knowing its container type.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array to inspect, which may be truncated or corrupt.
+///
+/// # Returns
+///
+/// A `Result` containing the description, or [RkyvVersionedError::BufferTooSmallError] if
+/// the buffer doesn't even hold a header.  Every other problem is recorded in the description.
+pub fn inspect_tagged(buf: &[u8]) -> Result<TaggedInspection, RkyvVersionedError> {
+    let (header_bytes, rest) = buf
+        .split_first_chunk::<TAGGED_HEADER_SIZE>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let header = TaggedVersionedHeader::decode(header_bytes);
+    let tagged_len = header.tagged_len().ok();
+    let checksum = match (DigestAlgorithm::from_id(header.digest), tagged_len) {
+        (Some(digest), Some(tagged_len))
+            if digest.is_available() && buf.len() >= tagged_len =>
+        {
+            match verify_payload(buf, &header) {
+                Ok(_) if digest == DigestAlgorithm::None => ChecksumStatus::Unchecked,
+                Ok(_) => ChecksumStatus::Valid,
+                Err(_) => ChecksumStatus::Mismatch,
+            }
+        }
+        (Some(digest), Some(_)) if digest.is_available() => ChecksumStatus::Truncated,
+        _ => ChecksumStatus::Unsupported(header.digest),
+    };
+
+    let body = rest.get(..header.payload_len as usize).unwrap_or(rest);
+    let (metadata_len, payload) = match split_metadata(&header, body) {
+        Ok((metadata, payload)) => (Some(metadata.len()), payload),
+        Err(_) => (None, body),
+    };
+    Ok(TaggedInspection {
+        header_bytes: *header_bytes,
+        type_id: header.type_id,
+        version_id: header.version_id,
+        payload_len: header.payload_len,
+        digest: header.digest,
+        compression: header.compression,
+        encryption: header.encryption,
+        flags: header.flags,
+        type_name: get_type_name_from_tagged_bytes(buf).ok().flatten(),
+        len: buf.len(),
+        tagged_len,
+        checksum,
+        metadata_len,
+        preview: payload
+            .get(..INSPECTION_PREVIEW_LEN)
+            .unwrap_or(payload)
+            .to_vec(),
+    })
+}
+
+impl fmt::Display for TaggedInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "header       ")?;
+        for (i, byte) in self.header_bytes.iter().enumerate() {
+            let sep = if i > 0 && i % 4 == 0 { " " } else { "" };
+            write!(f, "{sep}{byte:02x}")?;
+        }
+
+        write!(f, "\ntype_id      {:#010x}", self.type_id)?;
+        if let Some(name) = &self.type_name {
+            write!(f, " ({})", name.type_name)?;
+        }
+        write!(f, "\nversion_id   {}", self.version_id)?;
+        if let Some(variant) = self
+            .type_name
+            .as_ref()
+            .and_then(|n| n.variant_name.as_ref())
+        {
+            write!(f, " ({variant})")?;
+        }
+
+        write!(f, "\npayload_len  {} bytes", self.payload_len)?;
+        match self.tagged_len {
+            Some(tagged_len) => write!(f, ", tagged length {tagged_len}")?,
+            None => write!(f, ", tagged length unknown")?,
+        }
+        write!(f, ", buffer {}", self.len)?;
+
+        match DigestAlgorithm::from_id(self.digest) {
+            Some(digest) => write!(f, "\ndigest       {digest:?}")?,
+            None => write!(f, "\ndigest       unknown ({})", self.digest)?,
+        }
+        match self.checksum {
+            ChecksumStatus::Valid => write!(f, ", valid")?,
+            ChecksumStatus::Mismatch => write!(f, ", MISMATCH")?,
+            ChecksumStatus::Unchecked => write!(f, ", unchecked")?,
+            ChecksumStatus::Truncated => write!(f, ", truncated")?,
+            ChecksumStatus::Unsupported(_) => write!(f, ", unsupported")?,
+        }
+        match CompressionCodec::from_id(self.compression) {
+            Some(codec) => write!(f, "\ncompression  {codec:?}")?,
+            None => write!(f, "\ncompression  unknown ({})", self.compression)?,
+        }
+        match EncryptionAlgorithm::from_id(self.encryption) {
+            Some(algorithm) => write!(f, "\nencryption   {algorithm:?}")?,
+            None => write!(f, "\nencryption   unknown ({})", self.encryption)?,
+        }
+
+        write!(f, "\nflags        {:#04x}", self.flags)?;
+        let names = [
+            (HEADER_FLAG_SIGNED, "signed"),
+            (HEADER_FLAG_EXTENSIONS, "extensions"),
+            (HEADER_FLAG_USER_WORD, "user_word"),
+            (HEADER_FLAG_TIMESTAMP, "timestamp"),
+            (HEADER_FLAG_SEQUENCE, "sequence"),
+        ];
+        let mut sep = " (";
+        for (flag, name) in names {
+            if self.flags & flag != 0 {
+                write!(f, "{sep}{name}")?;
+                sep = ", ";
+            }
+        }
+        if self.unknown_flags() != 0 {
+            write!(f, "{sep}unknown {:#04x}", self.unknown_flags())?;
+            sep = ", ";
+        }
+        if sep == ", " {
+            write!(f, ")")?;
+        }
+
+        match self.metadata_len {
+            Some(metadata_len) => write!(f, "\nmetadata     {metadata_len} bytes")?,
+            None => write!(f, "\nmetadata     truncated or malformed")?,
+        }
+        for (i, line) in self.preview.chunks(16).enumerate() {
+            write!(f, "\n  {:04x}  ", i * 16)?;
+            for column in 0..16 {
+                match line.get(column) {
+                    Some(byte) => write!(f, "{byte:02x} ")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            write!(f, " |")?;
+            for &byte in line {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{c}")?;
+            }
+            write!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        to_tagged_bytes, to_tagged_bytes_named, TaggedOptions, VersionedArchiveContainer,
+        VersionedContainer,
+    };
+    use alloc::string::{String, ToString};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Reading {
+        V1(u32),
+        V2((u32, String)),
+    }
+
+    #[test]
+    fn test_inspect_tagged() {
+        let reading = Reading::V2((21, "celsius".to_string()));
+        let bytes = to_tagged_bytes_named(&reading, &TaggedOptions::default()).unwrap();
+        let inspection = inspect_tagged(&bytes).unwrap();
+        assert_eq!(inspection.type_id, Reading::ARCHIVE_TYPE_ID);
+        assert_eq!(inspection.version_id, 1);
+        assert_eq!(inspection.checksum, ChecksumStatus::Valid);
+        assert_eq!(inspection.tagged_len, Some(bytes.len()));
+        assert!(inspection.metadata_len.unwrap() > 0);
+        assert!(!inspection.preview.is_empty());
+
+        let text = inspection.to_string();
+        assert!(text.contains("(Reading)"), "{text}");
+        assert!(text.contains("1 (V2)"), "{text}");
+        assert!(text.contains("Crc32, valid"), "{text}");
+        assert!(text.contains("(extensions)"), "{text}");
+        assert!(text.contains("celsius"), "{text}");
+
+        // Damaged records are still described
+        let mut corrupt = to_tagged_bytes(&Reading::V1(7)).unwrap();
+        corrupt[TAGGED_HEADER_SIZE] ^= 1;
+        assert_eq!(
+            inspect_tagged(&corrupt).unwrap().checksum,
+            ChecksumStatus::Mismatch
+        );
+        let truncated = inspect_tagged(&corrupt[..corrupt.len() - 2]).unwrap();
+        assert_eq!(truncated.checksum, ChecksumStatus::Truncated);
+        assert_eq!(truncated.type_name, None);
+        let mut flagged = corrupt.clone();
+        flagged[15] |= 0x80;
+        let flagged = inspect_tagged(&flagged).unwrap();
+        assert_eq!(flagged.unknown_flags(), 0x80);
+        assert!(flagged.to_string().contains("unknown 0x80"));
+        assert!(matches!(
+            inspect_tagged(&corrupt[..4]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+//! Allocation accounting while serializing.
+//!
+//! [to_tagged_bytes_instrumented] serializes exactly like
+//! [to_tagged_bytes_with](crate::to_tagged_bytes_with), but routes the scratch allocations of
+//! the serializer through a caller-provided [Arena] and reports what happened in
+//! [SerializationStats], to help choose capacity hints for the output buffer and arena, and to
+//! decide whether pooling arenas is worthwhile.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::{Arena, ArenaHandle};
+use rkyv::ser::sharing::Share;
+use rkyv::ser::{Allocator, Positional, Serializer, Writer};
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    rkyv_error, tag_serialized, RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// What happened while serializing a payload with [to_tagged_bytes_instrumented].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SerializationStats {
+    /// The number of scratch allocations made by the serializer.
+    pub scratch_allocations: usize,
+    /// The total size of the scratch allocations, in bytes.
+    pub scratch_bytes: usize,
+    /// The most scratch space in use at once, in bytes.  An arena with at least this capacity
+    /// (plus alignment padding) doesn't need to grow.
+    pub peak_scratch_bytes: usize,
+    /// The capacity of the arena before serializing.
+    pub arena_capacity_before: usize,
+    /// The capacity of the arena after serializing.
+    pub arena_capacity_after: usize,
+    /// The number of times the buffer the payload was serialized into had to grow.
+    pub writer_growths: usize,
+    /// The size of the serialized payload before compression or encryption, in bytes.
+    pub payload_bytes: usize,
+}
+
+impl SerializationStats {
+    /// Whether the arena had to allocate a larger block to satisfy the serializer.
+    pub fn arena_grew(&self) -> bool {
+        self.arena_capacity_after > self.arena_capacity_before
+    }
+}
+
+/// A [Writer] appending to an [AlignedVec] and counting how often it grows.
+#[derive(Debug)]
+pub struct CountingWriter {
+    inner: AlignedVec,
+    growths: usize,
+}
+
+impl Positional for CountingWriter {
+    fn pos(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<E> Writer<E> for CountingWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        let capacity = self.inner.capacity();
+        self.inner.extend_from_slice(bytes);
+        if self.inner.capacity() != capacity {
+            self.growths += 1;
+        }
+        Ok(())
+    }
+}
+
+/// An [Allocator] forwarding to another one and counting the allocations made through it.
+#[derive(Debug)]
+pub struct CountingAllocator<A> {
+    inner: A,
+    allocations: usize,
+    bytes: usize,
+    live_bytes: usize,
+    peak_bytes: usize,
+}
+
+unsafe impl<A: Allocator<E>, E> Allocator<E> for CountingAllocator<A> {
+    unsafe fn push_alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, E> {
+        // SAFETY: The requirements are the same as those of `push_alloc` on the inner allocator
+        let ptr = unsafe { self.inner.push_alloc(layout)? };
+        self.allocations += 1;
+        self.bytes += layout.size();
+        self.live_bytes += layout.size();
+        self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+        Ok(ptr)
+    }
+
+    unsafe fn pop_alloc(&mut self, ptr: NonNull<u8>, layout: Layout) -> Result<(), E> {
+        // SAFETY: The requirements are the same as those of `pop_alloc` on the inner allocator
+        unsafe { self.inner.pop_alloc(ptr, layout)? };
+        self.live_bytes -= layout.size();
+        Ok(())
+    }
+}
+
+/// Serializes a versioned container into a tagged byte array like
+/// [to_tagged_bytes_with](crate::to_tagged_bytes_with), reporting allocation statistics.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `options` - The options controlling the header and trailer.
+/// * `arena` - The arena providing scratch space to the serializer, which can be reused
+///   across calls.
+/// * `capacity` - The initial capacity of the returned byte array.
+///
+/// # Returns
+///
+/// A `Result` containing either the serialized byte array and the statistics of the call, or
+/// an error if serialization fails.
+pub fn to_tagged_bytes_instrumented<T>(
+    item: &T,
+    options: &TaggedOptions,
+    arena: &mut Arena,
+    capacity: usize,
+) -> Result<(AlignedVec, SerializationStats), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<
+            HighSerializer<
+                CountingWriter,
+                CountingAllocator<ArenaHandle<'a>>,
+                rkyv::rancor::Error,
+            >,
+        >,
+{
+    let mut stats = SerializationStats {
+        arena_capacity_before: arena.shrink(),
+        ..Default::default()
+    };
+    let bytes = tag_serialized::<T>(
+        item.get_entry_version_id(),
+        options,
+        AlignedVec::with_capacity(capacity),
+        |out| {
+            let start = out.len();
+            let writer = CountingWriter {
+                inner: out,
+                growths: 0,
+            };
+            let allocator = CountingAllocator {
+                inner: arena.acquire(),
+                allocations: 0,
+                bytes: 0,
+                live_bytes: 0,
+                peak_bytes: 0,
+            };
+            let mut serializer = Serializer::new(writer, allocator, Share::new());
+            rkyv::api::serialize_using::<_, rkyv::rancor::Error>(item, &mut serializer)
+                .map_err(rkyv_error)?;
+
+            let (writer, allocator, _) = serializer.into_raw_parts();
+            stats.scratch_allocations = allocator.allocations;
+            stats.scratch_bytes = allocator.bytes;
+            stats.peak_scratch_bytes = allocator.peak_bytes;
+            stats.writer_growths = writer.growths;
+            stats.payload_bytes = writer.inner.len() - start;
+            Ok(writer.inner)
+        },
+    )?;
+    // Only drops the blocks which were outgrown, which the next acquire would do anyway
+    stats.arena_capacity_after = arena.shrink();
+    Ok((bytes, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Batch {
+        pub names: Vec<String>,
+        pub values: Vec<u64>,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum BatchContainer {
+        V1(Batch),
+    }
+
+    #[test]
+    fn test_instrumented_serialization() {
+        let batch = BatchContainer::V1(Batch {
+            names: (0..100).map(|i| format!("name-{}", i)).collect(),
+            values: (0..100).collect(),
+        });
+
+        // A tiny arena and buffer have to grow
+        let mut arena = Arena::with_capacity(16);
+        let (tagged, stats) =
+            to_tagged_bytes_instrumented(&batch, &TaggedOptions::default(), &mut arena, 0)
+                .unwrap();
+        assert_eq!(
+            tagged.as_slice(),
+            to_tagged_bytes(&batch).unwrap().as_slice()
+        );
+        match access_from_tagged_bytes::<BatchContainer>(&tagged).unwrap() {
+            ArchivedBatchContainer::V1(archived) => assert_eq!(archived.names.len(), 100),
+        }
+        assert!(stats.scratch_allocations > 0);
+        assert!(stats.peak_scratch_bytes > 16);
+        assert!(stats.scratch_bytes >= stats.peak_scratch_bytes);
+        assert!(stats.arena_grew());
+        assert!(stats.writer_growths > 0);
+        assert!(stats.payload_bytes < tagged.len());
+
+        // Reusing the arena and sizing the buffer from the first call avoids both
+        let (_, stats) = to_tagged_bytes_instrumented(
+            &batch,
+            &TaggedOptions::default(),
+            &mut arena,
+            tagged.len(),
+        )
+        .unwrap();
+        assert!(!stats.arena_grew());
+        assert_eq!(stats.writer_growths, 0);
+    }
+}
@@ -0,0 +1,448 @@
+//! Reading tagged byte arrays from [std::io] streams.
+//!
+//! [peek_type_and_version] reads only the fixed header of the next record in a stream, so a
+//! dispatcher can decide whether to read, skip or forward the record before buffering its
+//! payload.
+//!
+//! [write_tagged] and [read_tagged] (also available as
+//! [VersionedContainer::write_tagged] and [VersionedContainer::read_tagged]) write and read
+//! whole records as the length-prefixed frames of the [framing](crate::append_framed) format,
+//! so a file written record by record can also be split with
+//! [framed_records](crate::framed_records) once loaded.
+//!
+//! [write_tagged_vectored] writes the same frame around a payload which was already
+//! archived, handing the header, payload and trailer to the stream as separate [IoSlice]s so
+//! that large payloads aren't copied into one contiguous buffer first.
+
+use std::io::{self, IoSlice, Read, Write};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::framing::{frame_padding, frame_prefix, parse_frame_prefix};
+use crate::{
+    check_header, fingerprinted_options, read_header, tag_existing_payload_with, tag_payload,
+    to_tagged_bytes, write_header, CompressionCodec, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, FRAME_ALIGNMENT, FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE,
+};
+
+/// The header of a record read from a stream by [peek_type_and_version], or from the front of
+/// a slice by [peek_header].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeekedHeader {
+    /// The type ID of the record.
+    pub type_id: u32,
+    /// The version ID of the record.
+    pub version_id: u32,
+    /// The number of bytes of the record left in the stream after the header, i.e. the body
+    /// and trailers.
+    pub remaining_len: usize,
+    /// The header as read from the stream, to be put back in front of the rest of the record
+    /// when reading or forwarding it.
+    pub bytes: [u8; TAGGED_HEADER_SIZE],
+}
+
+/// Converts an IO error, treating a stream ending early like a truncated buffer.
+pub(crate) fn io_error(e: io::Error) -> RkyvVersionedError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => RkyvVersionedError::BufferTooSmallError,
+        _ => RkyvVersionedError::TransportError(Box::new(e)),
+    }
+}
+
+impl From<io::Error> for RkyvVersionedError {
+    fn from(e: io::Error) -> Self {
+        io_error(e)
+    }
+}
+
+/// Reads the header of the next tagged byte array from `reader`, leaving the rest of the
+/// record unread.
+///
+/// Like [get_type_and_version_from_tagged_bytes](crate::get_type_and_version_from_tagged_bytes)
+/// this does not require knowing the container type.  Exactly [TAGGED_HEADER_SIZE] bytes are
+/// consumed, so after deciding what to do with the record the caller can read
+/// [PeekedHeader::remaining_len] more bytes to get the rest of it, or skip them.
+///
+/// # Arguments
+///
+/// * `reader` - The stream positioned at the start of a tagged byte array.
+///
+/// # Returns
+///
+/// A `Result` containing either the [PeekedHeader] or an error if the stream ends before the
+/// header does, fails, or the header is invalid.
+pub fn peek_type_and_version(
+    reader: &mut impl Read,
+) -> Result<PeekedHeader, RkyvVersionedError> {
+    let mut bytes = [0u8; TAGGED_HEADER_SIZE];
+    reader.read_exact(&mut bytes).map_err(io_error)?;
+    decode_peeked(bytes)
+}
+
+/// Parses the header at the front of `buf`, which may hold a record of any container type.
+///
+/// Routers can use this to inspect foreign records, e.g. to forward them by type ID, without
+/// linking the container types.  Only the header is read, so the rest of the record may be
+/// missing and neither the payload nor its digest are validated.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to a byte array starting with a tagged header.
+///
+/// # Returns
+///
+/// A `Result` containing either the [PeekedHeader], whose
+/// [remaining_len](PeekedHeader::remaining_len) is the length of the record after the
+/// header, or an error if `buf` is shorter than the header or the header is invalid.
+pub fn peek_header(buf: &[u8]) -> Result<PeekedHeader, RkyvVersionedError> {
+    let bytes = buf
+        .first_chunk::<TAGGED_HEADER_SIZE>()
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    decode_peeked(*bytes)
+}
+
+/// Decodes the header in `bytes` into a [PeekedHeader].
+fn decode_peeked(bytes: [u8; TAGGED_HEADER_SIZE]) -> Result<PeekedHeader, RkyvVersionedError> {
+    let header = read_header(&bytes)?;
+    Ok(PeekedHeader {
+        type_id: header.type_id,
+        version_id: header.version_id,
+        remaining_len: header.tagged_len()? - TAGGED_HEADER_SIZE,
+        bytes,
+    })
+}
+
+/// Serializes `item` into a tagged byte array and writes it to `writer` as a length-prefixed
+/// frame.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `writer` - The stream to write the frame to.
+///
+/// # Returns
+///
+/// A `Result` containing either the number of bytes written, including the prefix and
+/// padding of the frame, or an error if serialization or writing fails.
+pub fn write_tagged<T>(item: &T, writer: &mut impl Write) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    write_frame(&to_tagged_bytes(item)?, writer)
+}
+
+/// Writes an already archived `payload` of version `version_id` of `T` to `writer` as a
+/// length-prefixed frame, like [write_tagged], without copying the payload.
+///
+/// # Arguments
+///
+/// * `writer` - The stream to write the frame to.
+/// * `version_id` - The version ID of the variant archived in `payload`.
+/// * `payload` - The `rkyv` archive of the container, e.g. as returned by `rkyv::to_bytes`.
+///
+/// # Returns
+///
+/// A `Result` containing either the number of bytes written, including the prefix and
+/// padding of the frame, or an error if `version_id` isn't a version of `T`
+/// ([RkyvVersionedError::UnsupportedVersionError]) or writing fails.
+pub fn write_tagged_vectored<T: VersionedContainer>(
+    writer: &mut impl Write,
+    version_id: u32,
+    payload: &[u8],
+) -> Result<usize, RkyvVersionedError> {
+    write_tagged_vectored_with::<T>(writer, version_id, payload, &TaggedOptions::default())
+}
+
+/// As [write_tagged_vectored], tagging the payload with `options`.  Payloads to be compressed
+/// or encrypted are transformed into a new buffer, only the header and trailer are written
+/// separately.
+pub fn write_tagged_vectored_with<T: VersionedContainer>(
+    writer: &mut impl Write,
+    version_id: u32,
+    payload: &[u8],
+    options: &TaggedOptions,
+) -> Result<usize, RkyvVersionedError> {
+    if !T::is_valid_version_id(version_id) {
+        return Err(RkyvVersionedError::UnsupportedVersionError(version_id));
+    }
+    // Record the fingerprint of fingerprinted versions, unless the caller already did
+    let fingerprinted;
+    let options = match fingerprinted_options::<T>(version_id, options)? {
+        Some(options) => {
+            fingerprinted = options;
+            &fingerprinted
+        }
+        None => options,
+    };
+    if options.compression != CompressionCodec::None || options.encryption.is_some() {
+        let record = tag_existing_payload_with::<T>(version_id, payload, options)?;
+        return write_frame(&record, writer);
+    }
+
+    // The header is followed by the metadata, which is padded to keep the payload aligned
+    let mut head = AlignedVec::<16>::new();
+    head.resize(TAGGED_HEADER_SIZE, 0);
+    options.header_words().encode(&mut head)?;
+    options.extensions.encode(&mut head)?;
+    let metadata = head.get(TAGGED_HEADER_SIZE..).unwrap_or_default();
+    let (header, digest) = tag_payload::<T>(version_id, &[metadata, payload], options)?;
+    write_header(&mut head, &header)?;
+
+    let record_len = header.tagged_len()?;
+    let padding = [0u8; FRAME_ALIGNMENT];
+    let padding = padding.get(..frame_padding(record_len)).unwrap_or_default();
+    let prefix = frame_prefix(record_len)?;
+    let mut slices = [
+        IoSlice::new(&prefix),
+        IoSlice::new(&head),
+        IoSlice::new(payload),
+        IoSlice::new(digest.as_bytes()),
+        IoSlice::new(padding),
+    ];
+    write_all_vectored(writer, &mut slices).map_err(io_error)?;
+    Ok(FRAME_PREFIX_SIZE + record_len + padding.len())
+}
+
+/// Writes all of `slices` to `writer`, as the unstable `Write::write_all_vectored` does.
+fn write_all_vectored(writer: &mut impl Write, mut slices: &mut [IoSlice]) -> io::Result<()> {
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes `record` to `writer` as a length-prefixed frame, returning the length of the frame.
+pub(crate) fn write_frame(
+    record: &[u8],
+    writer: &mut impl Write,
+) -> Result<usize, RkyvVersionedError> {
+    let padding = [0u8; FRAME_ALIGNMENT];
+    let padding = padding
+        .get(..frame_padding(record.len()))
+        .unwrap_or_default();
+    writer
+        .write_all(&frame_prefix(record.len())?)
+        .and_then(|_| writer.write_all(record))
+        .and_then(|_| writer.write_all(padding))
+        .map_err(io_error)?;
+    Ok(FRAME_PREFIX_SIZE + record.len() + padding.len())
+}
+
+/// Reads the next length-prefixed frame written by [write_tagged] from `reader`.
+///
+/// The record is read into an [AlignedVec] so that it can be accessed in place, and its
+/// header is checked to hold a supported version of `T`, but the digest and payload are only
+/// validated when the record is accessed, e.g. with
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+///
+/// # Arguments
+///
+/// * `reader` - The stream positioned at the start of a frame.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, `None` if the stream ended cleanly
+/// before the frame, or an error.  Truncated frames are reported as
+/// [RkyvVersionedError::BufferTooSmallError] and corrupt ones as
+/// [RkyvVersionedError::InvalidMessageError].
+pub fn read_tagged<T: VersionedContainer>(
+    reader: &mut impl Read,
+) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+    let mut prefix = [0u8; FRAME_PREFIX_SIZE];
+    let mut filled = 0;
+    while filled < FRAME_PREFIX_SIZE {
+        match reader.read(prefix.get_mut(filled..).unwrap_or_default()) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(RkyvVersionedError::BufferTooSmallError),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    let record_len =
+        parse_frame_prefix(&prefix).ok_or(RkyvVersionedError::InvalidMessageError)?;
+
+    // Only trust the prefix as far as the header agrees with it, and don't allocate for a
+    // record which isn't a `T` anyway
+    let mut header_bytes = [0u8; TAGGED_HEADER_SIZE];
+    reader.read_exact(&mut header_bytes).map_err(io_error)?;
+    let header = check_header::<T>(&header_bytes)?;
+    if header.tagged_len()? != record_len {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+
+    let mut record = AlignedVec::with_capacity(record_len);
+    record.extend_from_slice(&header_bytes);
+    let remaining = record_len - TAGGED_HEADER_SIZE + frame_padding(record_len);
+    let read = record
+        .extend_from_reader(&mut reader.by_ref().take(remaining as u64))
+        .map_err(io_error)?;
+    if read != remaining {
+        return Err(RkyvVersionedError::BufferTooSmallError);
+    }
+    // Drop the padding
+    record.resize(record_len, 0);
+    Ok(Some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer,
+        VersionedContainer,
+    };
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    /// Accepts at most a few bytes per call, to exercise partial vectored writes
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_tagged_vectored() {
+        let event = Event::V1("archived ahead of time".to_string());
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&event).unwrap();
+
+        let mut expected = Vec::new();
+        write_tagged(&event, &mut expected).unwrap();
+        let mut stream = Vec::new();
+        let written = write_tagged_vectored::<Event>(&mut stream, 0, &payload).unwrap();
+        assert_eq!(written, stream.len());
+        assert_eq!(stream, expected);
+
+        let mut extensions = crate::HeaderExtensions::new();
+        extensions.insert(1, b"meta").unwrap();
+        let options = TaggedOptions {
+            extensions,
+            sequence: Some(9),
+            ..Default::default()
+        };
+        let mut expected = Vec::new();
+        write_frame(
+            &crate::to_tagged_bytes_with(&event, &options).unwrap(),
+            &mut expected,
+        )
+        .unwrap();
+        let mut trickle = Trickle(Vec::new());
+        write_tagged_vectored_with::<Event>(&mut trickle, 0, &payload, &options).unwrap();
+        assert_eq!(trickle.0, expected);
+        let record = read_tagged::<Event>(&mut trickle.0.as_slice())
+            .unwrap()
+            .unwrap();
+        assert!(access_from_tagged_bytes::<Event>(&record).is_ok());
+
+        assert!(matches!(
+            write_tagged_vectored::<Event>(&mut Vec::new(), 2, &payload),
+            Err(RkyvVersionedError::UnsupportedVersionError(2))
+        ));
+    }
+
+    #[test]
+    fn test_peek_type_and_version() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&to_tagged_bytes(&Event::V1("skipped".to_string())).unwrap());
+        stream.extend_from_slice(&to_tagged_bytes(&Event::V2(7)).unwrap());
+        let mut reader = stream.as_slice();
+
+        // Skip the first record without reading its payload
+        let peeked = peek_type_and_version(&mut reader).unwrap();
+        assert_eq!(peek_header(&stream).unwrap(), peeked);
+        assert!(matches!(
+            peek_header(&stream[..8]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        assert_eq!(peeked.type_id, Event::ARCHIVE_TYPE_ID);
+        assert_eq!(peeked.version_id, 0);
+        io::copy(
+            &mut (&mut reader).take(peeked.remaining_len as u64),
+            &mut io::sink(),
+        )
+        .unwrap();
+
+        // Read the second one in full
+        let peeked = peek_type_and_version(&mut reader).unwrap();
+        assert_eq!(peeked.version_id, 1);
+        let mut record = AlignedVec::<16>::new();
+        record.extend_from_slice(&peeked.bytes);
+        record.resize(TAGGED_HEADER_SIZE + peeked.remaining_len, 0);
+        reader
+            .read_exact(&mut record[TAGGED_HEADER_SIZE..])
+            .unwrap();
+        match access_from_tagged_bytes::<Event>(&record).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 7),
+            _ => panic!("Expected V2"),
+        }
+
+        // The stream is exhausted
+        assert!(reader.is_empty());
+        assert!(matches!(
+            peek_type_and_version(&mut reader),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_write_and_read_tagged() {
+        let mut file = Vec::new();
+        let written = Event::V1("first".to_string())
+            .write_tagged(&mut file)
+            .unwrap();
+        assert_eq!(written, file.len());
+        assert_eq!(written % FRAME_ALIGNMENT, 0);
+        Event::V2(2).write_tagged(&mut file).unwrap();
+
+        // The frames are the same as those of an in-memory stream
+        let mut stream = AlignedVec::<16>::new();
+        stream.extend_from_slice(&file);
+        assert_eq!(crate::framed_records(&stream).count(), 2);
+
+        let mut reader = file.as_slice();
+        let first = Event::read_tagged(&mut reader).unwrap().unwrap();
+        match access_from_tagged_bytes::<Event>(&first).unwrap() {
+            ArchivedEvent::V1(text) => assert_eq!(text, "first"),
+            _ => panic!("Expected V1"),
+        }
+        let second = Event::read_tagged(&mut reader).unwrap().unwrap();
+        assert!(access_from_tagged_bytes::<Event>(&second).is_ok());
+        assert!(Event::read_tagged(&mut reader).unwrap().is_none());
+
+        // Truncated and corrupt frames
+        assert!(matches!(
+            Event::read_tagged(&mut &file[..written - 1]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        let mut corrupt = file.clone();
+        corrupt[0] ^= 1;
+        assert!(matches!(
+            Event::read_tagged(&mut corrupt.as_slice()),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+    }
+}
@@ -97,7 +97,9 @@
 //!         Err(RkyvVersionedError::UnsupportedVersionError(version)) => {
 //!             panic!("Found unsupported version {}", version)
 //!         }
+//!         Err(RkyvVersionedError::ChecksumMismatchError) => panic!("Payload was corrupted!"),
 //!         Err(RkyvVersionedError::RkyvError(rkyv_error)) => panic!("Rkyv error: {}", rkyv_error),
+//!         Err(other) => panic!("Other error: {}", other),
 //!     };
 //! }
 //! ```
@@ -105,13 +107,145 @@
 //! # Functions
 //! - [to_tagged_bytes]: Serializes a versioned container to a tagged byte stream, embedding
 //!   the type ID and the version ID of the variant along with the data.
+//! - [to_tagged_bytes_with]: As above, but with [TaggedOptions] controlling e.g. the
+//!   [DigestAlgorithm] used to protect the payload.
+//! - [to_tagged_bytes_with_strategy] / [access_from_tagged_bytes_with_strategy]: As
+//!   [to_tagged_bytes_with] and [access_from_tagged_bytes], but serializing and validating
+//!   with a `rancor` error type of the caller's choosing, e.g. `Failure` or `Panic`.
+//! - [serialized_tagged_size] / [serialized_tagged_size_with]: Computes the length of the
+//!   tagged byte array of a container ahead of time, to reserve buffers or enforce limits.
+//! - [to_tagged_bytes_in] / [to_tagged_bytes_in_with]: As [to_tagged_bytes], but writes to
+//!   any `rkyv` writer exposing what it has written, e.g. a `Vec<u8>`, or a `BytesMut`
+//!   through `BytesMutWriter`.
+//! - [to_tagged_bytes_in_slice]: As [to_tagged_bytes], but writes into a caller-provided
+//!   slice with scratch space from a caller-kept arena, failing if it's too small.
+//! - [to_tagged_bytes_in_fixed]: As above, but with `rkyv`'s low-level serializer and a
+//!   caller-provided scratch buffer, so no heap is needed at all.
+//! - [tag_existing_payload] / [tag_existing_payload_with]: Tags a payload which was already
+//!   archived, without re-serializing it.
+//! - `to_shared_bytes` / `TaggedBytes`: Serializes into a reference-counted `bytes::Bytes`
+//!   and accesses records held in one in place, with the `bytes` feature.
+//! - [to_tagged_bytes_dual] / [access_dual_from_tagged_bytes]: Writes a record holding both
+//!   the latest and a downgraded version, so builds rolled back to can still read it.
+//! - [to_tagged_bytes_into] / [to_tagged_bytes_into_with]: As [to_tagged_bytes], but reuse
+//!   a caller-owned buffer instead of allocating a new one for each record.
+//! - [TaggedSerializer]: Serializes many records back to back, reusing its scratch arena and
+//!   output buffer.
+//! - [to_tagged_bytes_instrumented]: As [to_tagged_bytes], but serializing with a
+//!   caller-provided arena and reporting [SerializationStats] to help tune capacity hints
+//!   and pooling.
+//! - [append_tagged] / [TaggedRecordIter]: Appends records back to back, padded to keep
+//!   them aligned, and scans them again, accessing each one in place.
+//! - [to_tagged_batch] / [TaggedBatch]: Serializes many records into one buffer with an
+//!   offsets table, reusing the serializer's allocations, and accesses the i-th record in
+//!   constant time.
+//! - [write_tagged] / [read_tagged]: Writes and reads whole records as length-prefixed frames
+//!   on [std::io::Write] and [std::io::Read] streams, also available as methods of
+//!   [VersionedContainer].
+//! - [write_tagged_vectored] / [write_tagged_vectored_with]: As [write_tagged], but for a
+//!   payload which was already archived, written without copying it.
+//! - `write_tagged_async` / `read_tagged_async`: As above, but on tokio streams, with the
+//!   `tokio` feature.  `AsyncTaggedReader` reads frames cancellation safely.
+//! - [ThrottledWriter]: As [write_tagged], but limited by a token bucket on bytes or records
+//!   per second, waiting for or dropping records over the limit.
+//! - [LogWriter]: Appends records to a file as the same frames, syncing them to stable
+//!   storage according to a [SyncPolicy].
+//! - [VersionedFileWriter] / [VersionedFile]: Writes the same frames followed by an index
+//!   of every record, and reads any record or the last ones with a single seek, splitting
+//!   records over a maximum entry length into pieces and joining them again.
+//! - [VersionedLog] / [LogTailer]: Appends records to a directory of segment files, rotated
+//!   by size and synced by a [SyncPolicy], and follows new records as they are appended.
+//! - [TypeRegistry]: Routes records of several container types in one stream to a handler
+//!   registered for each, by the type ID in their header.
+//! - [SchemaResolver] / [StaticSchemaRegistry]: Resolves type IDs to the names and versions
+//!   of containers, from containers linked in or an organization's own registry.
+//! - `HttpSchemaRegistry`: A [SchemaResolver] caching schemas fetched from an HTTP schema
+//!   registry, with the `schema_registry` feature.
+//! - [validate_stream] / [validate_stream_file]: Walks a stream of frames and reports the
+//!   versions and types it holds, checksum failures and truncation in a [StreamReport].
+//! - [scan_versions] / [write_version_report]: Counts the records of each type and version
+//!   in the files of a store, to plan the retirement of old versions.
+//! - [stream_stats] / [stream_stats_file]: Tallies the records of a stream of any types by
+//!   type and version, with their sizes, compression ratios and corruption, in a [StreamStats].
+//! - [resync_framed_records]: Reads the intact frames of a corrupted stream, scanning past
+//!   each corrupt range to the next plausible frame and reporting the range skipped.
+//! - [repair_stream] / [repair_file]: Copies the intact records of a corrupted stream of any
+//!   types into a clean one, reporting the ranges dropped in a [RepairReport].
+//! - [convert_stream] / [convert_file]: Re-envelopes the records of a stream of any types with
+//!   another digest, codec or [HeaderProfile], e.g. to recompress an archive.
+//! - [migrate_stream] / [migrate_file]: Runs a migration over the records of a container in a
+//!   stream, re-tagging the migrated values, and [MigrationRegistry] names the migrations of
+//!   several containers for binaries built with [migration_cli].
+//! - [write_snapshot_atomic] / [read_snapshot]: Atomically replaces a single-record state file,
+//!   e.g. a checkpoint, so that a crash can't leave it torn.
+//! - [BytesCodec]: Encodes and decodes the same frames through one trait implemented by
+//!   every container, for adapters to codec frameworks such as `BytesCodecAdapter`.
+//! - `VersionedCodec`: A tokio_util `Encoder`/`Decoder` of the same frames for `Framed`
+//!   transports, with the `codec` feature, wrapped by `into_record_stream` and
+//!   `into_record_sink` for `futures` pipelines.
+//! - `MmapArchive`: Maps a file of one or more records and accesses them in place, with the
+//!   `memmap2` feature.  `PinnedEntry` keeps the file mapped for as long as a record is in use.
+//! - `ReloadingReader`: Follows a mapped file through rotations and compactions, remapping it
+//!   when it is replaced, with the `memmap2` feature.
+//! - [peek_type_and_version]: Reads only the header of the next record in an
+//!   [std::io::Read] stream, to decide what to do with the record before reading it.
+//! - [peek_header]: Parses the header at the front of a slice holding a record of any type,
+//!   e.g. for routers inspecting foreign records.
+//! - [inspect_tagged]: Describes every part of a record of any type, including damaged ones,
+//!   in a [TaggedInspection] which pretty-prints with a hexdump of the payload for debugging.
+//! - `to_debug_json`: Renders a record of any version as JSON for triage, for containers
+//!   deriving `#[vac(debug_json)]`, with the `debug_json` feature.
+//! - [self_check]: Tags and reads back a sample of every version, optionally running
+//!   migrations on it, so a service can fail fast at startup on packaging mistakes.
+//! - [VersionGate]: Chooses between writing the new and the previous version of a container
+//!   by a [FeatureFlags] provider during staged rollouts, counting the versions chosen.
+//! - [truncate_to] / [drop_unknown_keys]: Shorten fields and drop unknown map keys in
+//!   migrations, recording the data lost in a [MigrationReport].
+//! - [minimize_failure]: Shrinks a buffer failing validation into a minimal reproducer which
+//!   fails with the same error variant, for bug reports.
+//! - [write_test_vectors]: Writes a directory of tagged samples with JSON descriptions of
+//!   their expected contents, for validating readers written in other languages.
 //! - [access_from_tagged_bytes]: Deserializes a versioned container from a tagged byte stream
 //!   and validates type and version IDs.
+//! - [access_prefix_from_tagged_bytes]: As above, but explicitly accepting data after the
+//!   record and returning the number of bytes it took up.
+//! - [access_any_from_tagged_bytes]: As above, but returning a `Box<dyn AnyVersionedRef>`
+//!   so records of several container types can be held together and downcast later.
+//! - [get_ref_from_tagged_bytes_checked]: As above, spelling out that every part of the input
+//!   is validated, for use with untrusted input such as network traffic.
+//! - [get_ref_from_tagged_bytes_at]: As above, for a record embedded at an offset in a larger
+//!   buffer, checking that it is aligned.
+//! - [access_from_tagged_bytes_with_scratch]: As above, but decompresses compressed payloads
+//!   into a scratch buffer first.
+//! - [access_from_encrypted_tagged_bytes]: As above, but decrypts encrypted payloads with an
+//!   [EncryptionKey] first.
+//! - [access_from_encrypted_tagged_bytes_in_place]: As above, but decrypts the payload in
+//!   place in the input buffer, without copying it.
+//! - [ValidationConfig]: Caps the payload length and nesting depth accepted by the access
+//!   functions above, failing with [RkyvVersionedError::LimitExceededError].
+//! - [get_ref_from_tagged_bytes_unchecked]: Unsafely accesses a trusted tagged byte stream,
+//!   checking only the header, for maximum throughput.
+//! - [payload_bytes] / [payload_range]: Returns the verified `rkyv` archive held in a tagged
+//!   byte stream without copying it, e.g. for other `rkyv` consumers.
+//! - [payload_content_hash]: Hashes the archived value held in a tagged byte stream,
+//!   independently of how it was encoded.
 //!
 //! # Traits
 //! - [VersionedContainer]: A trait that is automatically implemented on a versioned container
-//!   using the
-//! `#[derive(VersionedArchiveContainer)]` attribute.
+//!   using the `#[derive(VersionedArchiveContainer)]` attribute.
+//!
+//! The derive also generates [VersionedContainer::VERSION_TABLE], describing each version with
+//! a [VersionInfo] (name, schema hash, whether it is marked `#[vac(deprecated)]` and its
+//! `#[vac(sunset = "YYYY-MM-DD")]` date) for introspection at runtime.  Reading a version past
+//! its sunset date with a [ValidationConfig] is reported to its [VersionObserver], and
+//! rejected in strict mode.
+//!
+//! - [SchemaFingerprint]: A layout fingerprint of a payload type computed at compile time,
+//!   derived with `#[derive(SchemaFingerprint)]`.  Containers deriving with
+//!   `#[vac(fingerprint)]` record it per version in [VersionInfo::fingerprint], store it in
+//!   their records under [EXTENSION_KEY_SCHEMA_FINGERPRINT], and fail to read records of a
+//!   version whose type was edited without a new version with
+//!   [RkyvVersionedError::SchemaMismatchError].
 //!
 //! # Error Types
 //! Given that introspection of the deserialization errors are more useful in this context
@@ -120,36 +254,498 @@
 //! departs a little from the conventions of `rkyv` but is a little more practical in this
 //! scenario where failures might need to be dealt with programmatically.
 //!
-//! # Internal Container Structures
-//! These structures are used internally to handle versioned data and are generally not used
-//! directly.
-//! - [TaggedVersionedStruct]: A container that includes type and version IDs along with the
-//!   data.
+//! Code which only needs to tell broad classes of failure apart can use
+//! [RkyvVersionedError::kind], which returns a [VersionedError] category (type mismatch,
+//! unsupported version, truncated, checksum, validation, ...), and
+//! [RkyvVersionedError::code] gives every variant a stable number for logs and metrics.
+//!
+//! Errors reading a record out of a stream or file, e.g. with an `MmapArchive` or a
+//! [TaggedRecordIter], are wrapped in [RkyvVersionedError::RecordError] with the index and
+//! byte offset of the record, and every error wrapping another exposes it through
+//! [Error::source](core::error::Error::source).
+//!
+//! `rkyv` itself reports its errors through the `rancor` error type it is given, which is
+//! `rancor::Error` throughout the crate.  [to_tagged_bytes_with_strategy] and
+//! [access_from_tagged_bytes_with_strategy] take that type as a parameter instead, so that
+//! embedders can e.g. skip formatting error messages with `rancor::Failure`, panic on corrupt
+//! payloads with `rancor::Panic`, or use their own richer error type.  Its errors are
+//! reported as [RkyvVersionedError::RkyvError], which boxes them as they are so they can be
+//! downcast back to that type.
+//!
+//! # `no_std`
+//! Without the default `std` feature the crate is `#![no_std]` and only needs `alloc`.  The
+//! containers, tagging and access functions, header words, extensions, digests,
+//! encryption, header profiles and [mqtt] remain available, IO, files, streams, registries and
+//! [SystemClock] need `std`.
+//!
+//! # Streams
+//! Tagged byte arrays can be concatenated into a single stream with [append_framed], which
+//! prefixes each with its length, and split back apart with [framed_records].  A stream with
+//! corrupt frames in the middle can still be read with [resync_framed_records], which skips
+//! over them to the next intact frame.
+//!
+//! # Datagram Transports
+//! [DatagramProfile] budgets tagged byte arrays against the maximum datagram size of an
+//! unreliable transport such as QUIC datagrams, optionally fragmenting oversized ones to be
+//! reassembled by a [DatagramReassembler].
+//!
+//! # Messaging
+//! With the `zmq` feature, the `zeromq` module publishes and subscribes to tagged byte arrays
+//! over ZeroMQ sockets, using topics derived from the type and version IDs.
+//!
+//! With [mqtt], tagged byte arrays are carried in MQTT payloads under topics derived from the
+//! type and version IDs, optionally framed with the [HeaderProfile::Compact] header to save
+//! space on constrained links.
+//!
+//! [reframe] converts an [EntryView] between the standard, compact and extended
+//! [HeaderProfile]s by rewriting only the header, so gateways bridging constrained and rich
+//! transports don't copy payloads.
+//!
+//! # Clocks and Randomness
+//! Timestamps and encryption nonces come from the [Clock] and [Rng] set in [TaggedOptions],
+//! and sunset dates are checked against the [Clock] set in [ValidationConfig], falling back to
+//! the system otherwise.  Tests can use a [ManualClock] and a [SplitMixRng] for deterministic
+//! output.
+//!
+//! With the `sim` feature, the `sim` module provides in-memory files and tokio streams which
+//! inject torn writes, bit flips, short reads and reordering from a seeded source, to test
+//! recovery logic deterministically.
+//!
+//! # Tagged Byte Layout
+//! A tagged byte array consists of a fixed 16 byte header (holding the type ID, version ID,
+//! payload length, digest algorithm, compression codec, encryption algorithm, format version
+//! and flags), followed by an optional block of user word, timestamp and sequence number, an
+//! optional extension area and the payload, which is a plain `rkyv` archive of the versioned
+//! container, followed by a digest of the payload and, if signed, an ed25519 signature.  The
+//! header is sized so that the payload stays aligned when the byte array is.
+//!
+//! Data written before the format version was recorded in the header can't be read, and is
+//! rejected with [RkyvVersionedError::UnsupportedFormatError].
+//!
+//! The header has a fixed little-endian encoding, independent of the architecture and of the
+//! `rkyv` layout features (`big_endian`, `unaligned`) in use, which this crate passes through
+//! via features of the same name.  Payloads use the `rkyv` layout of the writer.
+//!
+//! With the `raw` feature, the header type and flags are exposed in the `raw` module, along
+//! with the invariants an envelope built by hand has to uphold.
+//!
+//! # Header Extensions
+//! Small key/value metadata such as tenant or trace IDs can be attached to a record without
+//! changing its schema by setting [HeaderExtensions] in [TaggedOptions].  They are read back
+//! with [get_extensions_from_tagged_bytes], without needing to know the container type.
+//!
+//! A [WriterIdentity] applied to the extensions records which deployment wrote each record,
+//! read back with [get_writer_identity_from_tagged_bytes] even when the payload is corrupt.
+//! Similarly, [set_idempotency_key] tags a record with the operation it performs, and a
+//! [DedupWindow] lets consumers drop redelivered records.  [to_tagged_bytes_named] embeds the
+//! name of the container and variant, so tooling can name the types of records it doesn't
+//! know with [get_type_name_from_tagged_bytes].
+//! [set_namespace] tags a record with the tenant it belongs to, and a [QuotaWriter] enforces a
+//! [Quota] on each tenant's share of a log.
+//!
+//! Since the extensions are never encrypted, a container implementing [CleartextFields] can
+//! declare fields such as a routing key to be copied into them by
+//! [to_tagged_bytes_with_cleartext], so brokers read them with
+//! [get_cleartext_field_from_tagged_bytes] and route encrypted records without the key.
+//!
+//! For a single routing key or shard ID, [TaggedOptions::user_word] stores a `u64` at a fixed
+//! offset after the header, which [get_user_word_from_tagged_bytes] reads without validating
+//! the payload.  Log-style storage can similarly stamp records with [TaggedOptions::timestamp],
+//! read back with [get_timestamp_from_tagged_bytes], and replicated logs can number them
+//! with a [Sequencer], read back with [get_sequence_from_tagged_bytes].  A [SequenceTracker]
+//! on the reading side reports gaps, reordering and duplicates.
+//!
+//! # Digest Algorithms
+//! Payloads are protected by a CRC32 digest by default.  Faster or stronger algorithms can be
+//! enabled through features and chosen per call through [TaggedOptions]:
+//! - `crc32c`: [DigestAlgorithm::Crc32c]
+//! - `xxh3`: [DigestAlgorithm::Xxh3]
+//! - `blake3`: [DigestAlgorithm::Blake3]
+//!
+//! # Compression
+//! Large payloads can be compressed by choosing a [CompressionCodec] in [TaggedOptions], with
+//! the `zstd` and `lz4` features enabling [CompressionCodec::Zstd] and [CompressionCodec::Lz4]
+//! respectively.  The codec is recorded in the header, and
+//! [access_from_tagged_bytes_with_scratch] transparently decompresses into a caller-provided
+//! buffer while still accessing uncompressed payloads in place.
+//!
+//! A container implementing [ClearRegion] can keep a struct of common fields uncompressed in
+//! front of the compressed payload with [to_tagged_bytes_with_clear_region], so filters read
+//! them with [access_clear_region] without decompressing.
+//!
+//! # Encryption
+//! With the `encryption` feature, setting an [EncryptionKey] in [TaggedOptions] encrypts the
+//! (possibly compressed) payload with XChaCha20-Poly1305.  The header stays readable so
+//! records can still be routed by type and version, while the payload must be read through
+//! [access_from_encrypted_tagged_bytes].
+//!
+//! # Signatures
+//! With the `ed25519` feature, `sign_tagged_bytes` appends a detached signature over the header
+//! and payload of a tagged byte array, and `access_from_signed_tagged_bytes` only returns the
+//! archived value once the signature has been verified against a trusted key.  Unsigned
+//! readers can still access signed byte arrays as usual.
+//!
+//! Whole archives of frames can be sealed once finished: `seal_archive` appends a signed
+//! manifest of their entries and makes the file read-only, and `verify_seal` detects any
+//! entry changed, added or removed since.
+
+// The `no_panic` feature, see "Panic-Free Reading" in the README
+#![cfg_attr(
+    all(feature = "no_panic", not(test)),
+    deny(
+        clippy::indexing_slicing,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic
+    )
+)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::mem::MaybeUninit;
+use core::ops::Range;
 use core::{error::Error, fmt};
 use rkyv::api::high::HighSerializer;
-use rkyv::ser::allocator::ArenaHandle;
+use rkyv::api::low::LowSerializer;
+use rkyv::rancor::Source;
+use rkyv::ser::allocator::{Arena, ArenaHandle, SubAllocator};
+use rkyv::ser::Writer;
 use rkyv::util::AlignedVec;
-use rkyv::with::InlineAsBox;
 use rkyv::{Archive, Serialize};
 
-// Re-export the derive macro
+pub(crate) use access::{
+    access_encrypted_tagged, access_encrypted_tagged_in_place, access_tagged,
+    access_tagged_with_scratch, access_tagged_with_strategy, check_header, compression_codec,
+    digest_algorithm, encryption_algorithm, split_metadata, verify_payload,
+};
+use extensions::HEADER_FLAG_EXTENSIONS;
+pub(crate) use header::{
+    read_header, write_header, TaggedVersionedHeader, HEADER_FLAG_SIGNED, TAGGED_HEADER_SIZE,
+};
+use header_words::HeaderWords;
+
+mod access;
+mod any;
+#[cfg(feature = "tokio")]
+mod async_io;
+mod batch;
+#[cfg(feature = "bench_support")]
+pub mod bench_support;
+#[cfg(feature = "std")]
+mod bulk_migration;
+#[cfg(feature = "std")]
+mod bytes_codec;
+#[cfg(feature = "std")]
+mod census;
+mod clear_region;
+mod cleartext;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "codec")]
+mod codec;
+mod compression;
+mod content_hash;
+#[cfg(feature = "std")]
+mod convert;
+#[cfg(feature = "std")]
+mod datagram;
+#[cfg(feature = "debug_json")]
+mod debug_json;
+mod digest;
+mod dual;
+mod encryption;
+mod extensions;
+mod fingerprint;
+mod framing;
+mod header;
+mod header_words;
+#[cfg(feature = "schema_registry")]
+mod http_registry;
+#[cfg(feature = "std")]
+mod idempotency;
+#[cfg(feature = "ids")]
+pub mod ids;
+mod inspect;
+mod instrumented;
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+mod log_writer;
+mod migration;
+mod minimize;
+#[cfg(feature = "memmap2")]
+mod mmap;
+pub mod mqtt;
+mod profile;
+mod provenance;
+#[cfg(feature = "std")]
+mod quota;
+#[cfg(feature = "raw")]
+pub mod raw;
+mod records;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "memmap2")]
+mod reload;
+#[cfg(feature = "std")]
+mod repair;
+#[cfg(feature = "std")]
+mod report;
+#[cfg(feature = "std")]
+mod resync;
+#[cfg(feature = "std")]
+mod rollout;
+#[cfg(feature = "std")]
+mod schema_resolver;
+#[cfg(all(feature = "ed25519", feature = "std"))]
+mod seal;
+mod self_check;
+mod serializer;
+#[cfg(feature = "bytes")]
+mod shared_bytes;
+mod signature;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod size;
+#[cfg(feature = "std")]
+mod snapshot;
+mod sources;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod test_vectors;
+#[cfg(feature = "proptest")]
+pub mod testing;
+#[cfg(feature = "std")]
+mod throttle;
+mod type_name;
+mod validation;
+#[cfg(feature = "std")]
+mod versioned_file;
+#[cfg(feature = "std")]
+mod versioned_log;
+#[cfg(feature = "zmq")]
+pub mod zeromq;
+
+pub use any::{access_any_from_tagged_bytes, AnyVersionedRef};
+#[cfg(feature = "tokio")]
+pub use async_io::{read_tagged_async, write_tagged_async, AsyncTaggedReader};
+pub use batch::{to_tagged_batch, to_tagged_batch_with, TaggedBatch};
+#[cfg(feature = "std")]
+pub use bulk_migration::{migrate_file, migrate_stream, MigrateReport, MigrationRegistry};
+#[cfg(feature = "std")]
+pub use bytes_codec::BytesCodec;
+#[cfg(feature = "std")]
+pub use census::{scan_versions, write_version_report, VersionCount};
+pub use clear_region::{
+    access_clear_region, to_tagged_bytes_with_clear_region, ClearRegion,
+    EXTENSION_KEY_CLEAR_REGION,
+};
+pub use cleartext::{
+    get_cleartext_field_from_tagged_bytes, to_tagged_bytes_with_cleartext, CleartextFields,
+};
+#[cfg(feature = "codec")]
+pub use codec::{
+    into_record_sink, into_record_stream, BytesCodecAdapter, RecordSink, RecordStream,
+    VersionedCodec,
+};
+pub use compression::CompressionCodec;
+pub use content_hash::{payload_content_hash, ContentHasher};
+#[cfg(feature = "std")]
+pub use convert::{
+    convert_file, convert_record, convert_stream, ConvertOptions, ConvertReport,
+};
+#[cfg(feature = "std")]
+pub use datagram::{DatagramProfile, DatagramReassembler, FRAGMENT_HEADER_SIZE};
+#[cfg(feature = "debug_json")]
+#[doc(hidden)]
+pub use debug_json::archived_variant_to_json;
+#[cfg(feature = "debug_json")]
+pub use debug_json::{to_debug_json, DebugJson};
+pub use digest::DigestAlgorithm;
+pub use dual::{
+    access_dual_from_tagged_bytes, select_dual_tagged_bytes, to_tagged_bytes_dual,
+    to_tagged_bytes_dual_with, EXTENSION_KEY_DUAL_LATEST,
+};
+pub use encryption::{EncryptionAlgorithm, EncryptionKey};
+pub use extensions::{get_extensions_from_tagged_bytes, HeaderExtensions};
+pub use fingerprint::{
+    combine_fingerprints, SchemaFingerprint, EXTENSION_KEY_SCHEMA_FINGERPRINT,
+};
+pub use framing::{
+    append_framed, framed_records, FramedRecords, FRAME_ALIGNMENT, FRAME_PREFIX_SIZE,
+};
+pub use header_words::{
+    get_sequence_from_tagged_bytes, get_timestamp_from_tagged_bytes,
+    get_user_word_from_tagged_bytes, SequenceEvent, SequenceTracker, Sequencer,
+};
+#[cfg(feature = "schema_registry")]
+pub use http_registry::HttpSchemaRegistry;
+#[cfg(feature = "std")]
+pub use idempotency::{
+    get_idempotency_key_from_tagged_bytes, set_idempotency_key, DedupWindow,
+    EXTENSION_KEY_IDEMPOTENCY,
+};
+pub use inspect::{inspect_tagged, ChecksumStatus, TaggedInspection, INSPECTION_PREVIEW_LEN};
+pub use instrumented::{
+    to_tagged_bytes_instrumented, CountingAllocator, CountingWriter, SerializationStats,
+};
+#[cfg(feature = "std")]
+pub use io::{
+    peek_header, peek_type_and_version, read_tagged, write_tagged, write_tagged_vectored,
+    write_tagged_vectored_with, PeekedHeader,
+};
+#[cfg(feature = "std")]
+pub use log_writer::{DurableWrite, LogWriter, SyncPolicy};
+pub use migration::{
+    drop_unknown_keys, truncate_to, DropUnknownKeys, LossyEdit, MigrationReport, TruncateTo,
+};
+pub use minimize::{minimize_failure, same_failure, MinimizedFailure};
+#[cfg(feature = "memmap2")]
+pub use mmap::{MmapArchive, PinnedEntry};
+pub use profile::{reframe, EntryView, HeaderProfile, Reframed};
+pub use provenance::{
+    get_writer_identity_from_tagged_bytes, WriterIdentity, EXTENSION_KEY_APP_VERSION,
+    EXTENSION_KEY_HOSTNAME, EXTENSION_KEY_WRITER_ID,
+};
+#[cfg(feature = "std")]
+pub use quota::{
+    get_namespace_from_tagged_bytes, set_namespace, NamespaceUsage, Quota, QuotaPolicy,
+    QuotaWriter, EXTENSION_KEY_NAMESPACE,
+};
+pub use records::{append_tagged, TaggedRecordHeader, TaggedRecordIter, RECORD_ALIGNMENT};
+#[cfg(feature = "std")]
+pub use registry::TypeRegistry;
+#[cfg(feature = "memmap2")]
+pub use reload::ReloadingReader;
+#[cfg(feature = "std")]
+pub use repair::{repair_file, repair_stream, RepairReport};
+#[cfg(feature = "std")]
+pub use report::{validate_stream, validate_stream_file, StreamReport};
+#[cfg(feature = "std")]
+pub use resync::{
+    resync_any_framed_records, resync_framed_records, ResyncFramedRecords, ResyncItem,
+};
+#[cfg(feature = "std")]
+pub use rollout::{FeatureFlags, RolloutObserver, VersionGate};
+#[cfg(feature = "std")]
+pub use schema_resolver::{SchemaResolver, SchemaVersion, StaticSchemaRegistry, TypeSchema};
+#[cfg(all(feature = "ed25519", feature = "std"))]
+pub use seal::{seal_archive, verify_seal, SealInfo};
+pub use self_check::{
+    self_check, self_check_with_migration, SelfCheckFailure, SelfCheckReport, VersionCheck,
+};
+pub use serializer::TaggedSerializer;
+#[cfg(feature = "bytes")]
+pub use shared_bytes::{to_shared_bytes, to_shared_bytes_with, BytesMutWriter, TaggedBytes};
+#[cfg(feature = "ed25519")]
+pub use signature::{
+    access_from_signed_tagged_bytes, sign_tagged_bytes, verify_signed_tagged_bytes,
+};
+pub use size::{serialized_tagged_size, serialized_tagged_size_with, SizeCounter};
+#[cfg(feature = "std")]
+pub use snapshot::{read_snapshot, write_snapshot_atomic, write_snapshot_atomic_with};
+#[cfg(feature = "std")]
+pub use sources::SystemClock;
+#[cfg(feature = "encryption")]
+pub use sources::SystemRng;
+pub use sources::{Clock, ManualClock, Rng, SplitMixRng};
+#[cfg(feature = "std")]
+pub use stats::{stream_stats, stream_stats_file, StreamStats, VersionStats};
+#[cfg(feature = "std")]
+pub use test_vectors::write_test_vectors;
+#[cfg(feature = "std")]
+pub use throttle::{ThrottleUnit, ThrottledWriter};
+pub use type_name::{
+    get_type_name_from_tagged_bytes, set_type_name, to_tagged_bytes_named, EmbeddedTypeName,
+    EXTENSION_KEY_TYPE_NAME, EXTENSION_KEY_VARIANT_NAME,
+};
+pub use validation::{ValidationConfig, ValidationLimit, VersionObserver};
+#[cfg(feature = "std")]
+pub use versioned_file::{
+    FileIndexEntry, VersionedFile, VersionedFileWriter, EXTENSION_KEY_CONTINUATIONS,
+    EXTENSION_KEY_CONTINUATION_OF,
+};
+#[cfg(feature = "std")]
+pub use versioned_log::{LogTailer, VersionedLog};
+
+// Re-exported for the derive macro, so users don't need these as direct dependencies
 pub use const_crc32;
-pub use rkyv_versioned_derive::VersionedArchiveContainer;
+#[cfg(feature = "bench_support")]
+pub use criterion;
+#[cfg(feature = "ed25519")]
+pub use ed25519_dalek;
+#[cfg(feature = "proptest")]
+pub use proptest;
+#[cfg(feature = "debug_json")]
+pub use serde_json;
+
+// Lets code generated by the derive macro refer to `::rkyv_versioned` from within this crate
+extern crate self as rkyv_versioned;
+pub use rkyv_versioned_derive::{SchemaFingerprint, VersionedArchiveContainer};
 
 #[derive(Debug)]
 pub enum RkyvVersionedError {
     BufferTooSmallError,
     UnexpectedTypeError(u32, u32),
     UnsupportedVersionError(u32),
-    RkyvError(rkyv::rancor::Error),
+    UnsupportedDigestError(u8),
+    ChecksumMismatchError,
+    PayloadTooLargeError(usize),
+    DatagramTooLargeError(usize, usize),
+    InvalidFragmentError(u32),
+    UnsupportedCompressionError(u8),
+    CompressedPayloadError,
+    CompressionError(Box<dyn Error + Send + Sync>),
+    TransportError(Box<dyn Error + Send + Sync>),
+    InvalidMessageError,
+    UnsupportedEncryptionError(u8),
+    EncryptedPayloadError,
+    UnsupportedHeaderFlagsError(u8),
+    MissingSignatureError,
+    SignatureMismatchError,
+    InvalidFrameError(usize),
+    InvalidExtensionError(u16),
+    LimitExceededError(ValidationLimit, usize),
+    VersionSunsetError(u32, u64),
+    UnknownTypeIdError(u32),
+    MisalignedError(usize),
+    TrailingBytesError(usize),
+    RkyvError(Box<dyn Error + Send + Sync>),
+    QuotaExceededError(String),
+    /// A user migration failed, with the message of its error, see
+    /// [migrate_stream](crate::migrate_stream).
+    MigrationError(String),
+    /// The [SchemaFingerprint] stored in a record doesn't match the one its version was built
+    /// with, with the expected and found fingerprints.
+    SchemaMismatchError(u32, u32),
+    /// The header records a version of the tagged format this crate can't read, with the
+    /// version found.  Data written before the format version was recorded in the header is
+    /// reported as format 0.
+    UnsupportedFormatError(u8),
+    /// An error reading the record with the given index, at the given byte offset of a
+    /// stream or file, see [RkyvVersionedError::at_record].
+    RecordError(usize, usize, Box<RkyvVersionedError>),
+}
+impl Error for RkyvVersionedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RkyvVersionedError::CompressionError(e)
+            | RkyvVersionedError::TransportError(e)
+            | RkyvVersionedError::RkyvError(e) => Some(&**e),
+            RkyvVersionedError::RecordError(_, _, e) => Some(&**e),
+            _ => None,
+        }
+    }
 }
-impl Error for RkyvVersionedError {}
 impl fmt::Display for RkyvVersionedError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             RkyvVersionedError::BufferTooSmallError => {
-                write!(f, "Buffer was less than the size of the header")
+                write!(f, "Buffer was less than the size of the header and payload")
             }
             RkyvVersionedError::UnexpectedTypeError(expected, got) => {
                 write!(f, "Expected type_id {}, got {}", expected, got)
@@ -157,53 +753,581 @@ impl fmt::Display for RkyvVersionedError {
             RkyvVersionedError::UnsupportedVersionError(version) => {
                 write!(f, "Unsupported version {}", version)
             }
+            RkyvVersionedError::UnsupportedDigestError(digest) => {
+                write!(f, "Unsupported or disabled digest algorithm {}", digest)
+            }
+            RkyvVersionedError::ChecksumMismatchError => {
+                write!(f, "Payload digest did not match the digest in the trailer")
+            }
+            RkyvVersionedError::PayloadTooLargeError(len) => {
+                write!(f, "Payload of {} bytes is too large to be tagged", len)
+            }
+            RkyvVersionedError::DatagramTooLargeError(len, max) => {
+                write!(
+                    f,
+                    "Tagged length {} exceeds the datagram budget of {}",
+                    len, max
+                )
+            }
+            RkyvVersionedError::InvalidFragmentError(message_id) => {
+                write!(f, "Invalid fragment header for message {}", message_id)
+            }
+            RkyvVersionedError::UnsupportedCompressionError(codec) => {
+                write!(f, "Unsupported or disabled compression codec {}", codec)
+            }
+            RkyvVersionedError::CompressedPayloadError => {
+                write!(f, "Payload is compressed and cannot be accessed in place")
+            }
+            RkyvVersionedError::CompressionError(e) => write!(f, "Compression failed: {}", e),
+            RkyvVersionedError::TransportError(e) => write!(f, "Transport failed: {}", e),
+            RkyvVersionedError::InvalidMessageError => {
+                write!(f, "Message was not framed as a tagged byte array")
+            }
+            RkyvVersionedError::UnsupportedEncryptionError(algorithm) => {
+                write!(
+                    f,
+                    "Unsupported or disabled encryption algorithm {}",
+                    algorithm
+                )
+            }
+            RkyvVersionedError::EncryptedPayloadError => write!(
+                f,
+                "Payload is encrypted and could not be decrypted with the provided key"
+            ),
+            RkyvVersionedError::UnsupportedHeaderFlagsError(flags) => {
+                write!(f, "Unsupported header flags {:#04x}", flags)
+            }
+            RkyvVersionedError::MissingSignatureError => {
+                write!(f, "Tagged bytes are not signed")
+            }
+            RkyvVersionedError::SignatureMismatchError => {
+                write!(f, "Signature did not match the header and payload")
+            }
+            RkyvVersionedError::InvalidFrameError(offset) => {
+                write!(f, "Invalid or truncated frame at offset {}", offset)
+            }
+            RkyvVersionedError::InvalidExtensionError(key) => {
+                write!(f, "Invalid header extension with key {}", key)
+            }
+            RkyvVersionedError::LimitExceededError(limit, max) => {
+                write!(f, "Exceeded the {} limit of {}", limit, max)
+            }
+            RkyvVersionedError::VersionSunsetError(version, sunset) => {
+                write!(f, "Version {} was sunset at {}", version, sunset)
+            }
+            RkyvVersionedError::UnknownTypeIdError(type_id) => {
+                write!(f, "No handler registered for type_id {}", type_id)
+            }
+            RkyvVersionedError::MisalignedError(offset) => {
+                write!(
+                    f,
+                    "Record at offset {} is not aligned for in-place access",
+                    offset
+                )
+            }
+            RkyvVersionedError::TrailingBytesError(len) => {
+                write!(f, "{} trailing bytes after the tagged byte array", len)
+            }
             RkyvVersionedError::RkyvError(e) => write!(f, "{}", e),
+            RkyvVersionedError::QuotaExceededError(namespace) => {
+                write!(f, "Namespace {:?} exceeded its quota", namespace)
+            }
+            RkyvVersionedError::MigrationError(message) => {
+                write!(f, "Migration failed: {}", message)
+            }
+            RkyvVersionedError::SchemaMismatchError(expected, found) => write!(
+                f,
+                "Schema fingerprint mismatch, expected {:#010x}, found {:#010x}",
+                expected, found
+            ),
+            RkyvVersionedError::UnsupportedFormatError(format) => {
+                write!(f, "Unsupported tagged format version {}", format)
+            }
+            RkyvVersionedError::RecordError(index, offset, e) => {
+                write!(f, "Record {} at offset {}: {}", index, offset, e)
+            }
+        }
+    }
+}
+
+impl RkyvVersionedError {
+    /// A stable numeric code identifying the variant, e.g. to report errors across a process
+    /// or language boundary.  Codes are never reused or renumbered, new variants get new
+    /// codes.
+    pub fn code(&self) -> u16 {
+        match self {
+            RkyvVersionedError::BufferTooSmallError => 1,
+            RkyvVersionedError::UnexpectedTypeError(..) => 2,
+            RkyvVersionedError::UnsupportedVersionError(_) => 3,
+            RkyvVersionedError::UnsupportedDigestError(_) => 4,
+            RkyvVersionedError::ChecksumMismatchError => 5,
+            RkyvVersionedError::PayloadTooLargeError(_) => 6,
+            RkyvVersionedError::DatagramTooLargeError(..) => 7,
+            RkyvVersionedError::InvalidFragmentError(_) => 8,
+            RkyvVersionedError::UnsupportedCompressionError(_) => 9,
+            RkyvVersionedError::CompressedPayloadError => 10,
+            RkyvVersionedError::CompressionError(_) => 11,
+            RkyvVersionedError::TransportError(_) => 12,
+            RkyvVersionedError::InvalidMessageError => 13,
+            RkyvVersionedError::UnsupportedEncryptionError(_) => 14,
+            RkyvVersionedError::EncryptedPayloadError => 15,
+            RkyvVersionedError::UnsupportedHeaderFlagsError(_) => 16,
+            RkyvVersionedError::MissingSignatureError => 17,
+            RkyvVersionedError::SignatureMismatchError => 18,
+            RkyvVersionedError::InvalidFrameError(_) => 19,
+            RkyvVersionedError::InvalidExtensionError(_) => 20,
+            RkyvVersionedError::LimitExceededError(..) => 21,
+            RkyvVersionedError::VersionSunsetError(..) => 22,
+            RkyvVersionedError::UnknownTypeIdError(_) => 23,
+            RkyvVersionedError::MisalignedError(_) => 24,
+            RkyvVersionedError::TrailingBytesError(_) => 25,
+            RkyvVersionedError::RkyvError(_) => 26,
+            RkyvVersionedError::QuotaExceededError(_) => 27,
+            RkyvVersionedError::RecordError(..) => 28,
+            RkyvVersionedError::MigrationError(_) => 29,
+            RkyvVersionedError::SchemaMismatchError(..) => 30,
+            RkyvVersionedError::UnsupportedFormatError(_) => 31,
+        }
+    }
+
+    /// The [VersionedError] category of the error, for handling errors programmatically
+    /// without matching every variant.  Located errors are categorized by the error they
+    /// wrap.
+    pub fn kind(&self) -> VersionedError {
+        match self {
+            RkyvVersionedError::UnexpectedTypeError(..)
+            | RkyvVersionedError::UnknownTypeIdError(_)
+            | RkyvVersionedError::SchemaMismatchError(..) => VersionedError::TypeMismatch,
+            RkyvVersionedError::UnsupportedVersionError(_)
+            | RkyvVersionedError::VersionSunsetError(..) => VersionedError::UnsupportedVersion,
+            RkyvVersionedError::BufferTooSmallError => VersionedError::Truncated,
+            RkyvVersionedError::ChecksumMismatchError => VersionedError::Checksum,
+            RkyvVersionedError::RkyvError(_) | RkyvVersionedError::MigrationError(_) => {
+                VersionedError::Validation
+            }
+            RkyvVersionedError::UnsupportedDigestError(_)
+            | RkyvVersionedError::UnsupportedCompressionError(_)
+            | RkyvVersionedError::CompressedPayloadError
+            | RkyvVersionedError::UnsupportedEncryptionError(_)
+            | RkyvVersionedError::UnsupportedHeaderFlagsError(_)
+            | RkyvVersionedError::UnsupportedFormatError(_) => VersionedError::Unsupported,
+            RkyvVersionedError::InvalidFragmentError(_)
+            | RkyvVersionedError::CompressionError(_)
+            | RkyvVersionedError::InvalidMessageError
+            | RkyvVersionedError::InvalidFrameError(_)
+            | RkyvVersionedError::InvalidExtensionError(_)
+            | RkyvVersionedError::MisalignedError(_)
+            | RkyvVersionedError::TrailingBytesError(_) => VersionedError::Malformed,
+            RkyvVersionedError::PayloadTooLargeError(_)
+            | RkyvVersionedError::DatagramTooLargeError(..)
+            | RkyvVersionedError::LimitExceededError(..)
+            | RkyvVersionedError::QuotaExceededError(_) => VersionedError::Limit,
+            RkyvVersionedError::EncryptedPayloadError
+            | RkyvVersionedError::MissingSignatureError
+            | RkyvVersionedError::SignatureMismatchError => VersionedError::Authentication,
+            RkyvVersionedError::TransportError(_) => VersionedError::Transport,
+            RkyvVersionedError::RecordError(_, _, e) => e.kind(),
+        }
+    }
+
+    /// Wraps the error in a [RkyvVersionedError::RecordError] locating the record it occurred
+    /// in, by its index and the byte offset of its start in the stream or file.  Errors which
+    /// are already located are returned as they are.
+    pub fn at_record(self, index: usize, offset: usize) -> Self {
+        match self {
+            RkyvVersionedError::RecordError(..) => self,
+            e => RkyvVersionedError::RecordError(index, offset, Box::new(e)),
+        }
+    }
+
+    /// The index and byte offset of the record the error occurred in, if it was located with
+    /// [at_record](Self::at_record).
+    pub fn record_location(&self) -> Option<(usize, usize)> {
+        match self {
+            RkyvVersionedError::RecordError(index, offset, _) => Some((*index, *offset)),
+            _ => None,
+        }
+    }
+
+    /// The error without the location added by [at_record](Self::at_record), e.g. to match
+    /// on what went wrong wherever it happened.
+    pub fn root(&self) -> &RkyvVersionedError {
+        match self {
+            RkyvVersionedError::RecordError(_, _, e) => e.root(),
+            e => e,
         }
     }
 }
 
-/// A container that holds a versioned item along with its type and version IDs.
+/// A coarse category of [RkyvVersionedError], returned by [RkyvVersionedError::kind].
 ///
-/// This is the structure that is the result of serializing a versioned container. We
-/// deserialize this with the `T` type set as `()` to allow us to "peek" at the header fields
-/// without deserializing the entire item.
-#[derive(Debug, Clone, Archive, Serialize)]
-pub struct TaggedVersionedStruct<'a, T: Archive> {
-    pub type_id: u32,
-    pub version_id: u32,
-    #[rkyv(with = InlineAsBox)]
-    pub inner: &'a T,
+/// The discriminants are stable, so they can be stored or sent across process boundaries
+/// with `kind as u8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum VersionedError {
+    /// The record holds a different container type, or no handler is registered for it.
+    TypeMismatch = 1,
+    /// The record holds a version the reader doesn't support, or which has been sunset.
+    UnsupportedVersion = 2,
+    /// The buffer is shorter than the record it holds.
+    Truncated = 3,
+    /// The payload doesn't match its digest.
+    Checksum = 4,
+    /// The payload failed `rkyv` validation.
+    Validation = 5,
+    /// The record uses a format version, digest, codec, encryption algorithm or header flag
+    /// which isn't supported or enabled, or needs an access function which supports it.
+    Unsupported = 6,
+    /// A frame, fragment, extension or compressed payload is malformed, or the record isn't
+    /// laid out as expected.
+    Malformed = 7,
+    /// The record exceeds a size, validation or namespace limit.
+    Limit = 8,
+    /// The record couldn't be decrypted, or its signature is missing or doesn't match.
+    Authentication = 9,
+    /// Reading or writing the underlying transport failed.
+    Transport = 10,
+}
+
+impl fmt::Display for VersionedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VersionedError::TypeMismatch => "type mismatch",
+            VersionedError::UnsupportedVersion => "unsupported version",
+            VersionedError::Truncated => "truncated",
+            VersionedError::Checksum => "checksum mismatch",
+            VersionedError::Validation => "validation failed",
+            VersionedError::Unsupported => "unsupported",
+            VersionedError::Malformed => "malformed",
+            VersionedError::Limit => "limit exceeded",
+            VersionedError::Authentication => "authentication failed",
+            VersionedError::Transport => "transport failed",
+        })
+    }
+}
+
+/// Options controlling how a versioned container is tagged by [to_tagged_bytes_with].
+#[derive(Debug, Default, Clone)]
+pub struct TaggedOptions {
+    /// The algorithm used to compute the payload digest trailer.
+    pub digest: DigestAlgorithm,
+    /// The codec used to compress the payload.
+    pub compression: CompressionCodec,
+    /// The key used to encrypt the payload with
+    /// [EncryptionAlgorithm::XChaCha20Poly1305], or `None` to store it in plaintext.
+    pub encryption: Option<EncryptionKey>,
+    /// Key/value metadata stored in front of the payload.
+    pub extensions: HeaderExtensions,
+    /// An application defined word, e.g. a routing key or shard ID, which can be read back with
+    /// [get_user_word_from_tagged_bytes] without validating the payload.
+    pub user_word: Option<u64>,
+    /// A timestamp, e.g. when the record was written in milliseconds since the Unix epoch,
+    /// which can be read back with [get_timestamp_from_tagged_bytes] without validating the
+    /// payload.  Its unit and epoch are up to the application.
+    pub timestamp: Option<u64>,
+    /// A sequence number, which can be read back with [get_sequence_from_tagged_bytes] without
+    /// validating the payload.  Usually set by a [Sequencer].
+    pub sequence: Option<u64>,
+    /// The clock stamping records with the current time, in milliseconds since the Unix
+    /// epoch, when no [timestamp](Self::timestamp) is given.  Records aren't timestamped if
+    /// neither is set.
+    pub clock: Option<&'static dyn Clock>,
+    /// The source of encryption nonces, or `None` for the operating system's entropy source.
+    pub rng: Option<&'static dyn Rng>,
+}
+
+impl TaggedOptions {
+    /// The words stored in front of the payload.
+    fn header_words(&self) -> HeaderWords {
+        HeaderWords {
+            user_word: self.user_word,
+            timestamp: self
+                .timestamp
+                .or_else(|| self.clock.map(|clock| clock.now_millis())),
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// Builds the header for a body stored in one or more consecutive `parts` and computes its
+/// digest trailer.
+fn tag_payload<T: VersionedContainer>(
+    version_id: u32,
+    parts: &[&[u8]],
+    options: &TaggedOptions,
+) -> Result<(TaggedVersionedHeader, digest::Digest), RkyvVersionedError> {
+    let len = parts.iter().map(|part| part.len()).sum();
+    let payload_len =
+        u32::try_from(len).map_err(|_| RkyvVersionedError::PayloadTooLargeError(len))?;
+    let header = TaggedVersionedHeader {
+        type_id: T::ARCHIVE_TYPE_ID,
+        version_id,
+        payload_len,
+        digest: options.digest.id(),
+        compression: options.compression.id(),
+        encryption: match options.encryption {
+            Some(_) => EncryptionAlgorithm::XChaCha20Poly1305.id(),
+            None => EncryptionAlgorithm::None.id(),
+        },
+        flags: match options.extensions.is_empty() {
+            true => 0,
+            false => HEADER_FLAG_EXTENSIONS,
+        } | options.header_words().flags(),
+    };
+    Ok((header, options.digest.compute_parts(parts)?))
 }
 
 /// Serializes a versioned container into a tagged byte array to be deserialized from
 /// [access_from_tagged_bytes].  This is analogous to `rkyv::to_bytes`, but only for
 /// [VersionedContainer] derived enums.
 ///
+/// The payload is protected by the default [DigestAlgorithm], use [to_tagged_bytes_with] to
+/// choose a different one.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+///
+/// # Returns
+///
+/// A `Result` containing either the serialized byte array or an error if serialization fails.
+pub fn to_tagged_bytes<T>(item: &T) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    to_tagged_bytes_with(item, &TaggedOptions::default())
+}
+
+/// Serializes a versioned container into a tagged byte array using the provided
+/// [TaggedOptions].
+///
 /// # Arguments
 ///
 /// * `item` - A reference to the item to be serialized.
+/// * `options` - The options controlling the header and trailer.
 ///
 /// # Returns
 ///
 /// A `Result` containing either the serialized byte array or an error if serialization fails.
-pub fn to_tagged_bytes<T: VersionedContainer>(
+pub fn to_tagged_bytes_with<T>(
     item: &T,
+    options: &TaggedOptions,
 ) -> Result<AlignedVec, RkyvVersionedError>
 where
-    T: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
 {
-    let container = TaggedVersionedStruct {
-        type_id: T::ARCHIVE_TYPE_ID,
-        version_id: item.get_entry_version_id(),
-        inner: item,
+    to_tagged_bytes_with_strategy::<T, rkyv::rancor::Error>(item, options)
+}
+
+/// As [to_tagged_bytes_with], but serializing with the `rancor` error type `E`, see
+/// [Error Types](crate#error-types).
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `options` - The options controlling the header and trailer.
+///
+/// # Returns
+///
+/// A `Result` containing either the serialized byte array or an error if serialization fails,
+/// where errors raised by `rkyv` are wrapped in [RkyvVersionedError::RkyvError].
+pub fn to_tagged_bytes_with_strategy<T, E>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, E>>,
+    E: Source,
+{
+    tag_serialized::<T>(
+        item.get_entry_version_id(),
+        options,
+        AlignedVec::new(),
+        |out| rkyv::api::high::to_bytes_in::<_, E>(item, out).map_err(rkyv_error),
+    )
+}
+
+/// Wraps an error of the `rancor` error type `E` in a [RkyvVersionedError::RkyvError], boxed
+/// as it is so that callers can downcast it back to `E`.
+pub(crate) fn rkyv_error<E: Source>(error: E) -> RkyvVersionedError {
+    RkyvVersionedError::RkyvError(Box::new(error))
+}
+
+/// As [to_tagged_bytes], but builds the tagged byte array in `buf`, replacing its contents and
+/// reusing its allocation, so loops serializing many records don't allocate a new buffer for
+/// each one.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `buf` - The buffer to build the tagged byte array in.  It is left empty if serialization
+///   fails.
+///
+/// # Returns
+///
+/// A `Result` which is an error if serialization fails.
+pub fn to_tagged_bytes_into<T>(
+    item: &T,
+    buf: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    to_tagged_bytes_into_with(item, &TaggedOptions::default(), buf)
+}
+
+/// As [to_tagged_bytes_with], but builds the tagged byte array in `buf` like
+/// [to_tagged_bytes_into].  Compressed and encrypted payloads are still staged in temporary
+/// buffers.
+pub fn to_tagged_bytes_into_with<T>(
+    item: &T,
+    options: &TaggedOptions,
+    buf: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let mut bytes = core::mem::take(buf);
+    bytes.clear();
+    *buf = tag_serialized::<T>(item.get_entry_version_id(), options, bytes, |out| {
+        rkyv::api::high::to_bytes_in::<_, rkyv::rancor::Error>(item, out).map_err(rkyv_error)
+    })?;
+    Ok(())
+}
+
+/// Builds a tagged byte array in `bytes`, which should be empty, from a payload produced by
+/// `serialize`.  `serialize` is called exactly once, and must append the `rkyv` archive of
+/// the container to the buffer it is passed and return it.
+pub(crate) fn tag_serialized<T: VersionedContainer>(
+    version_id: u32,
+    options: &TaggedOptions,
+    mut bytes: AlignedVec,
+    serialize: impl FnOnce(AlignedVec) -> Result<AlignedVec, RkyvVersionedError>,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    tag_serialized_in::<T, _>(version_id, options, &mut bytes, |out| {
+        *out = serialize(core::mem::take(out))?;
+        Ok(())
+    })?;
+    Ok(bytes)
+}
+
+/// As [tag_serialized], but appends the tagged byte array to `writer` in place, so it keeps
+/// its allocation even if tagging fails.  `serialize` must append the `rkyv` archive of the
+/// container to the writer it is passed, which is `writer` itself unless the payload is
+/// compressed or encrypted, in which case it is staged in a new `W` first.
+///
+/// The bytes written so far must be exposed through `AsMut<[u8]>`, so that the header can be
+/// filled in once the payload length is known.
+pub(crate) fn tag_serialized_in<T, W>(
+    version_id: u32,
+    options: &TaggedOptions,
+    writer: &mut W,
+    serialize: impl FnOnce(&mut W) -> Result<(), RkyvVersionedError>,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer,
+    W: Writer<rkyv::rancor::Error> + AsMut<[u8]> + Default,
+{
+    // Record the fingerprint of fingerprinted versions, unless the caller already did
+    let fingerprinted;
+    let options = match fingerprinted_options::<T>(version_id, options)? {
+        Some(options) => {
+            fingerprinted = options;
+            &fingerprinted
+        }
+        None => options,
     };
-    rkyv::to_bytes(&container).map_err(|e| RkyvVersionedError::RkyvError(e))
+
+    // Leave room for the header, we can only fill it in once the payload length is known
+    let start = writer.pos();
+    writer.write(&[0; TAGGED_HEADER_SIZE]).map_err(rkyv_error)?;
+    options.header_words().encode(writer)?;
+    options.extensions.encode(writer)?;
+    match (&options.compression, &options.encryption) {
+        (CompressionCodec::None, None) => serialize(writer)?,
+        (compression, encryption) => {
+            let mut payload = W::default();
+            serialize(&mut payload)?;
+            let mut stored = AlignedVec::new();
+            compression.compress(payload.as_mut(), &mut stored)?;
+            if let Some(key) = encryption {
+                // Compress before encrypting, ciphertext doesn't compress
+                let compressed = core::mem::take(&mut stored);
+                let aad = encryption::associated_data(
+                    T::ARCHIVE_TYPE_ID,
+                    version_id,
+                    tagged_body(writer, start)?,
+                );
+                encryption::encrypt(key, options.rng, &aad, &compressed, &mut stored)?;
+            }
+            writer.write(&stored).map_err(rkyv_error)?;
+        }
+    }
+
+    let (header, digest) =
+        tag_payload::<T>(version_id, &[tagged_body(writer, start)?], options)?;
+    write_header(
+        writer
+            .as_mut()
+            .get_mut(start..)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?,
+        &header,
+    )?;
+    writer.write(digest.as_bytes()).map_err(rkyv_error)
+}
+
+/// The body written to `writer` so far after the header of the tagged byte array starting at
+/// `start`.
+fn tagged_body<W: AsMut<[u8]>>(
+    writer: &mut W,
+    start: usize,
+) -> Result<&[u8], RkyvVersionedError> {
+    writer
+        .as_mut()
+        .get(start + TAGGED_HEADER_SIZE..)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)
+}
+
+/// Returns `options` with the [SchemaFingerprint] of version `version_id` of `T` added to its
+/// extensions, or `None` if the version has no fingerprint or it is already set.
+pub(crate) fn fingerprinted_options<T: VersionedContainer>(
+    version_id: u32,
+    options: &TaggedOptions,
+) -> Result<Option<TaggedOptions>, RkyvVersionedError> {
+    match fingerprint::expected_fingerprint::<T>(version_id) {
+        Some(fingerprint)
+            if options
+                .extensions
+                .get(EXTENSION_KEY_SCHEMA_FINGERPRINT)
+                .is_none() =>
+        {
+            let mut extensions = options.extensions.clone();
+            extensions.insert(EXTENSION_KEY_SCHEMA_FINGERPRINT, &fingerprint.to_le_bytes())?;
+            Ok(Some(TaggedOptions {
+                extensions,
+                ..options.clone()
+            }))
+        }
+        _ => Ok(None),
+    }
 }
 
 /// Serializes a versioned container into the provided writer, to be deserialized from
 /// [access_from_tagged_bytes].  This is analogous to `rkyv::to_bytes_in`, but only for
 /// [VersionedContainer] derived enums.
 ///
+/// Any `rkyv` writer exposing what it has written can be targeted, e.g. a `Vec<u8>` when
+/// alignment is handled downstream, or a `bytes::BytesMut` through `BytesMutWriter` with the
+/// `bytes` feature.  The payload is serialized straight into the writer after room for the
+/// header, which is filled in once the payload length is known.  Compressed and encrypted
+/// payloads are staged in a new writer first.  The writer should be positioned at a multiple
+/// of 16 bytes for the payload to be aligned correctly.
+///
 /// # Arguments
 ///
 /// * `item` - A reference to the item to be serialized.
@@ -212,28 +1336,218 @@ where
 /// # Returns
 ///
 /// A `Result` containing either the serialized byte array or an error if serialization fails.
-pub fn to_tagged_bytes_in<T: VersionedContainer, W>(
+pub fn to_tagged_bytes_in<T, W>(item: &T, writer: W) -> Result<W, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<W, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    W: Writer<rkyv::rancor::Error> + AsMut<[u8]> + Default,
+{
+    to_tagged_bytes_in_with(item, &TaggedOptions::default(), writer)
+}
+
+/// As [to_tagged_bytes_in], tagging the container with `options`.
+pub fn to_tagged_bytes_in_with<T, W>(
     item: &T,
-    writer: W,
+    options: &TaggedOptions,
+    mut writer: W,
 ) -> Result<W, RkyvVersionedError>
 where
-    T: for<'a> Serialize<HighSerializer<W, ArenaHandle<'a>, rkyv::rancor::Error>>,
-    W: rkyv::ser::Writer<rkyv::rancor::Error>,
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<W, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    W: Writer<rkyv::rancor::Error> + AsMut<[u8]> + Default,
 {
-    let container = TaggedVersionedStruct {
-        type_id: T::ARCHIVE_TYPE_ID,
-        version_id: item.get_entry_version_id(),
-        inner: item,
-    };
-    rkyv::api::high::to_bytes_in::<_, rkyv::rancor::Error>(&container, writer)
-        .map_err(|e| RkyvVersionedError::RkyvError(e))
+    tag_serialized_in::<T, W>(item.get_entry_version_id(), options, &mut writer, |out| {
+        *out =
+            rkyv::api::high::to_bytes_in::<_, rkyv::rancor::Error>(item, core::mem::take(out))
+                .map_err(rkyv_error)?;
+        Ok(())
+    })?;
+    Ok(writer)
+}
+
+/// Tags a payload which was already archived, e.g. received from elsewhere in a pipeline,
+/// prepending a header holding the type and version IDs and appending its digest, to be
+/// deserialized from [access_from_tagged_bytes].  The payload isn't re-serialized, and isn't
+/// validated until it is accessed.
+///
+/// # Arguments
+///
+/// * `version_id` - The version ID of the variant archived in `payload`.
+/// * `payload` - The `rkyv` archive of the container, e.g. as returned by `rkyv::to_bytes`.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, or an error if `version_id` isn't a
+/// version of `T` ([RkyvVersionedError::UnsupportedVersionError]).
+pub fn tag_existing_payload<T: VersionedContainer>(
+    version_id: u32,
+    payload: &[u8],
+) -> Result<AlignedVec, RkyvVersionedError> {
+    tag_existing_payload_with::<T>(version_id, payload, &TaggedOptions::default())
+}
+
+/// As [tag_existing_payload], tagging the payload with `options`.
+pub fn tag_existing_payload_with<T: VersionedContainer>(
+    version_id: u32,
+    payload: &[u8],
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    if !T::is_valid_version_id(version_id) {
+        return Err(RkyvVersionedError::UnsupportedVersionError(version_id));
+    }
+    tag_serialized::<T>(version_id, options, AlignedVec::new(), |mut out| {
+        out.extend_from_slice(payload);
+        Ok(out)
+    })
+}
+
+/// Serializes a versioned container into the start of a caller-provided slice, to be
+/// deserialized from [access_from_tagged_bytes].  Unlike [to_tagged_bytes_in], the payload is
+/// serialized in place after the header, and `rkyv` takes its scratch space from `arena`, so
+/// once the arena has grown to fit the largest record no allocations are made.
+///
+/// The tagged bytes are the same as those written by [to_tagged_bytes], and `buf` should be
+/// aligned to 16 bytes for the payload to be aligned correctly.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `buf` - The slice to write the tagged bytes to.
+/// * `arena` - The arena `rkyv` allocates scratch space from, kept by the caller between calls.
+///
+/// # Returns
+///
+/// A `Result` containing either the number of bytes written to the front of `buf`, or an
+/// [RkyvVersionedError::BufferTooSmallError] if they don't fit.
+pub fn to_tagged_bytes_in_slice<T>(
+    item: &T,
+    buf: &mut [u8],
+    arena: &mut Arena,
+) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a, 'b> Serialize<
+            HighSerializer<
+                rkyv::ser::writer::Buffer<'b>,
+                ArenaHandle<'a>,
+                rkyv::rancor::Failure,
+            >,
+        >,
+{
+    let version_id = item.get_entry_version_id();
+    let area =
+        fingerprint::expected_fingerprint::<T>(version_id).map(fingerprint::fingerprint_area);
+    let payload_area = buf
+        .get_mut(TAGGED_HEADER_SIZE + area.map_or(0, |area| area.len())..)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    // As in to_tagged_bytes_in_fixed, the serializer only reports that it failed, which with
+    // an arena's scratch space it does when the slice is full
+    let payload_len = rkyv::api::high::to_bytes_in_with_alloc::<_, _, rkyv::rancor::Failure>(
+        item,
+        rkyv::ser::writer::Buffer::from(payload_area),
+        arena.acquire(),
+    )
+    .map_err(|_| RkyvVersionedError::BufferTooSmallError)?
+    .len();
+    tag_in_slice::<T>(
+        version_id,
+        buf,
+        area.as_ref().map(|area| area.as_slice()),
+        payload_len,
+    )
+}
+
+/// As [to_tagged_bytes_in_slice], but serializing with `rkyv`'s low-level API and taking
+/// scratch space from `scratch` rather than an arena, so no heap allocations are made at all, e.g. on
+/// microcontrollers.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `buf` - The slice to write the tagged bytes to.
+/// * `scratch` - The scratch space `rkyv` may use while serializing, e.g. a static buffer.
+///
+/// # Returns
+///
+/// A `Result` containing either the number of bytes written to the front of `buf`, or an
+/// [RkyvVersionedError::BufferTooSmallError] if they, or the scratch space needed, don't fit.
+pub fn to_tagged_bytes_in_fixed<T>(
+    item: &T,
+    buf: &mut [u8],
+    scratch: &mut [MaybeUninit<u8>],
+) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a, 'b> Serialize<
+            LowSerializer<
+                rkyv::ser::writer::Buffer<'b>,
+                SubAllocator<'a>,
+                rkyv::rancor::Failure,
+            >,
+        >,
+{
+    let version_id = item.get_entry_version_id();
+    let area =
+        fingerprint::expected_fingerprint::<T>(version_id).map(fingerprint::fingerprint_area);
+    let payload_area = buf
+        .get_mut(TAGGED_HEADER_SIZE + area.map_or(0, |area| area.len())..)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    // The low-level API only reports that serialization failed, which it does when the buffer
+    // or the scratch space is full
+    let payload_len = rkyv::api::low::to_bytes_in_with_alloc::<_, _, rkyv::rancor::Failure>(
+        item,
+        rkyv::ser::writer::Buffer::from(payload_area),
+        SubAllocator::new(scratch),
+    )
+    .map_err(|_| RkyvVersionedError::BufferTooSmallError)?
+    .len();
+    tag_in_slice::<T>(
+        version_id,
+        buf,
+        area.as_ref().map(|area| area.as_slice()),
+        payload_len,
+    )
+}
+
+/// Tags the payload of `payload_len` bytes serialized after the header and the extension
+/// `area`, if any, in `buf` with the default options, returning the length of the tagged
+/// bytes.
+fn tag_in_slice<T: VersionedContainer>(
+    version_id: u32,
+    buf: &mut [u8],
+    area: Option<&[u8]>,
+    payload_len: usize,
+) -> Result<usize, RkyvVersionedError> {
+    let area = area.unwrap_or_default();
+    let body_end = TAGGED_HEADER_SIZE + area.len() + payload_len;
+    buf.get_mut(TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + area.len())
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?
+        .copy_from_slice(area);
+    let (mut header, digest) = tag_payload::<T>(
+        version_id,
+        &[buf
+            .get(TAGGED_HEADER_SIZE..body_end)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?],
+        &TaggedOptions::default(),
+    )?;
+    if !area.is_empty() {
+        header.flags |= HEADER_FLAG_EXTENSIONS;
+    }
+    let digest = digest.as_bytes();
+    let end = body_end + digest.len();
+    buf.get_mut(body_end..end)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?
+        .copy_from_slice(digest);
+    write_header(buf, &header)?;
+    Ok(end)
 }
 
 /// "Peeks" at the type_id and version_id inside a tagged byte array generated by
 /// [to_tagged_bytes] and returns them.
 ///
 /// This is useful in the context of pre-validating the type and version of a tagged byte array
-/// before deserializing.
+/// before deserializing.  The container type doesn't need to be known, [peek_header] returns
+/// the rest of the header as well.
 ///
 /// # Arguments
 ///
@@ -246,21 +1560,59 @@ where
 pub fn get_type_and_version_from_tagged_bytes(
     buf: &[u8],
 ) -> Result<(u32, u32), RkyvVersionedError> {
-    const MIN_SIZE: usize = core::mem::size_of::<ArchivedTaggedVersionedStruct<()>>();
-
-    if buf.len() < MIN_SIZE {
-        return Err(RkyvVersionedError::BufferTooSmallError);
-    }
+    let header = read_header(buf)?;
+    Ok((header.type_id, header.version_id))
+}
 
-    let header = rkyv::access::<ArchivedTaggedVersionedStruct<()>, rkyv::rancor::Error>(&buf)
-        .map_err(|e| RkyvVersionedError::RkyvError(e))?;
+/// Returns the `rkyv` archive held in a tagged byte array, without the header, header words,
+/// extension area and trailers, so that it can be handed to other `rkyv` consumers or hashed
+/// without copying it.
+///
+/// The header is read and the digest verified, but the container type isn't needed and the
+/// archive itself is not validated.  The archive starts at a multiple of 16 bytes from the
+/// header, so it is as aligned as `buf`.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either the archive, an
+/// [RkyvVersionedError::CompressedPayloadError] or
+/// [RkyvVersionedError::EncryptedPayloadError] if the stored payload isn't a plain archive, or
+/// an error if the header or digest is invalid.
+pub fn payload_bytes(buf: &[u8]) -> Result<&[u8], RkyvVersionedError> {
+    let range = payload_range(buf)?;
+    buf.get(range)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)
+}
 
-    Ok((header.type_id.into(), header.version_id.into()))
+/// As [payload_bytes], returning the position of the archive in `buf` instead.
+pub fn payload_range(buf: &[u8]) -> Result<Range<usize>, RkyvVersionedError> {
+    let header = read_header(buf)?;
+    let body = verify_payload(buf, &header)?;
+    if encryption_algorithm(&header)? != EncryptionAlgorithm::None {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    if compression_codec(&header)? != CompressionCodec::None {
+        return Err(RkyvVersionedError::CompressedPayloadError);
+    }
+    let (metadata, payload) = split_metadata(&header, body)?;
+    let start = TAGGED_HEADER_SIZE + metadata.len();
+    Ok(start..start + payload.len())
 }
 
 /// Zero-copy deserializes a versioned container from a tagged byte array generated by
 /// [to_tagged_bytes].
 ///
+/// The type and version IDs are checked first, then the payload digest is verified and
+/// finally the payload itself is validated by `rkyv`.  Compressed payloads cannot be accessed
+/// in place and fail with [RkyvVersionedError::CompressedPayloadError], use
+/// [access_from_tagged_bytes_with_scratch] if the payload may be compressed.  Similarly,
+/// encrypted payloads fail with [RkyvVersionedError::EncryptedPayloadError], use
+/// [access_from_encrypted_tagged_bytes] to decrypt them.
+///
 /// # Arguments
 ///
 /// * `buf` - A reference to the byte array containing the tagged serialized data.
@@ -278,29 +1630,12 @@ where
             rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
         >,
 {
-    let (type_id, version_id) = get_type_and_version_from_tagged_bytes(buf)?;
-
-    // Ensure the type header is correct
-    if type_id != T::ARCHIVE_TYPE_ID {
-        return Err(RkyvVersionedError::UnexpectedTypeError(
-            T::ARCHIVE_TYPE_ID,
-            type_id,
-        ));
-    }
-
-    // Ensure the version header is valid
-    if T::is_valid_version_id(version_id) {
-        let archived =
-            rkyv::access::<ArchivedTaggedVersionedStruct<T>, rkyv::rancor::Error>(&buf)
-                .map_err(|e| RkyvVersionedError::RkyvError(e))?;
-        Ok(&archived.inner)
-    } else {
-        Err(RkyvVersionedError::UnsupportedVersionError(version_id))
-    }
+    access_tagged::<T>(buf, &ValidationConfig::default())
 }
 
-/// Unsafely zero-copy deserializes a versioned container from a tagged byte array generated by
-/// [to_tagged_bytes].
+/// As [access_from_tagged_bytes], but validating the payload with the `rancor` error type `E`,
+/// see [Error Types](crate#error-types).  With `rancor::Panic`, a payload which fails
+/// validation panics rather than returning an error.
 ///
 /// # Arguments
 ///
@@ -308,39 +1643,395 @@ where
 ///
 /// # Returns
 ///
-/// A reference to the inner archived type
-///
-/// # SAFETY
-/// This function is unsafe because it does not perform any validation on the type or version
-/// ID or the underlying bytes. It is only recommended to use this when you have either already
-/// validated the buffer, or are just passing the data around internally.
-pub unsafe fn access_from_tagged_bytes_unchecked<'a, T: VersionedContainer + 'a>(
+/// A `Result` containing either a reference to the deserialized item or an error if
+/// deserialization fails, where errors raised by `rkyv` are wrapped in
+/// [RkyvVersionedError::RkyvError].
+pub fn access_from_tagged_bytes_with_strategy<'a, T: VersionedContainer + 'a, E: Source>(
     buf: &'a [u8],
-) -> &'a T::Archived {
-    let archived = rkyv::access_unchecked::<ArchivedTaggedVersionedStruct<T>>(&buf);
-    &archived.inner
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<rkyv::api::high::HighValidator<'b, E>>,
+{
+    access_tagged_with_strategy::<T, E>(buf, &ValidationConfig::default())
 }
 
-/// A trait that is automatically implemented on a versioned container using the
-/// `#[derive(VersionedArchiveContainer)]` attribute.
+/// As [access_from_tagged_bytes], explicitly accepting data after the tagged byte array, e.g.
+/// when records are read from the front of a buffer holding more of them.
 ///
-/// This trait extends the `Archive` trait and provides additional methods
-/// for handling versioned data. Manual implementors of this trait must provide
-/// methods for validating version IDs, retrieving the version ID of an entry,
-/// and converting between tagged bytes and archived data.
+/// # Returns
 ///
-/// # Example
-/// TODO
-pub trait VersionedContainer: Archive {
-    /// A constant representing the type ID of the archived data. When generated by
-    /// the derive macro, this is a CRC32 hash of the type name.
-    const ARCHIVE_TYPE_ID: u32;
-
+/// A `Result` containing either a reference to the deserialized item and the number of bytes
+/// of `buf` the tagged byte array took up, or an error if any of the checks of
+/// [access_from_tagged_bytes] fail.
+pub fn access_prefix_from_tagged_bytes<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+) -> Result<(&'a T::Archived, usize), RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let archived = access_from_tagged_bytes::<T>(buf)?;
+    Ok((archived, read_header(buf)?.tagged_len()?))
+}
+
+/// Zero-copy deserializes a versioned container from a tagged byte array received from an
+/// untrusted source, such as the network.
+///
+/// Nothing about `buf` is trusted: the header is bounds checked, the type and version IDs and
+/// the payload digest are verified, and the payload is fully validated with `bytecheck` before
+/// a reference to it is handed out, so malformed or malicious input results in an error
+/// rather than undefined behaviour.  Note that a digest only detects accidental corruption,
+/// use a signature (`access_from_signed_tagged_bytes` with the `ed25519` feature) or an
+/// [EncryptionKey] to authenticate the sender.
+/// This is the safe counterpart of [get_ref_from_tagged_bytes_unchecked].
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the validated item or an error if any of the
+/// checks fail.
+pub fn get_ref_from_tagged_bytes_checked<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_from_tagged_bytes::<T>(buf)
+}
+
+/// Zero-copy deserializes a versioned container from a tagged byte array embedded at
+/// `offset` in a larger buffer, e.g. a network packet with an envelope of its own, without
+/// the caller slicing or copying it out.  Any bytes after the record are ignored.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the buffer holding the record.
+/// * `offset` - The position of the tagged byte array in `buf`, which must be aligned to
+///   [FRAME_ALIGNMENT] bytes in memory unless the `unaligned` feature is enabled.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the deserialized item, an
+/// [RkyvVersionedError::MisalignedError] if the record isn't aligned, or an error if any of
+/// the checks of [access_from_tagged_bytes] fail.
+pub fn get_ref_from_tagged_bytes_at<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    offset: usize,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let record = buf
+        .get(offset..)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    if !cfg!(feature = "unaligned")
+        && !(record.as_ptr() as usize).is_multiple_of(FRAME_ALIGNMENT)
+    {
+        return Err(RkyvVersionedError::MisalignedError(offset));
+    }
+    access_from_tagged_bytes::<T>(record)
+}
+
+/// Zero-copy deserializes a versioned container from a tagged byte array generated by
+/// [to_tagged_bytes_with], transparently decompressing the payload if required.
+///
+/// Uncompressed payloads are accessed in place exactly like [access_from_tagged_bytes],
+/// compressed payloads are decompressed into `scratch` and accessed from there.  Reusing the
+/// same scratch buffer across calls avoids reallocating it for each record.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+/// * `scratch` - A buffer to decompress the payload into if necessary.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the deserialized item or an error if
+/// decompression or deserialization fails.
+pub fn access_from_tagged_bytes_with_scratch<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    scratch: &'a mut AlignedVec,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_tagged_with_scratch::<T>(buf, scratch, &ValidationConfig::default())
+}
+
+/// Zero-copy deserializes a versioned container from a tagged byte array generated by
+/// [to_tagged_bytes_with] with an [EncryptionKey], decrypting (and decompressing) the payload
+/// into `scratch` first.
+///
+/// Payloads that are not encrypted are accepted too and handled exactly like
+/// [access_from_tagged_bytes_with_scratch]; check [EncryptionAlgorithm] via the header if
+/// plaintext records must be rejected.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+/// * `key` - The key the payload was encrypted with.
+/// * `scratch` - A buffer to decrypt the payload into.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the deserialized item or an error.  If the key
+/// is wrong or the record has been tampered with, the error is
+/// [RkyvVersionedError::EncryptedPayloadError].
+pub fn access_from_encrypted_tagged_bytes<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+    key: &EncryptionKey,
+    scratch: &'a mut AlignedVec,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_encrypted_tagged::<T>(buf, key, scratch, &ValidationConfig::default())
+}
+
+/// As [access_from_encrypted_tagged_bytes], but decrypting the payload in place in `buf`
+/// instead of into a scratch buffer, so it is neither copied nor allocated for.
+///
+/// The decrypted payload overwrites the nonce and ciphertext, so `buf` no longer holds a
+/// valid tagged byte array afterwards, even if decryption fails.  Compressed payloads can't
+/// be decompressed in place and fail with [RkyvVersionedError::CompressedPayloadError].
+///
+/// # Arguments
+///
+/// * `buf` - A mutable reference to the byte array containing the tagged serialized data.
+/// * `key` - The key the payload was encrypted with.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the deserialized item or an error.  If the key
+/// is wrong or the record has been tampered with, the error is
+/// [RkyvVersionedError::EncryptedPayloadError].
+pub fn access_from_encrypted_tagged_bytes_in_place<'a, T: VersionedContainer + 'a>(
+    buf: &'a mut [u8],
+    key: &EncryptionKey,
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    access_encrypted_tagged_in_place::<T>(buf, key, &ValidationConfig::default())
+}
+
+/// Converts a tagged byte array with a compressed payload into an equivalent uncompressed one
+/// which can be accessed in place, keeping the same digest algorithm.  Uncompressed tagged
+/// byte arrays are copied as-is.
+///
+/// Like [get_type_and_version_from_tagged_bytes] this does not require knowing the container
+/// type, but the digest is verified before decompressing.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either the uncompressed tagged byte array or an error if the digest
+/// or decompression fails.
+pub fn decompress_tagged_bytes(buf: &[u8]) -> Result<AlignedVec, RkyvVersionedError> {
+    let mut header = read_header(buf)?;
+    let body = verify_payload(buf, &header)?;
+    if encryption_algorithm(&header)? != EncryptionAlgorithm::None {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    let (metadata, stored) = split_metadata(&header, body)?;
+
+    let mut payload = AlignedVec::new();
+    compression_codec(&header)?.decompress(stored, &mut payload)?;
+
+    let body_len = metadata.len() + payload.len();
+    header.payload_len = u32::try_from(body_len)
+        .map_err(|_| RkyvVersionedError::PayloadTooLargeError(body_len))?;
+    header.compression = CompressionCodec::None.id();
+    // The signature covered the compressed payload, so it no longer applies
+    header.flags &= !HEADER_FLAG_SIGNED;
+
+    let mut bytes = AlignedVec::with_capacity(TAGGED_HEADER_SIZE + body_len);
+    bytes.resize(TAGGED_HEADER_SIZE, 0);
+    write_header(&mut bytes, &header)?;
+    bytes.extend_from_slice(metadata);
+    bytes.extend_from_slice(&payload);
+    let digest = digest_algorithm(&header)?
+        .compute(bytes.get(TAGGED_HEADER_SIZE..).unwrap_or_default())?;
+    bytes.extend_from_slice(digest.as_bytes());
+    Ok(bytes)
+}
+
+/// Unsafely zero-copy deserializes a versioned container from a tagged byte array generated by
+/// [to_tagged_bytes].
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A reference to the inner archived type
+///
+/// # SAFETY
+/// This function is unsafe because it does not perform any validation on the type or version
+/// ID, the payload digest or the underlying bytes, and the payload must not be compressed or
+/// encrypted.  It is only recommended to use this when you have either already validated the
+/// buffer, or are just passing the data around internally.
+pub unsafe fn access_from_tagged_bytes_unchecked<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+) -> &'a T::Archived {
+    let header =
+        TaggedVersionedHeader::decode(&*buf.as_ptr().cast::<[u8; TAGGED_HEADER_SIZE]>());
+    let payload_end = TAGGED_HEADER_SIZE + header.payload_len as usize;
+    rkyv::access_unchecked::<T::Archived>(buf.get_unchecked(TAGGED_HEADER_SIZE..payload_end))
+}
+
+/// Zero-copy deserializes a versioned container from a trusted tagged byte array, checking
+/// the header but skipping all validation of the payload.
+///
+/// The header is read and checked exactly like [access_from_tagged_bytes] (type and version
+/// IDs, flags, and that `buf` is long enough), and header words and extensions are skipped,
+/// but the digest is not verified and the payload is not validated by `rkyv`.  This is the
+/// fastest way to access buffers which never left the process, or which were already
+/// validated, e.g. by [get_ref_from_tagged_bytes_checked] when they were received.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing either a reference to the archived item or an error if the header
+/// doesn't describe an uncompressed, unencrypted `T` which fits in `buf`.
+///
+/// # SAFETY
+/// The payload of `buf` must be a valid archive of `T::Archived`, as written by
+/// [to_tagged_bytes] for a `T` with the same `rkyv` layout features, and `buf` must be
+/// suitably aligned for it.  Passing untrusted input is undefined behaviour.
+pub unsafe fn get_ref_from_tagged_bytes_unchecked<'a, T: VersionedContainer + 'a>(
+    buf: &'a [u8],
+) -> Result<&'a T::Archived, RkyvVersionedError>
+where
+    T::Archived: rkyv::Portable,
+{
+    let header = check_header::<T>(buf)?;
+    if encryption_algorithm(&header)? != EncryptionAlgorithm::None {
+        return Err(RkyvVersionedError::EncryptedPayloadError);
+    }
+    if compression_codec(&header)? != CompressionCodec::None {
+        return Err(RkyvVersionedError::CompressedPayloadError);
+    }
+    let body = buf
+        .get(TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + header.payload_len as usize)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let (_, payload) = split_metadata(&header, body)?;
+
+    // SAFETY: The caller guarantees that the payload is a valid archive of `T::Archived`
+    Ok(unsafe { rkyv::access_unchecked::<T::Archived>(payload) })
+}
+
+/// A trait that is automatically implemented on a versioned container using the
+/// `#[derive(VersionedArchiveContainer)]` attribute.
+///
+/// This trait extends the `Archive` trait and provides additional methods
+/// for handling versioned data. Manual implementors of this trait must provide
+/// methods for validating version IDs, retrieving the version ID of an entry,
+/// and converting between tagged bytes and archived data.
+///
+/// # Example
+/// TODO
+pub trait VersionedContainer: Archive {
+    /// A constant representing the type ID of the archived data. When generated by
+    /// the derive macro, this is a CRC32 hash of the type name.
+    const ARCHIVE_TYPE_ID: u32;
+
+    /// The name of the container type, which [ARCHIVE_TYPE_ID](Self::ARCHIVE_TYPE_ID) is
+    /// derived from, e.g. to embed in records with [to_tagged_bytes_named].
+    const TYPE_NAME: &'static str;
+
+    /// Metadata about every version of the container, in version order.
+    const VERSION_TABLE: &'static [VersionInfo];
+
     /// Checks if the provided version ID is valid.
     fn is_valid_version_id(version: u32) -> bool;
 
     /// Retrieves the version ID of the current variant
     fn get_entry_version_id(&self) -> u32;
+
+    /// Looks up the [VersionInfo] of `version` in [VERSION_TABLE](Self::VERSION_TABLE).
+    fn version_info(version: u32) -> Option<&'static VersionInfo> {
+        Self::VERSION_TABLE
+            .iter()
+            .find(|info| info.version_id == version)
+    }
+
+    /// Serializes `self` and writes it to `writer` as a length-prefixed frame, see
+    /// [write_tagged].
+    #[cfg(feature = "std")]
+    fn write_tagged(
+        &self,
+        writer: &mut impl std::io::Write,
+    ) -> Result<usize, RkyvVersionedError>
+    where
+        Self: Sized
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        write_tagged(self, writer)
+    }
+
+    /// Reads the next length-prefixed frame holding a `Self` from `reader` into an aligned
+    /// buffer, see [read_tagged].
+    #[cfg(feature = "std")]
+    fn read_tagged(
+        reader: &mut impl std::io::Read,
+    ) -> Result<Option<AlignedVec>, RkyvVersionedError>
+    where
+        Self: Sized,
+    {
+        read_tagged::<Self>(reader)
+    }
+}
+
+/// Metadata about one version of a versioned container, see
+/// [VersionedContainer::VERSION_TABLE].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VersionInfo {
+    /// The version ID written in the header.
+    pub version_id: u32,
+    /// The name of the variant holding the version, e.g. `V1`.
+    pub name: &'static str,
+    /// A CRC32 of the variant name and its declared type.  This changes when a variant is
+    /// renamed or given a different type, but not when the fields of that type change.
+    pub schema_hash: u32,
+    /// The [SchemaFingerprint] of the type of the variant, if the container derives with
+    /// `#[vac(fingerprint)]`.  Unlike the schema hash, this changes when the fields of the
+    /// type change, and it is stored in records and checked when reading them.
+    pub fingerprint: Option<u32>,
+    /// Whether the variant is marked `#[vac(deprecated)]`.
+    pub deprecated: bool,
+    /// The Unix timestamp (in seconds) from which the version should no longer be read, set
+    /// with `#[vac(sunset = "YYYY-MM-DD")]` as the start of that day in UTC.  See
+    /// [ValidationConfig::observer] and [ValidationConfig::strict_sunset].
+    pub sunset: Option<u64>,
 }
 
 #[cfg(test)]
@@ -348,6 +2039,7 @@ mod tests {
     use core::panic;
 
     use super::*;
+    use rkyv::with::InlineAsBox;
     use rkyv::Deserialize;
 
     #[derive(Debug, PartialEq, Archive, Serialize, Deserialize)]
@@ -368,12 +2060,33 @@ mod tests {
     }
 
     #[derive(Debug, PartialEq, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
-    #[rkyv(compare(PartialEq))]
+    #[vac(compare(PartialEq))]
     enum TestContainer<'a> {
         V1(#[rkyv(with=InlineAsBox)] &'a TestStructV1),
         V2(#[rkyv(with=InlineAsBox)] &'a TestStructV2),
     }
 
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(compare(PartialEq))]
+    enum OwnedTestContainer {
+        V1(TestStructV1),
+        V2(TestStructV2),
+    }
+
+    /// Copies a tagged byte array, rewriting its header with `munge`
+    fn with_munged_header(
+        bytes: &[u8],
+        munge: impl FnOnce(&mut TaggedVersionedHeader),
+    ) -> AlignedVec {
+        let mut header = read_header(bytes).unwrap();
+        munge(&mut header);
+
+        let mut munged = AlignedVec::<16>::new();
+        munged.extend_from_slice(bytes);
+        write_header(&mut munged, &header).unwrap();
+        munged
+    }
+
     #[test]
     fn test_versioned_container() {
         // Longer strings will be serialized out-of-line in the data, so it is important to
@@ -409,7 +2122,9 @@ mod tests {
         }
 
         // Validate unchecked version is the same
-        let twsv_ref_unchecked = unsafe { access_from_tagged_bytes_unchecked::<TestContainer>(&tswv_container_bytes) };
+        let twsv_ref_unchecked = unsafe {
+            access_from_tagged_bytes_unchecked::<TestContainer>(&tswv_container_bytes)
+        };
         match twsv_ref_unchecked {
             ArchivedTestContainer::V1(v1_ref) => {
                 assert_eq!(v1_ref.a, 1);
@@ -451,13 +2166,10 @@ mod tests {
         const MUNGED_TYPE_ID: u32 = 0x01010101;
 
         // Generate invalid type id
-        let invalid_type_struct = TaggedVersionedStruct::<TestContainer> {
-            type_id: MUNGED_TYPE_ID,
-            version_id: 0,
-            inner: &v1_container,
-        };
         let invalid_type_bytes =
-            rkyv::to_bytes::<rkyv::rancor::Error>(&invalid_type_struct).unwrap();
+            with_munged_header(&to_tagged_bytes(&v1_container).unwrap(), |header| {
+                header.type_id = MUNGED_TYPE_ID;
+            });
 
         match access_from_tagged_bytes::<TestContainer>(&invalid_type_bytes) {
             Err(RkyvVersionedError::UnexpectedTypeError(expected, got)) => {
@@ -469,14 +2181,10 @@ mod tests {
 
         // Generate invalid version id
         const MUNGED_VERSION_ID: u32 = 0x01010101;
-        let invalid_version_struct = TaggedVersionedStruct::<TestContainer> {
-            type_id: EXPECTED_TYPE_ID,
-            version_id: MUNGED_VERSION_ID,
-            inner: &v1_container,
-        };
-
         let invalid_version_bytes =
-            rkyv::to_bytes::<rkyv::rancor::Error>(&invalid_version_struct).unwrap();
+            with_munged_header(&to_tagged_bytes(&v1_container).unwrap(), |header| {
+                header.version_id = MUNGED_VERSION_ID;
+            });
 
         match access_from_tagged_bytes::<TestContainer>(&invalid_version_bytes) {
             Err(RkyvVersionedError::UnsupportedVersionError(version)) => {
@@ -485,4 +2193,1189 @@ mod tests {
             _ => panic!("Expected RkyvVersionedError::UnsupportedVersionError"),
         };
     }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum DeprecatingContainer {
+        #[vac(deprecated)]
+        V1(TestStructV1),
+        V2(TestStructV2),
+    }
+
+    #[test]
+    fn test_version_table() {
+        assert_eq!(TestContainer::VERSION_TABLE.len(), 2);
+        let v2 = TestContainer::version_info(1).unwrap();
+        assert_eq!(v2.version_id, 1);
+        assert_eq!(v2.name, "V2");
+        assert!(!v2.deprecated);
+        assert!(TestContainer::version_info(2).is_none());
+
+        let table = DeprecatingContainer::VERSION_TABLE;
+        assert!(table[0].deprecated);
+        assert!(!table[1].deprecated);
+        assert_ne!(table[0].schema_hash, table[1].schema_hash);
+        // Same name and type, but borrowed rather than owned
+        assert_ne!(
+            table[0].schema_hash,
+            TestContainer::VERSION_TABLE[0].schema_hash
+        );
+        assert_eq!(
+            table[1].schema_hash,
+            OwnedTestContainer::VERSION_TABLE[1].schema_hash
+        );
+    }
+
+    #[derive(Debug, PartialEq, Archive, Serialize, VersionedArchiveContainer)]
+    #[vac(constructors)]
+    enum ConstructedContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a TestStructV1),
+        SecondVersion(#[rkyv(with=InlineAsBox)] &'a TestStructV2),
+    }
+
+    #[test]
+    fn test_constructors() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "ONE".to_owned(),
+        };
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "TWO".to_owned(),
+        };
+        assert_eq!(ConstructedContainer::v1(&v1), ConstructedContainer::V1(&v1));
+        assert_eq!(
+            ConstructedContainer::second_version(&v2),
+            ConstructedContainer::SecondVersion(&v2)
+        );
+        let latest = ConstructedContainer::latest(&v2);
+        assert_eq!(latest.get_entry_version_id(), 1);
+
+        let bytes = to_tagged_bytes(&latest).unwrap();
+        match access_from_tagged_bytes::<ConstructedContainer>(&bytes).unwrap() {
+            ArchivedConstructedContainer::SecondVersion(v2_ref) => {
+                assert!(*v2_ref.get() == v2)
+            }
+            _ => panic!("Expected SecondVersion"),
+        }
+    }
+
+    #[derive(Debug, PartialEq, Archive, Serialize, VersionedArchiveContainer)]
+    #[vac(from)]
+    enum ConvertedContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a TestStructV1),
+        V2(#[rkyv(with=InlineAsBox)] &'a TestStructV2),
+    }
+
+    #[test]
+    fn test_from_impls() {
+        // Generic code takes any payload convertible into the container
+        fn tag<'a>(payload: impl Into<ConvertedContainer<'a>>) -> AlignedVec {
+            to_tagged_bytes(&payload.into()).unwrap()
+        }
+
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "ONE".to_owned(),
+        };
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "TWO".to_owned(),
+        };
+        assert_eq!(ConvertedContainer::from(&v1), ConvertedContainer::V1(&v1));
+        let container: ConvertedContainer = (&v2).into();
+        assert_eq!(container, ConvertedContainer::V2(&v2));
+
+        let bytes = tag(&v2);
+        match access_from_tagged_bytes::<ConvertedContainer>(&bytes).unwrap() {
+            ArchivedConvertedContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+    }
+
+    /// Stands in for a downstream crate re-exporting this one under another name, without
+    /// anything imported from it
+    mod facade {
+        pub use crate as versioned;
+
+        #[derive(
+            Debug, rkyv::Archive, rkyv::Serialize, versioned::VersionedArchiveContainer,
+        )]
+        #[vac(crate = "crate::tests::facade::versioned")]
+        pub enum FacadeContainer {
+            V1(u32),
+            V2(u64),
+        }
+    }
+
+    /// Derives a container with nothing imported, as a downstream crate might, and a local
+    /// trait shadowing the prelude's `PartialEq`
+    mod hygiene {
+        #[allow(dead_code)]
+        trait PartialEq {}
+
+        #[derive(rkyv::Archive, rkyv::Serialize, crate::VersionedArchiveContainer)]
+        #[vac(compare(PartialEq))]
+        pub enum BareContainer {
+            V1(u32),
+        }
+    }
+
+    #[test]
+    fn test_generated_paths_are_qualified() {
+        assert_eq!(
+            hygiene::BareContainer::ARCHIVE_TYPE_ID,
+            const_crc32::crc32(b"BareContainer")
+        );
+        let bytes = to_tagged_bytes(&hygiene::BareContainer::V1(1)).unwrap();
+        let archived = access_from_tagged_bytes::<hygiene::BareContainer>(&bytes).unwrap();
+        assert!(*archived == hygiene::BareContainer::V1(1));
+    }
+
+    #[test]
+    fn test_crate_path() {
+        use facade::FacadeContainer;
+
+        let bytes = to_tagged_bytes(&FacadeContainer::V2(7)).unwrap();
+        assert_eq!(
+            get_type_and_version_from_tagged_bytes(&bytes).unwrap(),
+            (FacadeContainer::ARCHIVE_TYPE_ID, 1)
+        );
+        assert_eq!(FacadeContainer::VERSION_TABLE[1].name, "V2");
+
+        let bytes = to_tagged_bytes(&FacadeContainer::V1(3)).unwrap();
+        match access_from_tagged_bytes::<FacadeContainer>(&bytes).unwrap() {
+            facade::ArchivedFacadeContainer::V1(value) => assert_eq!(*value, 3),
+            _ => panic!("Expected V1"),
+        }
+    }
+
+    #[test]
+    fn test_canonical_header_layout() {
+        let header = TaggedVersionedHeader {
+            type_id: 0x1234_5678,
+            version_id: 2,
+            payload_len: 0x0102_0304,
+            digest: DigestAlgorithm::Crc32.id(),
+            compression: CompressionCodec::Lz4.id(),
+            encryption: EncryptionAlgorithm::XChaCha20Poly1305.id(),
+            flags: HEADER_FLAG_SIGNED,
+        };
+        // The encoded header, as written on any architecture, MUST NOT CHANGE
+        let encoded: [u8; TAGGED_HEADER_SIZE] = [
+            0x78, 0x56, 0x34, 0x12, 0x02, 0x00, 0x00, 0x00, 0x04, 0x03, 0x02, 0x01, 0x01,
+            0x12, 0x01, 0x01,
+        ];
+        assert_eq!(header.encode(), encoded);
+        assert_eq!(read_header(&encoded).unwrap(), header);
+        assert_eq!(
+            get_type_and_version_from_tagged_bytes(&encoded).unwrap(),
+            (0x1234_5678, 2)
+        );
+
+        // Tagged byte arrays start with the same encoding
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "header".to_owned(),
+        };
+        let bytes = to_tagged_bytes(&TestContainer::V1(&v1)).unwrap();
+        assert_eq!(bytes[..4], TestContainer::ARCHIVE_TYPE_ID.to_le_bytes());
+        assert_eq!(bytes[4..8], 0u32.to_le_bytes());
+        let payload_len = (bytes.len() - TAGGED_HEADER_SIZE - 4) as u32;
+        assert_eq!(bytes[8..12], payload_len.to_le_bytes());
+
+        // The header doesn't rely on the alignment of the buffer either
+        let mut misaligned = AlignedVec::<16>::new();
+        misaligned.push(0);
+        misaligned.extend_from_slice(&bytes);
+        assert_eq!(
+            get_type_and_version_from_tagged_bytes(&misaligned[1..]).unwrap(),
+            (TestContainer::ARCHIVE_TYPE_ID, 0)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_format_version() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "format".to_owned(),
+        };
+        let bytes = to_tagged_bytes(&TestContainer::V1(&v1)).unwrap();
+        assert_eq!(bytes[14], header::TAGGED_FORMAT_VERSION);
+
+        let mut future = AlignedVec::<16>::new();
+        future.extend_from_slice(&bytes);
+        future[14] = header::TAGGED_FORMAT_VERSION + 1;
+        assert!(matches!(
+            read_header(&future),
+            Err(RkyvVersionedError::UnsupportedFormatError(2))
+        ));
+        assert!(matches!(
+            access_from_tagged_bytes::<TestContainer>(&future),
+            Err(RkyvVersionedError::UnsupportedFormatError(2))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_format_is_rejected() {
+        // The layout written before the header was introduced, with the IDs in the root
+        #[derive(Archive, Serialize)]
+        struct LegacyTaggedStruct<'a, T: Archive> {
+            type_id: u32,
+            version_id: u32,
+            #[rkyv(with = InlineAsBox)]
+            inner: &'a T,
+        }
+
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "written by an old release".to_owned(),
+        };
+        let container = TestContainer::V1(&v1);
+        let legacy = rkyv::to_bytes::<rkyv::rancor::Error>(&LegacyTaggedStruct {
+            type_id: TestContainer::ARCHIVE_TYPE_ID,
+            version_id: 0,
+            inner: &container,
+        })
+        .unwrap();
+        assert!(matches!(
+            access_from_tagged_bytes::<TestContainer>(&legacy),
+            Err(RkyvVersionedError::UnsupportedFormatError(0))
+        ));
+    }
+
+    #[test]
+    fn test_payload_digests() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "YEEEEEEEEEEEEEEEEEEEET".to_owned(),
+        };
+        let v1_container = TestContainer::V1(&v1);
+
+        for digest in [
+            DigestAlgorithm::None,
+            DigestAlgorithm::Crc32,
+            DigestAlgorithm::Crc32c,
+            DigestAlgorithm::Xxh3,
+            DigestAlgorithm::Blake3,
+        ] {
+            let options = TaggedOptions {
+                digest,
+                ..Default::default()
+            };
+            let result = to_tagged_bytes_with(&v1_container, &options);
+            if !digest.is_available() {
+                assert!(matches!(
+                    result,
+                    Err(RkyvVersionedError::UnsupportedDigestError(id)) if id == digest.id()
+                ));
+                continue;
+            }
+
+            let bytes = result.unwrap();
+            let header = read_header(&bytes).unwrap();
+            assert_eq!(header.digest, digest.id());
+            assert_eq!(header.tagged_len().unwrap(), bytes.len());
+            match access_from_tagged_bytes::<TestContainer>(&bytes).unwrap() {
+                ArchivedTestContainer::V1(v1_ref) => assert!(*v1_ref.get() == v1),
+                _ => panic!("Expected V1"),
+            }
+
+            // Flip a bit in the middle of the payload
+            let mut corrupted = AlignedVec::<16>::new();
+            corrupted.extend_from_slice(&bytes);
+            corrupted[TAGGED_HEADER_SIZE + header.payload_len as usize / 2] ^= 0x10;
+            match access_from_tagged_bytes::<TestContainer>(&corrupted) {
+                Err(RkyvVersionedError::ChecksumMismatchError) => {
+                    assert_ne!(digest, DigestAlgorithm::None)
+                }
+                // Without a digest, corruption can only be caught by rkyv validation (or not
+                // at all if it lands in plain data)
+                _ => assert_eq!(digest, DigestAlgorithm::None),
+            }
+
+            // Truncate the trailer
+            assert!(matches!(
+                access_from_tagged_bytes::<TestContainer>(&bytes[..bytes.len() - 1]),
+                Err(RkyvVersionedError::BufferTooSmallError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_checked_access_of_untrusted_input() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "YEEEEEEEEEEEEEEEEEEEET".to_owned(),
+        };
+        let options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            ..Default::default()
+        };
+        let bytes = to_tagged_bytes_with(&TestContainer::V1(&v1), &options).unwrap();
+        match get_ref_from_tagged_bytes_checked::<TestContainer>(&bytes).unwrap() {
+            ArchivedTestContainer::V1(v1_ref) => assert!(*v1_ref.get() == v1),
+            _ => panic!("Expected V1"),
+        }
+
+        // Without a digest to catch it, a garbage payload is rejected by validation
+        let mut garbage = AlignedVec::<16>::new();
+        garbage.extend_from_slice(&bytes);
+        let header = read_header(&bytes).unwrap();
+        garbage[TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + header.payload_len as usize]
+            .fill(0xff);
+        assert!(matches!(
+            get_ref_from_tagged_bytes_checked::<TestContainer>(&garbage),
+            Err(RkyvVersionedError::RkyvError(_))
+        ));
+
+        // As is a truncated buffer
+        assert!(matches!(
+            get_ref_from_tagged_bytes_checked::<TestContainer>(
+                &bytes[..TAGGED_HEADER_SIZE - 1]
+            ),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_unchecked_access_of_trusted_input() {
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "YEEEEEEEEEEEEEEEEEEEET".to_owned(),
+        };
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(1, b"tenant").unwrap();
+        let options = TaggedOptions {
+            user_word: Some(42),
+            extensions,
+            ..Default::default()
+        };
+        let bytes = to_tagged_bytes_with(&TestContainer::V2(&v2), &options).unwrap();
+        match unsafe { get_ref_from_tagged_bytes_unchecked::<TestContainer>(&bytes) }.unwrap()
+        {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+
+        // The header is still checked
+        assert!(matches!(
+            unsafe { get_ref_from_tagged_bytes_unchecked::<OwnedTestContainer>(&bytes) },
+            Err(RkyvVersionedError::UnexpectedTypeError(_, _))
+        ));
+        assert!(matches!(
+            unsafe { get_ref_from_tagged_bytes_unchecked::<TestContainer>(&bytes[..32]) },
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_to_tagged_bytes_in() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".to_owned(),
+        };
+        let v2_container = TestContainer::V2(&v2);
+
+        let bytes = to_tagged_bytes_in(&v2_container, AlignedVec::<16>::new()).unwrap();
+        assert_eq!(
+            bytes.as_slice(),
+            to_tagged_bytes(&v2_container).unwrap().as_slice()
+        );
+        match access_from_tagged_bytes::<TestContainer>(&bytes).unwrap() {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+
+        // Plain vectors can be targeted too, appending after what's already written
+        let options = TaggedOptions {
+            sequence: Some(7),
+            ..Default::default()
+        };
+        let vec = to_tagged_bytes_in_with(&v2_container, &options, vec![0u8; 16]).unwrap();
+        assert_eq!(
+            &vec[16..],
+            to_tagged_bytes_with(&v2_container, &options)
+                .unwrap()
+                .as_slice()
+        );
+    }
+
+    #[test]
+    fn test_to_tagged_bytes_in_staged_payloads() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".repeat(100),
+        };
+        let v2_container = TestContainer::V2(&v2);
+
+        // Compressed payloads are staged in a new writer and written after the metadata
+        for compression in [CompressionCodec::Zstd, CompressionCodec::Lz4]
+            .into_iter()
+            .filter(|compression| compression.is_available())
+        {
+            let options = TaggedOptions {
+                compression,
+                user_word: Some(3),
+                ..Default::default()
+            };
+            let vec = to_tagged_bytes_in_with(&v2_container, &options, vec![0u8; 16]).unwrap();
+            assert_eq!(
+                &vec[16..],
+                to_tagged_bytes_with(&v2_container, &options)
+                    .unwrap()
+                    .as_slice()
+            );
+        }
+    }
+
+    #[test]
+    fn test_tag_existing_payload() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "PRE-ARCHIVED".to_owned(),
+        };
+        let container = OwnedTestContainer::V1(v1);
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&container).unwrap();
+
+        let bytes = tag_existing_payload::<OwnedTestContainer>(0, &payload).unwrap();
+        assert_eq!(
+            bytes.as_slice(),
+            to_tagged_bytes(&container).unwrap().as_slice()
+        );
+        assert!(*access_from_tagged_bytes::<OwnedTestContainer>(&bytes).unwrap() == container);
+
+        let options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            user_word: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            tag_existing_payload_with::<OwnedTestContainer>(0, &payload, &options)
+                .unwrap()
+                .as_slice(),
+            to_tagged_bytes_with(&container, &options)
+                .unwrap()
+                .as_slice()
+        );
+        assert!(matches!(
+            tag_existing_payload::<OwnedTestContainer>(5, &payload),
+            Err(RkyvVersionedError::UnsupportedVersionError(5))
+        ));
+    }
+
+    #[test]
+    fn test_to_tagged_bytes_into() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "YEET".to_owned(),
+        };
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".to_owned(),
+        };
+
+        let mut buf = AlignedVec::<16>::new();
+        to_tagged_bytes_into(&TestContainer::V2(&v2), &mut buf).unwrap();
+        let capacity = buf.capacity();
+        assert_eq!(
+            buf.as_slice(),
+            to_tagged_bytes(&TestContainer::V2(&v2)).unwrap().as_slice()
+        );
+
+        // The shorter record replaces the longer one in the same allocation
+        to_tagged_bytes_into(&TestContainer::V1(&v1), &mut buf).unwrap();
+        assert_eq!(buf.capacity(), capacity);
+        match access_from_tagged_bytes::<TestContainer>(&buf).unwrap() {
+            ArchivedTestContainer::V1(v1_ref) => assert!(*v1_ref.get() == v1),
+            _ => panic!("Expected V1"),
+        }
+    }
+
+    #[test]
+    fn test_to_tagged_bytes_in_slice() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".to_owned(),
+        };
+        let v2_container = TestContainer::V2(&v2);
+        let expected = to_tagged_bytes(&v2_container).unwrap();
+
+        let mut buf = AlignedVec::<16>::new();
+        buf.resize(expected.len() + 32, 0xaa);
+        let mut arena = Arena::new();
+        let len = to_tagged_bytes_in_slice(&v2_container, &mut buf, &mut arena).unwrap();
+        assert_eq!(&buf[..len], expected.as_slice());
+        match access_from_tagged_bytes::<TestContainer>(&buf[..len]).unwrap() {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+
+        // Too small for the payload, the digest or even the header
+        for short in [expected.len() - 1, expected.len() / 2, 8] {
+            assert!(matches!(
+                to_tagged_bytes_in_slice(&v2_container, &mut buf[..short], &mut arena),
+                Err(RkyvVersionedError::BufferTooSmallError)
+            ));
+        }
+    }
+
+    #[derive(Debug, Archive, Serialize, VersionedArchiveContainer)]
+    enum Telemetry {
+        V1(Vec<String>),
+    }
+
+    #[test]
+    fn test_error_codes() {
+        // Codes and categories are part of the public contract, so they are pinned here
+        let errors = [
+            (
+                RkyvVersionedError::BufferTooSmallError,
+                1,
+                VersionedError::Truncated,
+            ),
+            (
+                RkyvVersionedError::UnexpectedTypeError(1, 2),
+                2,
+                VersionedError::TypeMismatch,
+            ),
+            (
+                RkyvVersionedError::UnsupportedVersionError(9),
+                3,
+                VersionedError::UnsupportedVersion,
+            ),
+            (
+                RkyvVersionedError::ChecksumMismatchError,
+                5,
+                VersionedError::Checksum,
+            ),
+            (
+                RkyvVersionedError::InvalidFrameError(0),
+                19,
+                VersionedError::Malformed,
+            ),
+            (
+                RkyvVersionedError::TrailingBytesError(4),
+                25,
+                VersionedError::Malformed,
+            ),
+        ];
+        for (error, code, kind) in &errors {
+            assert_eq!(error.code(), *code);
+            assert_eq!(error.kind(), *kind);
+        }
+        assert_eq!(VersionedError::TypeMismatch as u8, 1);
+        assert_eq!(VersionedError::Validation as u8, 5);
+        assert_eq!(VersionedError::Checksum.to_string(), "checksum mismatch");
+
+        // Located errors keep the category of the error they wrap
+        let located = RkyvVersionedError::ChecksumMismatchError.at_record(3, 4096);
+        assert_eq!(located.code(), 28);
+        assert_eq!(located.kind(), VersionedError::Checksum);
+        assert_eq!(located.record_location(), Some((3, 4096)));
+        assert_eq!(
+            located.to_string(),
+            "Record 3 at offset 4096: Payload digest did not match the digest in the trailer"
+        );
+        assert!(located.source().is_some());
+
+        // Errors raised by rkyv are validation failures
+        let mut garbage = AlignedVec::<16>::new();
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "codes".to_owned(),
+        };
+        let options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            ..Default::default()
+        };
+        garbage.extend_from_slice(
+            &to_tagged_bytes_with(&TestContainer::V1(&v1), &options).unwrap(),
+        );
+        let payload_len = read_header(&garbage).unwrap().payload_len as usize;
+        garbage[TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + payload_len].fill(0xff);
+        let Err(error) = access_from_tagged_bytes::<TestContainer>(&garbage) else {
+            panic!("Expected an error");
+        };
+        assert_eq!(error.kind(), VersionedError::Validation);
+        assert_eq!(error.code(), 26);
+    }
+
+    #[test]
+    fn test_rancor_strategies() {
+        use rkyv::rancor::{Failure, Panic};
+
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "strategy".to_owned(),
+        };
+        let options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            ..Default::default()
+        };
+        let bytes =
+            to_tagged_bytes_with_strategy::<_, Failure>(&TestContainer::V1(&v1), &options)
+                .unwrap();
+        assert_eq!(
+            bytes.as_slice(),
+            to_tagged_bytes_with(&TestContainer::V1(&v1), &options)
+                .unwrap()
+                .as_slice()
+        );
+        match access_from_tagged_bytes_with_strategy::<TestContainer, Panic>(&bytes).unwrap() {
+            ArchivedTestContainer::V1(v1_ref) => assert!(*v1_ref.get() == v1),
+            _ => panic!("Expected V1"),
+        }
+
+        // A garbage payload fails with the chosen strategy, header errors are unaffected
+        let mut garbage = AlignedVec::<16>::new();
+        garbage.extend_from_slice(&bytes);
+        let header = read_header(&bytes).unwrap();
+        garbage[TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + header.payload_len as usize]
+            .fill(0xff);
+        match access_from_tagged_bytes_with_strategy::<TestContainer, Failure>(&garbage) {
+            Err(RkyvVersionedError::RkyvError(e)) => {
+                assert!(e.downcast_ref::<Failure>().is_some())
+            }
+            _ => panic!("Expected RkyvError"),
+        }
+        assert!(std::panic::catch_unwind(|| {
+            access_from_tagged_bytes_with_strategy::<TestContainer, Panic>(&garbage)
+        })
+        .is_err());
+        assert!(matches!(
+            access_from_tagged_bytes_with_strategy::<TestContainer, Panic>(&garbage[..4]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_to_tagged_bytes_in_fixed() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".to_owned(),
+        };
+        let v2_container = TestContainer::V2(&v2);
+        let expected = to_tagged_bytes(&v2_container).unwrap();
+
+        let mut buf = AlignedVec::<16>::new();
+        buf.resize(expected.len() + 32, 0xaa);
+        let mut scratch = [MaybeUninit::uninit(); 256];
+        let len = to_tagged_bytes_in_fixed(&v2_container, &mut buf, &mut scratch).unwrap();
+        assert_eq!(&buf[..len], expected.as_slice());
+        assert!(matches!(
+            to_tagged_bytes_in_fixed(
+                &v2_container,
+                &mut buf[..expected.len() - 1],
+                &mut scratch
+            ),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+
+        // Vectors of strings need scratch space for their resolvers
+        let telemetry = Telemetry::V1(vec!["temperature".to_owned(); 8]);
+        let expected = to_tagged_bytes(&telemetry).unwrap();
+        buf.resize(expected.len(), 0);
+        let len = to_tagged_bytes_in_fixed(&telemetry, &mut buf, &mut scratch).unwrap();
+        assert_eq!(&buf[..len], expected.as_slice());
+        assert!(matches!(
+            to_tagged_bytes_in_fixed(&telemetry, &mut buf, &mut scratch[..8]),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_payload_compression() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".repeat(1000),
+        };
+        let v2_container = TestContainer::V2(&v2);
+        let uncompressed = to_tagged_bytes(&v2_container).unwrap();
+
+        for compression in [CompressionCodec::Zstd, CompressionCodec::Lz4] {
+            let options = TaggedOptions {
+                compression,
+                ..Default::default()
+            };
+            let result = to_tagged_bytes_with(&v2_container, &options);
+            if !compression.is_available() {
+                assert!(matches!(
+                    result,
+                    Err(RkyvVersionedError::UnsupportedCompressionError(id)) if id == compression.id()
+                ));
+                continue;
+            }
+
+            let bytes = result.unwrap();
+            assert!(bytes.len() < uncompressed.len());
+            assert_eq!(
+                get_type_and_version_from_tagged_bytes(&bytes).unwrap(),
+                (TestContainer::ARCHIVE_TYPE_ID, 1)
+            );
+
+            // In place access is not possible
+            assert!(matches!(
+                access_from_tagged_bytes::<TestContainer>(&bytes),
+                Err(RkyvVersionedError::CompressedPayloadError)
+            ));
+
+            let mut scratch = AlignedVec::new();
+            match access_from_tagged_bytes_with_scratch::<TestContainer>(&bytes, &mut scratch)
+                .unwrap()
+            {
+                ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+                _ => panic!("Expected V2"),
+            }
+
+            // Decompressing yields exactly what we would have written uncompressed
+            let decompressed = decompress_tagged_bytes(&bytes).unwrap();
+            assert_eq!(decompressed.as_slice(), uncompressed.as_slice());
+        }
+
+        // Uncompressed payloads are accessed in place through the scratch API too
+        let mut scratch = AlignedVec::new();
+        access_from_tagged_bytes_with_scratch::<TestContainer>(&uncompressed, &mut scratch)
+            .unwrap();
+        assert!(scratch.is_empty());
+    }
+
+    #[test]
+    fn test_payload_encryption() {
+        let v2 = TestStructV2 {
+            a: 100,
+            b: 200,
+            c: 300,
+            d: "SKEET".repeat(100),
+        };
+        let v2_container = TestContainer::V2(&v2);
+        let key = EncryptionKey::from_bytes([42; 32]);
+        let options = TaggedOptions {
+            compression: CompressionCodec::Lz4,
+            encryption: Some(key.clone()),
+            ..Default::default()
+        };
+
+        let result = to_tagged_bytes_with(&v2_container, &options);
+        if !CompressionCodec::Lz4.is_available() {
+            assert!(matches!(
+                result,
+                Err(RkyvVersionedError::UnsupportedCompressionError(2))
+            ));
+            return;
+        }
+        if !EncryptionAlgorithm::XChaCha20Poly1305.is_available() {
+            assert!(matches!(
+                result,
+                Err(RkyvVersionedError::UnsupportedEncryptionError(1))
+            ));
+            return;
+        }
+
+        let bytes = result.unwrap();
+        assert_eq!(
+            get_type_and_version_from_tagged_bytes(&bytes).unwrap(),
+            (TestContainer::ARCHIVE_TYPE_ID, 1)
+        );
+
+        let mut scratch = AlignedVec::new();
+        match access_from_encrypted_tagged_bytes::<TestContainer>(&bytes, &key, &mut scratch)
+            .unwrap()
+        {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+
+        // The plaintext APIs refuse encrypted payloads
+        assert!(matches!(
+            access_from_tagged_bytes::<TestContainer>(&bytes),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+        assert!(matches!(
+            access_from_tagged_bytes_with_scratch::<TestContainer>(&bytes, &mut scratch),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+        assert!(matches!(
+            decompress_tagged_bytes(&bytes),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+
+        // A wrong key is rejected
+        let wrong_key = EncryptionKey::from_bytes([43; 32]);
+        assert!(matches!(
+            access_from_encrypted_tagged_bytes::<TestContainer>(
+                &bytes,
+                &wrong_key,
+                &mut scratch
+            ),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+
+        // Uncompressed payloads can be decrypted in place, compressed ones can't
+        let mut compressed = bytes.clone();
+        assert!(matches!(
+            access_from_encrypted_tagged_bytes_in_place::<TestContainer>(
+                &mut compressed,
+                &key
+            ),
+            Err(RkyvVersionedError::CompressedPayloadError)
+        ));
+        let encrypted = to_tagged_bytes_with(
+            &v2_container,
+            &TaggedOptions {
+                encryption: Some(key.clone()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        match access_from_encrypted_tagged_bytes::<TestContainer>(
+            &encrypted,
+            &key,
+            &mut scratch,
+        )
+        .unwrap()
+        {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+        let mut in_place = encrypted.clone();
+        match access_from_encrypted_tagged_bytes_in_place::<TestContainer>(&mut in_place, &key)
+            .unwrap()
+        {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+        let mut in_place = encrypted.clone();
+        assert!(matches!(
+            access_from_encrypted_tagged_bytes_in_place::<TestContainer>(
+                &mut in_place,
+                &wrong_key
+            ),
+            Err(RkyvVersionedError::EncryptedPayloadError)
+        ));
+
+        // Unencrypted payloads are still readable through the encrypted API
+        let plain = to_tagged_bytes(&v2_container).unwrap();
+        match access_from_encrypted_tagged_bytes::<TestContainer>(&plain, &key, &mut scratch)
+            .unwrap()
+        {
+            ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+            _ => panic!("Expected V2"),
+        }
+    }
+
+    #[test]
+    fn test_archived_partial_eq() {
+        let v1 = TestStructV1 {
+            a: 1,
+            b: 2,
+            c: "YEET".to_owned(),
+        };
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "SKEET".to_owned(),
+        };
+
+        let bytes = to_tagged_bytes(&TestContainer::V1(&v1)).unwrap();
+        let archived = access_from_tagged_bytes::<TestContainer>(&bytes).unwrap();
+        assert!(*archived == TestContainer::V1(&v1));
+        assert!(*archived != TestContainer::V2(&v2));
+        let other_v1 = TestStructV1 {
+            c: "SKEET".to_owned(),
+            ..v1
+        };
+        assert!(*archived != TestContainer::V1(&other_v1));
+
+        // Owned variants are compared directly
+        let owned = OwnedTestContainer::V2(v2);
+        let bytes = to_tagged_bytes(&owned).unwrap();
+        let archived = access_from_tagged_bytes::<OwnedTestContainer>(&bytes).unwrap();
+        assert!(*archived == owned);
+        assert!(*archived != OwnedTestContainer::V1(other_v1));
+    }
+
+    #[test]
+    fn test_header_extensions() {
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "SKEET".repeat(100),
+        };
+        let container = TestContainer::V2(&v2);
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(1, b"tenant-42").unwrap();
+        extensions.insert(2, &[7; 20]).unwrap();
+
+        let plain = to_tagged_bytes(&container).unwrap();
+        assert!(get_extensions_from_tagged_bytes(&plain).unwrap().is_empty());
+
+        let mut options_list = vec![TaggedOptions::default()];
+        if CompressionCodec::Zstd.is_available() {
+            options_list.push(TaggedOptions {
+                compression: CompressionCodec::Zstd,
+                ..Default::default()
+            });
+        }
+        for options in options_list {
+            let options = TaggedOptions {
+                extensions: extensions.clone(),
+                ..options
+            };
+            let bytes = to_tagged_bytes_with(&container, &options).unwrap();
+            assert_eq!(
+                get_extensions_from_tagged_bytes(&bytes).unwrap(),
+                extensions
+            );
+
+            let mut scratch = AlignedVec::new();
+            match access_from_tagged_bytes_with_scratch::<TestContainer>(&bytes, &mut scratch)
+                .unwrap()
+            {
+                ArchivedTestContainer::V2(v2_ref) => assert!(*v2_ref.get() == v2),
+                _ => panic!("Expected V2"),
+            }
+
+            // Decompressing keeps the extensions
+            let decompressed = decompress_tagged_bytes(&bytes).unwrap();
+            assert_eq!(
+                get_extensions_from_tagged_bytes(&decompressed).unwrap(),
+                extensions
+            );
+            assert!(
+                *access_from_tagged_bytes::<TestContainer>(&decompressed).unwrap()
+                    == container
+            );
+
+            // The extensions are covered by the digest
+            let mut corrupt = bytes.clone();
+            corrupt[TAGGED_HEADER_SIZE + 8] ^= 1;
+            assert!(matches!(
+                access_from_tagged_bytes_with_scratch::<TestContainer>(&corrupt, &mut scratch),
+                Err(RkyvVersionedError::ChecksumMismatchError)
+            ));
+        }
+
+        if EncryptionAlgorithm::XChaCha20Poly1305.is_available() {
+            let key = EncryptionKey::from_bytes([1; 32]);
+            let options = TaggedOptions {
+                encryption: Some(key.clone()),
+                extensions: extensions.clone(),
+                ..Default::default()
+            };
+            let bytes = to_tagged_bytes_with(&container, &options).unwrap();
+            assert_eq!(
+                get_extensions_from_tagged_bytes(&bytes).unwrap(),
+                extensions
+            );
+            let mut scratch = AlignedVec::new();
+            assert!(
+                *access_from_encrypted_tagged_bytes::<TestContainer>(
+                    &bytes,
+                    &key,
+                    &mut scratch
+                )
+                .unwrap()
+                    == container
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_path_is_panic_free() {
+        let v2 = TestStructV2 {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: "SKEET".repeat(10),
+        };
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(1, b"tenant-42").unwrap();
+        let options = TaggedOptions {
+            extensions,
+            user_word: Some(7),
+            sequence: Some(9),
+            ..Default::default()
+        };
+        let mut records = vec![
+            to_tagged_bytes(&TestContainer::V2(&v2)).unwrap(),
+            to_tagged_bytes_with(&TestContainer::V2(&v2), &options).unwrap(),
+        ];
+        if CompressionCodec::Zstd.is_available() {
+            let options = TaggedOptions {
+                compression: CompressionCodec::Zstd,
+                ..options.clone()
+            };
+            records.push(to_tagged_bytes_with(&TestContainer::V2(&v2), &options).unwrap());
+        }
+
+        let read_all = |buf: &[u8]| {
+            let _ = read_header(buf);
+            #[cfg(feature = "std")]
+            let _ = peek_header(buf);
+            let _ = get_type_and_version_from_tagged_bytes(buf);
+            let _ = get_extensions_from_tagged_bytes(buf);
+            let _ = get_user_word_from_tagged_bytes(buf);
+            let _ = decompress_tagged_bytes(buf);
+            let mut aligned = AlignedVec::<16>::new();
+            aligned.extend_from_slice(buf);
+            let _ = access_from_tagged_bytes::<TestContainer>(&aligned);
+            TaggedRecordIter::<TestContainer>::new(&aligned).for_each(drop);
+            #[cfg(feature = "std")]
+            {
+                let mut framed = Vec::new();
+                crate::io::write_frame(buf, &mut framed).unwrap();
+                framed_records(&framed).for_each(drop);
+                resync_framed_records::<TestContainer>(&framed).for_each(drop);
+                resync_any_framed_records(&framed).for_each(drop);
+                resync_framed_records::<TestContainer>(buf).for_each(drop);
+            }
+            framed_records(buf).for_each(drop);
+        };
+
+        // Truncations, byte flips and random overwrites of valid records only ever fail
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for record in &records {
+            for len in 0..record.len() {
+                assert!(std::panic::catch_unwind(|| read_all(&record[..len])).is_ok());
+            }
+            for i in 0..record.len() {
+                for flip in [0x01, 0x80, 0xff] {
+                    let mut corrupt = record.as_slice().to_vec();
+                    corrupt[i] ^= flip;
+                    assert!(std::panic::catch_unwind(|| read_all(&corrupt)).is_ok());
+                }
+            }
+            for _ in 0..200 {
+                let mut corrupt = record.as_slice().to_vec();
+                for _ in 0..4 {
+                    state = state
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+                    let i = (state >> 33) as usize % corrupt.len();
+                    corrupt[i] = (state >> 17) as u8;
+                }
+                assert!(std::panic::catch_unwind(|| read_all(&corrupt)).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_bytes() {
+        let v1 = TestStructV1 {
+            a: 4,
+            b: 5,
+            c: "raw".to_string(),
+        };
+        let container = TestContainer::V1(&v1);
+        let options = TaggedOptions {
+            user_word: Some(1),
+            ..Default::default()
+        };
+        for bytes in [
+            to_tagged_bytes(&container).unwrap(),
+            to_tagged_bytes_with(&container, &options).unwrap(),
+        ] {
+            let range = payload_range(&bytes).unwrap();
+            assert_eq!(range.start % 16, 0);
+            let payload = payload_bytes(&bytes).unwrap();
+            assert_eq!(payload, &bytes[range]);
+            assert_eq!(
+                payload,
+                rkyv::to_bytes::<rkyv::rancor::Error>(&container)
+                    .unwrap()
+                    .as_slice()
+            );
+            match rkyv::access::<ArchivedTestContainer, rkyv::rancor::Error>(payload).unwrap()
+            {
+                ArchivedTestContainer::V1(v1) => assert_eq!(v1.b, 5),
+                _ => panic!("Expected V1"),
+            }
+
+            let mut corrupt = bytes.clone();
+            let last = corrupt.len() - 1;
+            corrupt[last] ^= 1;
+            assert!(matches!(
+                payload_bytes(&corrupt),
+                Err(RkyvVersionedError::ChecksumMismatchError)
+            ));
+        }
+
+        if CompressionCodec::Zstd.is_available() {
+            let options = TaggedOptions {
+                compression: CompressionCodec::Zstd,
+                ..Default::default()
+            };
+            let bytes = to_tagged_bytes_with(&container, &options).unwrap();
+            assert!(matches!(
+                payload_bytes(&bytes),
+                Err(RkyvVersionedError::CompressedPayloadError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_get_ref_from_tagged_bytes_at() {
+        let v1 = TestStructV1 {
+            a: 7,
+            b: 8,
+            c: "embedded".to_string(),
+        };
+        let bytes = to_tagged_bytes(&TestContainer::V1(&v1)).unwrap();
+
+        // A packet with an envelope of its own around the record
+        let mut packet = AlignedVec::<16>::new();
+        packet.extend_from_slice(&[0xee; 32]);
+        packet.extend_from_slice(&bytes);
+        packet.extend_from_slice(b"envelope trailer");
+
+        match get_ref_from_tagged_bytes_at::<TestContainer>(&packet, 32).unwrap() {
+            ArchivedTestContainer::V1(v1) => assert_eq!(v1.c, "embedded"),
+            _ => panic!("Expected V1"),
+        }
+        assert!(get_ref_from_tagged_bytes_at::<TestContainer>(&packet, 0).is_err());
+        assert!(matches!(
+            get_ref_from_tagged_bytes_at::<TestContainer>(&packet, packet.len() + 1),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        if !cfg!(feature = "unaligned") {
+            let mut shifted = AlignedVec::<16>::new();
+            shifted.extend_from_slice(&[0xee; 8]);
+            shifted.extend_from_slice(&bytes);
+            assert!(matches!(
+                get_ref_from_tagged_bytes_at::<TestContainer>(&shifted, 8),
+                Err(RkyvVersionedError::MisalignedError(8))
+            ));
+        }
+    }
 }
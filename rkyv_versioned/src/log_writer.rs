@@ -0,0 +1,283 @@
+//! Appending tagged records to a file with a configurable durability policy.
+//!
+//! [LogWriter] writes records as the length-prefixed frames of
+//! [write_tagged](crate::write_tagged) and syncs them to stable storage according to a
+//! [SyncPolicy], trading the latency of each append against how many records can be lost if
+//! the machine crashes.  Whatever the policy, a record is only durable once a sync following
+//! it has succeeded, and every policy syncs on [sync](LogWriter::sync),
+//! [close](LogWriter::close) and when the writer is dropped.  Errors syncing on drop are
+//! ignored, so use [close](LogWriter::close) to find out about them.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::io::{io_error, write_frame};
+use crate::{
+    read_header, to_tagged_bytes_with, RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// A stream whose writes can be synced to stable storage.
+pub trait DurableWrite: Write {
+    /// Flushes any buffered data and waits for it to reach stable storage.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl DurableWrite for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+impl<W: DurableWrite> DurableWrite for BufWriter<W> {
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync()
+    }
+}
+
+/// When a [LogWriter] syncs the records appended to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync before each append returns, so that every record appended is durable.  The
+    /// slowest policy, losing nothing on a crash.
+    EveryEntry,
+    /// Sync after every `n`th record, so that at most `n - 1` records are lost on a crash.
+    EveryEntries(u32),
+    /// Sync on the first append at least the given interval after the previous sync.  There is
+    /// no background thread, so the records appended last stay unsynced until the next append,
+    /// [sync](LogWriter::sync) or [close](LogWriter::close) call.
+    Interval(Duration),
+    /// Only sync on [sync](LogWriter::sync), [close](LogWriter::close) and drop, leaving
+    /// durability to the caller and the OS.
+    OnDrop,
+}
+
+/// Appends tagged records to a [DurableWrite] stream, syncing them according to a
+/// [SyncPolicy], see the [module](self) documentation.
+#[derive(Debug)]
+pub struct LogWriter<W: DurableWrite = File> {
+    /// Only `None` once the writer has been closed.
+    writer: Option<W>,
+    options: TaggedOptions,
+    policy: SyncPolicy,
+    /// The records appended since the last sync.
+    unsynced: u32,
+    last_sync: Instant,
+}
+
+impl<W: DurableWrite> LogWriter<W> {
+    /// Wraps `writer`, positioned where the next record should be appended.
+    pub fn new(writer: W, policy: SyncPolicy) -> Self {
+        LogWriter {
+            writer: Some(writer),
+            options: TaggedOptions::default(),
+            policy,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    /// Tags the records appended with `options` instead of the default [TaggedOptions].
+    pub fn with_options(mut self, options: TaggedOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the sync policy.
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Returns the number of records appended since the last successful sync.
+    pub fn unsynced(&self) -> u32 {
+        self.unsynced
+    }
+
+    /// Returns a reference to the underlying stream.
+    // The writer is only taken by `close`, which consumes the `LogWriter`
+    #[allow(clippy::expect_used)]
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("LogWriter used after close")
+    }
+
+    /// Serializes `item` and appends it to the log as a tagged frame, syncing if the policy
+    /// requires it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of bytes appended, or an error if serialization, the
+    /// write or the sync fails.  If the sync fails, the record has been written but may not be
+    /// durable.
+    pub fn append<T>(&mut self, item: &T) -> Result<usize, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let record = to_tagged_bytes_with(item, &self.options)?;
+        self.append_record(&record)
+    }
+
+    /// Appends an already tagged byte array to the log, e.g. one received from a peer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of bytes appended, or an error if `record` is not a
+    /// complete tagged byte array, or the write or sync fails.
+    pub fn append_record(&mut self, record: &[u8]) -> Result<usize, RkyvVersionedError> {
+        let tagged_len = read_header(record)?.tagged_len()?;
+        let record = record
+            .get(..tagged_len)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let writer = self.writer.as_mut().ok_or_else(closed_error)?;
+        let written = write_frame(record, writer)?;
+        self.unsynced = self.unsynced.saturating_add(1);
+
+        let due = match self.policy {
+            SyncPolicy::EveryEntry => true,
+            SyncPolicy::EveryEntries(n) => self.unsynced >= n,
+            SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            SyncPolicy::OnDrop => false,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(written)
+    }
+
+    /// Syncs the records appended so far, whatever the policy.
+    pub fn sync(&mut self) -> Result<(), RkyvVersionedError> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.sync().map_err(io_error)?;
+        }
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Syncs the records appended so far and returns the underlying stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the stream, or an error if the final sync fails.
+    pub fn close(mut self) -> Result<W, RkyvVersionedError> {
+        self.sync()?;
+        self.writer.take().ok_or_else(closed_error)
+    }
+}
+
+/// The error for a [LogWriter] used after [close](LogWriter::close), which the API rules out.
+fn closed_error() -> RkyvVersionedError {
+    io_error(io::ErrorKind::NotConnected.into())
+}
+
+impl<W: DurableWrite> Drop for LogWriter<W> {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.sync();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{framed_records, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+    }
+
+    /// An in-memory stream counting its syncs.
+    #[derive(Debug, Default)]
+    struct CountingSync {
+        data: Vec<u8>,
+        syncs: Rc<Cell<u32>>,
+    }
+
+    impl Write for CountingSync {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl DurableWrite for CountingSync {
+        fn sync(&mut self) -> io::Result<()> {
+            self.syncs.set(self.syncs.get() + 1);
+            Ok(())
+        }
+    }
+
+    fn syncs_after(policy: SyncPolicy, appends: u64) -> u32 {
+        let stream = CountingSync::default();
+        let syncs = stream.syncs.clone();
+        let mut log = LogWriter::new(stream, policy);
+        for value in 0..appends {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        drop(log);
+        syncs.get()
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        let stream = CountingSync::default();
+        let syncs = stream.syncs.clone();
+        let mut log = LogWriter::new(stream, SyncPolicy::EveryEntries(3));
+        for value in 0..7 {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        assert_eq!(syncs.get(), 2);
+        assert_eq!(log.unsynced(), 1);
+        let stream = log.close().unwrap();
+        assert_eq!(syncs.get(), 3);
+        assert_eq!(framed_records(&stream.data).count(), 7);
+
+        // Dropping the writer syncs whatever the policy, on top of the policy's own syncs
+        assert_eq!(syncs_after(SyncPolicy::EveryEntry, 5), 6);
+        assert_eq!(syncs_after(SyncPolicy::OnDrop, 5), 1);
+        assert_eq!(
+            syncs_after(SyncPolicy::Interval(Duration::from_secs(3600)), 5),
+            1
+        );
+        assert_eq!(syncs_after(SyncPolicy::Interval(Duration::ZERO), 5), 6);
+
+        // Pre-tagged records are framed like serialized ones
+        let mut log = LogWriter::new(CountingSync::default(), SyncPolicy::OnDrop);
+        let record = crate::to_tagged_bytes(&Event::V1(1)).unwrap();
+        log.append_record(&record).unwrap();
+        assert!(log.append_record(&record[..8]).is_err());
+        let mut expected = Vec::new();
+        crate::write_tagged(&Event::V1(1), &mut expected).unwrap();
+        assert_eq!(log.get_ref().data, expected);
+    }
+
+    #[test]
+    fn test_log_writer_file() {
+        let path = std::env::temp_dir()
+            .join(format!("rkyv_versioned_log_writer_{}", std::process::id()));
+        let file = BufWriter::new(File::create(&path).unwrap());
+        let mut log = LogWriter::new(file, SyncPolicy::EveryEntries(2));
+        for value in 0..3 {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        drop(log);
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(framed_records(&contents).count(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+}
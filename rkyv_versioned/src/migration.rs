@@ -0,0 +1,282 @@
+//! Combinators for common lossy edits in migrations between versions.
+//!
+//! Migrations are plain functions from the archived value of one version to another version,
+//! e.g. as passed to [self_check_with_migration](crate::self_check_with_migration).  Moving to
+//! a version with tighter limits often means the same edits: shortening strings and lists to
+//! a new maximum length ([truncate_to]) or dropping map entries the new version has no field
+//! for ([drop_unknown_keys]).  The combinators make those edits on archived or owned values
+//! and record every change which loses data in a [MigrationReport], so callers can log,
+//! count or reject lossy migrations without writing the bookkeeping by hand.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A change made by a migration which lost data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LossyEdit {
+    /// A string or list was shortened from `from` to `to` characters or elements.
+    Truncated {
+        /// The name of the field.
+        field: String,
+        /// The original length.
+        from: usize,
+        /// The length after truncation.
+        to: usize,
+    },
+    /// Map entries with keys unknown to the new version were dropped.
+    DroppedKeys {
+        /// The name of the field.
+        field: String,
+        /// The keys dropped.
+        keys: Vec<String>,
+    },
+    /// Any other lossy change, recorded with [MigrationReport::record].
+    Other {
+        /// The name of the field.
+        field: String,
+        /// What was lost.
+        description: String,
+    },
+}
+
+impl fmt::Display for LossyEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LossyEdit::Truncated { field, from, to } => {
+                write!(f, "{}: truncated from {} to {}", field, from, to)
+            }
+            LossyEdit::DroppedKeys { field, keys } => {
+                write!(f, "{}: dropped keys {}", field, keys.join(", "))
+            }
+            LossyEdit::Other { field, description } => write!(f, "{}: {}", field, description),
+        }
+    }
+}
+
+/// The lossy edits made while migrating one record.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The lossy edits, in the order they were made.
+    pub edits: Vec<LossyEdit>,
+}
+
+impl MigrationReport {
+    /// Creates an empty report.
+    pub fn new() -> Self {
+        MigrationReport::default()
+    }
+
+    /// Returns whether the migration kept all of the data.
+    pub fn is_lossless(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Records a lossy change to `field` made by bespoke migration code.
+    pub fn record(&mut self, field: &str, description: impl Into<String>) {
+        self.edits.push(LossyEdit::Other {
+            field: field.to_string(),
+            description: description.into(),
+        });
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.edits.is_empty() {
+            return write!(f, "lossless");
+        }
+        for (i, edit) in self.edits.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", edit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shortens strings and lists to at most `max_len` characters or elements, see
+/// [TruncateTo].
+pub fn truncate_to(max_len: usize) -> TruncateTo {
+    TruncateTo { max_len }
+}
+
+/// The combinator returned by [truncate_to].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncateTo {
+    max_len: usize,
+}
+
+impl TruncateTo {
+    /// Copies `value`, an archived or owned string, keeping at most the first `max_len`
+    /// characters and recording the truncation of `field` in `report`.
+    pub fn string(&self, field: &str, value: &str, report: &mut MigrationReport) -> String {
+        match value.char_indices().nth(self.max_len) {
+            None => value.to_string(),
+            Some((end, _)) => {
+                report.edits.push(LossyEdit::Truncated {
+                    field: field.to_string(),
+                    from: value.chars().count(),
+                    to: self.max_len,
+                });
+                value[..end].to_string()
+            }
+        }
+    }
+
+    /// Converts the elements of `value`, e.g. an archived vector, with `convert`, keeping at
+    /// most the first `max_len` and recording the truncation of `field` in `report`.
+    pub fn list<A, B>(
+        &self,
+        field: &str,
+        value: &[A],
+        convert: impl FnMut(&A) -> B,
+        report: &mut MigrationReport,
+    ) -> Vec<B> {
+        if value.len() > self.max_len {
+            report.edits.push(LossyEdit::Truncated {
+                field: field.to_string(),
+                from: value.len(),
+                to: self.max_len,
+            });
+        }
+        value.iter().take(self.max_len).map(convert).collect()
+    }
+}
+
+/// Keeps only the map entries whose keys are in `known`, see [DropUnknownKeys].
+pub fn drop_unknown_keys<'k>(known: &'k [&'k str]) -> DropUnknownKeys<'k> {
+    DropUnknownKeys { known }
+}
+
+/// The combinator returned by [drop_unknown_keys].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropUnknownKeys<'k> {
+    known: &'k [&'k str],
+}
+
+impl DropUnknownKeys<'_> {
+    /// Filters `entries`, e.g. those of an archived map, keeping the entries with known keys
+    /// and recording the keys of the others dropped from `field` in `report`.
+    pub fn apply<K: AsRef<str>, V>(
+        &self,
+        field: &str,
+        entries: impl IntoIterator<Item = (K, V)>,
+        report: &mut MigrationReport,
+    ) -> Vec<(K, V)> {
+        let mut dropped = Vec::new();
+        let kept = entries
+            .into_iter()
+            .filter(|(key, _)| {
+                let known = self.known.contains(&key.as_ref());
+                if !known {
+                    dropped.push(key.as_ref().to_string());
+                }
+                known
+            })
+            .collect();
+        if !dropped.is_empty() {
+            dropped.sort_unstable();
+            report.edits.push(LossyEdit::DroppedKeys {
+                field: field.to_string(),
+                keys: dropped,
+            });
+        }
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct ProfileV1 {
+        bio: String,
+        tags: Vec<String>,
+        settings: BTreeMap<String, u32>,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Profile {
+        V1(ProfileV1),
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct CompactProfile {
+        bio: String,
+        tags: Vec<String>,
+        settings: BTreeMap<String, u32>,
+    }
+
+    fn migrate(archived: &ArchivedProfile, report: &mut MigrationReport) -> CompactProfile {
+        match archived {
+            ArchivedProfile::V1(v1) => CompactProfile {
+                bio: truncate_to(8).string("bio", &v1.bio, report),
+                tags: truncate_to(2).list("tags", &v1.tags, |tag| tag.to_string(), report),
+                settings: drop_unknown_keys(&["theme", "locale"])
+                    .apply("settings", v1.settings.iter(), report)
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_native()))
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_migration_combinators() {
+        let profile = Profile::V1(ProfileV1 {
+            bio: "Ünïcödé bio that is too long".to_string(),
+            tags: vec!["a".into(), "b".into(), "c".into()],
+            settings: BTreeMap::from([
+                ("theme".to_string(), 1),
+                ("legacy_flag".to_string(), 2),
+                ("beta".to_string(), 3),
+            ]),
+        });
+        let bytes = to_tagged_bytes(&profile).unwrap();
+        let archived = access_from_tagged_bytes::<Profile>(&bytes).unwrap();
+
+        let mut report = MigrationReport::new();
+        let migrated = migrate(archived, &mut report);
+        assert_eq!(migrated.bio, "Ünïcödé ");
+        assert_eq!(migrated.tags, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            migrated.settings,
+            BTreeMap::from([("theme".to_string(), 1)])
+        );
+        assert!(!report.is_lossless());
+        assert_eq!(
+            report.edits,
+            vec![
+                LossyEdit::Truncated {
+                    field: "bio".into(),
+                    from: 28,
+                    to: 8
+                },
+                LossyEdit::Truncated {
+                    field: "tags".into(),
+                    from: 3,
+                    to: 2
+                },
+                LossyEdit::DroppedKeys {
+                    field: "settings".into(),
+                    keys: vec!["beta".into(), "legacy_flag".into()]
+                },
+            ]
+        );
+        assert!(report
+            .to_string()
+            .contains("settings: dropped keys beta, legacy_flag"));
+
+        // Values within the limits are migrated losslessly
+        let mut report = MigrationReport::new();
+        assert_eq!(truncate_to(8).string("bio", "short", &mut report), "short");
+        assert!(report.is_lossless());
+        assert_eq!(report.to_string(), "lossless");
+    }
+}
@@ -0,0 +1,161 @@
+//! Shrinking failing tagged byte arrays into minimal reproducers.
+//!
+//! Inputs found by fuzzing, or captured from production, are usually much larger than the
+//! part of them which triggers a failure.  [minimize_failure] repeatedly removes chunks of a
+//! failing buffer and zeroes its bytes, keeping every change which still fails with the same
+//! [RkyvVersionedError] variant, until no single change does.  What is left is small enough
+//! to attach to a bug report or check in as a regression test.
+
+use core::mem::discriminant;
+
+use rkyv::util::AlignedVec;
+
+use crate::RkyvVersionedError;
+
+/// A minimal reproducer produced by [minimize_failure].
+#[derive(Debug)]
+pub struct MinimizedFailure {
+    /// The shrunk buffer.
+    pub bytes: AlignedVec,
+    /// The error the shrunk buffer fails with, of the same variant as the original failure.
+    pub error: RkyvVersionedError,
+    /// The number of candidates checked.
+    pub attempts: usize,
+}
+
+/// Shrinks `buf` while `check` keeps failing on it with the same error variant.
+///
+/// # Arguments
+///
+/// * `buf` - The failing buffer.
+/// * `check` - The check to reproduce, e.g. a call to an access function.  It is passed
+///   aligned copies of the candidates, so archives can be accessed in place.
+///
+/// # Returns
+///
+/// The minimal reproducer, or `None` if `check` doesn't fail on `buf` to begin with.
+pub fn minimize_failure(
+    buf: &[u8],
+    mut check: impl FnMut(&[u8]) -> Result<(), RkyvVersionedError>,
+) -> Option<MinimizedFailure> {
+    let mut current = aligned(buf);
+    let mut error = check(&current).err()?;
+    let kind = discriminant(&error);
+    let mut attempts = 1;
+
+    let mut try_candidate = |candidate: AlignedVec, error: &mut RkyvVersionedError| {
+        attempts += 1;
+        match check(&candidate) {
+            Err(e) if discriminant(&e) == kind => {
+                *error = e;
+                Some(candidate)
+            }
+            _ => None,
+        }
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        // Remove ever smaller chunks
+        let mut chunk = current.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < current.len() {
+                let end = (start + chunk).min(current.len());
+                let mut candidate = aligned(current.get(..start).unwrap_or_default());
+                candidate.extend_from_slice(current.get(end..).unwrap_or_default());
+                match try_candidate(candidate, &mut error) {
+                    Some(candidate) => {
+                        current = candidate;
+                        changed = true;
+                    }
+                    None => start += chunk,
+                }
+            }
+            chunk /= 2;
+        }
+
+        // Then zero the bytes which don't matter
+        for i in 0..current.len() {
+            let mut candidate = aligned(&current);
+            match candidate.get_mut(i) {
+                Some(byte) if *byte != 0 => *byte = 0,
+                _ => continue,
+            }
+            if let Some(candidate) = try_candidate(candidate, &mut error) {
+                current = candidate;
+                changed = true;
+            }
+        }
+    }
+
+    Some(MinimizedFailure {
+        bytes: current,
+        error,
+        attempts,
+    })
+}
+
+/// Returns whether `error` is of the same variant as `kind`, e.g. to tell whether a fixed
+/// build still fails on a reproducer in the same way.
+pub fn same_failure(error: &RkyvVersionedError, kind: &RkyvVersionedError) -> bool {
+    discriminant(error) == discriminant(kind)
+}
+
+/// Copies `bytes` into an aligned buffer.
+fn aligned(bytes: &[u8]) -> AlignedVec {
+    let mut out = AlignedVec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer,
+        TAGGED_HEADER_SIZE,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Report {
+        V1((String, Vec<u32>)),
+    }
+
+    fn check(buf: &[u8]) -> Result<(), RkyvVersionedError> {
+        access_from_tagged_bytes::<Report>(buf).map(|_| ())
+    }
+
+    #[test]
+    fn test_minimize_failure() {
+        let report = Report::V1(("quarterly".repeat(20), (0..64).collect()));
+        let bytes = to_tagged_bytes(&report).unwrap();
+        assert!(minimize_failure(&bytes, check).is_none());
+
+        // A bad version only needs the header to reproduce
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 9;
+        let minimized = minimize_failure(&bad_version, check).unwrap();
+        assert!(matches!(
+            minimized.error,
+            RkyvVersionedError::UnsupportedVersionError(9)
+        ));
+        assert_eq!(minimized.bytes.len(), TAGGED_HEADER_SIZE);
+        assert!(minimized.attempts > 1);
+
+        // A corrupt payload keeps its length, but loses its contents
+        let mut corrupt = bytes.clone();
+        corrupt[TAGGED_HEADER_SIZE + 3] ^= 1;
+        let minimized = minimize_failure(&corrupt, check).unwrap();
+        assert!(same_failure(
+            &minimized.error,
+            &RkyvVersionedError::ChecksumMismatchError
+        ));
+        let nonzero = |buf: &[u8]| buf.iter().filter(|byte| **byte != 0).count();
+        assert!(nonzero(&minimized.bytes) < nonzero(&corrupt) / 4);
+        assert!(check(&minimized.bytes).is_err());
+    }
+}
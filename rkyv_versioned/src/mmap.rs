@@ -0,0 +1,381 @@
+//! Zero-copy access to records in memory-mapped files, enabled with the `memmap2` feature.
+//!
+//! [MmapArchive] maps a file holding either a single tagged byte array, as written by
+//! [to_tagged_bytes](crate::to_tagged_bytes), or a stream of frames, as written by
+//! [append_framed](crate::append_framed) or [write_tagged](crate::write_tagged).  Opening it
+//! only reads the headers, the payloads are paged in by the OS when they are accessed, so
+//! large files can be served without reading them into memory.
+//!
+//! The mapping starts on a page boundary and frames keep every record at a multiple of
+//! [FRAME_ALIGNMENT](crate::FRAME_ALIGNMENT) bytes, so records are accessed in place.
+//! Compressed and encrypted records can't be, and are rejected when accessed.
+//!
+//! References returned by [MmapArchive::get] borrow the archive, so it can't be dropped, and
+//! the file unmapped, while they are alive.  Long-lived services which rotate archive files
+//! can't always keep to such a borrow, e.g. when records are handed to other tasks, so
+//! [MmapArchive::pin] returns a [PinnedEntry] instead, which shares ownership of the mapping:
+//! the file stays mapped until the archive and every entry pinned from it are dropped.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::io::io_error;
+use crate::{
+    access_from_tagged_bytes, check_header, framed_records,
+    get_ref_from_tagged_bytes_unchecked, payload_range, RkyvVersionedError,
+    VersionedContainer,
+};
+
+/// A memory-mapped file of tagged byte arrays of `T`, see the [module](self) documentation.
+#[derive(Debug)]
+pub struct MmapArchive<T> {
+    mmap: Arc<Mmap>,
+    /// The position of each record in the file.
+    records: Vec<Range<usize>>,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<T: VersionedContainer> MmapArchive<T> {
+    /// Maps a file holding a single tagged byte array and checks its header.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, by this or any other
+    /// process, see [Mmap::map].  Access is undefined behavior otherwise.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the archive, holding one record, or an error if the file can't be
+    /// mapped, or its header doesn't hold a supported version of `T` or a length which fits in
+    /// the file.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, RkyvVersionedError> {
+        let mmap = map(path.as_ref())?;
+        let tagged_len = check_header::<T>(&mmap)?.tagged_len()?;
+        if tagged_len > mmap.len() {
+            return Err(RkyvVersionedError::BufferTooSmallError);
+        }
+        Ok(MmapArchive {
+            mmap: Arc::new(mmap),
+            records: core::iter::once(0..tagged_len).collect(),
+            _container: PhantomData,
+        })
+    }
+
+    /// Maps a file holding a stream of length-prefixed frames and indexes its records,
+    /// checking every frame and record header.
+    ///
+    /// # Safety
+    ///
+    /// As for [open](Self::open), the file must not be modified or truncated while mapped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the archive, or an error if the file can't be mapped, a frame is
+    /// truncated or corrupt ([RkyvVersionedError::InvalidFrameError]), or a record doesn't
+    /// hold a supported version of `T`.
+    pub unsafe fn open_framed(path: impl AsRef<Path>) -> Result<Self, RkyvVersionedError> {
+        let mmap = map(path.as_ref())?;
+        let base = mmap.as_ptr() as usize;
+        let records = framed_records(&mmap)
+            .enumerate()
+            .map(|(index, record)| {
+                let record = record?;
+                let start = record.as_ptr() as usize - base;
+                check_header::<T>(record).map_err(|e| e.at_record(index, start))?;
+                Ok(start..start + record.len())
+            })
+            .collect::<Result<_, RkyvVersionedError>>()?;
+        Ok(MmapArchive {
+            mmap: Arc::new(mmap),
+            records,
+            _container: PhantomData,
+        })
+    }
+
+    /// Returns the number of records in the file.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns whether the file holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the tagged byte array of the record at `index`, e.g. to forward it without
+    /// accessing it.
+    pub fn record_bytes(&self, index: usize) -> Option<&[u8]> {
+        let range = self.records.get(index)?;
+        self.mmap.get(range.clone())
+    }
+
+    /// Accesses the record at `index` in place, validating its digest and payload like
+    /// [access_from_tagged_bytes].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the archived container, or an error if `index` is out of
+    /// range ([RkyvVersionedError::BufferTooSmallError]), or the record fails validation or is
+    /// compressed or encrypted, located with its index and offset in a
+    /// [RkyvVersionedError::RecordError].
+    pub fn get(&self, index: usize) -> Result<&T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        let record = self
+            .records
+            .get(index)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let bytes = self
+            .mmap
+            .get(record.clone())
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        access_from_tagged_bytes::<T>(bytes).map_err(|e| e.at_record(index, record.start))
+    }
+
+    /// Accesses the record at `index` in place without validating its digest or payload, like
+    /// [get_ref_from_tagged_bytes_unchecked].
+    ///
+    /// # Safety
+    ///
+    /// The record must be known to be valid, e.g. because the file was written by this
+    /// process or was validated with [get](Self::get) before.
+    pub unsafe fn get_unchecked(
+        &self,
+        index: usize,
+    ) -> Result<&T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable,
+    {
+        let record = self
+            .record_bytes(index)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        get_ref_from_tagged_bytes_unchecked::<T>(record)
+    }
+
+    /// Validates the record at `index` like [get](Self::get) and pins it, so that it can be
+    /// accessed after the archive is dropped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the pinned entry, or an error if `index` is out of range
+    /// ([RkyvVersionedError::BufferTooSmallError]), or the record fails validation or is
+    /// compressed or encrypted.
+    pub fn pin(&self, index: usize) -> Result<PinnedEntry<T>, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        let record = self
+            .records
+            .get(index)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let bytes = self
+            .mmap
+            .get(record.clone())
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let payload = access_from_tagged_bytes::<T>(bytes)
+            .and_then(|_| payload_range(bytes))
+            .map_err(|e| e.at_record(index, record.start))?;
+        Ok(PinnedEntry {
+            mmap: self.mmap.clone(),
+            record: record.clone(),
+            payload: record.start + payload.start..record.start + payload.end,
+            _container: PhantomData,
+        })
+    }
+
+    /// Returns the number of entries pinned from the archive which are still alive.
+    pub fn pinned(&self) -> usize {
+        Arc::strong_count(&self.mmap) - 1
+    }
+
+    /// Returns an iterator accessing every record in turn with [get](Self::get).
+    pub fn iter(&self) -> impl Iterator<Item = Result<&T::Archived, RkyvVersionedError>>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        (0..self.len()).map(|index| self.get(index))
+    }
+}
+
+/// A validated record of an [MmapArchive], keeping the file mapped while it is alive, see
+/// the [module](self) documentation.
+pub struct PinnedEntry<T> {
+    mmap: Arc<Mmap>,
+    /// The position of the record in the file.
+    record: Range<usize>,
+    /// The position of the payload in the file.
+    payload: Range<usize>,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for PinnedEntry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedEntry")
+            .field("record", &self.record)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for PinnedEntry<T> {
+    fn clone(&self) -> Self {
+        PinnedEntry {
+            mmap: self.mmap.clone(),
+            record: self.record.clone(),
+            payload: self.payload.clone(),
+            _container: PhantomData,
+        }
+    }
+}
+
+impl<T: VersionedContainer> PinnedEntry<T>
+where
+    T::Archived: rkyv::Portable,
+{
+    /// Accesses the archived container in place, without validating it again.
+    // The payload range was checked when the entry was pinned, and must not fall back to other
+    // bytes since they are accessed unchecked
+    #[allow(clippy::indexing_slicing)]
+    pub fn get(&self) -> &T::Archived {
+        // SAFETY: The payload was validated when the entry was pinned, and the mapping is
+        // kept alive, and unmodified per the contract of the archive's constructors, by `self`
+        unsafe { rkyv::access_unchecked::<T::Archived>(&self.mmap[self.payload.clone()]) }
+    }
+}
+
+impl<T> PinnedEntry<T> {
+    /// Returns the tagged byte array of the record.
+    pub fn record_bytes(&self) -> &[u8] {
+        self.mmap.get(self.record.clone()).unwrap_or_default()
+    }
+}
+
+impl<T> AsRef<[u8]> for PinnedEntry<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.record_bytes()
+    }
+}
+
+/// Maps `path` read-only.
+unsafe fn map(path: &Path) -> Result<Mmap, RkyvVersionedError> {
+    let file = File::open(path).map_err(io_error)?;
+    Mmap::map(&file).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{append_framed, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::path::PathBuf;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u64),
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "rkyv_versioned_mmap_{}_{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_mmap_archive() {
+        let single = temp_file("single", &to_tagged_bytes(&Event::V2(7)).unwrap());
+        let archive = unsafe { MmapArchive::<Event>::open(&single) }.unwrap();
+        assert_eq!(archive.len(), 1);
+        match archive.get(0).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 7),
+            _ => panic!("Expected V2"),
+        }
+        assert!(archive.get(1).is_err());
+        assert!(matches!(
+            unsafe { MmapArchive::<Other>::open(&single) },
+            Err(RkyvVersionedError::UnexpectedTypeError(_, _))
+        ));
+
+        let mut stream = AlignedVec::<16>::new();
+        for value in 0..5 {
+            append_framed(&mut stream, &to_tagged_bytes(&Event::V2(value)).unwrap()).unwrap();
+        }
+        append_framed(
+            &mut stream,
+            &to_tagged_bytes(&Event::V1("last".to_string())).unwrap(),
+        )
+        .unwrap();
+        let framed = temp_file("framed", &stream);
+        let archive = unsafe { MmapArchive::<Event>::open_framed(&framed) }.unwrap();
+        assert_eq!(archive.len(), 6);
+        for value in 0..5 {
+            match unsafe { archive.get_unchecked(value) }.unwrap() {
+                ArchivedEvent::V2(v) => assert_eq!(*v, value as u64),
+                _ => panic!("Expected V2"),
+            }
+        }
+        assert!(matches!(archive.get(5).unwrap(), ArchivedEvent::V1(_)));
+        assert!(archive.get(6).is_err());
+        assert_eq!(archive.iter().filter(Result::is_ok).count(), 6);
+        assert_eq!(
+            archive.record_bytes(5).unwrap(),
+            to_tagged_bytes(&Event::V1("last".to_string()))
+                .unwrap()
+                .as_slice()
+        );
+
+        // Truncated streams are rejected when opened
+        let truncated = temp_file("truncated", &stream[..stream.len() - 20]);
+        assert!(matches!(
+            unsafe { MmapArchive::<Event>::open_framed(&truncated) },
+            Err(RkyvVersionedError::InvalidFrameError(_))
+        ));
+
+        // Pinned entries outlive the archive they were pinned from
+        let archive = unsafe { MmapArchive::<Event>::open_framed(&framed) }.unwrap();
+        let pinned = archive.pin(5).unwrap();
+        let cloned = pinned.clone();
+        assert_eq!(archive.pinned(), 2);
+        assert!(archive.pin(6).is_err());
+        drop(archive);
+        match pinned.get() {
+            ArchivedEvent::V1(text) => assert_eq!(text, "last"),
+            _ => panic!("Expected V1"),
+        }
+        assert_eq!(
+            cloned.record_bytes(),
+            to_tagged_bytes(&Event::V1("last".to_string()))
+                .unwrap()
+                .as_slice()
+        );
+
+        for path in [single, framed, truncated] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
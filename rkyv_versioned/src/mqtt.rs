@@ -0,0 +1,219 @@
+//! Helpers for carrying tagged byte arrays in MQTT payloads.
+//!
+//! These helpers only build topics and payloads, so they work with any MQTT client, and avoid
+//! `std`-only APIs so that constrained publishers can share them with the gateways and
+//! services consuming their messages.
+//!
+//! Messages are published under `{prefix}/{type_id}/{version_id}`, with the `type_id` written
+//! as 8 lowercase hex digits and the `version_id` in decimal, e.g. `sensors/1a2b3c4d/0`.
+//! Subscribers can use [topic_filter] to receive every version of a container, or
+//! [topic_for_version] for a single version.
+//!
+//! The payload is the tagged byte array framed with a [HeaderProfile].  Publishers on
+//! constrained links can use [HeaderProfile::Compact] to save 8 bytes per message, and
+//! subscribers must decode with the same profile.
+
+use alloc::format;
+use alloc::string::String;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::profile::{reframe_from_standard, reframe_to_standard};
+use crate::{
+    read_header, to_tagged_bytes_with, HeaderProfile, RkyvVersionedError, TaggedOptions,
+    VersionedContainer,
+};
+
+/// A topic and payload ready to be handed to an MQTT client.
+#[derive(Debug)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: AlignedVec,
+}
+
+/// Returns the topic filter matching every version of `T` under `prefix`.
+pub fn topic_filter<T: VersionedContainer>(prefix: &str) -> String {
+    format!("{}/{:08x}/+", prefix, T::ARCHIVE_TYPE_ID)
+}
+
+/// Returns the topic of a specific version of `T` under `prefix`.
+pub fn topic_for_version<T: VersionedContainer>(prefix: &str, version_id: u32) -> String {
+    topic(prefix, T::ARCHIVE_TYPE_ID, version_id)
+}
+
+fn topic(prefix: &str, type_id: u32, version_id: u32) -> String {
+    format!("{}/{:08x}/{}", prefix, type_id, version_id)
+}
+
+/// Parses the type and version IDs out of a topic published under `prefix`, returning `None`
+/// if the topic doesn't follow the convention.
+pub fn parse_topic(prefix: &str, topic: &str) -> Option<(u32, u32)> {
+    let (type_id, version_id) = topic
+        .strip_prefix(prefix)?
+        .strip_prefix('/')?
+        .split_once('/')?;
+    if type_id.len() != 8 {
+        return None;
+    }
+    Some((
+        u32::from_str_radix(type_id, 16).ok()?,
+        version_id.parse().ok()?,
+    ))
+}
+
+/// Serializes `item` into a message published under `prefix`, framed with `profile`.
+pub fn encode<T>(
+    prefix: &str,
+    item: &T,
+    options: &TaggedOptions,
+    profile: HeaderProfile,
+) -> Result<MqttMessage, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let tagged = to_tagged_bytes_with(item, options)?;
+    if profile == HeaderProfile::Standard {
+        return Ok(MqttMessage {
+            topic: topic(prefix, T::ARCHIVE_TYPE_ID, item.get_entry_version_id()),
+            payload: tagged,
+        });
+    }
+    encode_tagged(prefix, &tagged, profile)
+}
+
+/// Builds a message published under `prefix` from an already tagged byte array, reframing it
+/// with `profile` and deriving the topic from its header.
+pub fn encode_tagged(
+    prefix: &str,
+    tagged: &[u8],
+    profile: HeaderProfile,
+) -> Result<MqttMessage, RkyvVersionedError> {
+    let header = read_header(tagged)?;
+    let mut payload = AlignedVec::new();
+    reframe_from_standard(tagged, profile, &mut payload)?;
+    Ok(MqttMessage {
+        topic: topic(prefix, header.type_id, header.version_id),
+        payload,
+    })
+}
+
+/// Decodes a message received on `topic` whose payload was framed with `profile`, returning
+/// the standard tagged byte array so it can be accessed with
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+///
+/// Topics that don't follow the convention under `prefix`, or don't match the header of the
+/// payload, are rejected with [RkyvVersionedError::InvalidMessageError].
+pub fn decode(
+    prefix: &str,
+    topic: &str,
+    payload: &[u8],
+    profile: HeaderProfile,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    let ids = parse_topic(prefix, topic).ok_or(RkyvVersionedError::InvalidMessageError)?;
+    let tagged = reframe_to_standard(payload, profile)?;
+    let header = read_header(&tagged)?;
+    if ids != (header.type_id, header.version_id) {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+    Ok(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer,
+        TAGGED_HEADER_SIZE,
+    };
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Telemetry {
+        pub device: u32,
+        pub millivolts: u16,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum TelemetryContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Telemetry),
+    }
+
+    #[test]
+    fn test_mqtt_round_trip() {
+        let telemetry = Telemetry {
+            device: 42,
+            millivolts: 3300,
+        };
+        let container = TelemetryContainer::V1(&telemetry);
+        let tagged = to_tagged_bytes(&container).unwrap();
+
+        for profile in [HeaderProfile::Standard, HeaderProfile::Compact] {
+            let message =
+                encode("sensors", &container, &TaggedOptions::default(), profile).unwrap();
+            assert_eq!(
+                message.topic,
+                topic_for_version::<TelemetryContainer>("sensors", 0)
+            );
+            assert_eq!(
+                message.payload.len(),
+                tagged.len() - TAGGED_HEADER_SIZE + profile.header_size()
+            );
+            assert_eq!(
+                parse_topic("sensors", &message.topic),
+                Some((TelemetryContainer::ARCHIVE_TYPE_ID, 0))
+            );
+
+            let decoded =
+                decode("sensors", &message.topic, &message.payload, profile).unwrap();
+            assert_eq!(decoded.as_slice(), tagged.as_slice());
+            match access_from_tagged_bytes::<TelemetryContainer>(&decoded).unwrap() {
+                ArchivedTelemetryContainer::V1(t) => {
+                    assert_eq!(t.device, 42);
+                    assert_eq!(t.millivolts, 3300);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mqtt_topics() {
+        let type_id = TelemetryContainer::ARCHIVE_TYPE_ID;
+        assert_eq!(
+            topic_filter::<TelemetryContainer>("a/b"),
+            format!("a/b/{:08x}/+", type_id)
+        );
+        assert_eq!(
+            parse_topic("a/b", &format!("a/b/{:08x}/3", type_id)),
+            Some((type_id, 3))
+        );
+        assert_eq!(parse_topic("a/b", "a/c/00000001/3"), None);
+        assert_eq!(parse_topic("a/b", "a/b/1/3"), None);
+        assert_eq!(parse_topic("a/b", "a/b/00000001/x"), None);
+
+        // The topic must agree with the payload
+        let message = encode(
+            "a/b",
+            &TelemetryContainer::V1(&Telemetry {
+                device: 1,
+                millivolts: 1,
+            }),
+            &TaggedOptions::default(),
+            HeaderProfile::Compact,
+        )
+        .unwrap();
+        assert!(matches!(
+            decode(
+                "a/b",
+                "a/b/00000001/0",
+                &message.payload,
+                HeaderProfile::Compact
+            ),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+//! Alternative header encodings for transports where every byte counts, or where more than
+//! the payload needs protecting.
+//!
+//! Tagged byte arrays produced by this crate always use the [HeaderProfile::Standard] 16 byte
+//! header.  Transports carrying small messages from constrained devices can instead frame
+//! entries with the 8 byte [HeaderProfile::Compact] header, which drops the payload length
+//! (implied by the message length) and narrows the version ID to 16 bits:
+//!
+//! | Offset | Size | Field                                                      |
+//! |--------|------|------------------------------------------------------------|
+//! | 0      | 4    | `type_id`, little-endian                                   |
+//! | 4      | 2    | `version_id`, little-endian                                |
+//! | 6      | 1    | [DigestAlgorithm](crate::DigestAlgorithm) id in the low nibble, [CompressionCodec](crate::CompressionCodec) id in the high nibble |
+//! | 7      | 1    | [EncryptionAlgorithm](crate::EncryptionAlgorithm) id in the low nibble, header flags in the high nibble |
+//!
+//! Rich transports whose framing doesn't protect the header can use the 32 byte
+//! [HeaderProfile::Extended] header, which follows the standard header with a CRC32 of it:
+//!
+//! | Offset | Size | Field                                                      |
+//! |--------|------|------------------------------------------------------------|
+//! | 0      | 16   | The standard header                                        |
+//! | 16     | 4    | CRC32 (IEEE) of the standard header, little-endian         |
+//! | 20     | 12   | Reserved, zero                                             |
+//!
+//! The body and trailers are identical in every profile, so converting between them with
+//! [reframe] only rewrites the header and borrows the rest of the entry.
+
+#[cfg(feature = "std")]
+use std::io::{self, IoSlice, Write};
+
+use rkyv::util::AlignedVec;
+
+use crate::{
+    read_header, write_header, RkyvVersionedError, TaggedVersionedHeader, TAGGED_HEADER_SIZE,
+};
+
+/// The size of the [HeaderProfile::Compact] header.
+const COMPACT_HEADER_SIZE: usize = 8;
+
+/// The size of the [HeaderProfile::Extended] header.
+const EXTENDED_HEADER_SIZE: usize = 32;
+
+/// The header encoding used to frame a tagged entry on a transport.
+///
+/// The discriminant of each variant MUST NOT CHANGE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum HeaderProfile {
+    /// The 16 byte header written by [to_tagged_bytes](crate::to_tagged_bytes), which keeps
+    /// the payload aligned for in place access.
+    #[default]
+    Standard = 0,
+    /// An 8 byte header for constrained transports, only able to represent version IDs up to
+    /// `u16::MAX`.  Entries must be converted back to the standard profile to be accessed.
+    Compact = 1,
+    /// A 32 byte header protected by a CRC32, for transports which don't protect it
+    /// themselves.  Entries must be converted back to the standard profile to be accessed.
+    Extended = 2,
+}
+
+impl HeaderProfile {
+    /// Returns the identifier of the profile.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the profile for an identifier, or `None` if the identifier is unknown.
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(HeaderProfile::Standard),
+            1 => Some(HeaderProfile::Compact),
+            2 => Some(HeaderProfile::Extended),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes taken by the header in this profile.
+    pub const fn header_size(self) -> usize {
+        match self {
+            HeaderProfile::Standard => TAGGED_HEADER_SIZE,
+            HeaderProfile::Compact => COMPACT_HEADER_SIZE,
+            HeaderProfile::Extended => EXTENDED_HEADER_SIZE,
+        }
+    }
+}
+
+/// A header encoded in one of the profiles.
+type EncodedHeader = ([u8; EXTENDED_HEADER_SIZE], usize);
+
+/// Encodes `header` in `profile`.
+fn encode_profile_header(
+    header: &TaggedVersionedHeader,
+    profile: HeaderProfile,
+) -> Result<EncodedHeader, RkyvVersionedError> {
+    let mut bytes = [0u8; EXTENDED_HEADER_SIZE];
+    match profile {
+        HeaderProfile::Standard => {
+            write_header(&mut bytes, header)?;
+        }
+        HeaderProfile::Compact => {
+            let version_id = u16::try_from(header.version_id)
+                .map_err(|_| RkyvVersionedError::UnsupportedVersionError(header.version_id))?;
+            let nibble = |value: u8, error: fn(u8) -> RkyvVersionedError| match value {
+                0..=0x0f => Ok(value),
+                _ => Err(error(value)),
+            };
+            bytes[..4].copy_from_slice(&header.type_id.to_le_bytes());
+            bytes[4..6].copy_from_slice(&version_id.to_le_bytes());
+            bytes[6] = nibble(header.digest, RkyvVersionedError::UnsupportedDigestError)?
+                | nibble(
+                    header.compression,
+                    RkyvVersionedError::UnsupportedCompressionError,
+                )? << 4;
+            bytes[7] = nibble(
+                header.encryption,
+                RkyvVersionedError::UnsupportedEncryptionError,
+            )? | nibble(
+                header.flags,
+                RkyvVersionedError::UnsupportedHeaderFlagsError,
+            )? << 4;
+        }
+        HeaderProfile::Extended => {
+            write_header(&mut bytes, header)?;
+            let crc = crc32fast::hash(&bytes[..TAGGED_HEADER_SIZE]);
+            bytes[TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + 4]
+                .copy_from_slice(&crc.to_le_bytes());
+        }
+    }
+    Ok((bytes, profile.header_size()))
+}
+
+/// Reads the header of an entry framed in `profile`.  `buf` must hold exactly the entry, since
+/// the compact profile derives the payload length from it.
+pub(crate) fn read_profile_header(
+    buf: &[u8],
+    profile: HeaderProfile,
+) -> Result<TaggedVersionedHeader, RkyvVersionedError> {
+    match profile {
+        HeaderProfile::Standard => read_header(buf),
+        HeaderProfile::Compact => {
+            let (bytes, _) = buf
+                .split_first_chunk::<COMPACT_HEADER_SIZE>()
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+            let mut header = TaggedVersionedHeader {
+                type_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                version_id: u16::from_le_bytes([bytes[4], bytes[5]]).into(),
+                payload_len: 0,
+                digest: bytes[6] & 0x0f,
+                compression: bytes[6] >> 4,
+                encryption: bytes[7] & 0x0f,
+                flags: bytes[7] >> 4,
+            };
+            let payload_len = (buf.len() - COMPACT_HEADER_SIZE)
+                .checked_sub(header.trailer_len()?)
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+            header.payload_len = u32::try_from(payload_len)
+                .map_err(|_| RkyvVersionedError::PayloadTooLargeError(payload_len))?;
+            Ok(header)
+        }
+        HeaderProfile::Extended => {
+            let (bytes, _) = buf
+                .split_first_chunk::<EXTENDED_HEADER_SIZE>()
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+            let (standard, rest) = bytes
+                .split_first_chunk::<TAGGED_HEADER_SIZE>()
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+            let (crc, reserved) = rest
+                .split_first_chunk::<4>()
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+            if crc32fast::hash(standard).to_le_bytes() != *crc {
+                return Err(RkyvVersionedError::ChecksumMismatchError);
+            }
+            if reserved.iter().any(|b| *b != 0) {
+                return Err(RkyvVersionedError::InvalidMessageError);
+            }
+            read_header(standard)
+        }
+    }
+}
+
+/// A tagged entry framed in a [HeaderProfile], borrowed from the buffer holding it.
+#[derive(Debug, Clone, Copy)]
+pub struct EntryView<'a> {
+    profile: HeaderProfile,
+    header: TaggedVersionedHeader,
+    /// The body and trailers following the header.
+    rest: &'a [u8],
+}
+
+impl<'a> EntryView<'a> {
+    /// Reads the header of an entry framed in `profile`.
+    ///
+    /// `buf` must hold exactly the entry for the [HeaderProfile::Compact] profile, since it
+    /// derives the payload length from it.  Any bytes after the entry are ignored otherwise.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the view, or an error if the header is invalid or `buf` is
+    /// shorter than the entry it describes.
+    pub fn new(buf: &'a [u8], profile: HeaderProfile) -> Result<Self, RkyvVersionedError> {
+        let header = read_profile_header(buf, profile)?;
+        let rest_len = header.tagged_len()? - TAGGED_HEADER_SIZE;
+        let rest = buf
+            .get(profile.header_size()..profile.header_size() + rest_len)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        Ok(EntryView {
+            profile,
+            header,
+            rest,
+        })
+    }
+
+    /// Returns the profile the entry is framed in.
+    pub fn profile(&self) -> HeaderProfile {
+        self.profile
+    }
+
+    /// Returns the type ID of the entry.
+    pub fn type_id(&self) -> u32 {
+        self.header.type_id
+    }
+
+    /// Returns the version ID of the entry.
+    pub fn version_id(&self) -> u32 {
+        self.header.version_id
+    }
+
+    /// Returns the body and trailers of the entry, which are the same in every profile.
+    pub fn body(&self) -> &'a [u8] {
+        self.rest
+    }
+}
+
+/// An entry reframed by [reframe]: a newly encoded header in front of the borrowed body of
+/// the original entry.
+#[derive(Debug, Clone, Copy)]
+pub struct Reframed<'a> {
+    header: EncodedHeader,
+    body: &'a [u8],
+}
+
+impl<'a> Reframed<'a> {
+    /// Returns the encoded header.
+    pub fn header(&self) -> &[u8] {
+        self.header.0.get(..self.header.1).unwrap_or_default()
+    }
+
+    /// Returns the body and trailers, borrowed from the original entry.
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// Returns the length of the reframed entry.
+    pub fn len(&self) -> usize {
+        self.header.1 + self.body.len()
+    }
+
+    /// Returns whether the reframed entry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the header and body as slices for a vectored write, e.g. with
+    /// [Write::write_vectored].
+    #[cfg(feature = "std")]
+    pub fn io_slices(&self) -> [IoSlice<'_>; 2] {
+        [IoSlice::new(self.header()), IoSlice::new(self.body)]
+    }
+
+    /// Writes the reframed entry to `writer` without copying the body.
+    #[cfg(feature = "std")]
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<usize> {
+        writer.write_all(self.header())?;
+        writer.write_all(self.body)?;
+        Ok(self.len())
+    }
+
+    /// Appends the reframed entry to `out`.
+    pub fn extend_into(&self, out: &mut AlignedVec) {
+        out.reserve(self.len());
+        out.extend_from_slice(self.header());
+        out.extend_from_slice(self.body);
+    }
+
+    /// Copies the reframed entry into an [AlignedVec], e.g. to access an entry reframed in
+    /// the standard profile in place.
+    pub fn to_bytes(&self) -> AlignedVec {
+        let mut bytes = AlignedVec::with_capacity(self.len());
+        self.extend_into(&mut bytes);
+        bytes
+    }
+}
+
+/// Converts an entry to `profile`, e.g. in a gateway bridging a constrained transport and a
+/// rich one.
+///
+/// Only the header is rewritten, the body and trailers are borrowed from `input`, so the
+/// result can be written out or sent without copying the payload.
+///
+/// # Returns
+///
+/// A `Result` containing the reframed entry, or an error if the header can't be represented
+/// in `profile`, e.g. a version ID over `u16::MAX` in [HeaderProfile::Compact]
+/// ([RkyvVersionedError::UnsupportedVersionError]).
+pub fn reframe<'a>(
+    input: &EntryView<'a>,
+    profile: HeaderProfile,
+) -> Result<Reframed<'a>, RkyvVersionedError> {
+    Ok(Reframed {
+        header: encode_profile_header(&input.header, profile)?,
+        body: input.rest,
+    })
+}
+
+/// Reframes a standard tagged byte array in `profile`, appending the result to `out`.
+pub(crate) fn reframe_from_standard(
+    tagged: &[u8],
+    profile: HeaderProfile,
+    out: &mut AlignedVec,
+) -> Result<(), RkyvVersionedError> {
+    let input = EntryView::new(tagged, HeaderProfile::Standard)?;
+    reframe(&input, profile)?.extend_into(out);
+    Ok(())
+}
+
+/// Reframes an entry framed in `profile` as a standard tagged byte array.
+pub(crate) fn reframe_to_standard(
+    buf: &[u8],
+    profile: HeaderProfile,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    let input = EntryView::new(buf, profile)?;
+    Ok(reframe(&input, HeaderProfile::Standard)?.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Archive, Serialize, Deserialize)]
+    struct Reading {
+        pub sensor: u16,
+        pub celsius: i32,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum ReadingContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Reading),
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        let reading = Reading {
+            sensor: 7,
+            celsius: -12,
+        };
+        let tagged = to_tagged_bytes(&ReadingContainer::V1(&reading)).unwrap();
+
+        let mut compact = AlignedVec::<16>::new();
+        reframe_from_standard(&tagged, HeaderProfile::Compact, &mut compact).unwrap();
+        assert_eq!(compact.len(), tagged.len() - 8);
+        assert_eq!(
+            read_profile_header(&compact, HeaderProfile::Compact).unwrap(),
+            read_header(&tagged).unwrap()
+        );
+
+        let standard = reframe_to_standard(&compact, HeaderProfile::Compact).unwrap();
+        assert_eq!(standard.as_slice(), tagged.as_slice());
+        match access_from_tagged_bytes::<ReadingContainer>(&standard).unwrap() {
+            ArchivedReadingContainer::V1(r) => assert_eq!(r.celsius, -12),
+        }
+
+        // Truncated entries are rejected
+        assert!(matches!(
+            reframe_to_standard(&compact[..4], HeaderProfile::Compact),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+        assert!(matches!(
+            reframe_to_standard(&compact[..10], HeaderProfile::Compact),
+            Err(RkyvVersionedError::BufferTooSmallError)
+        ));
+    }
+
+    #[test]
+    fn test_compact_rejects_wide_versions() {
+        let mut header = read_header(
+            &to_tagged_bytes(&ReadingContainer::V1(&Reading {
+                sensor: 1,
+                celsius: 1,
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        header.version_id = 0x1_0000;
+        assert!(matches!(
+            encode_profile_header(&header, HeaderProfile::Compact),
+            Err(RkyvVersionedError::UnsupportedVersionError(0x1_0000))
+        ));
+        assert_eq!(
+            HeaderProfile::from_id(HeaderProfile::Compact.id()),
+            Some(HeaderProfile::Compact)
+        );
+        assert_eq!(HeaderProfile::from_id(0xff), None);
+    }
+
+    #[test]
+    fn test_reframe() {
+        let tagged = to_tagged_bytes(&ReadingContainer::V1(&Reading {
+            sensor: 3,
+            celsius: 21,
+        }))
+        .unwrap();
+        let standard = EntryView::new(&tagged, HeaderProfile::Standard).unwrap();
+
+        // Standard -> extended -> compact -> standard, borrowing the same body throughout
+        let extended = reframe(&standard, HeaderProfile::Extended).unwrap();
+        assert_eq!(extended.len(), tagged.len() + 16);
+        assert_eq!(extended.body().as_ptr(), standard.body().as_ptr());
+        let extended_bytes = extended.to_bytes();
+        let extended_view = EntryView::new(&extended_bytes, HeaderProfile::Extended).unwrap();
+        assert_eq!(extended_view.version_id(), 0);
+
+        let compact = reframe(&extended_view, HeaderProfile::Compact).unwrap();
+        let written = compact.to_bytes();
+        assert_eq!(written.len(), tagged.len() - 8);
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            assert_eq!(compact.write_to(&mut out).unwrap(), written.len());
+            assert_eq!(out, written.as_slice());
+        }
+        let compact_view = EntryView::new(&written, HeaderProfile::Compact).unwrap();
+        assert_eq!(compact_view.type_id(), standard.type_id());
+
+        let round_trip = reframe(&compact_view, HeaderProfile::Standard)
+            .unwrap()
+            .to_bytes();
+        assert_eq!(round_trip.as_slice(), tagged.as_slice());
+
+        // A corrupt extended header is detected
+        let mut corrupt = extended_bytes.clone();
+        corrupt[4] ^= 1;
+        assert!(matches!(
+            EntryView::new(&corrupt, HeaderProfile::Extended),
+            Err(RkyvVersionedError::ChecksumMismatchError)
+        ));
+    }
+}
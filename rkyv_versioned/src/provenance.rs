@@ -0,0 +1,164 @@
+//! Recording which deployment wrote a record, in its header extensions.
+//!
+//! A [WriterIdentity] is configured once per process and applied to the
+//! [HeaderExtensions] of its [TaggedOptions](crate::TaggedOptions), so every record it writes
+//! carries e.g. the instance ID, host and application version.  When a record turns out to be
+//! corrupt or of an unexpected version, [get_writer_identity_from_tagged_bytes] reads them
+//! back, without needing the container type and without verifying the digest, to trace it to
+//! the deployment that produced it.
+
+use alloc::string::String;
+
+use crate::{get_extensions_from_tagged_bytes, HeaderExtensions, RkyvVersionedError};
+
+/// The extension key holding [WriterIdentity::writer_id].
+pub const EXTENSION_KEY_WRITER_ID: u16 = 0xff01;
+
+/// The extension key holding [WriterIdentity::hostname].
+pub const EXTENSION_KEY_HOSTNAME: u16 = 0xff02;
+
+/// The extension key holding [WriterIdentity::app_version].
+pub const EXTENSION_KEY_APP_VERSION: u16 = 0xff03;
+
+/// The identity of the process writing records, stored as UTF-8 header extensions.
+///
+/// Each field is optional and stored under its own key in the range reserved for this crate,
+/// so a record only pays for the fields that are set.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WriterIdentity {
+    /// An application defined ID of the writer, e.g. a service instance or pod name.
+    pub writer_id: Option<String>,
+    /// The host the writer runs on.
+    pub hostname: Option<String>,
+    /// The version of the application, e.g. `env!("CARGO_PKG_VERSION")` or a commit hash.
+    pub app_version: Option<String>,
+}
+
+impl WriterIdentity {
+    /// The fields paired with their extension keys.
+    fn fields(&self) -> [(u16, Option<&String>); 3] {
+        [
+            (EXTENSION_KEY_WRITER_ID, self.writer_id.as_ref()),
+            (EXTENSION_KEY_HOSTNAME, self.hostname.as_ref()),
+            (EXTENSION_KEY_APP_VERSION, self.app_version.as_ref()),
+        ]
+    }
+
+    /// Returns whether no field is set.
+    pub fn is_empty(&self) -> bool {
+        self.fields().iter().all(|(_, value)| value.is_none())
+    }
+
+    /// Stores the identity in `extensions`, replacing any previous identity.
+    ///
+    /// Fails with [RkyvVersionedError::InvalidExtensionError] if a field is longer than
+    /// `u16::MAX` bytes.
+    pub fn apply(&self, extensions: &mut HeaderExtensions) -> Result<(), RkyvVersionedError> {
+        for (key, value) in self.fields() {
+            match value {
+                Some(value) => extensions.insert(key, value.as_bytes())?,
+                None => {
+                    extensions.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the identity back from `extensions`.
+    ///
+    /// # Returns
+    ///
+    /// The identity, or `None` if the record carries none.  Values which aren't valid UTF-8
+    /// are converted lossily rather than hiding the rest of the identity.
+    pub fn from_extensions(extensions: &HeaderExtensions) -> Option<Self> {
+        let field = |key| {
+            extensions
+                .get(key)
+                .map(|value| String::from_utf8_lossy(value).into_owned())
+        };
+        let identity = WriterIdentity {
+            writer_id: field(EXTENSION_KEY_WRITER_ID),
+            hostname: field(EXTENSION_KEY_HOSTNAME),
+            app_version: field(EXTENSION_KEY_APP_VERSION),
+        };
+        match identity.is_empty() {
+            true => None,
+            false => Some(identity),
+        }
+    }
+}
+
+/// Reads the [WriterIdentity] of a tagged byte array without accessing the payload.
+///
+/// Like [get_extensions_from_tagged_bytes] this does not require knowing the container type
+/// and the payload digest is not verified, so the writer of a corrupt record can still be
+/// found.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the identity, `None` if none was written, or an error if the buffer
+/// is truncated or the extension area is malformed.
+pub fn get_writer_identity_from_tagged_bytes(
+    buf: &[u8],
+) -> Result<Option<WriterIdentity>, RkyvVersionedError> {
+    Ok(WriterIdentity::from_extensions(
+        &get_extensions_from_tagged_bytes(buf)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, to_tagged_bytes_with, TaggedOptions,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+    }
+
+    #[test]
+    fn test_writer_identity() {
+        let identity = WriterIdentity {
+            writer_id: Some("ingest-3".to_string()),
+            hostname: None,
+            app_version: Some("1.4.2".to_string()),
+        };
+        let mut options = TaggedOptions::default();
+        options.extensions.insert(1, b"tenant-42").unwrap();
+        identity.apply(&mut options.extensions).unwrap();
+
+        let mut bytes =
+            to_tagged_bytes_with(&Event::V1("hello".to_string()), &options).unwrap();
+        assert!(access_from_tagged_bytes::<Event>(&bytes).is_ok());
+        assert_eq!(
+            get_writer_identity_from_tagged_bytes(&bytes).unwrap(),
+            Some(identity)
+        );
+
+        // Still readable once the payload is corrupt
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(access_from_tagged_bytes::<Event>(&bytes).is_err());
+        assert!(get_writer_identity_from_tagged_bytes(&bytes)
+            .unwrap()
+            .is_some());
+
+        // Clearing the identity leaves other extensions alone
+        WriterIdentity::default()
+            .apply(&mut options.extensions)
+            .unwrap();
+        assert_eq!(options.extensions.len(), 1);
+
+        let plain = to_tagged_bytes(&Event::V1("hello".to_string())).unwrap();
+        assert_eq!(get_writer_identity_from_tagged_bytes(&plain).unwrap(), None);
+    }
+}
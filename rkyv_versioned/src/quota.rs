@@ -0,0 +1,322 @@
+//! Namespaces and per-namespace quotas for logs shared by several tenants.
+//!
+//! A record is tagged with the namespace of the tenant it belongs to with [set_namespace],
+//! stored in the [HeaderExtensions] under [EXTENSION_KEY_NAMESPACE], so it can be attributed
+//! without knowing the container type.  A [QuotaWriter] appends records to a [LogWriter],
+//! counting the bytes and records of each namespace and enforcing a [Quota] on them, so one
+//! tenant's payloads can't monopolize the log.  Records over quota are either rejected with
+//! [RkyvVersionedError::QuotaExceededError] or silently dropped, depending on the
+//! [QuotaPolicy].  Records without a namespace are counted under the empty namespace.
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    get_extensions_from_tagged_bytes, read_header, to_tagged_bytes_with, DurableWrite,
+    HeaderExtensions, LogWriter, RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// The extension key holding the namespace of a record.
+pub const EXTENSION_KEY_NAMESPACE: u16 = 0xff09;
+
+/// Sets the namespace stored in `extensions`, e.g. a tenant ID.
+///
+/// Fails with [RkyvVersionedError::InvalidExtensionError] if `namespace` is longer than
+/// `u16::MAX` bytes.
+pub fn set_namespace(
+    extensions: &mut HeaderExtensions,
+    namespace: &str,
+) -> Result<(), RkyvVersionedError> {
+    extensions.insert(EXTENSION_KEY_NAMESPACE, namespace.as_bytes())
+}
+
+/// Reads the namespace of a tagged byte array without accessing the payload.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the namespace, `None` if none was written, or an error if the buffer
+/// is truncated, the extension area is malformed or the namespace isn't UTF-8.
+pub fn get_namespace_from_tagged_bytes(
+    buf: &[u8],
+) -> Result<Option<String>, RkyvVersionedError> {
+    get_extensions_from_tagged_bytes(buf)?
+        .remove(EXTENSION_KEY_NAMESPACE)
+        .map(|namespace| {
+            String::from_utf8(namespace).map_err(|_| {
+                RkyvVersionedError::InvalidExtensionError(EXTENSION_KEY_NAMESPACE)
+            })
+        })
+        .transpose()
+}
+
+/// The limits on what a namespace may append to a [QuotaWriter].  `None` is unlimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    /// The most bytes of tagged byte arrays, excluding frame prefixes and padding.
+    pub max_bytes: Option<u64>,
+    /// The most records.
+    pub max_entries: Option<u64>,
+}
+
+/// What a [QuotaWriter] does with a record over its namespace's quota.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Fail the append with [RkyvVersionedError::QuotaExceededError].
+    #[default]
+    Reject,
+    /// Skip the record, counting it in [NamespaceUsage::dropped].
+    Drop,
+}
+
+/// What a namespace has appended to a [QuotaWriter].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceUsage {
+    /// The bytes of tagged byte arrays appended.
+    pub bytes: u64,
+    /// The records appended.
+    pub entries: u64,
+    /// The records dropped for being over quota.
+    pub dropped: u64,
+}
+
+/// Appends the records of several namespaces to a [LogWriter], enforcing a [Quota] on each,
+/// see the [module](self) documentation.
+#[derive(Debug)]
+pub struct QuotaWriter<W: DurableWrite = File> {
+    log: LogWriter<W>,
+    options: TaggedOptions,
+    policy: QuotaPolicy,
+    default_quota: Quota,
+    quotas: HashMap<String, Quota>,
+    usage: HashMap<String, NamespaceUsage>,
+}
+
+impl<W: DurableWrite> QuotaWriter<W> {
+    /// Wraps `log`, handling records over quota according to `policy`.  Namespaces are
+    /// unlimited until given a quota.
+    pub fn new(log: LogWriter<W>, policy: QuotaPolicy) -> Self {
+        QuotaWriter {
+            log,
+            options: TaggedOptions::default(),
+            policy,
+            default_quota: Quota::default(),
+            quotas: HashMap::new(),
+            usage: HashMap::new(),
+        }
+    }
+
+    /// Tags the records appended with [append](Self::append) with `options` instead of the
+    /// default [TaggedOptions], adding the namespace to its extensions.
+    pub fn with_options(mut self, options: TaggedOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Limits `namespace` to `quota`.
+    pub fn with_quota(mut self, namespace: impl Into<String>, quota: Quota) -> Self {
+        self.quotas.insert(namespace.into(), quota);
+        self
+    }
+
+    /// Limits every namespace without a quota of its own to `quota`.
+    pub fn with_default_quota(mut self, quota: Quota) -> Self {
+        self.default_quota = quota;
+        self
+    }
+
+    /// Returns the quota enforced on `namespace`.
+    pub fn quota(&self, namespace: &str) -> Quota {
+        self.quotas
+            .get(namespace)
+            .copied()
+            .unwrap_or(self.default_quota)
+    }
+
+    /// Returns what `namespace` has appended so far.
+    pub fn usage(&self, namespace: &str) -> NamespaceUsage {
+        self.usage.get(namespace).copied().unwrap_or_default()
+    }
+
+    /// Forgets the usage of every namespace, e.g. at the start of a new accounting period or
+    /// after the log has been compacted.
+    pub fn reset_usage(&mut self) {
+        self.usage.clear();
+    }
+
+    /// Serializes `item`, tagged with `namespace`, and appends it to the log if the namespace
+    /// is within its quota.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of bytes appended, `None` if the record was dropped
+    /// for being over quota, or an error if it was rejected, or serialization or the append
+    /// fails.
+    pub fn append<T>(
+        &mut self,
+        namespace: &str,
+        item: &T,
+    ) -> Result<Option<usize>, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let mut options = self.options.clone();
+        set_namespace(&mut options.extensions, namespace)?;
+        let record = to_tagged_bytes_with(item, &options)?;
+        self.append_record(&record)
+    }
+
+    /// Appends an already tagged byte array to the log if its namespace is within its quota.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of bytes appended, `None` if the record was dropped
+    /// for being over quota, or an error if it was rejected, `record` is not a complete tagged
+    /// byte array, or the append fails.
+    pub fn append_record(
+        &mut self,
+        record: &[u8],
+    ) -> Result<Option<usize>, RkyvVersionedError> {
+        let len = read_header(record)?.tagged_len()? as u64;
+        let namespace = get_namespace_from_tagged_bytes(record)?.unwrap_or_default();
+        let quota = self.quota(&namespace);
+        let usage = self.usage(&namespace);
+
+        let over = quota
+            .max_entries
+            .is_some_and(|max| usage.entries.saturating_add(1) > max)
+            || quota
+                .max_bytes
+                .is_some_and(|max| usage.bytes.saturating_add(len) > max);
+        if over {
+            return match self.policy {
+                QuotaPolicy::Reject => Err(RkyvVersionedError::QuotaExceededError(namespace)),
+                QuotaPolicy::Drop => {
+                    self.usage.entry(namespace).or_default().dropped += 1;
+                    Ok(None)
+                }
+            };
+        }
+
+        let written = self.log.append_record(record)?;
+        let usage = self.usage.entry(namespace).or_default();
+        usage.bytes += len;
+        usage.entries += 1;
+        Ok(Some(written))
+    }
+
+    /// Returns a reference to the underlying log.
+    pub fn get_ref(&self) -> &LogWriter<W> {
+        &self.log
+    }
+
+    /// Syncs the records appended so far and returns the underlying stream, see
+    /// [LogWriter::close].
+    pub fn close(self) -> Result<W, RkyvVersionedError> {
+        self.log.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{framed_records, SyncPolicy, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+    }
+
+    /// An in-memory stream which needs no syncing.
+    #[derive(Debug, Default)]
+    struct Memory(Vec<u8>);
+
+    impl std::io::Write for Memory {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl DurableWrite for Memory {
+        fn sync(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_quota_writer() {
+        let event = Event::V1("x".repeat(40));
+        let log = LogWriter::new(Memory::default(), SyncPolicy::OnDrop);
+        let mut writer = QuotaWriter::new(log, QuotaPolicy::Reject)
+            .with_quota(
+                "noisy",
+                Quota {
+                    max_entries: Some(2),
+                    ..Default::default()
+                },
+            )
+            .with_default_quota(Quota {
+                max_bytes: Some(1 << 20),
+                ..Default::default()
+            });
+
+        for _ in 0..2 {
+            assert!(writer.append("noisy", &event).unwrap().is_some());
+        }
+        assert!(matches!(
+            writer.append("noisy", &event),
+            Err(RkyvVersionedError::QuotaExceededError(namespace)) if namespace == "noisy"
+        ));
+        assert!(writer.append("quiet", &event).unwrap().is_some());
+
+        // Records without a namespace are counted under the empty one
+        let untagged = crate::to_tagged_bytes(&event).unwrap();
+        assert!(writer.append_record(&untagged).unwrap().is_some());
+        assert_eq!(writer.usage("").entries, 1);
+        assert_eq!(writer.usage("noisy").entries, 2);
+        let mut options = TaggedOptions::default();
+        set_namespace(&mut options.extensions, "noisy").unwrap();
+        let noisy = to_tagged_bytes_with(&event, &options).unwrap();
+        assert_eq!(writer.usage("noisy").bytes, 2 * noisy.len() as u64);
+
+        let stream = writer.close().unwrap().0;
+        let namespaces = framed_records(&stream)
+            .map(|record| get_namespace_from_tagged_bytes(record.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            namespaces,
+            [
+                Some("noisy".to_string()),
+                Some("noisy".to_string()),
+                Some("quiet".to_string()),
+                None
+            ]
+        );
+
+        // Dropped records are counted, and quotas start over once usage is reset
+        let log = LogWriter::new(Memory::default(), SyncPolicy::OnDrop);
+        let mut writer = QuotaWriter::new(log, QuotaPolicy::Drop).with_default_quota(Quota {
+            max_entries: Some(1),
+            ..Default::default()
+        });
+        assert!(writer.append("a", &event).unwrap().is_some());
+        assert!(writer.append("a", &event).unwrap().is_none());
+        assert_eq!(writer.usage("a").dropped, 1);
+        writer.reset_usage();
+        assert!(writer.append("a", &event).unwrap().is_some());
+    }
+}
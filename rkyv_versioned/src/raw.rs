@@ -0,0 +1,100 @@
+//! The raw building blocks of the tagged format, enabled with the `raw` feature.
+//!
+//! Most users should go through [to_tagged_bytes](crate::to_tagged_bytes) and
+//! [access_from_tagged_bytes](crate::access_from_tagged_bytes).  This module exposes the
+//! header itself so that advanced users, and the derives of other crates, can build and parse
+//! compatible envelopes without copying private code.
+//!
+//! # Invariants
+//!
+//! A tagged byte array is laid out as `[header][body][digest][signature]` and any envelope
+//! built by hand must uphold the following for the rest of the crate to accept it:
+//!
+//! - The header is [TAGGED_HEADER_SIZE] bytes long and always encoded with
+//!   [TaggedVersionedHeader::encode], which is little-endian regardless of the platform and
+//!   records the [TAGGED_FORMAT_VERSION].
+//! - `type_id` is the [ARCHIVE_TYPE_ID](crate::VersionedContainer::ARCHIVE_TYPE_ID) of the
+//!   container and `version_id` a version for which
+//!   [is_valid_version_id](crate::VersionedContainer::is_valid_version_id) returns `true`.
+//! - `payload_len` is the length of the whole body: the header words block (if
+//!   [HEADER_FLAG_USER_WORD], [HEADER_FLAG_TIMESTAMP] or [HEADER_FLAG_SEQUENCE] is set), then
+//!   the extension area (if [HEADER_FLAG_EXTENSIONS] is set), then the payload.
+//! - The payload is an `rkyv` archive of the container, written with the same `rkyv` layout
+//!   features as the reader, and starts at an offset from the header which is a multiple of
+//!   16 so that it stays aligned.
+//! - The body is followed by its digest, computed with the [DigestAlgorithm] recorded in the
+//!   header (integer digests are little-endian), then by the signature if
+//!   [HEADER_FLAG_SIGNED] is set.
+//! - No flags outside of [KNOWN_HEADER_FLAGS] are set, [read_header] rejects them.
+//!
+//! [DigestAlgorithm]: crate::DigestAlgorithm
+
+pub use crate::extensions::HEADER_FLAG_EXTENSIONS;
+pub use crate::header::{
+    read_header, write_header, TaggedVersionedHeader, HEADER_FLAG_SIGNED, KNOWN_HEADER_FLAGS,
+    TAGGED_FORMAT_VERSION, TAGGED_HEADER_SIZE,
+};
+pub use crate::header_words::{
+    HEADER_FLAG_SEQUENCE, HEADER_FLAG_TIMESTAMP, HEADER_FLAG_USER_WORD,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, DigestAlgorithm, RkyvVersionedError,
+        VersionedArchiveContainer, VersionedContainer,
+    };
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Message {
+        V1(String),
+    }
+
+    #[test]
+    fn test_hand_built_envelope() {
+        let message = Message::V1("hello".to_string());
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&message).unwrap();
+
+        let header = TaggedVersionedHeader {
+            type_id: Message::ARCHIVE_TYPE_ID,
+            version_id: message.get_entry_version_id(),
+            payload_len: payload.len() as u32,
+            digest: DigestAlgorithm::Crc32.id(),
+            compression: 0,
+            encryption: 0,
+            flags: 0,
+        };
+        let mut tagged = AlignedVec::<16>::new();
+        tagged.extend_from_slice(&[0u8; TAGGED_HEADER_SIZE]);
+        write_header(&mut tagged, &header).unwrap();
+        tagged.extend_from_slice(&payload);
+        tagged.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+
+        assert_eq!(tagged.len(), header.tagged_len().unwrap());
+        assert_eq!(
+            tagged.as_slice(),
+            to_tagged_bytes(&message).unwrap().as_slice()
+        );
+        assert_eq!(read_header(&tagged).unwrap(), header);
+        match access_from_tagged_bytes::<Message>(&tagged).unwrap() {
+            ArchivedMessage::V1(text) => assert_eq!(text, "hello"),
+        }
+
+        // Unknown flags are rejected
+        write_header(
+            &mut tagged,
+            &TaggedVersionedHeader {
+                flags: !KNOWN_HEADER_FLAGS,
+                ..header
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            read_header(&tagged),
+            Err(RkyvVersionedError::UnsupportedHeaderFlagsError(_))
+        ));
+    }
+}
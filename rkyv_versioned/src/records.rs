@@ -0,0 +1,311 @@
+//! Scanning back-to-back tagged byte arrays without a length prefix.
+//!
+//! Unlike the [framing](crate::append_framed) format, a log written with [append_tagged] holds
+//! the records themselves, each starting at the next multiple of [RECORD_ALIGNMENT] bytes:
+//!
+//! `[record][zero padding][record][zero padding]...`
+//!
+//! Every record's header gives its length, so [TaggedRecordIter] finds the next one without
+//! offset math in the caller, and the padding keeps each payload aligned so that it is
+//! accessed in place when the log is held in an [AlignedVec].
+
+use core::marker::PhantomData;
+
+use rkyv::util::AlignedVec;
+
+use crate::{access_from_tagged_bytes, read_header, RkyvVersionedError, VersionedContainer};
+
+/// The alignment of every record in a log written with [append_tagged].
+pub const RECORD_ALIGNMENT: usize = 16;
+
+/// Appends `tagged` to `out`, followed by the zero padding aligning the next record.
+///
+/// `out` should either be empty or hold previously appended records for the record to stay
+/// aligned.
+///
+/// # Returns
+///
+/// A `Result` which is an error if `tagged` is not a complete tagged byte array.
+pub fn append_tagged(out: &mut AlignedVec, tagged: &[u8]) -> Result<(), RkyvVersionedError> {
+    let tagged_len = read_header(tagged)?.tagged_len()?;
+    let record = tagged
+        .get(..tagged_len)
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    out.extend_from_slice(record);
+    out.resize(out.len().next_multiple_of(RECORD_ALIGNMENT), 0);
+    Ok(())
+}
+
+/// Where a record yielded by [TaggedRecordIter] was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggedRecordHeader {
+    /// The offset of the record in the buffer.
+    pub offset: usize,
+    /// The version ID of the record.
+    pub version_id: u32,
+    /// The length of the record, excluding the padding after it.
+    pub len: usize,
+}
+
+/// An iterator over the records of `T` in a buffer written with [append_tagged], accessing
+/// each one in place.
+///
+/// Records failing validation, e.g. because of a digest mismatch, an unsupported version or
+/// another type, are yielded as errors, located with [RkyvVersionedError::at_record], and
+/// iteration carries on with the next record.  A record whose header can't be read, claims to
+/// extend past the end of the buffer, or is followed by non-zero padding means the rest of the
+/// buffer can't be trusted: it is reported as [RkyvVersionedError::InvalidFrameError] with its
+/// offset, after which iteration stops.
+#[derive(Debug, Clone)]
+pub struct TaggedRecordIter<'a, T> {
+    buf: &'a [u8],
+    offset: usize,
+    /// The index of the next record.
+    index: usize,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: VersionedContainer> TaggedRecordIter<'a, T> {
+    /// Creates an iterator over the records in `buf`.
+    pub fn new(buf: &'a [u8]) -> Self {
+        TaggedRecordIter {
+            buf,
+            offset: 0,
+            index: 0,
+            _container: PhantomData,
+        }
+    }
+
+    /// The offset of the next record in the buffer.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Finds the record at the current offset and the offset of the one after it.
+    ///
+    /// Only the framing is checked here, so that a record of an unsupported version or another
+    /// type is reported by [access_from_tagged_bytes] without ending the iteration.
+    fn next_record(
+        &self,
+    ) -> Result<(TaggedRecordHeader, &'a [u8], usize), RkyvVersionedError> {
+        let invalid = |_| RkyvVersionedError::InvalidFrameError(self.offset);
+        let rest = self
+            .buf
+            .get(self.offset..)
+            .ok_or(RkyvVersionedError::InvalidFrameError(self.offset))?;
+        let header = read_header(rest).map_err(invalid)?;
+        let len = header.tagged_len().map_err(invalid)?;
+        let (record, after) = rest
+            .split_at_checked(len)
+            .ok_or(RkyvVersionedError::InvalidFrameError(self.offset))?;
+
+        let next = (self.offset + len)
+            .next_multiple_of(RECORD_ALIGNMENT)
+            .min(self.buf.len());
+        if after.iter().take(next - self.offset - len).any(|b| *b != 0) {
+            return Err(RkyvVersionedError::InvalidFrameError(self.offset));
+        }
+        let header = TaggedRecordHeader {
+            offset: self.offset,
+            version_id: header.version_id,
+            len,
+        };
+        Ok((header, record, next))
+    }
+}
+
+impl<'a, T: VersionedContainer + 'a> Iterator for TaggedRecordIter<'a, T>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    type Item = Result<(TaggedRecordHeader, &'a T::Archived), RkyvVersionedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+        match self.next_record() {
+            Ok((header, record, next)) => {
+                let index = self.index;
+                self.offset = next;
+                self.index += 1;
+                Some(
+                    access_from_tagged_bytes::<T>(record)
+                        .map(|archived| (header, archived))
+                        .map_err(|e| e.at_record(index, header.offset)),
+                )
+            }
+            Err(e) => {
+                self.offset = self.buf.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum LogEntry {
+        V1(String),
+        V2(u64),
+    }
+
+    /// Appends four records alternating between the versions, returning the log and the
+    /// offset of each record.
+    fn sample_log() -> (AlignedVec, Vec<usize>) {
+        let mut log = AlignedVec::<16>::new();
+        let mut offsets = Vec::new();
+        for i in 0..4u64 {
+            offsets.push(log.len());
+            let entry = match i % 2 {
+                0 => LogEntry::V1("x".repeat(i as usize + 1)),
+                _ => LogEntry::V2(i),
+            };
+            append_tagged(&mut log, &to_tagged_bytes(&entry).unwrap()).unwrap();
+            assert_eq!(log.len() % RECORD_ALIGNMENT, 0);
+        }
+        (log, offsets)
+    }
+
+    /// Rewrites the header of the record at `offset` in `log`.
+    fn munge_header(
+        log: &mut AlignedVec,
+        offset: usize,
+        munge: impl FnOnce(&mut crate::TaggedVersionedHeader),
+    ) {
+        let mut header = read_header(&log[offset..]).unwrap();
+        munge(&mut header);
+        crate::write_header(&mut log[offset..], &header).unwrap();
+    }
+
+    #[test]
+    fn test_tagged_record_iter() {
+        let (log, offsets) = sample_log();
+        let records: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records.len(), 4);
+        for (i, (header, archived)) in records.iter().enumerate() {
+            assert_eq!(header.offset, offsets[i]);
+            assert_eq!(header.version_id, (i % 2) as u32);
+            match archived {
+                ArchivedLogEntry::V1(text) => assert_eq!(text.len(), i + 1),
+                ArchivedLogEntry::V2(value) => assert_eq!(*value, i as u64),
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        assert!(TaggedRecordIter::<LogEntry>::new(&[]).next().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_payload_is_skipped() {
+        let (mut log, offsets) = sample_log();
+        let len = read_header(&log[offsets[1]..])
+            .unwrap()
+            .tagged_len()
+            .unwrap();
+        log[offsets[1] + len - 1] ^= 1;
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok() && results[2].is_ok() && results[3].is_ok());
+        let Err(e) = &results[1] else {
+            panic!("Expected an error");
+        };
+        assert_eq!(e.record_location(), Some((1, offsets[1])));
+        assert!(matches!(
+            e.root(),
+            RkyvVersionedError::ChecksumMismatchError
+        ));
+        assert!(matches!(
+            core::error::Error::source(e)
+                .and_then(|source| source.downcast_ref::<RkyvVersionedError>()),
+            Some(RkyvVersionedError::ChecksumMismatchError)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version_is_skipped() {
+        let (mut log, offsets) = sample_log();
+        munge_header(&mut log, offsets[1], |header| header.version_id = 7);
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok() && results[2].is_ok() && results[3].is_ok());
+        let Err(e) = &results[1] else {
+            panic!("Expected an error");
+        };
+        assert_eq!(e.record_location(), Some((1, offsets[1])));
+        assert!(matches!(
+            e.root(),
+            RkyvVersionedError::UnsupportedVersionError(7)
+        ));
+    }
+
+    #[test]
+    fn test_other_type_is_skipped() {
+        let (mut log, offsets) = sample_log();
+        munge_header(&mut log, offsets[2], |header| header.type_id ^= 1);
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok() && results[1].is_ok() && results[3].is_ok());
+        assert!(matches!(
+            results[2].as_ref().map_err(RkyvVersionedError::root),
+            Err(RkyvVersionedError::UnexpectedTypeError(..))
+        ));
+    }
+
+    #[test]
+    fn test_unreadable_header_ends_iteration() {
+        let (mut log, offsets) = sample_log();
+        // The format version
+        log[offsets[1] + crate::TAGGED_HEADER_SIZE - 2] = 0xff;
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(RkyvVersionedError::InvalidFrameError(offset)) if offset == offsets[1]
+        ));
+    }
+
+    #[test]
+    fn test_overlong_record_ends_iteration() {
+        let (mut log, offsets) = sample_log();
+        munge_header(&mut log, offsets[2], |header| header.payload_len = u32::MAX);
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(
+            results[2],
+            Err(RkyvVersionedError::InvalidFrameError(offset)) if offset == offsets[2]
+        ));
+    }
+
+    #[test]
+    fn test_non_zero_padding_ends_iteration() {
+        let (mut log, offsets) = sample_log();
+        log[offsets[2] - 1] = 1;
+
+        let results: Vec<_> = TaggedRecordIter::<LogEntry>::new(&log).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(RkyvVersionedError::InvalidFrameError(offset)) if offset == offsets[1]
+        ));
+    }
+}
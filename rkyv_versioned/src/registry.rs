@@ -0,0 +1,161 @@
+//! Dispatching records of several container types by their type ID.
+//!
+//! A stream can hold records of different versioned containers, e.g. the commands and events
+//! of a service.  A [TypeRegistry] maps the
+//! [ARCHIVE_TYPE_ID](crate::VersionedContainer::ARCHIVE_TYPE_ID) of each container to a
+//! handler, and [TypeRegistry::dispatch] reads the type ID from the header of a record and
+//! accesses it as that container before calling its handler.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rkyv::util::AlignedVec;
+
+use crate::{
+    access_from_tagged_bytes_with_scratch, get_type_and_version_from_tagged_bytes,
+    RkyvVersionedError, VersionedContainer,
+};
+
+type Handler<'h, R> =
+    Box<dyn FnMut(&[u8], &mut AlignedVec) -> Result<R, RkyvVersionedError> + 'h>;
+
+/// Handlers of records of several container types, each returning an `R`.
+///
+/// Compressed records are decompressed into a scratch buffer owned by the registry, which is
+/// reused across calls.
+pub struct TypeRegistry<'h, R = ()> {
+    handlers: HashMap<u32, Handler<'h, R>>,
+    scratch: AlignedVec,
+}
+
+impl<'h, R> TypeRegistry<'h, R> {
+    /// Creates a registry without handlers.
+    pub fn new() -> Self {
+        TypeRegistry {
+            handlers: HashMap::new(),
+            scratch: AlignedVec::new(),
+        }
+    }
+
+    /// Registers `handler` for records of `T`, replacing any handler previously registered
+    /// for it.
+    pub fn register<T, F>(&mut self, mut handler: F) -> &mut Self
+    where
+        T: VersionedContainer + 'h,
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+        F: FnMut(&T::Archived) -> R + 'h,
+    {
+        self.handlers.insert(
+            T::ARCHIVE_TYPE_ID,
+            Box::new(move |buf, scratch| {
+                access_from_tagged_bytes_with_scratch::<T>(buf, scratch).map(&mut handler)
+            }),
+        );
+        self
+    }
+
+    /// Returns whether a handler is registered for `type_id`.
+    pub fn contains(&self, type_id: u32) -> bool {
+        self.handlers.contains_key(&type_id)
+    }
+
+    /// Accesses `buf` as the container its header names and calls the handler registered for
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the tagged serialized data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the result of the handler, an
+    /// [RkyvVersionedError::UnknownTypeIdError] if no handler is registered for the type ID,
+    /// or an error if accessing the record fails.
+    pub fn dispatch(&mut self, buf: &[u8]) -> Result<R, RkyvVersionedError> {
+        let (type_id, _) = get_type_and_version_from_tagged_bytes(buf)?;
+        let handler = self
+            .handlers
+            .get_mut(&type_id)
+            .ok_or(RkyvVersionedError::UnknownTypeIdError(type_id))?;
+        handler(buf, &mut self.scratch)
+    }
+}
+
+impl<R> Default for TypeRegistry<'_, R> {
+    fn default() -> Self {
+        TypeRegistry::new()
+    }
+}
+
+impl<R> fmt::Debug for TypeRegistry<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypeRegistry")
+            .field("type_ids", &self.handlers.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{append_framed, framed_records, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Command {
+        V1(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+        V2((u64, u64)),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Unregistered {
+        V1(u8),
+    }
+
+    #[test]
+    fn test_type_registry() {
+        let mut stream = AlignedVec::<16>::new();
+        append_framed(
+            &mut stream,
+            &to_tagged_bytes(&Command::V1("start".into())).unwrap(),
+        )
+        .unwrap();
+        append_framed(&mut stream, &to_tagged_bytes(&Event::V1(3)).unwrap()).unwrap();
+        append_framed(&mut stream, &to_tagged_bytes(&Event::V2((4, 5))).unwrap()).unwrap();
+        append_framed(&mut stream, &to_tagged_bytes(&Unregistered::V1(1)).unwrap()).unwrap();
+
+        let mut commands = Vec::new();
+        let mut registry = TypeRegistry::<u64>::new();
+        registry
+            .register::<Command, _>(|command| match command {
+                ArchivedCommand::V1(name) => {
+                    commands.push(name.to_string());
+                    0
+                }
+            })
+            .register::<Event, _>(|event| match event {
+                ArchivedEvent::V1(value) => value.to_native(),
+                ArchivedEvent::V2(pair) => pair.0.to_native() + pair.1.to_native(),
+            });
+        assert!(registry.contains(Event::ARCHIVE_TYPE_ID));
+
+        let results: Vec<_> = framed_records(&stream)
+            .map(|record| registry.dispatch(record.unwrap()))
+            .collect();
+        assert!(matches!(results[..3], [Ok(0), Ok(3), Ok(9)]));
+        assert!(matches!(
+            results[3],
+            Err(RkyvVersionedError::UnknownTypeIdError(id)) if id == Unregistered::ARCHIVE_TYPE_ID
+        ));
+        drop(registry);
+        assert_eq!(commands, vec!["start".to_string()]);
+    }
+}
@@ -0,0 +1,245 @@
+//! Following archive files which are replaced while they are read, enabled with the `memmap2`
+//! feature.
+//!
+//! Services reading an archive usually outlive it: log rotation and compaction write a new
+//! file and rename it over the old one.  A [ReloadingReader] wraps an [MmapArchive] and, before
+//! every access, checks whether the file at its path has been replaced, remapping it if so.
+//! Entries pinned with [pin](ReloadingReader::pin) before a reload keep the previous file
+//! mapped, so they stay valid however many times the file is replaced.
+//!
+//! A file is considered replaced when its identity (device and inode on Unix), length or
+//! modification time changes, so files which are only ever appended to, such as logs written
+//! with [append_framed](crate::append_framed), are picked up as they grow.  Replacing a file by
+//! renaming a new one over it is safe, rewriting it in place is not, see [MmapArchive::open].
+
+use core::fmt;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::io::io_error;
+use crate::{MmapArchive, PinnedEntry, RkyvVersionedError, VersionedContainer};
+
+/// What identifies a version of a file, to tell when it has been replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileIdentity {
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileIdentity {
+    fn of(path: &Path) -> Result<Self, RkyvVersionedError> {
+        let metadata = fs::metadata(path).map_err(io_error)?;
+        Ok(FileIdentity {
+            #[cfg(unix)]
+            dev: metadata.dev(),
+            #[cfg(unix)]
+            ino: metadata.ino(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// An [MmapArchive] which is remapped when its file is replaced, see the [module](self)
+/// documentation.
+pub struct ReloadingReader<T> {
+    path: PathBuf,
+    /// Whether the file holds a stream of frames rather than a single record.
+    framed: bool,
+    archive: MmapArchive<T>,
+    identity: FileIdentity,
+    reloads: u64,
+}
+
+impl<T> fmt::Debug for ReloadingReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingReader")
+            .field("path", &self.path)
+            .field("framed", &self.framed)
+            .field("reloads", &self.reloads)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: VersionedContainer> ReloadingReader<T> {
+    /// Maps a file holding a single tagged byte array like [MmapArchive::open].
+    ///
+    /// # Safety
+    ///
+    /// As for [MmapArchive::open], no version of the file may be modified or truncated while
+    /// it is mapped.  Replacing it by renaming another file over it is fine.
+    pub unsafe fn open(path: impl Into<PathBuf>) -> Result<Self, RkyvVersionedError> {
+        Self::new(path.into(), false)
+    }
+
+    /// Maps a file holding a stream of frames like [MmapArchive::open_framed].
+    ///
+    /// # Safety
+    ///
+    /// As for [open](Self::open).
+    pub unsafe fn open_framed(path: impl Into<PathBuf>) -> Result<Self, RkyvVersionedError> {
+        Self::new(path.into(), true)
+    }
+
+    unsafe fn new(path: PathBuf, framed: bool) -> Result<Self, RkyvVersionedError> {
+        let identity = FileIdentity::of(&path)?;
+        let archive = map::<T>(&path, framed)?;
+        Ok(ReloadingReader {
+            path,
+            framed,
+            archive,
+            identity,
+            reloads: 0,
+        })
+    }
+
+    /// Remaps the file if it has been replaced since it was last mapped.
+    ///
+    /// While the path doesn't exist, e.g. between a rotation removing the old file and
+    /// renaming the new one, the current mapping is kept.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the file was remapped, or an error if the replacement
+    /// can't be mapped or its headers are invalid, in which case the current mapping is kept
+    /// and the next call tries again.
+    pub fn reload_if_changed(&mut self) -> Result<bool, RkyvVersionedError> {
+        let identity = match FileIdentity::of(&self.path) {
+            Ok(identity) => identity,
+            Err(_) if !self.path.exists() => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if identity == self.identity {
+            return Ok(false);
+        }
+        // SAFETY: The caller of the constructor promised that no version of the file is
+        // modified while mapped
+        self.archive = unsafe { map::<T>(&self.path, self.framed) }?;
+        self.identity = identity;
+        self.reloads += 1;
+        Ok(true)
+    }
+
+    /// Returns the archive as currently mapped, without checking for a replacement.
+    pub fn archive(&self) -> &MmapArchive<T> {
+        &self.archive
+    }
+
+    /// Checks for a replacement with [reload_if_changed](Self::reload_if_changed), then
+    /// accesses the record at `index` like [MmapArchive::get].
+    pub fn get(&mut self, index: usize) -> Result<&T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        self.reload_if_changed()?;
+        self.archive.get(index)
+    }
+
+    /// Checks for a replacement with [reload_if_changed](Self::reload_if_changed), then
+    /// pins the record at `index` like [MmapArchive::pin].  The entry stays valid after later
+    /// reloads.
+    pub fn pin(&mut self, index: usize) -> Result<PinnedEntry<T>, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        self.reload_if_changed()?;
+        self.archive.pin(index)
+    }
+
+    /// The path of the file followed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of times the file has been remapped.
+    pub fn reloads(&self) -> u64 {
+        self.reloads
+    }
+}
+
+/// Maps `path` as a single record or a stream of frames.
+unsafe fn map<T: VersionedContainer>(
+    path: &Path,
+    framed: bool,
+) -> Result<MmapArchive<T>, RkyvVersionedError> {
+    match framed {
+        true => MmapArchive::open_framed(path),
+        false => MmapArchive::open(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{append_framed, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+    }
+
+    fn stream(values: core::ops::Range<u64>) -> AlignedVec<16> {
+        let mut stream = AlignedVec::<16>::new();
+        for value in values {
+            append_framed(&mut stream, &to_tagged_bytes(&Event::V1(value)).unwrap()).unwrap();
+        }
+        stream
+    }
+
+    /// Replaces `path` like a rotation, by renaming a new file over it
+    fn rotate(path: &Path, contents: &[u8]) {
+        let staged = path.with_extension("staged");
+        fs::write(&staged, contents).unwrap();
+        fs::rename(&staged, path).unwrap();
+    }
+
+    #[test]
+    fn test_reloading_reader() {
+        let path = std::env::temp_dir()
+            .join(format!("rkyv_versioned_reload_{}.log", std::process::id()));
+        fs::write(&path, stream(0..3)).unwrap();
+        let mut reader = unsafe { ReloadingReader::<Event>::open_framed(&path) }.unwrap();
+        assert_eq!(reader.archive().len(), 3);
+        let pinned = reader.pin(2).unwrap();
+        assert!(!reader.reload_if_changed().unwrap());
+
+        rotate(&path, &stream(10..15));
+        match reader.get(4).unwrap() {
+            ArchivedEvent::V1(value) => assert_eq!(*value, 14),
+        }
+        assert_eq!(reader.reloads(), 1);
+        assert_eq!(reader.archive().len(), 5);
+
+        // The entry pinned before the rotation still reads the old file
+        match pinned.get() {
+            ArchivedEvent::V1(value) => assert_eq!(*value, 2),
+        }
+
+        // A missing file keeps the current mapping, a corrupt replacement is reported
+        fs::remove_file(&path).unwrap();
+        assert!(!reader.reload_if_changed().unwrap());
+        assert_eq!(reader.archive().len(), 5);
+        let mut truncated = stream(0..2);
+        truncated.resize(truncated.len() - 20, 0);
+        rotate(&path, &truncated);
+        assert!(reader.pin(0).is_err());
+        assert_eq!(reader.archive().len(), 5);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Rewriting a corrupted stream of frames without its corrupt ranges.
+//!
+//! [repair_stream] runs [resync_any_framed_records] over a stream and copies the intact
+//! records into a new stream, recording what was dropped in a [RepairReport].
+//! [repair_file] does the same for a file, writing the cleaned copy next to it so the
+//! original is kept for forensics, e.g. with the `repair` subcommand of the `rkyv_versioned`
+//! binary.  Since frames are located without knowing their container types, records of types
+//! the repairing process doesn't link are kept too.
+
+use core::ops::Range;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use rkyv::util::AlignedVec;
+
+use crate::io::io_error;
+use crate::{append_framed, resync_any_framed_records, ResyncItem, RkyvVersionedError};
+
+/// What [repair_stream] kept and dropped.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The number of intact records kept.
+    pub records: usize,
+    /// The ranges of the original stream which held no intact frame and were dropped.
+    pub dropped: Vec<Range<usize>>,
+    /// The length of the original stream.
+    pub len: usize,
+    /// The length of the repaired stream.
+    pub repaired_len: usize,
+}
+
+impl RepairReport {
+    /// The number of bytes of the original stream dropped.
+    pub fn dropped_bytes(&self) -> usize {
+        self.dropped.iter().map(|range| range.len()).sum()
+    }
+
+    /// Returns whether nothing was dropped, i.e. the stream needed no repair.
+    pub fn is_intact(&self) -> bool {
+        self.dropped.is_empty()
+    }
+}
+
+/// Copies the intact records of a stream of frames of any types into a new stream.
+///
+/// # Arguments
+///
+/// * `buf` - The stream, e.g. the contents of a log file.
+///
+/// # Returns
+///
+/// The repaired stream and a report of what was dropped.
+pub fn repair_stream(buf: &[u8]) -> (AlignedVec, RepairReport) {
+    let mut repaired = AlignedVec::with_capacity(buf.len());
+    let mut report = RepairReport {
+        len: buf.len(),
+        ..Default::default()
+    };
+    for item in resync_any_framed_records(buf) {
+        match item {
+            ResyncItem::Record { record, .. } => {
+                // The record was checked to be complete, so framing it can't fail
+                if append_framed(&mut repaired, record).is_ok() {
+                    report.records += 1;
+                }
+            }
+            ResyncItem::Skipped(range) => report.dropped.push(range),
+        }
+    }
+    report.repaired_len = repaired.len();
+    (repaired, report)
+}
+
+/// As [repair_stream], reading the stream from the file at `path` and writing the repaired
+/// stream to a new file at `repaired_path`, which is synced before returning.
+///
+/// # Returns
+///
+/// A `Result` containing the report, or an error if `path` can't be read, or `repaired_path`
+/// already exists or can't be written.
+pub fn repair_file(
+    path: impl AsRef<Path>,
+    repaired_path: impl AsRef<Path>,
+) -> Result<RepairReport, RkyvVersionedError> {
+    let contents = std::fs::read(path).map_err(io_error)?;
+    let (repaired, report) = repair_stream(&contents);
+    File::create_new(repaired_path)
+        .and_then(|mut file| {
+            file.write_all(&repaired)?;
+            file.sync_all()
+        })
+        .map_err(io_error)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        framed_records, to_tagged_bytes, VersionedArchiveContainer, FRAME_PREFIX_SIZE,
+        TAGGED_HEADER_SIZE,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Sample {
+        V1(u64),
+        V2(String),
+    }
+
+    #[test]
+    fn test_repair_file() {
+        let mut stream = AlignedVec::<16>::new();
+        let mut offsets = Vec::new();
+        for i in 0..4 {
+            offsets.push(stream.len());
+            let sample = match i % 2 {
+                0 => Sample::V1(i),
+                _ => Sample::V2(format!("sample {i}")),
+            };
+            append_framed(&mut stream, &to_tagged_bytes(&sample).unwrap()).unwrap();
+        }
+        stream[offsets[1] + FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE] ^= 1;
+
+        let dir =
+            std::env::temp_dir().join(format!("rkyv_versioned_repair_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (path, repaired_path) = (dir.join("events.log"), dir.join("events.log.repaired"));
+        std::fs::write(&path, &stream).unwrap();
+
+        let report = repair_file(&path, &repaired_path).unwrap();
+        assert_eq!(report.records, 3);
+        assert_eq!(report.dropped, vec![offsets[1]..offsets[2]]);
+        assert_eq!(report.dropped_bytes(), offsets[2] - offsets[1]);
+        assert!(!report.is_intact());
+        let repaired = std::fs::read(&repaired_path).unwrap();
+        assert_eq!(repaired.len(), report.repaired_len);
+        assert_eq!(framed_records(&repaired).filter(Result::is_ok).count(), 3);
+
+        // An existing file isn't overwritten
+        assert!(repair_file(&path, &repaired_path).is_err());
+        let (_, report) = repair_stream(&repaired);
+        assert!(report.is_intact());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+//! Diagnosing streams of framed records.
+//!
+//! [validate_stream] walks a stream written by e.g. [append_framed](crate::append_framed) or
+//! a [LogWriter](crate::LogWriter) and summarizes its health in a [StreamReport], for ops
+//! tooling checking a log before replaying it or after an incident.  Unlike
+//! [framed_records](crate::framed_records), it keeps going past records which fail their
+//! digest or hold another type, only stopping where the framing itself is lost.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::io::io_error;
+use crate::{
+    read_header, verify_payload, RkyvVersionedError, VersionedContainer, FRAME_PREFIX_SIZE,
+};
+
+/// A summary of a stream of framed records, see [validate_stream].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StreamReport {
+    /// The number of records with an intact frame and header.
+    pub records: usize,
+    /// The number of records of each supported version of the expected type, by version ID.
+    pub versions: BTreeMap<u32, usize>,
+    /// The number of records of the expected type with a version ID it doesn't support, by
+    /// version ID.
+    pub unsupported_versions: BTreeMap<u32, usize>,
+    /// The number of records of other types, by type ID.
+    pub unknown_types: BTreeMap<u32, usize>,
+    /// The offsets of the frames holding records whose payload doesn't match its digest, or
+    /// whose digest algorithm is unsupported.
+    pub checksum_failures: Vec<usize>,
+    /// The offset of a frame cut short by the end of the stream, usually a torn final write.
+    pub truncated_at: Option<usize>,
+    /// The offset of a frame whose prefix or header is corrupt, after which the rest of the
+    /// stream couldn't be walked.
+    pub corrupt_at: Option<usize>,
+    /// The number of bytes walked, i.e. the length of the stream up to any truncated or
+    /// corrupt frame.
+    pub valid_len: usize,
+}
+
+impl StreamReport {
+    /// Returns whether every record is an intact, supported version of the expected type and
+    /// the stream ends on a frame boundary.
+    pub fn is_clean(&self) -> bool {
+        self.unsupported_versions.is_empty()
+            && self.unknown_types.is_empty()
+            && self.checksum_failures.is_empty()
+            && self.truncated_at.is_none()
+            && self.corrupt_at.is_none()
+    }
+}
+
+/// Walks a stream of framed records expected to hold versions of `T` and reports what it
+/// holds.
+///
+/// Each record's header is checked and its payload verified against its digest, but the
+/// payload isn't accessed, so the walk is cheap and needs nothing but the type and version
+/// IDs of `T`.
+///
+/// # Arguments
+///
+/// * `buf` - The stream, e.g. the contents of a log file.
+///
+/// # Returns
+///
+/// The report.  Problems with the stream are recorded in it rather than returned as errors.
+pub fn validate_stream<T: VersionedContainer>(buf: &[u8]) -> StreamReport {
+    let mut report = StreamReport::default();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let frame = buf.get(offset..).unwrap_or_default();
+        let Some((prefix, rest)) = frame.split_first_chunk::<FRAME_PREFIX_SIZE>() else {
+            report.truncated_at = Some(offset);
+            break;
+        };
+        let Some(record_len) = parse_frame_prefix(prefix) else {
+            report.corrupt_at = Some(offset);
+            break;
+        };
+        let Ok(header) = read_header(rest) else {
+            report.truncated_at = Some(offset);
+            break;
+        };
+        if header.tagged_len().ok() != Some(record_len) {
+            report.corrupt_at = Some(offset);
+            break;
+        }
+        let Some(record) = rest.get(..record_len) else {
+            report.truncated_at = Some(offset);
+            break;
+        };
+
+        report.records += 1;
+        if verify_payload(record, &header).is_err() {
+            report.checksum_failures.push(offset);
+        }
+        let counts = if header.type_id != T::ARCHIVE_TYPE_ID {
+            report.unknown_types.entry(header.type_id)
+        } else if T::is_valid_version_id(header.version_id) {
+            report.versions.entry(header.version_id)
+        } else {
+            report.unsupported_versions.entry(header.version_id)
+        };
+        *counts.or_default() += 1;
+
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        offset += frame_len.min(frame.len());
+    }
+    report.valid_len = offset;
+    report
+}
+
+/// As [validate_stream], reading the stream from the file at `path`.
+///
+/// # Returns
+///
+/// A `Result` containing the report, or an error if the file can't be read.
+pub fn validate_stream_file<T: VersionedContainer>(
+    path: impl AsRef<Path>,
+) -> Result<StreamReport, RkyvVersionedError> {
+    let contents = std::fs::read(path).map_err(io_error)?;
+    Ok(validate_stream::<T>(&contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{append_framed, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+        V2(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u64),
+    }
+
+    #[test]
+    fn test_validate_stream() {
+        let mut stream = AlignedVec::<16>::new();
+        for i in 0..3 {
+            append_framed(&mut stream, &to_tagged_bytes(&Event::V1(i)).unwrap()).unwrap();
+        }
+        let corrupt_offset = stream.len();
+        append_framed(
+            &mut stream,
+            &to_tagged_bytes(&Event::V2("x".repeat(40))).unwrap(),
+        )
+        .unwrap();
+
+        let report = validate_stream::<Event>(&stream);
+        assert!(report.is_clean());
+        let (v1, v2) = (
+            Event::V1(0).get_entry_version_id(),
+            Event::V2(String::new()).get_entry_version_id(),
+        );
+        assert_eq!(report.versions, BTreeMap::from([(v1, 3), (v2, 1)]));
+
+        // Records of other types are counted separately
+        append_framed(&mut stream, &to_tagged_bytes(&Other::V1(1)).unwrap()).unwrap();
+        let report = validate_stream::<Event>(&stream);
+        assert!(!report.is_clean());
+        assert_eq!(report.records, 5);
+        assert_eq!(
+            report.unknown_types,
+            BTreeMap::from([(Other::ARCHIVE_TYPE_ID, 1)])
+        );
+        assert_eq!(report.valid_len, stream.len());
+
+        // A flipped payload byte is reported and skipped, a torn final frame ends the walk
+        let mut damaged = stream[..stream.len() - 20].to_vec();
+        damaged[corrupt_offset + FRAME_PREFIX_SIZE + 40] ^= 1;
+        let report = validate_stream::<Event>(&damaged);
+        assert!(!report.is_clean());
+        assert_eq!(report.records, 4);
+        assert_eq!(report.checksum_failures, vec![corrupt_offset]);
+        assert!(report.truncated_at.is_some());
+        assert_eq!(report.valid_len, report.truncated_at.unwrap());
+
+        // A corrupt prefix loses the rest of the stream
+        damaged[5] = 0xff;
+        let report = validate_stream::<Event>(&damaged);
+        assert_eq!(report.corrupt_at, Some(0));
+        assert_eq!(report.records, 0);
+    }
+}
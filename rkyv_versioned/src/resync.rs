@@ -0,0 +1,251 @@
+//! Recovering the intact records of a corrupted stream of frames.
+//!
+//! [framed_records](crate::framed_records) stops at the first corrupt frame, since its length
+//! prefix can no longer be trusted to find the next one.  [resync_framed_records] instead
+//! scans forward from a corrupt frame, one [FRAME_ALIGNMENT] step at a time, for the next
+//! plausible frame, reports the range it skipped, and resumes from there.
+//!
+//! The tagged header has no magic bytes of its own, so a frame is only considered plausible
+//! if everything which can be checked without accessing the payload holds: the zero bytes of
+//! its prefix, the type ID of `T` and a supported version ID in its header, a length
+//! consistent between the two, and a payload matching its digest.  The digest makes a false
+//! positive in corrupt data as unlikely as an undetected corruption.
+//!
+//! Tooling which doesn't know the container types of a stream, such as the `repair`
+//! subcommand of the `rkyv_versioned` binary, can use [resync_any_framed_records] instead,
+//! which accepts frames holding any type and version ID and relies on the rest of the checks.
+//! Without the type ID, a frame written without a digest is only checked by its length, so
+//! those are only accepted where the previous frame ends, never when scanning.
+
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::{
+    check_header, read_header, verify_payload, DigestAlgorithm, RkyvVersionedError,
+    TaggedVersionedHeader, VersionedContainer, FRAME_ALIGNMENT, FRAME_PREFIX_SIZE,
+};
+
+/// An item yielded by [ResyncFramedRecords].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResyncItem<'a> {
+    /// An intact record, i.e. a tagged byte array which can be passed to
+    /// [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+    Record {
+        /// The offset of the frame holding the record in the stream.
+        offset: usize,
+        /// The record.
+        record: &'a [u8],
+    },
+    /// A range of the stream which holds no plausible frame and was skipped.
+    Skipped(Range<usize>),
+}
+
+/// Returns an iterator over the intact records of a stream of frames of `T`, skipping over
+/// corrupt ranges, see the [module](self) documentation.
+pub fn resync_framed_records<T: VersionedContainer>(buf: &[u8]) -> ResyncFramedRecords<'_, T> {
+    ResyncFramedRecords {
+        buf,
+        offset: 0,
+        check_header: check_header::<T>,
+        scan_needs_digest: false,
+        _container: PhantomData,
+    }
+}
+
+/// As [resync_framed_records], for a stream holding records of any container types.
+///
+/// A frame's header only has to be readable rather than hold a supported version of a known
+/// type, so resynchronization relies on the prefix, the length and above all the digest.
+/// Records written without a digest are read where the previous frame ends, as
+/// [framed_records](crate::framed_records) would, but scanning skips over them, since they
+/// can't be told apart from corrupt data.
+pub fn resync_any_framed_records(buf: &[u8]) -> ResyncFramedRecords<'_> {
+    ResyncFramedRecords {
+        buf,
+        offset: 0,
+        check_header: read_header,
+        scan_needs_digest: true,
+        _container: PhantomData,
+    }
+}
+
+/// An iterator over the intact records of a stream of frames, see [resync_framed_records].
+#[derive(Debug, Clone)]
+pub struct ResyncFramedRecords<'a, T = ()> {
+    buf: &'a [u8],
+    offset: usize,
+    /// Reads the header of a record, failing unless it is of an accepted type.
+    check_header: fn(&[u8]) -> Result<TaggedVersionedHeader, RkyvVersionedError>,
+    /// Whether frames found by scanning must have a digest.
+    scan_needs_digest: bool,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> ResyncFramedRecords<'a, T> {
+    /// The offset in the stream the iterator will continue from.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the record and the length of the frame at `offset`, if it is plausible.
+    /// `scanning` is set when `offset` wasn't reached by the length of the previous frame.
+    fn frame_at(&self, offset: usize, scanning: bool) -> Option<(&'a [u8], usize)> {
+        let frame = self.buf.get(offset..)?;
+        let (prefix, rest) = frame.split_first_chunk::<FRAME_PREFIX_SIZE>()?;
+        let record_len = parse_frame_prefix(prefix)?;
+        let record = rest.get(..record_len)?;
+        let header = (self.check_header)(record).ok()?;
+        if header.tagged_len().ok()? != record_len
+            || (scanning
+                && self.scan_needs_digest
+                && header.digest == DigestAlgorithm::None.id())
+        {
+            return None;
+        }
+        verify_payload(record, &header).ok()?;
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        Some((record, frame_len.min(frame.len())))
+    }
+}
+
+impl<'a, T> Iterator for ResyncFramedRecords<'a, T> {
+    type Item = ResyncItem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+        if let Some((record, frame_len)) = self.frame_at(self.offset, false) {
+            let offset = self.offset;
+            self.offset += frame_len;
+            return Some(ResyncItem::Record { offset, record });
+        }
+
+        let start = self.offset;
+        let resumed = (start + FRAME_ALIGNMENT..self.buf.len())
+            .step_by(FRAME_ALIGNMENT)
+            .find(|offset| self.frame_at(*offset, true).is_some());
+        self.offset = resumed.unwrap_or(self.buf.len());
+        Some(ResyncItem::Skipped(start..self.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        append_framed, framed_records, to_tagged_bytes, to_tagged_bytes_with, TaggedOptions,
+        VersionedArchiveContainer,
+    };
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum LogEntry {
+        V1(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u64),
+    }
+
+    #[test]
+    fn test_resync_framed_records() {
+        let mut stream = AlignedVec::<16>::new();
+        let mut offsets = Vec::new();
+        for i in 0..5 {
+            offsets.push(stream.len());
+            let entry = LogEntry::V1(format!("entry {}", i).repeat(i + 1));
+            append_framed(&mut stream, &to_tagged_bytes(&entry).unwrap()).unwrap();
+        }
+
+        let intact: Vec<_> = resync_framed_records::<LogEntry>(&stream).collect();
+        assert_eq!(intact.len(), 5);
+        assert!(
+            matches!(intact[3], ResyncItem::Record { offset, .. } if offset == offsets[3])
+        );
+
+        // Corrupt the prefix of the second frame and the payload of the fourth
+        let mut corrupt = stream.clone();
+        corrupt[offsets[1] + 8] = 0xff;
+        corrupt[offsets[4] - 20] ^= 1;
+        assert_eq!(framed_records(&corrupt).filter(Result::is_ok).count(), 1);
+
+        let items: Vec<_> = resync_framed_records::<LogEntry>(&corrupt).collect();
+        assert_eq!(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    ResyncItem::Record { offset, .. } => Some(*offset),
+                    ResyncItem::Skipped(_) => None,
+                })
+                .collect::<Vec<_>>(),
+            vec![offsets[0], offsets[2], offsets[4]]
+        );
+        assert_eq!(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    ResyncItem::Skipped(range) => Some(range.clone()),
+                    ResyncItem::Record { .. } => None,
+                })
+                .collect::<Vec<_>>(),
+            vec![offsets[1]..offsets[2], offsets[3]..offsets[4]]
+        );
+
+        // Garbage at the end is skipped in one go
+        corrupt.extend_from_slice(&[0xaa; 40]);
+        let last = resync_framed_records::<LogEntry>(&corrupt).last().unwrap();
+        assert_eq!(last, ResyncItem::Skipped(stream.len()..corrupt.len()));
+
+        // Records of other types are only accepted when resynchronizing any type
+        let mut mixed = AlignedVec::<16>::new();
+        append_framed(&mut mixed, &to_tagged_bytes(&Other::V1(1)).unwrap()).unwrap();
+        let other_len = mixed.len();
+        mixed.extend_from_slice(&corrupt);
+        assert_eq!(
+            resync_framed_records::<LogEntry>(&mixed).next(),
+            Some(ResyncItem::Skipped(0..other_len))
+        );
+        let any: Vec<_> = resync_any_framed_records(&mixed).collect();
+        assert_eq!(any.len(), 7);
+        assert!(matches!(any[0], ResyncItem::Record { offset: 0, .. }));
+
+        // Records without a digest are read in sequence, but not picked out of corrupt data
+        let no_digest = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            ..Default::default()
+        };
+        let mut bare = AlignedVec::<16>::new();
+        append_framed(
+            &mut bare,
+            &to_tagged_bytes_with(&Other::V1(1), &no_digest).unwrap(),
+        )
+        .unwrap();
+        let corrupt_at = bare.len();
+        bare.extend_from_slice(&[0xaa; FRAME_ALIGNMENT]);
+        let bare_at = bare.len();
+        append_framed(
+            &mut bare,
+            &to_tagged_bytes_with(&Other::V1(2), &no_digest).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            resync_any_framed_records(&bare)
+                .map(|item| match item {
+                    ResyncItem::Record { offset, .. } => offset..offset,
+                    ResyncItem::Skipped(range) => range,
+                })
+                .collect::<Vec<_>>(),
+            vec![0..0, corrupt_at..bare.len()]
+        );
+        assert_eq!(
+            resync_framed_records::<Other>(&bare)
+                .filter(|item| matches!(item, ResyncItem::Record { offset, .. } if *offset == bare_at))
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Staged rollouts of new versions behind feature flags.
+//!
+//! A new version can only be written once every reader understands it, so rollouts usually
+//! ship a binary able to write both the new and the previous version, and switch between them
+//! at runtime.  A [VersionGate] consults a [FeatureFlags] provider for every record, building
+//! the new version while the flag is enabled and the previous one otherwise, and counts the
+//! versions it chose so that the progress of the rollout shows up in metrics, either by
+//! reading [version_counts](VersionGate::version_counts) or through a [RolloutObserver].
+
+use core::fmt;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::{VersionInfo, VersionedContainer};
+
+/// Answers whether a feature flag is enabled, e.g. backed by a configuration service.
+pub trait FeatureFlags: Sync {
+    /// Returns whether `flag` is enabled.
+    fn is_enabled(&self, flag: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool + Sync> FeatureFlags for F {
+    fn is_enabled(&self, flag: &str) -> bool {
+        self(flag)
+    }
+}
+
+/// Notified of the version chosen for every record by a [VersionGate].
+pub trait RolloutObserver: Sync {
+    /// Called when a record of the container with type ID `type_id` is built in the version
+    /// described by `info`, typically to increment a counter labelled with its name.
+    /// `enabled` is whether the flag of the gate was enabled.
+    fn version_chosen(&self, type_id: u32, info: &VersionInfo, enabled: bool);
+}
+
+/// Chooses between the new and the previous version of a container by a feature flag, see
+/// the [module](self) documentation.
+pub struct VersionGate<'a> {
+    flag: String,
+    flags: &'a dyn FeatureFlags,
+    observer: Option<&'a dyn RolloutObserver>,
+    /// The number of records built in each version ID
+    counts: Mutex<BTreeMap<u32, u64>>,
+}
+
+impl fmt::Debug for VersionGate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionGate")
+            .field("flag", &self.flag)
+            .field("observer", &self.observer.is_some())
+            .field("counts", &self.version_counts())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> VersionGate<'a> {
+    /// Creates a gate writing the new version while `flag` is enabled in `flags`.
+    pub fn new(flag: impl Into<String>, flags: &'a dyn FeatureFlags) -> Self {
+        VersionGate {
+            flag: flag.into(),
+            flags,
+            observer: None,
+            counts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Notifies `observer` of the version chosen for every record.
+    pub fn with_observer(mut self, observer: &'a dyn RolloutObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// The flag the gate consults.
+    pub fn flag(&self) -> &str {
+        &self.flag
+    }
+
+    /// Returns whether the flag is currently enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.flags.is_enabled(&self.flag)
+    }
+
+    /// Builds the record to write, consulting the flag once.
+    ///
+    /// # Arguments
+    ///
+    /// * `new` - Builds the record in the version being rolled out.
+    /// * `previous` - Builds the record in the version every reader understands, typically
+    ///   by migrating the new value down.
+    ///
+    /// # Returns
+    ///
+    /// The record built by `new` if the flag is enabled, or by `previous` otherwise.
+    pub fn choose<T: VersionedContainer>(
+        &self,
+        new: impl FnOnce() -> T,
+        previous: impl FnOnce() -> T,
+    ) -> T {
+        let enabled = self.is_enabled();
+        let item = match enabled {
+            true => new(),
+            false => previous(),
+        };
+
+        let version_id = item.get_entry_version_id();
+        *self
+            .counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(version_id)
+            .or_default() += 1;
+        if let (Some(observer), Some(info)) = (self.observer, T::version_info(version_id)) {
+            observer.version_chosen(T::ARCHIVE_TYPE_ID, info, enabled);
+        }
+        item
+    }
+
+    /// The number of records built in each version ID so far.
+    pub fn version_counts(&self) -> BTreeMap<u32, u64> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Order {
+        V1(u64),
+        V2((u64, String)),
+        V3((u64, String, u32)),
+    }
+
+    /// A flag toggled at runtime
+    struct Toggle(AtomicBool);
+
+    impl FeatureFlags for Toggle {
+        fn is_enabled(&self, flag: &str) -> bool {
+            flag == "orders_v3" && self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Counts the records built with the flag enabled
+    struct CountingObserver(AtomicUsize);
+
+    impl RolloutObserver for CountingObserver {
+        fn version_chosen(&self, type_id: u32, info: &VersionInfo, enabled: bool) {
+            assert_eq!(type_id, Order::ARCHIVE_TYPE_ID);
+            assert_eq!(info.name == "V3", enabled);
+            if enabled {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_gate() {
+        let toggle = Toggle(AtomicBool::new(false));
+        let observer = CountingObserver(AtomicUsize::new(0));
+        let gate = VersionGate::new("orders_v3", &toggle).with_observer(&observer);
+
+        let write = |id: u64| {
+            let order = gate.choose(
+                || Order::V3((id, "widget".to_string(), 2)),
+                || Order::V2((id, "widget".to_string())),
+            );
+            to_tagged_bytes(&order).unwrap()
+        };
+
+        let bytes = write(1);
+        assert!(matches!(
+            access_from_tagged_bytes::<Order>(&bytes).unwrap(),
+            ArchivedOrder::V2(_)
+        ));
+        toggle.0.store(true, Ordering::Relaxed);
+        write(2);
+        let bytes = write(3);
+        assert!(matches!(
+            access_from_tagged_bytes::<Order>(&bytes).unwrap(),
+            ArchivedOrder::V3(_)
+        ));
+
+        assert_eq!(gate.version_counts(), BTreeMap::from([(1, 1), (2, 2)]));
+        assert_eq!(observer.0.load(Ordering::Relaxed), 2);
+
+        // Closures are providers too
+        let off = |_: &str| false;
+        assert!(!VersionGate::new("orders_v3", &off).is_enabled());
+    }
+}
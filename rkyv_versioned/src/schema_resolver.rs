@@ -0,0 +1,194 @@
+//! Resolving type IDs to the names and versions of the containers they identify.
+//!
+//! Headers only carry numeric type and version IDs.  Tooling reading records of containers it
+//! wasn't compiled with, e.g. an inspector or the [scan_versions](crate::scan_versions)
+//! report of a shared store, asks a [SchemaResolver] what they mean.  The crate provides
+//! [StaticSchemaRegistry], filled from the
+//! [VERSION_TABLE](crate::VersionedContainer::VERSION_TABLE) of containers linked into the
+//! program, while organizations centralizing their type identities can implement the trait
+//! over a network-backed registry, reporting failures to reach it as
+//! [RkyvVersionedError::TransportError].
+
+use std::collections::HashMap;
+
+use crate::{RkyvVersionedError, VersionInfo, VersionedContainer};
+
+/// One version of a container, as described by a [SchemaResolver].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SchemaVersion {
+    /// The version ID written in the header.
+    pub version_id: u32,
+    /// The name of the variant holding the version, e.g. `V1`.
+    pub name: String,
+    /// The [schema_hash](VersionInfo::schema_hash) of the version.
+    pub schema_hash: u32,
+    /// Whether the version is deprecated.
+    pub deprecated: bool,
+}
+
+impl From<&VersionInfo> for SchemaVersion {
+    fn from(info: &VersionInfo) -> Self {
+        SchemaVersion {
+            version_id: info.version_id,
+            name: info.name.to_string(),
+            schema_hash: info.schema_hash,
+            deprecated: info.deprecated,
+        }
+    }
+}
+
+/// A container type, as described by a [SchemaResolver].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeSchema {
+    /// The [ARCHIVE_TYPE_ID](VersionedContainer::ARCHIVE_TYPE_ID) of the container.
+    pub type_id: u32,
+    /// The name of the container, e.g. `OrderEvent`.
+    pub name: String,
+    /// Every version of the container, in version order.
+    pub versions: Vec<SchemaVersion>,
+}
+
+impl TypeSchema {
+    /// Returns the version with ID `version_id`, if the container has one.
+    pub fn version(&self, version_id: u32) -> Option<&SchemaVersion> {
+        self.versions
+            .iter()
+            .find(|version| version.version_id == version_id)
+    }
+}
+
+/// A source of [TypeSchema]s by type ID.
+pub trait SchemaResolver {
+    /// Looks up the container with type ID `type_id`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the schema of the container, `None` if the resolver doesn't know
+    /// the type ID, or an error if the resolver failed, e.g. couldn't reach a remote registry.
+    fn resolve(&self, type_id: u32) -> Result<Option<TypeSchema>, RkyvVersionedError>;
+
+    /// Looks up the name of the container with type ID `type_id`.
+    fn type_name(&self, type_id: u32) -> Result<Option<String>, RkyvVersionedError> {
+        Ok(self.resolve(type_id)?.map(|schema| schema.name))
+    }
+
+    /// Looks up version `version_id` of the container with type ID `type_id`.
+    fn resolve_version(
+        &self,
+        type_id: u32,
+        version_id: u32,
+    ) -> Result<Option<SchemaVersion>, RkyvVersionedError> {
+        Ok(self
+            .resolve(type_id)?
+            .and_then(|schema| schema.version(version_id).cloned()))
+    }
+}
+
+impl<R: SchemaResolver + ?Sized> SchemaResolver for &R {
+    fn resolve(&self, type_id: u32) -> Result<Option<TypeSchema>, RkyvVersionedError> {
+        (**self).resolve(type_id)
+    }
+}
+
+/// A [SchemaResolver] over schemas registered in process, usually from the containers linked
+/// into the program.
+#[derive(Debug, Default, Clone)]
+pub struct StaticSchemaRegistry {
+    schemas: HashMap<u32, TypeSchema>,
+}
+
+impl StaticSchemaRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        StaticSchemaRegistry::default()
+    }
+
+    /// Registers `T` under `name`, with the versions of its
+    /// [VERSION_TABLE](VersionedContainer::VERSION_TABLE), replacing any schema previously
+    /// registered for its type ID.
+    pub fn register<T: VersionedContainer>(&mut self, name: &str) -> &mut Self {
+        self.insert(TypeSchema {
+            type_id: T::ARCHIVE_TYPE_ID,
+            name: name.to_string(),
+            versions: T::VERSION_TABLE.iter().map(SchemaVersion::from).collect(),
+        })
+    }
+
+    /// Registers `schema`, e.g. one loaded from a file, replacing any schema previously
+    /// registered for its type ID.
+    pub fn insert(&mut self, schema: TypeSchema) -> &mut Self {
+        self.schemas.insert(schema.type_id, schema);
+        self
+    }
+
+    /// Returns the number of registered schemas.
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    /// Returns whether no schemas are registered.
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+}
+
+impl SchemaResolver for StaticSchemaRegistry {
+    fn resolve(&self, type_id: u32) -> Result<Option<TypeSchema>, RkyvVersionedError> {
+        Ok(self.schemas.get(&type_id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{peek_header, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum OrderEvent {
+        V1(u64),
+        V2(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Unregistered {
+        V1(u8),
+    }
+
+    #[test]
+    fn test_static_schema_registry() {
+        let mut registry = StaticSchemaRegistry::new();
+        registry.register::<OrderEvent>("OrderEvent");
+        assert_eq!(registry.len(), 1);
+
+        let bytes = to_tagged_bytes(&OrderEvent::V2("shipped".into())).unwrap();
+        let header = peek_header(&bytes).unwrap();
+        let resolver: &dyn SchemaResolver = &registry;
+        assert_eq!(
+            resolver.type_name(header.type_id).unwrap().as_deref(),
+            Some("OrderEvent")
+        );
+        let version = resolver
+            .resolve_version(header.type_id, header.version_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(version.name, "V2");
+        assert_eq!(
+            version,
+            SchemaVersion::from(OrderEvent::version_info(header.version_id).unwrap())
+        );
+        assert_eq!(
+            registry
+                .resolve(OrderEvent::ARCHIVE_TYPE_ID)
+                .unwrap()
+                .unwrap()
+                .versions
+                .len(),
+            2
+        );
+        assert!(registry
+            .resolve(Unregistered::ARCHIVE_TYPE_ID)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,230 @@
+//! Sealing finished archives with a signed manifest, enabled with the `ed25519` feature.
+//!
+//! Once a stream of frames, e.g. a rotated [VersionedLog](crate::VersionedLog) segment, is
+//! complete, [seal_archive] appends a manifest of its entries signed with an ed25519 key and
+//! makes the file read-only, turning it into a tamper-evident artifact for retention.
+//! [verify_seal] later checks that the archive still holds exactly the sealed entries.
+//!
+//! The seal follows the last frame:
+//!
+//! `[magic][entry count (u64)][content length (u64)][CRC32 of each record (u32)]*[zero padding]`
+//! `[signature][seal length (u64)][magic]`
+//!
+//! all little-endian, where the signature covers the content and the manifest in front of
+//! it.  The CRC32s let tooling point at the entries which changed, while the signature makes
+//! any change to the content or the manifest detectable by whoever holds the public key.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use crate::io::io_error;
+use crate::signature::SIGNATURE_LEN;
+use crate::{framed_records, RkyvVersionedError, FRAME_ALIGNMENT};
+
+/// The magic bytes opening the manifest and ending the seal.
+const SEAL_MAGIC: &[u8; 8] = b"VACSEAL1";
+
+/// The size of the fixed part of the manifest: the magic, entry count and content length.
+const MANIFEST_HEADER_SIZE: usize = 24;
+
+/// The size of the footer ending the seal: the seal length and magic.
+const SEAL_FOOTER_SIZE: usize = 16;
+
+/// What a seal covers, returned by [seal_archive] and [verify_seal].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealInfo {
+    /// The number of records sealed.
+    pub entries: u64,
+    /// The length of the sealed frames, i.e. the offset of the seal in the file.  Readers of
+    /// the records should stop there.
+    pub content_len: u64,
+}
+
+/// Seals the stream of frames in the file at `path` with `key` and makes the file read-only.
+///
+/// # Arguments
+///
+/// * `path` - The file holding the stream, e.g. written by a [LogWriter](crate::LogWriter).
+/// * `key` - The key to sign the manifest with.
+///
+/// # Returns
+///
+/// A `Result` containing what the seal covers, or an error if the file can't be read or
+/// written, is already sealed
+/// ([RkyvVersionedError::InvalidMessageError]), or holds a truncated or corrupt frame
+/// ([RkyvVersionedError::InvalidFrameError]).
+pub fn seal_archive(
+    path: impl AsRef<Path>,
+    key: &SigningKey,
+) -> Result<SealInfo, RkyvVersionedError> {
+    let path = path.as_ref();
+    let content = fs::read(path).map_err(io_error)?;
+    if seal_len(&content).is_some() {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+    let (manifest, info) = build_manifest(&content)?;
+
+    let mut signed = content;
+    signed.extend_from_slice(&manifest);
+    let signature = key.sign(&signed);
+
+    let mut seal = manifest;
+    seal.extend_from_slice(&signature.to_bytes());
+    let total_len = seal.len() + SEAL_FOOTER_SIZE;
+    seal.extend_from_slice(&(total_len as u64).to_le_bytes());
+    seal.extend_from_slice(SEAL_MAGIC);
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(io_error)?;
+    file.write_all(&seal).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+
+    let mut permissions = file.metadata().map_err(io_error)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(path, permissions).map_err(io_error)?;
+    Ok(info)
+}
+
+/// Verifies the seal of an archive sealed by [seal_archive] against `key`.
+///
+/// # Returns
+///
+/// A `Result` containing what the seal covers, [RkyvVersionedError::MissingSignatureError]
+/// if the archive isn't sealed, or [RkyvVersionedError::SignatureMismatchError] if it was
+/// sealed by another key or its content or manifest have been tampered with.
+pub fn verify_seal(
+    path: impl AsRef<Path>,
+    key: &VerifyingKey,
+) -> Result<SealInfo, RkyvVersionedError> {
+    let archive = fs::read(path).map_err(io_error)?;
+    let seal_len = seal_len(&archive).ok_or(RkyvVersionedError::MissingSignatureError)?;
+    let seal_start = archive
+        .len()
+        .checked_sub(seal_len)
+        .ok_or(RkyvVersionedError::SignatureMismatchError)?;
+    let signature_start = archive
+        .len()
+        .checked_sub(SEAL_FOOTER_SIZE + SIGNATURE_LEN)
+        .filter(|start| *start >= seal_start)
+        .ok_or(RkyvVersionedError::SignatureMismatchError)?;
+
+    let (signed, trailer) = archive
+        .split_at_checked(signature_start)
+        .ok_or(RkyvVersionedError::SignatureMismatchError)?;
+    let signature = trailer
+        .get(..SIGNATURE_LEN)
+        .and_then(|signature| Signature::from_slice(signature).ok())
+        .ok_or(RkyvVersionedError::SignatureMismatchError)?;
+    key.verify_strict(signed, &signature)
+        .map_err(|_| RkyvVersionedError::SignatureMismatchError)?;
+
+    // The manifest is authentic, check that it describes the content in front of it
+    let (content, manifest) = signed
+        .split_at_checked(seal_start)
+        .ok_or(RkyvVersionedError::SignatureMismatchError)?;
+    let (expected, info) = build_manifest(content)?;
+    if expected != manifest {
+        return Err(RkyvVersionedError::SignatureMismatchError);
+    }
+    Ok(info)
+}
+
+/// Returns the length of the seal ending `archive`, if it ends with one.
+fn seal_len(archive: &[u8]) -> Option<usize> {
+    let footer = archive.last_chunk::<SEAL_FOOTER_SIZE>()?;
+    if &footer[8..] != SEAL_MAGIC {
+        return None;
+    }
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&footer[..8]);
+    usize::try_from(u64::from_le_bytes(len)).ok()
+}
+
+/// Builds the manifest of the stream of frames in `content`.
+fn build_manifest(content: &[u8]) -> Result<(Vec<u8>, SealInfo), RkyvVersionedError> {
+    let mut crcs = Vec::new();
+    for record in framed_records(content) {
+        crcs.extend_from_slice(&crc32fast::hash(record?).to_le_bytes());
+    }
+    let info = SealInfo {
+        entries: (crcs.len() / 4) as u64,
+        content_len: content.len() as u64,
+    };
+
+    let mut manifest = Vec::with_capacity(MANIFEST_HEADER_SIZE + crcs.len() + FRAME_ALIGNMENT);
+    manifest.extend_from_slice(SEAL_MAGIC);
+    manifest.extend_from_slice(&info.entries.to_le_bytes());
+    manifest.extend_from_slice(&info.content_len.to_le_bytes());
+    manifest.extend_from_slice(&crcs);
+    manifest.resize(manifest.len().next_multiple_of(FRAME_ALIGNMENT), 0);
+    Ok((manifest, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{append_framed, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::util::AlignedVec;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum AuditEntry {
+        V1(String),
+    }
+
+    #[test]
+    fn test_seal_archive() {
+        let dir =
+            std::env::temp_dir().join(format!("rkyv_versioned_seal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+
+        let mut stream = AlignedVec::<16>::new();
+        for i in 0..4 {
+            let entry = AuditEntry::V1(format!("user {} logged in", i));
+            append_framed(&mut stream, &to_tagged_bytes(&entry).unwrap()).unwrap();
+        }
+        fs::write(&path, &stream).unwrap();
+
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let info = seal_archive(&path, &key).unwrap();
+        assert_eq!(info.entries, 4);
+        assert_eq!(info.content_len, stream.len() as u64);
+        assert!(fs::metadata(&path).unwrap().permissions().readonly());
+        assert_eq!(verify_seal(&path, &key.verifying_key()).unwrap(), info);
+
+        // Sealing twice is refused, and other keys don't verify
+        assert!(seal_archive(&path, &key).is_err());
+        let other = SigningKey::from_bytes(&[10; 32]).verifying_key();
+        assert!(matches!(
+            verify_seal(&path, &other),
+            Err(RkyvVersionedError::SignatureMismatchError)
+        ));
+
+        // Tampering with an entry breaks the seal
+        let mut archive = fs::read(&path).unwrap();
+        archive[40] ^= 1;
+        let tampered = dir.join("tampered.log");
+        fs::write(&tampered, &archive).unwrap();
+        assert!(matches!(
+            verify_seal(&tampered, &key.verifying_key()),
+            Err(RkyvVersionedError::SignatureMismatchError)
+        ));
+        fs::write(&tampered, &stream).unwrap();
+        assert!(matches!(
+            verify_seal(&tampered, &key.verifying_key()),
+            Err(RkyvVersionedError::MissingSignatureError)
+        ));
+
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&path, permissions).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! Checking at startup that every version of a container can be written and read back.
+//!
+//! Packaging and feature flag mistakes, such as a binary built with a different `rkyv`
+//! layout or without the codec its configuration asks for, otherwise only show up when the
+//! first record is read.  [self_check] tags a sample of each version with the options the
+//! service writes with, reads it back and validates it, and
+//! [self_check_with_migration] also runs the migration each record would go through, so a
+//! service can fail fast at boot:
+//!
+//! ```
+//! use rkyv::{Archive, Deserialize, Serialize};
+//! use rkyv_versioned::{self_check, TaggedOptions, VersionedArchiveContainer};
+//!
+//! #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+//! enum Setting {
+//!     V1(u32),
+//!     V2(String),
+//! }
+//!
+//! let samples = [Setting::V1(Default::default()), Setting::V2(Default::default())];
+//! self_check(&samples, &TaggedOptions::default())
+//!     .into_result()
+//!     .expect("Settings can't be read by this build");
+//! ```
+//!
+//! With the `proptest` feature, containers deriving `#[vac(proptest)]` can have their samples
+//! generated with `testing::samples_of_each_version` instead.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    access_from_encrypted_tagged_bytes, access_from_tagged_bytes_with_scratch,
+    get_type_and_version_from_tagged_bytes, to_tagged_bytes_with, RkyvVersionedError,
+    TaggedOptions, VersionedContainer,
+};
+
+/// Why a version failed [self_check].
+#[derive(Debug)]
+pub enum SelfCheckFailure {
+    /// No sample of the version was given.
+    MissingSample,
+    /// The sample could not be tagged.
+    Serialize(RkyvVersionedError),
+    /// The tagged sample could not be read back or failed validation.
+    Access(RkyvVersionedError),
+    /// The tagged sample was read back with a different type ID and version ID.
+    Mismatch(u32, u32),
+    /// The migration failed on the archived sample, with its error message.
+    Migration(String),
+}
+
+impl fmt::Display for SelfCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelfCheckFailure::MissingSample => write!(f, "No sample was given"),
+            SelfCheckFailure::Serialize(e) => write!(f, "Could not be tagged: {}", e),
+            SelfCheckFailure::Access(e) => write!(f, "Could not be read back: {}", e),
+            SelfCheckFailure::Mismatch(type_id, version_id) => write!(
+                f,
+                "Read back as type ID {:#010x} version {}",
+                type_id, version_id
+            ),
+            SelfCheckFailure::Migration(e) => write!(f, "Migration failed: {}", e),
+        }
+    }
+}
+
+/// The outcome of [self_check] for one version.
+#[derive(Debug)]
+pub struct VersionCheck {
+    /// The version ID.
+    pub version_id: u32,
+    /// The name of the variant holding the version.
+    pub name: &'static str,
+    /// `Ok(())` if the sample of the version was written, read back and migrated.
+    pub result: Result<(), SelfCheckFailure>,
+}
+
+/// The outcome of [self_check], with one [VersionCheck] per entry of the
+/// [VERSION_TABLE](VersionedContainer::VERSION_TABLE).
+#[derive(Debug)]
+pub struct SelfCheckReport {
+    /// The name of the container checked.
+    pub container: &'static str,
+    /// The outcome for each version, in the order of the version table.
+    pub versions: Vec<VersionCheck>,
+}
+
+impl SelfCheckReport {
+    /// Returns `true` if every version passed.
+    pub fn is_ok(&self) -> bool {
+        self.versions.iter().all(|version| version.result.is_ok())
+    }
+
+    /// Returns the versions which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &VersionCheck> {
+        self.versions
+            .iter()
+            .filter(|version| version.result.is_err())
+    }
+
+    /// Converts the report into a `Result`, to fail with `?` or `expect` when any version
+    /// failed.
+    pub fn into_result(self) -> Result<(), SelfCheckReport> {
+        match self.is_ok() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl core::error::Error for SelfCheckReport {}
+impl fmt::Display for SelfCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_ok() {
+            true => write!(f, "Self-check of {} passed", self.container),
+            false => {
+                write!(f, "Self-check of {} failed:", self.container)?;
+                for version in self.failures() {
+                    if let Err(e) = &version.result {
+                        write!(
+                            f,
+                            "\n- version {} ({}): {}",
+                            version.version_id, version.name, e
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Tags a sample of each version of `T` with `options`, then reads it back, checking its
+/// header, digest and payload.
+///
+/// # Arguments
+///
+/// * `samples` - At least one value holding each version of `T`, e.g. built from the
+///   `Default` of each variant type.  Only the first sample of each version is used.
+/// * `options` - The options the service writes records with.
+///
+/// # Returns
+///
+/// A [SelfCheckReport] with the outcome for every version.  Versions without a sample fail
+/// with [SelfCheckFailure::MissingSample].
+pub fn self_check<T>(samples: &[T], options: &TaggedOptions) -> SelfCheckReport
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    self_check_with_migration(
+        samples,
+        options,
+        |_| Ok::<(), core::convert::Infallible>(()),
+    )
+}
+
+/// Like [self_check], but also runs `migrate` on each sample once it has been read back.
+///
+/// # Arguments
+///
+/// * `samples` - At least one value holding each version of `T`.
+/// * `options` - The options the service writes records with.
+/// * `migrate` - The migration every record read goes through, typically from any version to
+///   the latest one.
+///
+/// # Returns
+///
+/// A [SelfCheckReport] with the outcome for every version, failing with
+/// [SelfCheckFailure::Migration] where `migrate` returned an error.
+pub fn self_check_with_migration<T, U, E>(
+    samples: &[T],
+    options: &TaggedOptions,
+    migrate: impl Fn(&T::Archived) -> Result<U, E>,
+) -> SelfCheckReport
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: fmt::Display,
+{
+    let full_name = core::any::type_name::<T>();
+    let path = full_name.split('<').next().unwrap_or(full_name);
+    let versions = T::VERSION_TABLE
+        .iter()
+        .map(|info| VersionCheck {
+            version_id: info.version_id,
+            name: info.name,
+            result: match samples
+                .iter()
+                .find(|sample| sample.get_entry_version_id() == info.version_id)
+            {
+                Some(sample) => check_sample(sample, options, &migrate),
+                None => Err(SelfCheckFailure::MissingSample),
+            },
+        })
+        .collect();
+    SelfCheckReport {
+        container: path.rsplit("::").next().unwrap_or(path),
+        versions,
+    }
+}
+
+/// Writes, reads back and migrates a single sample.
+fn check_sample<T, U, E>(
+    sample: &T,
+    options: &TaggedOptions,
+    migrate: impl Fn(&T::Archived) -> Result<U, E>,
+) -> Result<(), SelfCheckFailure>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: fmt::Display,
+{
+    let bytes = to_tagged_bytes_with(sample, options).map_err(SelfCheckFailure::Serialize)?;
+
+    let (type_id, version_id) =
+        get_type_and_version_from_tagged_bytes(&bytes).map_err(SelfCheckFailure::Access)?;
+    if type_id != T::ARCHIVE_TYPE_ID || version_id != sample.get_entry_version_id() {
+        return Err(SelfCheckFailure::Mismatch(type_id, version_id));
+    }
+
+    let mut scratch = AlignedVec::new();
+    let archived = match &options.encryption {
+        Some(key) => access_from_encrypted_tagged_bytes::<T>(&bytes, key, &mut scratch),
+        None => access_from_tagged_bytes_with_scratch::<T>(&bytes, &mut scratch),
+    }
+    .map_err(SelfCheckFailure::Access)?;
+
+    migrate(archived)
+        .map(|_| ())
+        .map_err(|e| SelfCheckFailure::Migration(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionedArchiveContainer;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Default, Archive, Serialize, Deserialize)]
+    struct SettingV1 {
+        value: u32,
+    }
+
+    #[derive(Debug, Default, Archive, Serialize, Deserialize)]
+    struct SettingV2 {
+        value: u64,
+        label: String,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Setting {
+        V1(SettingV1),
+        V2(SettingV2),
+    }
+
+    fn migrate(archived: &ArchivedSetting) -> Result<SettingV2, &'static str> {
+        match archived {
+            ArchivedSetting::V1(_) => Err("no migration from V1"),
+            ArchivedSetting::V2(v2) => Ok(SettingV2 {
+                value: v2.value.to_native(),
+                label: v2.label.to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_self_check() {
+        let samples = [
+            Setting::V1(Default::default()),
+            Setting::V2(Default::default()),
+        ];
+        let report = self_check(&samples, &TaggedOptions::default());
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.container, "Setting");
+        assert_eq!(report.versions.len(), 2);
+
+        // Missing samples and failing migrations are reported per version
+        let report =
+            self_check_with_migration(&samples[..1], &TaggedOptions::default(), migrate);
+        assert!(matches!(
+            report.versions[0].result,
+            Err(SelfCheckFailure::Migration(_))
+        ));
+        assert!(matches!(
+            report.versions[1].result,
+            Err(SelfCheckFailure::MissingSample)
+        ));
+        assert_eq!(
+            report.into_result().unwrap_err().to_string(),
+            "Self-check of Setting failed:\n\
+             - version 0 (V1): Migration failed: no migration from V1\n\
+             - version 1 (V2): No sample was given"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Serializing many records with the same scratch space and output buffer.
+//!
+//! Each call to [to_tagged_bytes_with](crate::to_tagged_bytes_with) sets up `rkyv`'s
+//! serializer state and allocates an output buffer.  A [TaggedSerializer] keeps the arena the
+//! serializer allocates scratch space from and the buffer it writes to, so serializing
+//! containers back to back reuses both, and once they have grown to fit the largest record
+//! no further allocations are made for uncompressed payloads.
+
+use core::fmt;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::{Arena, ArenaHandle};
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    rkyv_error, tag_serialized_in, RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// A reusable serializer of tagged byte arrays.
+pub struct TaggedSerializer {
+    arena: Arena,
+    buf: AlignedVec,
+    options: TaggedOptions,
+}
+
+impl TaggedSerializer {
+    /// Creates a serializer tagging records with the default [TaggedOptions].
+    pub fn new() -> Self {
+        TaggedSerializer::with_options(TaggedOptions::default())
+    }
+
+    /// Creates a serializer tagging records with `options`.
+    pub fn with_options(options: TaggedOptions) -> Self {
+        TaggedSerializer {
+            arena: Arena::new(),
+            buf: AlignedVec::new(),
+            options,
+        }
+    }
+
+    /// The options records are tagged with.
+    pub fn options(&self) -> &TaggedOptions {
+        &self.options
+    }
+
+    /// The options records are tagged with, e.g. to set the
+    /// [sequence](TaggedOptions::sequence) number of the next record.
+    pub fn options_mut(&mut self) -> &mut TaggedOptions {
+        &mut self.options
+    }
+
+    /// Serializes `item` into a tagged byte array, replacing the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - A reference to the item to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the tagged byte array, valid until the next call, or an
+    /// error if serialization fails.
+    pub fn serialize<T>(&mut self, item: &T) -> Result<&AlignedVec, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a, 'b> Serialize<
+                HighSerializer<&'b mut AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>,
+            >,
+    {
+        // Serialize in place, so the buffer is kept even if this record fails
+        self.buf.clear();
+        let arena = &mut self.arena;
+        tag_serialized_in::<T, _>(
+            item.get_entry_version_id(),
+            &self.options,
+            &mut self.buf,
+            |out| {
+                rkyv::api::high::to_bytes_in_with_alloc::<_, _, rkyv::rancor::Error>(
+                    item,
+                    out,
+                    arena.acquire(),
+                )
+                .map(|_| ())
+                .map_err(rkyv_error)
+            },
+        )?;
+        Ok(&self.buf)
+    }
+
+    /// Releases the scratch space and output buffer grown to fit past records.
+    pub fn shrink(&mut self) {
+        self.arena.shrink();
+        self.buf = AlignedVec::new();
+    }
+}
+
+impl Default for TaggedSerializer {
+    fn default() -> Self {
+        TaggedSerializer::new()
+    }
+}
+
+impl fmt::Debug for TaggedSerializer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedSerializer")
+            .field("buf_capacity", &self.buf.capacity())
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes_with, DigestAlgorithm,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Quote {
+        V1((String, u64)),
+        V2((String, u64, Vec<u32>)),
+    }
+
+    #[test]
+    fn test_tagged_serializer() {
+        let mut serializer = TaggedSerializer::with_options(TaggedOptions {
+            sequence: Some(0),
+            ..Default::default()
+        });
+        let quotes: Vec<Quote> = (0..50)
+            .map(|i| match i % 2 {
+                0 => Quote::V1((format!("SYM{}", i), i)),
+                _ => Quote::V2((format!("SYM{}", i), i, (0..i as u32).collect())),
+            })
+            .collect();
+
+        let mut capacity = 0;
+        for (i, quote) in quotes.iter().enumerate() {
+            serializer.options_mut().sequence = Some(i as u64);
+            let expected = to_tagged_bytes_with(quote, serializer.options()).unwrap();
+            let bytes = serializer.serialize(quote).unwrap();
+            assert_eq!(bytes.as_slice(), expected.as_slice());
+            assert!(access_from_tagged_bytes::<Quote>(bytes).is_ok());
+            // The buffer only grows for longer records
+            assert!(bytes.capacity() >= capacity);
+            capacity = bytes.capacity();
+        }
+
+        // A record failing after its payload was written keeps the buffer
+        let unavailable = [
+            DigestAlgorithm::Crc32c,
+            DigestAlgorithm::Xxh3,
+            DigestAlgorithm::Blake3,
+        ]
+        .into_iter()
+        .find(|algorithm| !algorithm.is_available());
+        if let Some(algorithm) = unavailable {
+            serializer.options_mut().digest = algorithm;
+            assert!(matches!(
+                serializer.serialize(&quotes[1]),
+                Err(RkyvVersionedError::UnsupportedDigestError(_))
+            ));
+            assert_eq!(serializer.buf.capacity(), capacity);
+            serializer.options_mut().digest = DigestAlgorithm::default();
+            assert!(serializer.serialize(&quotes[1]).is_ok());
+        }
+
+        serializer.shrink();
+        assert!(serializer.serialize(&quotes[0]).is_ok());
+    }
+}
@@ -0,0 +1,268 @@
+//! Records in reference-counted `bytes` buffers, enabled with the `bytes` feature.
+//!
+//! Networking stacks built on `bytes` pass frames around as [Bytes], which are cheap to clone
+//! and slice.  [to_shared_bytes] converts a serialized container into one without copying
+//! it, and a [TaggedBytes] keeps such a buffer alive alongside the archived value it holds,
+//! validated once when it is created, so the value can be handed to other tasks and accessed
+//! any number of times in place.  [BytesMutWriter] lets
+//! [to_tagged_bytes_in](crate::to_tagged_bytes_in) append records to a [BytesMut].
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use bytes::{Bytes, BytesMut};
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::ser::{Positional, Writer};
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    access_from_tagged_bytes, read_header, split_metadata, to_tagged_bytes_with,
+    RkyvVersionedError, TaggedOptions, VersionedContainer, FRAME_ALIGNMENT,
+    TAGGED_HEADER_SIZE,
+};
+
+/// Serializes a versioned container into a tagged byte array held by a [Bytes], which takes
+/// over the aligned buffer without copying it.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array or an error if serialization fails.
+pub fn to_shared_bytes<T>(item: &T) -> Result<Bytes, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    to_shared_bytes_with(item, &TaggedOptions::default())
+}
+
+/// As [to_shared_bytes], tagging the container with `options`.
+pub fn to_shared_bytes_with<T>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<Bytes, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    Ok(Bytes::from_owner(to_tagged_bytes_with(item, options)?))
+}
+
+/// A validated tagged byte array of `T` in a [Bytes], accessed in place with
+/// [get](Self::get).
+pub struct TaggedBytes<T> {
+    bytes: Bytes,
+    version_id: u32,
+    /// The position of the payload in `bytes`.
+    payload: Range<usize>,
+    _container: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for TaggedBytes<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedBytes")
+            .field("len", &self.bytes.len())
+            .field("version_id", &self.version_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for TaggedBytes<T> {
+    fn clone(&self) -> Self {
+        TaggedBytes {
+            bytes: self.bytes.clone(),
+            version_id: self.version_id,
+            payload: self.payload.clone(),
+            _container: PhantomData,
+        }
+    }
+}
+
+impl<T: VersionedContainer> TaggedBytes<T>
+where
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    /// Validates the tagged byte array at the front of `bytes` like
+    /// [access_from_tagged_bytes].  Buffers which aren't aligned to
+    /// [FRAME_ALIGNMENT](crate::FRAME_ALIGNMENT) bytes are copied into an aligned one, any
+    /// other buffer is kept as it is.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the record, or an error if any of the checks of
+    /// [access_from_tagged_bytes] fail.
+    pub fn new(bytes: Bytes) -> Result<Self, RkyvVersionedError> {
+        let bytes = match bytes.as_ptr() as usize % FRAME_ALIGNMENT {
+            0 => bytes,
+            _ => {
+                let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+                aligned.extend_from_slice(&bytes);
+                Bytes::from_owner(aligned)
+            }
+        };
+        access_from_tagged_bytes::<T>(&bytes)?;
+
+        let header = read_header(&bytes)?;
+        let tagged_len = header.tagged_len()?;
+        let body = bytes
+            .get(TAGGED_HEADER_SIZE..TAGGED_HEADER_SIZE + header.payload_len as usize)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let (metadata, payload) = split_metadata(&header, body)?;
+        let start = TAGGED_HEADER_SIZE + metadata.len();
+        Ok(TaggedBytes {
+            bytes: bytes.slice(..tagged_len),
+            version_id: header.version_id,
+            payload: start..start + payload.len(),
+            _container: PhantomData,
+        })
+    }
+
+    /// As [new](Self::new), freezing `bytes` first.
+    pub fn from_bytes_mut(bytes: BytesMut) -> Result<Self, RkyvVersionedError> {
+        Self::new(bytes.freeze())
+    }
+}
+
+impl<T: VersionedContainer> TaggedBytes<T>
+where
+    T::Archived: rkyv::Portable,
+{
+    /// Accesses the archived container in place, without validating it again.
+    // The payload range was checked when the record was created, and must not fall back to
+    // other bytes since they are accessed unchecked
+    #[allow(clippy::indexing_slicing)]
+    pub fn get(&self) -> &T::Archived {
+        // SAFETY: The payload was validated when the record was created, and `Bytes` are
+        // immutable and keep their address
+        unsafe { rkyv::access_unchecked::<T::Archived>(&self.bytes[self.payload.clone()]) }
+    }
+}
+
+impl<T> TaggedBytes<T> {
+    /// The version ID of the container.
+    pub fn version_id(&self) -> u32 {
+        self.version_id
+    }
+
+    /// The tagged byte array, e.g. to forward it.
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// Returns the tagged byte array.
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+}
+
+impl<T> AsRef<[u8]> for TaggedBytes<T> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<T> From<TaggedBytes<T>> for Bytes {
+    fn from(record: TaggedBytes<T>) -> Self {
+        record.bytes
+    }
+}
+
+/// An `rkyv` writer appending to a [BytesMut], e.g. for
+/// [to_tagged_bytes_in](crate::to_tagged_bytes_in).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BytesMutWriter {
+    buf: BytesMut,
+}
+
+impl BytesMutWriter {
+    /// Creates a writer appending to `buf`.
+    pub fn new(buf: BytesMut) -> Self {
+        BytesMutWriter { buf }
+    }
+
+    /// Returns the buffer written to.
+    pub fn into_inner(self) -> BytesMut {
+        self.buf
+    }
+}
+
+impl Positional for BytesMutWriter {
+    fn pos(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl<E> Writer<E> for BytesMutWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl AsMut<[u8]> for BytesMutWriter {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_tagged_bytes, to_tagged_bytes_in, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    #[test]
+    fn test_tagged_bytes() {
+        let event = Event::V1("shared".to_string());
+        let bytes = to_shared_bytes(&event).unwrap();
+        assert_eq!(&bytes[..], to_tagged_bytes(&event).unwrap().as_slice());
+
+        // The record keeps the buffer alive after the original handle is dropped
+        let record = TaggedBytes::<Event>::new(bytes.clone()).unwrap();
+        let ptr = bytes.as_ptr();
+        drop(bytes);
+        assert_eq!(record.version_id(), 0);
+        assert_eq!(record.as_bytes().as_ptr(), ptr);
+        let cloned = record.clone();
+        match cloned.get() {
+            ArchivedEvent::V1(text) => assert_eq!(text, "shared"),
+            _ => panic!("Expected V1"),
+        }
+
+        // Records appended to a `BytesMut` are accessed too
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0xaa; 16]);
+        let mut buf = BytesMutWriter::new(buf);
+        buf = to_tagged_bytes_in(&Event::V2(5), buf).unwrap();
+        let mut buf = buf.into_inner();
+        let record = TaggedBytes::<Event>::from_bytes_mut(buf.split_off(16)).unwrap();
+        match record.get() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 5),
+            _ => panic!("Expected V2"),
+        }
+        assert_eq!(
+            Bytes::from(record).as_ref(),
+            to_tagged_bytes(&Event::V2(5)).unwrap().as_slice()
+        );
+
+        let mut corrupt = BytesMut::from(&to_tagged_bytes(&event).unwrap()[..]);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(TaggedBytes::<Event>::from_bytes_mut(corrupt).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Optional detached ed25519 signatures.
+//!
+//! Digests only protect against accidental corruption.  When authenticity matters, e.g. for
+//! configuration blobs distributed to many machines, [sign_tagged_bytes] appends a 64 byte
+//! ed25519 signature over the header and payload after the digest trailer, and sets a flag in
+//! the header so readers know to skip it.  Readers without the key can still access the
+//! payload as usual, while [access_from_signed_tagged_bytes] refuses it unless the signature
+//! verifies.
+//!
+//! Signing requires the `ed25519` feature.
+
+/// The length of an ed25519 signature trailer.
+pub(crate) const SIGNATURE_LEN: usize = 64;
+
+#[cfg(feature = "ed25519")]
+pub use self::ed25519::*;
+
+#[cfg(feature = "ed25519")]
+mod ed25519 {
+    use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+    use rkyv::util::AlignedVec;
+
+    use super::SIGNATURE_LEN;
+    use crate::{
+        access_from_tagged_bytes, read_header, write_header, RkyvVersionedError,
+        VersionedContainer, HEADER_FLAG_SIGNED, TAGGED_HEADER_SIZE,
+    };
+
+    /// Signs a tagged byte array generated by [to_tagged_bytes](crate::to_tagged_bytes) or
+    /// [to_tagged_bytes_with](crate::to_tagged_bytes_with), returning a copy with the
+    /// signature trailer appended.  An existing signature is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `tagged` - The tagged byte array to sign.
+    /// * `key` - The key to sign it with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the signed byte array or an error if `tagged` is invalid.
+    pub fn sign_tagged_bytes(
+        tagged: &[u8],
+        key: &SigningKey,
+    ) -> Result<AlignedVec, RkyvVersionedError> {
+        let mut header = read_header(tagged)?;
+        let body_end = header.tagged_len()?
+            - if header.flags & HEADER_FLAG_SIGNED != 0 {
+                SIGNATURE_LEN
+            } else {
+                0
+            };
+        let body = tagged
+            .get(TAGGED_HEADER_SIZE..body_end)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        header.flags |= HEADER_FLAG_SIGNED;
+
+        let mut bytes = AlignedVec::with_capacity(body_end + SIGNATURE_LEN);
+        bytes.resize(TAGGED_HEADER_SIZE, 0);
+        write_header(&mut bytes, &header)?;
+        bytes.extend_from_slice(body);
+
+        let signed_len = TAGGED_HEADER_SIZE + header.payload_len as usize;
+        let signature = key.sign(
+            bytes
+                .get(..signed_len)
+                .ok_or(RkyvVersionedError::BufferTooSmallError)?,
+        );
+        bytes.extend_from_slice(&signature.to_bytes());
+        Ok(bytes)
+    }
+
+    /// Verifies the signature trailer of a tagged byte array signed by [sign_tagged_bytes].
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the signature is valid, [RkyvVersionedError::MissingSignatureError] if the
+    /// byte array isn't signed, or [RkyvVersionedError::SignatureMismatchError] if it was
+    /// signed by another key or has been tampered with.
+    pub fn verify_signed_tagged_bytes(
+        buf: &[u8],
+        key: &VerifyingKey,
+    ) -> Result<(), RkyvVersionedError> {
+        let header = read_header(buf)?;
+        if header.flags & HEADER_FLAG_SIGNED == 0 {
+            return Err(RkyvVersionedError::MissingSignatureError);
+        }
+        let tagged_len = header.tagged_len()?;
+        let signature = tagged_len
+            .checked_sub(SIGNATURE_LEN)
+            .and_then(|start| buf.get(start..tagged_len))
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let signature = Signature::from_slice(signature)
+            .map_err(|_| RkyvVersionedError::SignatureMismatchError)?;
+
+        let signed_len = TAGGED_HEADER_SIZE + header.payload_len as usize;
+        let signed = buf
+            .get(..signed_len)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        key.verify_strict(signed, &signature)
+            .map_err(|_| RkyvVersionedError::SignatureMismatchError)
+    }
+
+    /// Zero-copy deserializes a versioned container from a tagged byte array signed by
+    /// [sign_tagged_bytes], only returning a reference once the signature has been verified.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the signed tagged data.
+    /// * `key` - The key the byte array is expected to be signed with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error.
+    pub fn access_from_signed_tagged_bytes<'a, T: VersionedContainer + 'a>(
+        buf: &'a [u8],
+        key: &VerifyingKey,
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable
+            + for<'b> rkyv::bytecheck::CheckBytes<
+                rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+            >,
+    {
+        verify_signed_tagged_bytes(buf, key)?;
+        access_from_tagged_bytes::<T>(buf)
+    }
+}
+
+#[cfg(all(test, feature = "ed25519"))]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, to_tagged_bytes, RkyvVersionedError,
+        VersionedArchiveContainer,
+    };
+    use ed25519_dalek::SigningKey;
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Config {
+        pub name: String,
+        pub replicas: u32,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum ConfigContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Config),
+    }
+
+    #[test]
+    fn test_signed_tagged_bytes() {
+        let config = Config {
+            name: "frontend".to_owned(),
+            replicas: 3,
+        };
+        let tagged = to_tagged_bytes(&ConfigContainer::V1(&config)).unwrap();
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let other_key = SigningKey::from_bytes(&[2; 32]);
+
+        assert!(matches!(
+            verify_signed_tagged_bytes(&tagged, &key.verifying_key()),
+            Err(RkyvVersionedError::MissingSignatureError)
+        ));
+
+        let signed = sign_tagged_bytes(&tagged, &key).unwrap();
+        assert_eq!(signed.len(), tagged.len() + SIGNATURE_LEN);
+        match access_from_signed_tagged_bytes::<ConfigContainer>(&signed, &key.verifying_key())
+            .unwrap()
+        {
+            ArchivedConfigContainer::V1(c) => {
+                assert_eq!(c.name, "frontend");
+                assert_eq!(c.replicas, 3);
+            }
+        }
+
+        // Readers that don't check signatures can still access the payload
+        access_from_tagged_bytes::<ConfigContainer>(&signed).unwrap();
+
+        assert!(matches!(
+            verify_signed_tagged_bytes(&signed, &other_key.verifying_key()),
+            Err(RkyvVersionedError::SignatureMismatchError)
+        ));
+
+        // Re-signing replaces the signature rather than stacking another one
+        let resigned = sign_tagged_bytes(&signed, &other_key).unwrap();
+        assert_eq!(resigned.len(), signed.len());
+        verify_signed_tagged_bytes(&resigned, &other_key.verifying_key()).unwrap();
+
+        // Tampering with the version in the header is detected
+        let mut tampered = signed.clone();
+        tampered[4] ^= 1;
+        assert!(matches!(
+            verify_signed_tagged_bytes(&tampered, &key.verifying_key()),
+            Err(RkyvVersionedError::SignatureMismatchError)
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+//! Deterministic in-memory IO with fault injection, enabled with the `sim` feature.
+//!
+//! Recovery logic, e.g. reopening a [LogWriter](crate::LogWriter) after a crash or
+//! resynchronizing a stream with [resync_framed_records](crate::resync_framed_records), is
+//! hard to test against real files and sockets.  The types here stand in for them and inject
+//! faults drawn from a seeded [Faults] source, so a failing test replays identically:
+//!
+//! - [SimFile]: An in-memory file implementing [DurableWrite], [Read] and [Seek], which can be
+//!   passed to [LogWriter](crate::LogWriter), [VersionedFileWriter](crate::VersionedFileWriter)
+//!   and [VersionedFile](crate::VersionedFile).  [SimFile::crash] loses the writes since the
+//!   last sync, leaving a torn prefix of them behind, and reads flip bits.
+//! - `SimStream`: An in-memory tokio stream, with the `tokio` feature, whose reads are short,
+//!   return `Pending` and flip bits.
+//! - [Faults::reorder_frames]: Swaps adjacent frames of a framed stream, as a transport
+//!   without ordering guarantees would.
+//!
+//! Every fault is disabled in the default [FaultConfig].
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rkyv::util::AlignedVec;
+
+use crate::{framed_records, DurableWrite, SplitMixRng};
+
+/// The faults injected by a [Faults] source.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultConfig {
+    /// The probability of each byte read back having one of its bits flipped.
+    pub bit_flip_rate: f64,
+    /// Whether [SimFile::crash] keeps a random prefix of the writes since the last sync,
+    /// rather than losing all of them.
+    pub torn_writes: bool,
+    /// The probability of each frame being swapped with the next one by
+    /// [Faults::reorder_frames].
+    pub reorder_rate: f64,
+    /// The maximum number of bytes returned by a read, or 0 for no limit.  Each read returns
+    /// a random number of bytes up to the limit.
+    pub max_read_len: usize,
+    /// The probability of an async read returning `Pending` before making progress.
+    pub pending_rate: f64,
+}
+
+/// A seeded source of faults, shared by the simulated IO types.
+#[derive(Debug)]
+pub struct Faults {
+    config: FaultConfig,
+    rng: SplitMixRng,
+}
+
+impl Faults {
+    /// Creates a source injecting the faults in `config`, drawn from a generator seeded with
+    /// `seed`.
+    pub const fn new(seed: u64, config: FaultConfig) -> Self {
+        Faults {
+            config,
+            rng: SplitMixRng::new(seed),
+        }
+    }
+
+    /// The faults injected.
+    pub fn config(&self) -> &FaultConfig {
+        &self.config
+    }
+
+    /// Flips one random bit of each byte of `buf` selected at the bit flip rate.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes corrupted.
+    pub fn flip_bits(&self, buf: &mut [u8]) -> usize {
+        let mut flipped = 0;
+        for byte in buf.iter_mut() {
+            if self.chance(self.config.bit_flip_rate) {
+                *byte ^= 1 << self.below(8);
+                flipped += 1;
+            }
+        }
+        flipped
+    }
+
+    /// Returns a copy of a stream written by [append_framed](crate::append_framed) with each
+    /// frame swapped with the next one at the reorder rate.  Anything following the first
+    /// corrupt frame is copied unchanged.
+    pub fn reorder_frames(&self, stream: &[u8]) -> AlignedVec {
+        let mut frames = Vec::new();
+        let mut records = framed_records(stream);
+        let mut start = 0;
+        while let Some(Ok(_)) = records.next() {
+            frames.push(stream.get(start..records.offset()).unwrap_or_default());
+            start = records.offset();
+        }
+
+        let mut i = 0;
+        while i + 1 < frames.len() {
+            if self.chance(self.config.reorder_rate) {
+                frames.swap(i, i + 1);
+                i += 1;
+            }
+            i += 1;
+        }
+
+        let mut reordered = AlignedVec::with_capacity(stream.len());
+        for frame in frames {
+            reordered.extend_from_slice(frame);
+        }
+        reordered.extend_from_slice(stream.get(start..).unwrap_or_default());
+        reordered
+    }
+
+    /// The number of bytes to return from a read into a buffer of `len` bytes.
+    fn read_len(&self, len: usize) -> usize {
+        match self.config.max_read_len {
+            0 => len,
+            _ if len == 0 => 0,
+            max => 1 + self.below(len.min(max)),
+        }
+    }
+
+    fn chance(&self, probability: f64) -> bool {
+        probability > 0.0
+            && ((self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    /// A random number below `n`, which must not be 0.
+    fn below(&self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileState {
+    data: Vec<u8>,
+    synced_len: usize,
+}
+
+/// An in-memory file, see the [module](self) documentation.
+///
+/// Clones share the same contents, each with its own position, so a test can keep a handle
+/// to crash or inspect a file while a writer owns another.
+#[derive(Debug, Clone)]
+pub struct SimFile {
+    state: Arc<Mutex<FileState>>,
+    faults: Arc<Faults>,
+    position: u64,
+}
+
+impl SimFile {
+    /// Creates an empty file injecting `faults`.
+    pub fn new(faults: Arc<Faults>) -> Self {
+        SimFile {
+            state: Arc::default(),
+            faults,
+            position: 0,
+        }
+    }
+
+    /// A copy of the current contents of the file, without faults.
+    pub fn contents(&self) -> Vec<u8> {
+        self.state().data.clone()
+    }
+
+    /// The length of the contents which have been synced.
+    pub fn synced_len(&self) -> usize {
+        self.state().synced_len
+    }
+
+    /// Simulates a crash: the contents past the last sync are lost, except for a random
+    /// prefix of them if torn writes are enabled.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes lost.
+    pub fn crash(&self) -> usize {
+        let mut state = self.state();
+        let unsynced = state.data.len().saturating_sub(state.synced_len);
+        let kept = match unsynced {
+            0 => 0,
+            _ if self.faults.config.torn_writes => self.faults.below(unsynced),
+            _ => 0,
+        };
+        let len = state.synced_len + kept;
+        state.data.truncate(len);
+        state.synced_len = len;
+        unsynced - kept
+    }
+
+    /// Returns a handle to the same file positioned at its start, as if it were reopened.
+    pub fn reopen(&self) -> SimFile {
+        SimFile {
+            position: 0,
+            ..self.clone()
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, FileState> {
+        // A panic while holding the lock can't leave the contents inconsistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for SimFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let position = self.position as usize;
+        let mut state = self.state();
+        if state.data.len() < position {
+            state.data.resize(position, 0);
+        }
+        let overlap = buf.len().min(state.data.len() - position);
+        let (overwritten, appended) = buf.split_at_checked(overlap).unwrap_or((buf, &[]));
+        if let Some(existing) = state.data.get_mut(position..position + overlap) {
+            existing.copy_from_slice(overwritten);
+        }
+        state.data.extend_from_slice(appended);
+        // Overwritten contents are no longer synced
+        state.synced_len = state.synced_len.min(position);
+        drop(state);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl DurableWrite for SimFile {
+    fn sync(&mut self) -> io::Result<()> {
+        let mut state = self.state();
+        state.synced_len = state.data.len();
+        Ok(())
+    }
+}
+
+impl Read for SimFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = self.state();
+        let remaining = state.data.get(self.position as usize..).unwrap_or_default();
+        let len = self.faults.read_len(buf.len().min(remaining.len()));
+        let read = buf.get_mut(..len).unwrap_or_default();
+        read.copy_from_slice(remaining.get(..len).unwrap_or_default());
+        drop(state);
+        self.faults.flip_bits(read);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for SimFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => {
+                (self.state().data.len() as u64).checked_add_signed(offset)
+            }
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file")
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use stream::SimStream;
+
+#[cfg(feature = "tokio")]
+mod stream {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::Faults;
+
+    /// An in-memory tokio stream, see the [module](super) documentation.
+    ///
+    /// The stream is a loopback: clones share the same buffer, so bytes written through one
+    /// clone are read through another.  Reads return the end of the stream once everything
+    /// written so far has been read.
+    #[derive(Debug, Clone)]
+    pub struct SimStream {
+        buf: Arc<Mutex<VecDeque<u8>>>,
+        faults: Arc<Faults>,
+    }
+
+    impl SimStream {
+        /// Creates an empty stream injecting `faults`.
+        pub fn new(faults: Arc<Faults>) -> Self {
+            SimStream {
+                buf: Arc::default(),
+                faults,
+            }
+        }
+
+        /// The number of bytes written but not yet read.
+        pub fn pending_len(&self) -> usize {
+            self.buf.lock().unwrap_or_else(|e| e.into_inner()).len()
+        }
+    }
+
+    impl AsyncRead for SimStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            out: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if self.faults.chance(self.faults.config.pending_rate) {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let mut buf = self.buf.lock().unwrap_or_else(|e| e.into_inner());
+            let len = self.faults.read_len(out.remaining().min(buf.len()));
+            let mut chunk: Vec<u8> = buf.drain(..len).collect();
+            drop(buf);
+            self.faults.flip_bits(&mut chunk);
+            out.put_slice(&chunk);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for SimStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            data: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buf = self.buf.lock().unwrap_or_else(|e| e.into_inner());
+            buf.extend(data);
+            Poll::Ready(Ok(data.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        append_framed, resync_framed_records, to_tagged_bytes, LogWriter, ResyncItem,
+        SyncPolicy, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Entry {
+        V1(u64),
+        V2(String),
+    }
+
+    fn faults(config: FaultConfig) -> Arc<Faults> {
+        Arc::new(Faults::new(7, config))
+    }
+
+    #[test]
+    fn test_sim() {
+        // A crash loses the unsynced entry, leaving a torn frame behind
+        let file = SimFile::new(faults(FaultConfig {
+            torn_writes: true,
+            max_read_len: 5,
+            ..Default::default()
+        }));
+        let mut log = LogWriter::new(file.clone(), SyncPolicy::EveryEntries(2));
+        for i in 0..5 {
+            log.append(&Entry::V2(format!("entry {}", i))).unwrap();
+        }
+        assert!(file.crash() > 0);
+        drop(log);
+        let mut contents = Vec::new();
+        file.reopen().read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, file.contents());
+        assert_eq!(contents.len(), file.synced_len());
+        let intact = framed_records(&contents).filter(Result::is_ok).count();
+        assert_eq!(intact, 4);
+
+        // Bit flips are caught by the digests and skipped over
+        let mut stream = AlignedVec::new();
+        for i in 0..8 {
+            append_framed(&mut stream, &to_tagged_bytes(&Entry::V1(i)).unwrap()).unwrap();
+        }
+        let noisy = faults(FaultConfig {
+            bit_flip_rate: 0.01,
+            ..Default::default()
+        });
+        let mut corrupt = stream.clone();
+        assert!(noisy.flip_bits(&mut corrupt) > 0);
+        let items: Vec<_> = resync_framed_records::<Entry>(&corrupt).collect();
+        assert!(items
+            .iter()
+            .any(|item| matches!(item, ResyncItem::Skipped(_))));
+
+        // Reordering keeps every frame intact
+        let shuffled = faults(FaultConfig {
+            reorder_rate: 0.5,
+            ..Default::default()
+        })
+        .reorder_frames(&stream);
+        let ids: Vec<u64> = framed_records(&shuffled)
+            .map(|record| {
+                match crate::access_from_tagged_bytes::<Entry>(record.unwrap()).unwrap() {
+                    ArchivedEntry::V1(id) => id.to_native(),
+                    ArchivedEntry::V2(_) => panic!("Expected V1"),
+                }
+            })
+            .collect();
+        assert_ne!(ids, (0..8).collect::<Vec<_>>());
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+
+        // The same seed injects the same faults
+        let replay = faults(FaultConfig {
+            reorder_rate: 0.5,
+            ..Default::default()
+        })
+        .reorder_frames(&stream);
+        assert_eq!(shuffled.as_slice(), replay.as_slice());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_sim_stream() {
+        use crate::{write_tagged_async, AsyncTaggedReader};
+
+        let stream = SimStream::new(faults(FaultConfig {
+            max_read_len: 3,
+            pending_rate: 0.3,
+            ..Default::default()
+        }));
+        let mut writer = stream.clone();
+        for i in 0..3 {
+            write_tagged_async(&Entry::V1(i), &mut writer)
+                .await
+                .unwrap();
+        }
+
+        let mut reader = stream.clone();
+        let mut reader = AsyncTaggedReader::new(&mut reader);
+        let mut count = 0;
+        while let Some(record) = reader.read_tagged::<Entry>().await.unwrap() {
+            assert!(crate::access_from_tagged_bytes::<Entry>(&record).is_ok());
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(stream.pending_len(), 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Computing the size of tagged byte arrays before writing them.
+//!
+//! [serialized_tagged_size] runs the `rkyv` serializer over a container into a [SizeCounter],
+//! which discards the bytes and only counts them, and adds the header, metadata and trailers
+//! the [TaggedOptions] call for.  Callers can then reserve a buffer or shared-memory slot of
+//! the right size, or reject a record which won't fit in a datagram, before serializing it
+//! for real.
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::{Arena, ArenaHandle};
+use rkyv::ser::{Positional, Writer};
+use rkyv::Serialize;
+
+use crate::{
+    fingerprinted_options, rkyv_error, EncryptionAlgorithm, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, TAGGED_HEADER_SIZE,
+};
+
+/// An `rkyv` writer which discards the bytes written to it, only counting them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SizeCounter {
+    len: usize,
+}
+
+impl SizeCounter {
+    /// Creates a counter at position 0.
+    pub fn new() -> Self {
+        SizeCounter::default()
+    }
+
+    /// The number of bytes written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Positional for SizeCounter {
+    fn pos(&self) -> usize {
+        self.len
+    }
+}
+
+impl<E> Writer<E> for SizeCounter {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+/// Computes the length of the tagged byte array [to_tagged_bytes](crate::to_tagged_bytes)
+/// would produce for `item`, without allocating an output buffer.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be measured.
+///
+/// # Returns
+///
+/// A `Result` containing either the length, or an error if serialization fails.
+pub fn serialized_tagged_size<T>(item: &T) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<SizeCounter, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    serialized_tagged_size_with(item, &TaggedOptions::default())
+}
+
+/// As [serialized_tagged_size], for the tagged byte array
+/// [to_tagged_bytes_with](crate::to_tagged_bytes_with) would produce with `options`.
+///
+/// The length is exact unless `options` sets a
+/// [compression](TaggedOptions::compression) codec, in which case it is an upper bound: the
+/// length if the payload didn't compress at all.
+///
+/// # Returns
+///
+/// A `Result` containing either the length, or an error if serialization fails or a codec,
+/// digest or encryption algorithm set in `options` isn't supported.
+pub fn serialized_tagged_size_with<T>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<SizeCounter, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    // Fingerprinted versions get an extension the caller didn't set
+    let fingerprinted;
+    let options = match fingerprinted_options::<T>(item.get_entry_version_id(), options)? {
+        Some(options) => {
+            fingerprinted = options;
+            &fingerprinted
+        }
+        None => options,
+    };
+
+    let mut arena = Arena::new();
+    let payload_len = rkyv::api::high::to_bytes_in_with_alloc::<_, _, rkyv::rancor::Error>(
+        item,
+        SizeCounter::new(),
+        arena.acquire(),
+    )
+    .map_err(rkyv_error)?
+    .len();
+
+    let mut stored_len = options.compression.max_stored_len(payload_len)?;
+    if options.encryption.is_some() {
+        let algorithm = EncryptionAlgorithm::XChaCha20Poly1305;
+        if !algorithm.is_available() {
+            return Err(RkyvVersionedError::UnsupportedEncryptionError(
+                algorithm.id(),
+            ));
+        }
+        stored_len += algorithm.overhead();
+    }
+    if !options.digest.is_available() {
+        return Err(RkyvVersionedError::UnsupportedDigestError(
+            options.digest.id(),
+        ));
+    }
+
+    Ok(TAGGED_HEADER_SIZE
+        + options.header_words().encoded_len()
+        + options.extensions.encoded_len()
+        + stored_len
+        + options.digest.digest_len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        to_tagged_bytes, to_tagged_bytes_with, CompressionCodec, DigestAlgorithm,
+        HeaderExtensions, VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Telemetry {
+        V1(u32),
+        V2((String, Vec<u64>)),
+    }
+
+    #[test]
+    fn test_serialized_tagged_size() {
+        let samples = [
+            Telemetry::V1(7),
+            Telemetry::V2(("sensor-12".to_string(), (0..100).collect())),
+        ];
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(3, b"host-a").unwrap();
+        let options = TaggedOptions {
+            digest: DigestAlgorithm::None,
+            extensions,
+            user_word: Some(1),
+            sequence: Some(2),
+            ..Default::default()
+        };
+        for sample in &samples {
+            assert_eq!(
+                serialized_tagged_size(sample).unwrap(),
+                to_tagged_bytes(sample).unwrap().len()
+            );
+            assert_eq!(
+                serialized_tagged_size_with(sample, &options).unwrap(),
+                to_tagged_bytes_with(sample, &options).unwrap().len()
+            );
+        }
+
+        if CompressionCodec::Lz4.is_available() {
+            let options = TaggedOptions {
+                compression: CompressionCodec::Lz4,
+                ..Default::default()
+            };
+            let bound = serialized_tagged_size_with(&samples[1], &options).unwrap();
+            assert!(bound >= to_tagged_bytes_with(&samples[1], &options).unwrap().len());
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Crash-safe single-record state files.
+//!
+//! [write_snapshot_atomic] replaces a file holding one tagged byte array, e.g. a service
+//! checkpoint, so that after a crash the file holds either the previous snapshot or the new
+//! one, never a torn mix of both.  The record is written to a temporary file in the same
+//! directory, synced, and renamed over the destination, after which the directory is synced
+//! so that the rename itself is durable.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::io::io_error;
+use crate::{
+    check_header, to_tagged_bytes_with, RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// Atomically replaces the file at `path` with `item` serialized as a tagged byte array, using
+/// the default [TaggedOptions].
+///
+/// # Returns
+///
+/// A `Result` which is an error if serialization fails, or the temporary file can't be
+/// written, synced or renamed.  The destination is left untouched in that case.
+pub fn write_snapshot_atomic<T>(
+    path: impl AsRef<Path>,
+    item: &T,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    write_snapshot_atomic_with(path, item, &TaggedOptions::default())
+}
+
+/// As [write_snapshot_atomic], tagging `item` with `options`.
+pub fn write_snapshot_atomic_with<T>(
+    path: impl AsRef<Path>,
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let path = path.as_ref();
+    let record = to_tagged_bytes_with(item, options)?;
+    let temp = temp_path(path)?;
+
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(&record)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(io_error(e));
+    }
+    sync_parent(path)
+}
+
+/// Reads a snapshot written by [write_snapshot_atomic] into an [AlignedVec], so that it can be
+/// accessed in place with e.g. [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+///
+/// # Returns
+///
+/// A `Result` containing the tagged byte array, or an error if the file can't be read or its
+/// header doesn't hold a supported version of `T`.
+pub fn read_snapshot<T: VersionedContainer>(
+    path: impl AsRef<Path>,
+) -> Result<AlignedVec, RkyvVersionedError> {
+    let contents = fs::read(path).map_err(io_error)?;
+    check_header::<T>(&contents)?;
+    let mut record = AlignedVec::with_capacity(contents.len());
+    record.extend_from_slice(&contents);
+    Ok(record)
+}
+
+/// The temporary file a snapshot of `path` is written to before being renamed, in the same
+/// directory so that the rename doesn't cross filesystems.
+fn temp_path(path: &Path) -> Result<PathBuf, RkyvVersionedError> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io_error(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "snapshot path has no file name",
+        ))
+    })?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    Ok(path.with_file_name(temp_name))
+}
+
+/// Syncs the directory holding `path`, making a rename into it durable.  Directories can't be
+/// opened for syncing on every platform, in which case this is a no-op.
+fn sync_parent(path: &Path) -> Result<(), RkyvVersionedError> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(io_error)?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Checkpoint {
+        V1(u64),
+        V2(String),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u64),
+    }
+
+    #[test]
+    fn test_write_snapshot_atomic() {
+        let dir = std::env::temp_dir()
+            .join(format!("rkyv_versioned_snapshot_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint");
+
+        write_snapshot_atomic(&path, &Checkpoint::V1(10)).unwrap();
+        write_snapshot_atomic(&path, &Checkpoint::V2("offset-20".to_string())).unwrap();
+
+        let record = read_snapshot::<Checkpoint>(&path).unwrap();
+        match access_from_tagged_bytes::<Checkpoint>(&record).unwrap() {
+            ArchivedCheckpoint::V2(position) => assert_eq!(position, "offset-20"),
+            _ => panic!("Expected V2"),
+        }
+        assert!(matches!(
+            read_snapshot::<Other>(&path),
+            Err(RkyvVersionedError::UnexpectedTypeError(_, _))
+        ));
+
+        // Only the snapshot is left behind
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        // Snapshots which can't be written are reported
+        assert!(write_snapshot_atomic(
+            dir.join("missing").join("checkpoint"),
+            &Checkpoint::V1(1)
+        )
+        .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
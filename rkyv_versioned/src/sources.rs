@@ -0,0 +1,177 @@
+//! Injectable sources of time and randomness.
+//!
+//! Wherever the crate needs the current time (stamping timestamps with
+//! [TaggedOptions::clock](crate::TaggedOptions::clock), checking sunset dates with
+//! [ValidationConfig::clock](crate::ValidationConfig::clock)) or random bytes (encryption
+//! nonces, with [TaggedOptions::rng](crate::TaggedOptions::rng)), it asks a [Clock] or [Rng]
+//! if one is provided, and falls back to the system otherwise.  Tests can provide a
+//! [ManualClock] or [SplitMixRng] to make their output deterministic, and targets without a
+//! system clock or entropy source can provide their own.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of wall-clock time.
+pub trait Clock: fmt::Debug + Sync {
+    /// The current time, in milliseconds since the Unix epoch.
+    fn now_millis(&self) -> u64;
+}
+
+/// A source of random bytes.
+///
+/// Implementations used for encryption nonces must be cryptographically secure, a nonce
+/// reused with the same key breaks the confidentiality of both payloads.
+pub trait Rng: fmt::Debug + Sync {
+    /// Fills `dest` with random bytes.
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+/// The system clock, used when no [Clock] is provided.  Requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+/// A clock which only moves when told to, for tests.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    millis: AtomicU64,
+}
+
+impl ManualClock {
+    /// Creates a clock reading `millis` milliseconds since the Unix epoch.
+    pub const fn new(millis: u64) -> Self {
+        ManualClock {
+            millis: AtomicU64::new(millis),
+        }
+    }
+
+    /// Sets the time, in milliseconds since the Unix epoch.
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::Relaxed);
+    }
+
+    /// Moves the time forward by `millis` milliseconds.
+    pub fn advance(&self, millis: u64) {
+        self.millis.fetch_add(millis, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.millis.load(Ordering::Relaxed)
+    }
+}
+
+/// The operating system's entropy source, used when no [Rng] is provided.  Requires the
+/// `encryption` feature.
+#[cfg(feature = "encryption")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRng;
+
+#[cfg(feature = "encryption")]
+impl Rng for SystemRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        use chacha20poly1305::aead::rand_core::RngCore;
+        chacha20poly1305::aead::OsRng.fill_bytes(dest)
+    }
+}
+
+/// A deterministic generator seeded with a fixed value, for tests.
+///
+/// This is NOT cryptographically secure: payloads encrypted with nonces from it are only as
+/// confidential as the seed is secret, and two generators with the same seed reuse nonces.
+#[derive(Debug)]
+pub struct SplitMixRng {
+    state: AtomicU64,
+}
+
+impl SplitMixRng {
+    /// Creates a generator from `seed`.
+    pub const fn new(seed: u64) -> Self {
+        SplitMixRng {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Rng for SplitMixRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            for (dest, byte) in chunk.iter_mut().zip(bytes) {
+                *dest = byte;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        get_timestamp_from_tagged_bytes, to_tagged_bytes_with, TaggedOptions,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+    }
+
+    static CLOCK: ManualClock = ManualClock::new(1_700_000_000_000);
+
+    #[test]
+    fn test_sources() {
+        let options = TaggedOptions {
+            clock: Some(&CLOCK),
+            ..Default::default()
+        };
+        let tagged = to_tagged_bytes_with(&Event::V1(1), &options).unwrap();
+        assert_eq!(
+            get_timestamp_from_tagged_bytes(&tagged).unwrap(),
+            Some(1_700_000_000_000)
+        );
+
+        // An explicit timestamp takes precedence over the clock
+        CLOCK.advance(5);
+        let options = TaggedOptions {
+            timestamp: Some(7),
+            ..options
+        };
+        let tagged = to_tagged_bytes_with(&Event::V1(1), &options).unwrap();
+        assert_eq!(get_timestamp_from_tagged_bytes(&tagged).unwrap(), Some(7));
+        assert_eq!(CLOCK.now_millis(), 1_700_000_000_005);
+        #[cfg(feature = "std")]
+        assert!(SystemClock.now_millis() > 1_700_000_000_000);
+
+        let (a, b) = (SplitMixRng::new(42), SplitMixRng::new(42));
+        let (mut x, mut y) = ([0u8; 24], [0u8; 24]);
+        a.fill_bytes(&mut x);
+        b.fill_bytes(&mut y);
+        assert_eq!(x, y);
+        a.fill_bytes(&mut x);
+        assert_ne!(x, y);
+    }
+}
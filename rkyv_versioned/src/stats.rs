@@ -0,0 +1,263 @@
+//! Summarizing what a stream of framed records holds, without knowing its container types.
+//!
+//! [stream_stats] walks a stream written by e.g. [append_framed](crate::append_framed) or a
+//! [LogWriter](crate::LogWriter) and counts its records, bytes and digest failures by type
+//! and version ID in a [StreamStats], along with how well compressed payloads compress.
+//! Unlike [validate_stream](crate::validate_stream) it needs no container type, so operators
+//! can assess any store, e.g. with the `stats` subcommand of the `rkyv_versioned` binary.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rkyv::util::AlignedVec;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::io::io_error;
+use crate::{
+    compression_codec, get_type_name_from_tagged_bytes, read_header, split_metadata,
+    verify_payload, CompressionCodec, EncryptionAlgorithm, RkyvVersionedError,
+    FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE,
+};
+
+/// What the records of one type and version ID in a stream add up to, see [StreamStats].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VersionStats {
+    /// The number of records.
+    pub records: usize,
+    /// The length of their tagged byte arrays.
+    pub bytes: u64,
+    /// The length of their payloads as stored, i.e. after compression and encryption.
+    pub stored_payload_bytes: u64,
+    /// The length of the stored payloads which were compressed.
+    pub compressed_bytes: u64,
+    /// The length of the compressed payloads once decompressed.  Payloads which are encrypted,
+    /// or compressed with a codec which isn't enabled, can't be measured and aren't counted in
+    /// either.
+    pub decompressed_bytes: u64,
+    /// The number of records whose payload doesn't match its digest, or whose digest
+    /// algorithm isn't enabled.
+    pub checksum_failures: usize,
+}
+
+impl VersionStats {
+    /// The ratio of the decompressed to the compressed length of the compressed payloads, or
+    /// `None` if none could be measured.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.compressed_bytes {
+            0 => None,
+            compressed => Some(self.decompressed_bytes as f64 / compressed as f64),
+        }
+    }
+}
+
+/// A summary of a stream of framed records by type and version ID, see [stream_stats].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StreamStats {
+    /// The records with an intact frame and header, by type ID and version ID.
+    pub versions: BTreeMap<(u32, u32), VersionStats>,
+    /// The names of the types whose records embed them, see
+    /// [to_tagged_bytes_named](crate::to_tagged_bytes_named), by type ID.
+    pub type_names: BTreeMap<u32, String>,
+    /// The offset of a frame cut short by the end of the stream, usually a torn final write.
+    pub truncated_at: Option<usize>,
+    /// The offset of a frame whose prefix or header is corrupt, after which the rest of the
+    /// stream couldn't be walked.
+    pub corrupt_at: Option<usize>,
+    /// The number of bytes walked, i.e. the length of the stream up to any truncated or
+    /// corrupt frame.
+    pub valid_len: usize,
+    /// The length of the stream.
+    pub len: usize,
+}
+
+impl StreamStats {
+    /// The number of records with an intact frame and header.
+    pub fn records(&self) -> usize {
+        self.versions.values().map(|stats| stats.records).sum()
+    }
+
+    /// The number of records whose payload doesn't match its digest.
+    pub fn checksum_failures(&self) -> usize {
+        self.versions
+            .values()
+            .map(|stats| stats.checksum_failures)
+            .sum()
+    }
+
+    /// Returns whether every record matches its digest and the stream ends on a frame
+    /// boundary.
+    pub fn is_clean(&self) -> bool {
+        self.checksum_failures() == 0
+            && self.truncated_at.is_none()
+            && self.corrupt_at.is_none()
+    }
+}
+
+/// Walks a stream of framed records of any types and counts what it holds.
+///
+/// Each record's header is read and its payload verified against its digest, and compressed
+/// payloads are decompressed to measure them, but no payload is accessed.
+///
+/// # Arguments
+///
+/// * `buf` - The stream, e.g. the contents of a log file.
+///
+/// # Returns
+///
+/// The summary.  Problems with the stream are recorded in it rather than returned as errors.
+pub fn stream_stats(buf: &[u8]) -> StreamStats {
+    let mut stats = StreamStats {
+        len: buf.len(),
+        ..Default::default()
+    };
+    let mut scratch = AlignedVec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let frame = buf.get(offset..).unwrap_or_default();
+        let Some((prefix, rest)) = frame.split_first_chunk::<FRAME_PREFIX_SIZE>() else {
+            stats.truncated_at = Some(offset);
+            break;
+        };
+        let Some(record_len) = parse_frame_prefix(prefix) else {
+            stats.corrupt_at = Some(offset);
+            break;
+        };
+        let Ok(header) = read_header(rest) else {
+            stats.truncated_at = Some(offset);
+            break;
+        };
+        if header.tagged_len().ok() != Some(record_len) {
+            stats.corrupt_at = Some(offset);
+            break;
+        }
+        let Some(record) = rest.get(..record_len) else {
+            stats.truncated_at = Some(offset);
+            break;
+        };
+
+        let version = stats
+            .versions
+            .entry((header.type_id, header.version_id))
+            .or_default();
+        version.records += 1;
+        version.bytes += record_len as u64;
+        match verify_payload(record, &header) {
+            Ok(body) => {
+                let stored = split_metadata(&header, body).map_or(body, |(_, stored)| stored);
+                version.stored_payload_bytes += stored.len() as u64;
+                let encrypted = header.encryption != EncryptionAlgorithm::None.id();
+                match compression_codec(&header) {
+                    Ok(codec) if codec != CompressionCodec::None && !encrypted => {
+                        scratch.clear();
+                        if codec.decompress(stored, &mut scratch).is_ok() {
+                            version.compressed_bytes += stored.len() as u64;
+                            version.decompressed_bytes += scratch.len() as u64;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Err(_) => {
+                version.checksum_failures += 1;
+                version.stored_payload_bytes +=
+                    (header.payload_len as usize).min(record_len - TAGGED_HEADER_SIZE) as u64;
+            }
+        }
+        if let Entry::Vacant(entry) = stats.type_names.entry(header.type_id) {
+            if let Ok(Some(name)) = get_type_name_from_tagged_bytes(record) {
+                entry.insert(name.type_name);
+            }
+        }
+
+        let frame_len = FRAME_PREFIX_SIZE + record_len + frame_padding(record_len);
+        offset += frame_len.min(frame.len());
+    }
+    stats.valid_len = offset;
+    stats
+}
+
+/// As [stream_stats], reading the stream from the file at `path`.
+///
+/// # Returns
+///
+/// A `Result` containing the summary, or an error if the file can't be read.
+pub fn stream_stats_file(path: impl AsRef<Path>) -> Result<StreamStats, RkyvVersionedError> {
+    let contents = std::fs::read(path).map_err(io_error)?;
+    Ok(stream_stats(&contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        append_framed, to_tagged_bytes, to_tagged_bytes_named, to_tagged_bytes_with,
+        TaggedOptions, VersionedArchiveContainer, VersionedContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Metric {
+        V1(u64),
+        V2((u64, String)),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Other {
+        V1(u32),
+    }
+
+    #[test]
+    fn test_stream_stats() {
+        let mut stream = AlignedVec::<16>::new();
+        for value in 0..3 {
+            append_framed(&mut stream, &to_tagged_bytes(&Metric::V1(value)).unwrap()).unwrap();
+        }
+        let named = to_tagged_bytes_named(
+            &Metric::V2((1, "cpu".to_string())),
+            &TaggedOptions::default(),
+        )
+        .unwrap();
+        append_framed(&mut stream, &named).unwrap();
+        let other = to_tagged_bytes(&Other::V1(7)).unwrap();
+        append_framed(&mut stream, &other).unwrap();
+        let last = stream.len() - 1 - frame_padding(other.len());
+        stream[last] ^= 1;
+
+        let stats = stream_stats(&stream);
+        assert_eq!(stats.records(), 5);
+        assert_eq!(stats.versions[&(Metric::ARCHIVE_TYPE_ID, 0)].records, 3);
+        assert_eq!(
+            stats.versions[&(Metric::ARCHIVE_TYPE_ID, 1)].bytes,
+            named.len() as u64
+        );
+        assert_eq!(
+            stats.versions[&(Other::ARCHIVE_TYPE_ID, 0)].checksum_failures,
+            1
+        );
+        assert_eq!(stats.type_names[&Metric::ARCHIVE_TYPE_ID], "Metric");
+        assert!(!stats.type_names.contains_key(&Other::ARCHIVE_TYPE_ID));
+        assert_eq!(stats.valid_len, stream.len());
+        assert!(!stats.is_clean());
+
+        // Compressed payloads are measured when their codec is enabled
+        let options = TaggedOptions {
+            compression: CompressionCodec::Lz4,
+            ..Default::default()
+        };
+        if let Ok(compressed) =
+            to_tagged_bytes_with(&Metric::V2((2, "x".repeat(1000))), &options)
+        {
+            let mut stream = AlignedVec::<16>::new();
+            append_framed(&mut stream, &compressed).unwrap();
+            let stats = stream_stats(&stream[..stream.len() - 20]);
+            assert_eq!(stats.truncated_at, Some(0));
+            let stats = stream_stats(&stream);
+            assert!(stats.is_clean());
+            let ratio = stats.versions[&(Metric::ARCHIVE_TYPE_ID, 1)]
+                .compression_ratio()
+                .unwrap();
+            assert!(ratio > 5.0);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Conformance test vectors for readers written in other languages.
+//!
+//! [write_test_vectors] tags a sample of each version of a container with each of a set of
+//! named [TaggedOptions] and writes every result to a directory as a pair of files:
+//!
+//! - `{version}-{options}.bin`: The tagged byte array.
+//! - `{version}-{options}.json`: What a conforming reader should find in it: the decoded
+//!   header, the header words and extensions, the offset and bytes of the stored payload and
+//!   its digest, and the `Debug` output of the sample for reference.
+//!
+//! An `index.json` lists the vectors, and a `README.md` documents the layout, so the
+//! directory can be checked in next to a reader in another language and regenerated
+//! whenever the format changes.  Options which encrypt or timestamp records should set an
+//! [Rng](crate::Rng) and [Clock](crate::Clock) (or a fixed timestamp) so that the vectors
+//! are reproducible.
+
+use core::fmt::{self, Debug, Write as _};
+use std::fs;
+use std::path::Path;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::io::io_error;
+use crate::{
+    digest_algorithm, get_extensions_from_tagged_bytes, get_sequence_from_tagged_bytes,
+    get_timestamp_from_tagged_bytes, get_user_word_from_tagged_bytes, read_header,
+    split_metadata, to_tagged_bytes_with, verify_payload, RkyvVersionedError, TaggedOptions,
+    VersionedContainer, TAGGED_HEADER_SIZE,
+};
+
+/// The documentation written next to the vectors.
+const VECTORS_README: &str = "\
+# Test vectors
+
+Each `.bin` file holds one tagged byte array, described by the `.json` file of the same name.
+
+A tagged byte array is laid out as `[header][body][digest][signature]`:
+
+- The 16 byte header holds, little-endian, the `type_id` (u32), `version_id` (u32),
+  `payload_len` (u32, the length of the body), the `digest` id (one byte), the
+  `compression` and `encryption` ids (the low and high nibble of one byte), the format
+  version (one byte, 1) and the `flags` (one byte).
+- The body holds, in order and only when flagged, the header words (user word, timestamp and
+  sequence number, u64 each, padded to 16 bytes), the extension area, and the stored payload,
+  an `rkyv` archive of the container in the layout given by `rkyv_layout` (possibly
+  compressed or encrypted).
+- The digest of the body follows, then a 64 byte ed25519 signature if flag `0x01` is set.
+
+`payload_offset` is the offset of the stored payload in the whole byte array, and
+`payload_hex` and `digest_hex` hold the expected bytes.
+";
+
+/// Writes conformance test vectors for every sample in `samples` tagged with each of
+/// `options` into `dir`, see the [module](self) documentation.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to write to, created if necessary.  Existing vectors with the same
+///   names are overwritten.
+/// * `samples` - A sample of each version to cover.
+/// * `options` - The options to tag each sample with, each with a name used in file names,
+///   e.g. `("default", TaggedOptions::default())`.
+///
+/// # Returns
+///
+/// A `Result` containing the number of vectors written, or an error if a sample can't be
+/// tagged or a file can't be written.
+pub fn write_test_vectors<T>(
+    dir: impl AsRef<Path>,
+    samples: &[T],
+    options: &[(&str, TaggedOptions)],
+) -> Result<usize, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + Debug
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).map_err(io_error)?;
+
+    let mut names = Vec::new();
+    for sample in samples {
+        let version_id = sample.get_entry_version_id();
+        let version = match T::version_info(version_id) {
+            Some(info) => info.name.to_string(),
+            None => format!("v{}", version_id),
+        };
+        for (options_name, options) in options {
+            let name = format!("{}-{}", version, options_name);
+            let bytes = to_tagged_bytes_with(sample, options)?;
+            let description = describe(&name, &bytes, sample)?;
+            fs::write(dir.join(format!("{}.bin", name)), &bytes).map_err(io_error)?;
+            fs::write(dir.join(format!("{}.json", name)), description).map_err(io_error)?;
+            names.push(name);
+        }
+    }
+
+    let mut index = format!(
+        "{{\n  \"type_id\": {},\n  \"vectors\": [",
+        T::ARCHIVE_TYPE_ID
+    );
+    for (i, name) in names.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let _ = write!(index, "{}\n    {}", separator, JsonStr(name));
+    }
+    index.push_str("\n  ]\n}\n");
+    fs::write(dir.join("index.json"), index).map_err(io_error)?;
+    fs::write(dir.join("README.md"), VECTORS_README).map_err(io_error)?;
+    Ok(names.len())
+}
+
+/// Describes the tagged byte array `bytes` holding `sample` as JSON.
+fn describe(
+    name: &str,
+    bytes: &[u8],
+    sample: &impl Debug,
+) -> Result<String, RkyvVersionedError> {
+    let header = read_header(bytes)?;
+    let body = verify_payload(bytes, &header)?;
+    let (metadata, payload) = split_metadata(&header, body)?;
+    let digest_start = TAGGED_HEADER_SIZE + body.len();
+    let digest = bytes
+        .get(digest_start..digest_start + digest_algorithm(&header)?.digest_len())
+        .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+    let optional = |value: Option<u64>| value.map_or("null".to_string(), |v| v.to_string());
+
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        "{{\n  \"name\": {},\n  \"description\": {},\n  \"length\": {},\n  \"header\": {{\n    \
+         \"type_id\": {},\n    \"version_id\": {},\n    \"payload_len\": {},\n    \
+         \"digest\": {},\n    \"compression\": {},\n    \"encryption\": {},\n    \
+         \"flags\": {}\n  }},\n  \"user_word\": {},\n  \"timestamp\": {},\n  \
+         \"sequence\": {},\n  \"extensions\": [",
+        JsonStr(name),
+        JsonStr(&format!("{:?}", sample)),
+        bytes.len(),
+        header.type_id,
+        header.version_id,
+        header.payload_len,
+        header.digest,
+        header.compression,
+        header.encryption,
+        header.flags,
+        optional(get_user_word_from_tagged_bytes(bytes)?),
+        optional(get_timestamp_from_tagged_bytes(bytes)?),
+        optional(get_sequence_from_tagged_bytes(bytes)?),
+    );
+    for (i, (key, value)) in get_extensions_from_tagged_bytes(bytes)?.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let _ = write!(
+            json,
+            "{}\n    {{ \"key\": {}, \"value_hex\": \"{}\" }}",
+            separator,
+            key,
+            Hex(value)
+        );
+    }
+    let _ = write!(
+        json,
+        "\n  ],\n  \"payload_offset\": {},\n  \"payload_hex\": \"{}\",\n  \
+         \"digest_hex\": \"{}\",\n  \"rkyv_layout\": {{ \"endian\": \"{}\", \"aligned\": {} }}\n}}\n",
+        TAGGED_HEADER_SIZE + metadata.len(),
+        Hex(payload),
+        Hex(digest),
+        if cfg!(feature = "big_endian") {
+            "big"
+        } else {
+            "little"
+        },
+        !cfg!(feature = "unaligned"),
+    );
+    Ok(json)
+}
+
+/// Formats bytes as lowercase hex.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Formats a string as a quoted JSON string.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, HeaderExtensions, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Message {
+        V1(u32),
+        V2(String),
+    }
+
+    #[test]
+    fn test_write_test_vectors() {
+        let dir = std::env::temp_dir()
+            .join(format!("rkyv_versioned_vectors_{}", std::process::id()));
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(7, b"tenant").unwrap();
+        let options = [
+            ("default", TaggedOptions::default()),
+            (
+                "metadata",
+                TaggedOptions {
+                    extensions,
+                    user_word: Some(5),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let samples = [Message::V1(1), Message::V2("say \"hi\"\n".to_string())];
+        assert_eq!(write_test_vectors(&dir, &samples, &options).unwrap(), 4);
+
+        let bytes = fs::read(dir.join("V2-metadata.bin")).unwrap();
+        let mut aligned = AlignedVec::<16>::new();
+        aligned.extend_from_slice(&bytes);
+        assert!(access_from_tagged_bytes::<Message>(&aligned).is_ok());
+
+        let json = fs::read_to_string(dir.join("V2-metadata.json")).unwrap();
+        assert!(json.contains("\"name\": \"V2-metadata\""));
+        assert!(json.contains(r#""description": "V2(\"say \\\"hi\\\"\\n\")""#));
+        assert!(json.contains("\"user_word\": 5"));
+        assert!(json.contains("{ \"key\": 7, \"value_hex\": \"74656e616e74\" }"));
+        assert!(json.contains(&format!("\"length\": {}", bytes.len())));
+
+        let index = fs::read_to_string(dir.join("index.json")).unwrap();
+        assert!(index.contains("\"V1-default\",\n    \"V1-metadata\""));
+        assert!(dir.join("README.md").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
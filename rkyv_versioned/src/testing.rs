@@ -0,0 +1,317 @@
+//! Property-based testing support, enabled with the `proptest` feature.
+//!
+//! Containers with owned variants can derive a `proptest` strategy covering every version with
+//! `#[vac(proptest)]`, and [check_round_trip] checks that a value survives being tagged and
+//! accessed again:
+//!
+//! ```
+//! use proptest::prelude::*;
+//! use rkyv::{Archive, Deserialize, Serialize};
+//! use rkyv_versioned::testing::check_round_trip;
+//! use rkyv_versioned::{TaggedOptions, VersionedArchiveContainer};
+//!
+//! #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+//! #[vac(compare(PartialEq), proptest)]
+//! enum Setting {
+//!     V1(u32),
+//!     V2(String),
+//! }
+//!
+//! proptest! {
+//!     fn round_trips(setting: Setting) {
+//!         check_round_trip(&setting, &TaggedOptions::default())?;
+//!     }
+//! }
+//! # round_trips();
+//! ```
+//!
+//! Containers borrowing their variants can still use [check_round_trip] by generating the
+//! variant values and wrapping them inside the test.
+//!
+//! Migrations between versions can be fuzzed with [check_migration], which tags a generated
+//! older version, runs the migration on the archived value read back from it and then checks
+//! user-supplied invariants against the result, catching migrations that lose or corrupt data.
+//!
+//! [samples_of_each_version] generates one value of each version, e.g. to feed
+//! [self_check](crate::self_check) at startup.
+
+use core::fmt::Debug;
+
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::prop_assert;
+use proptest::strategy::{Strategy, ValueTree};
+use proptest::test_runner::TestCaseError;
+use proptest::test_runner::TestRunner;
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    access_from_tagged_bytes, access_from_tagged_bytes_with_scratch,
+    get_type_and_version_from_tagged_bytes, to_tagged_bytes_with, TaggedOptions,
+    VersionedContainer,
+};
+
+fn fail(e: impl Debug) -> TestCaseError {
+    TestCaseError::fail(format!("{:?}", e))
+}
+
+/// Tags `item` with `options`, then checks that the header holds its type and version and that
+/// the archived value accessed from it compares equal to `item`.
+///
+/// The archived container must be comparable against the owned one, e.g. through
+/// `#[vac(compare(PartialEq))]`.
+///
+/// # Returns
+///
+/// `Ok(())` on success, or a [TestCaseError] describing the failure so it can be used with
+/// `?` inside `proptest!`.
+pub fn check_round_trip<T>(item: &T, options: &TaggedOptions) -> Result<(), TestCaseError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: PartialEq<T>
+        + rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+{
+    let bytes = to_tagged_bytes_with(item, options).map_err(fail)?;
+
+    let (type_id, version_id) =
+        get_type_and_version_from_tagged_bytes(&bytes).map_err(fail)?;
+    prop_assert!(
+        type_id == T::ARCHIVE_TYPE_ID,
+        "unexpected type_id {}",
+        type_id
+    );
+    prop_assert!(
+        version_id == item.get_entry_version_id(),
+        "unexpected version_id {}",
+        version_id
+    );
+
+    let mut scratch = AlignedVec::new();
+    let archived =
+        access_from_tagged_bytes_with_scratch::<T>(&bytes, &mut scratch).map_err(fail)?;
+    prop_assert!(
+        *archived == *item,
+        "archived value differs from the original"
+    );
+    Ok(())
+}
+
+/// Tags `item`, holding an older version, reads it back and runs `migrate` on the archived
+/// container, then checks `invariants` against the migrated value.
+///
+/// This is intended to be driven by `proptest!` with generated values of the older version,
+/// with `invariants` comparing the migrated value against the original one.
+///
+/// # Arguments
+///
+/// * `item` - The container holding the version to migrate from.
+/// * `migrate` - The migration under test, from the archived container to the newer version.
+/// * `invariants` - Checks that must hold for the migrated value, typically using
+///   `prop_assert!`.
+///
+/// # Returns
+///
+/// `Ok(())` if the value could be tagged, read and migrated and the invariants hold, or a
+/// [TestCaseError] describing the failure.
+pub fn check_migration<T, U, E>(
+    item: &T,
+    migrate: impl FnOnce(&T::Archived) -> Result<U, E>,
+    invariants: impl FnOnce(&U) -> Result<(), TestCaseError>,
+) -> Result<(), TestCaseError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    T::Archived: rkyv::Portable
+        + for<'b> rkyv::bytecheck::CheckBytes<
+            rkyv::api::high::HighValidator<'b, rkyv::rancor::Error>,
+        >,
+    E: Debug,
+{
+    let bytes = to_tagged_bytes_with(item, &TaggedOptions::default()).map_err(fail)?;
+    let archived = access_from_tagged_bytes::<T>(&bytes).map_err(fail)?;
+    let migrated = migrate(archived).map_err(fail)?;
+    invariants(&migrated)
+}
+
+/// Generates one value of each version of `T` from its `proptest` strategy, e.g. derived
+/// with `#[vac(proptest)]`.
+///
+/// Values are generated with a deterministic runner, so the samples are the same on every
+/// call.
+///
+/// # Returns
+///
+/// The samples in the order of the [VERSION_TABLE](VersionedContainer::VERSION_TABLE).
+/// Versions the strategy didn't produce within a bounded number of attempts are left out.
+pub fn samples_of_each_version<T: VersionedContainer + Arbitrary>() -> Vec<T> {
+    const MAX_ATTEMPTS: usize = 1024;
+
+    let mut runner = TestRunner::deterministic();
+    let strategy = any::<T>();
+    let mut samples: Vec<T> = Vec::with_capacity(T::VERSION_TABLE.len());
+    for _ in 0..MAX_ATTEMPTS {
+        if samples.len() == T::VERSION_TABLE.len() {
+            break;
+        }
+        let Ok(tree) = strategy.new_tree(&mut runner) else {
+            continue;
+        };
+        let sample = tree.current();
+        let version_id = sample.get_entry_version_id();
+        if !samples
+            .iter()
+            .any(|other| other.get_entry_version_id() == version_id)
+        {
+            samples.push(sample);
+        }
+    }
+    samples.sort_by_key(|sample| {
+        T::VERSION_TABLE
+            .iter()
+            .position(|info| info.version_id == sample.get_entry_version_id())
+    });
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionCodec, DigestAlgorithm, VersionedArchiveContainer};
+    use proptest::prelude::*;
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(compare(PartialEq), proptest)]
+    enum OwnedContainer {
+        V1(u32),
+        V2(String),
+        V3(Vec<i16>),
+    }
+
+    #[derive(Debug, PartialEq, Archive, Serialize, Deserialize)]
+    #[rkyv(compare(PartialEq))]
+    struct Borrowed {
+        pub name: String,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    #[vac(compare(PartialEq))]
+    enum BorrowedContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Borrowed),
+    }
+
+    proptest! {
+        #[test]
+        fn test_owned_round_trip(item: OwnedContainer, digest in 0u8..=4, compression in 0u8..=2) {
+            let options = TaggedOptions {
+                digest: DigestAlgorithm::from_id(digest).unwrap(),
+                compression: CompressionCodec::from_id(compression).unwrap(),
+                ..Default::default()
+            };
+            prop_assume!(options.digest.is_available() && options.compression.is_available());
+            check_round_trip(&item, &options)?;
+        }
+
+        #[test]
+        fn test_borrowed_round_trip(name: String) {
+            let item = Borrowed { name };
+            check_round_trip(&BorrowedContainer::V1(&item), &TaggedOptions::default())?;
+        }
+    }
+
+    #[test]
+    fn test_strategy_covers_all_versions() {
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        let strategy = any::<OwnedContainer>();
+        let mut seen = [false; 3];
+        for _ in 0..100 {
+            let item = strategy.new_tree(&mut runner).unwrap().current();
+            seen[item.get_entry_version_id() as usize] = true;
+        }
+        assert_eq!(seen, [true; 3]);
+    }
+
+    #[test]
+    fn test_samples_of_each_version() {
+        let samples = samples_of_each_version::<OwnedContainer>();
+        let versions: Vec<u32> = samples.iter().map(|s| s.get_entry_version_id()).collect();
+        assert_eq!(versions, [0, 1, 2]);
+        assert!(crate::self_check(&samples, &TaggedOptions::default()).is_ok());
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct AccountV1 {
+        pub balance: u32,
+        pub owner: String,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct AccountV2 {
+        pub balance: u64,
+        pub owner: String,
+        pub frozen: bool,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum AccountContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a AccountV1),
+        V2(#[rkyv(with=InlineAsBox)] &'a AccountV2),
+    }
+
+    fn migrate(archived: &ArchivedAccountContainer) -> Result<AccountV2, &'static str> {
+        match archived {
+            ArchivedAccountContainer::V1(v1) => Ok(AccountV2 {
+                balance: v1.balance.to_native().into(),
+                owner: v1.owner.to_string(),
+                frozen: false,
+            }),
+            ArchivedAccountContainer::V2(_) => Err("already migrated"),
+        }
+    }
+
+    fn preserves_account(
+        original: &AccountV1,
+        migrated: &AccountV2,
+    ) -> Result<(), TestCaseError> {
+        prop_assert_eq!(migrated.balance, u64::from(original.balance));
+        prop_assert_eq!(&migrated.owner, &original.owner);
+        prop_assert!(!migrated.frozen);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_account_migration(balance: u32, owner: String) {
+            let original = AccountV1 { balance, owner };
+            check_migration(&AccountContainer::V1(&original), migrate, |migrated| {
+                preserves_account(&original, migrated)
+            })?;
+        }
+    }
+
+    #[test]
+    fn test_lossy_migration_is_caught() {
+        let lossy = |archived: &ArchivedAccountContainer| {
+            migrate(archived).map(|mut migrated| {
+                migrated.owner.truncate(3);
+                migrated
+            })
+        };
+
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        let result = runner.run(&(any::<u32>(), "[a-z]{0,8}"), |(balance, owner)| {
+            let original = AccountV1 { balance, owner };
+            check_migration(&AccountContainer::V1(&original), lossy, |migrated| {
+                preserves_account(&original, migrated)
+            })
+        });
+        assert!(result.is_err());
+    }
+}
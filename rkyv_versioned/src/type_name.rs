@@ -0,0 +1,153 @@
+//! Embedding the name of the container in its records, for debugging.
+//!
+//! A record only identifies its container by a CRC32 of the type name, so a record of a type
+//! which has since been renamed or deleted can't be told apart from noise.
+//! [to_tagged_bytes_named] additionally stores the [TYPE_NAME](VersionedContainer::TYPE_NAME)
+//! of the container and the name of the variant holding the version in the
+//! [HeaderExtensions](crate::HeaderExtensions), under [EXTENSION_KEY_TYPE_NAME] and
+//! [EXTENSION_KEY_VARIANT_NAME], and [get_type_name_from_tagged_bytes] reads them back without
+//! knowing the container type, so tooling inspecting old files can print a human-readable
+//! identity for every type ID it finds.  The names cost a few bytes per record, so this is
+//! best kept to debugging and low-volume records.
+
+use alloc::string::String;
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    get_extensions_from_tagged_bytes, to_tagged_bytes_with, HeaderExtensions,
+    RkyvVersionedError, TaggedOptions, VersionedContainer,
+};
+
+/// The extension key holding the name of the container type.
+pub const EXTENSION_KEY_TYPE_NAME: u16 = 0xff0a;
+
+/// The extension key holding the name of the variant holding the version, e.g. `V2`.
+pub const EXTENSION_KEY_VARIANT_NAME: u16 = 0xff0b;
+
+/// The names embedded in a record by [to_tagged_bytes_named].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedTypeName {
+    /// The name of the container type, e.g. `Invoice`.
+    pub type_name: String,
+    /// The name of the variant holding the version, e.g. `V2`, if it was stored.
+    pub variant_name: Option<String>,
+}
+
+/// As [to_tagged_bytes_with], additionally storing the names of the container type and of
+/// the variant of `item` in the header extensions, alongside any already set in `options`.
+///
+/// # Arguments
+///
+/// * `item` - A reference to the item to be serialized.
+/// * `options` - The options to tag the item with.
+///
+/// # Returns
+///
+/// A `Result` containing either the tagged byte array, or an error if serialization fails.
+pub fn to_tagged_bytes_named<T>(
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<AlignedVec, RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let mut extensions = options.extensions.clone();
+    set_type_name::<T>(&mut extensions, item.get_entry_version_id())?;
+    let options = TaggedOptions {
+        extensions,
+        ..options.clone()
+    };
+    to_tagged_bytes_with(item, &options)
+}
+
+/// Stores the name of `T`, and of its variant holding `version_id` if there is one, in
+/// `extensions`, e.g. for records tagged with [to_tagged_bytes_in](crate::to_tagged_bytes_in).
+pub fn set_type_name<T: VersionedContainer>(
+    extensions: &mut HeaderExtensions,
+    version_id: u32,
+) -> Result<(), RkyvVersionedError> {
+    extensions.insert(EXTENSION_KEY_TYPE_NAME, T::TYPE_NAME.as_bytes())?;
+    match T::version_info(version_id) {
+        Some(info) => extensions.insert(EXTENSION_KEY_VARIANT_NAME, info.name.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+/// Reads the names embedded in a tagged byte array without accessing the payload, and without
+/// knowing the container type.
+///
+/// # Arguments
+///
+/// * `buf` - A reference to the byte array containing the tagged serialized data.
+///
+/// # Returns
+///
+/// A `Result` containing the names, `None` if the record wasn't written with them, or an error
+/// if the buffer is truncated, the extension area is malformed or a name isn't UTF-8
+/// ([RkyvVersionedError::InvalidExtensionError]).
+pub fn get_type_name_from_tagged_bytes(
+    buf: &[u8],
+) -> Result<Option<EmbeddedTypeName>, RkyvVersionedError> {
+    let mut extensions = get_extensions_from_tagged_bytes(buf)?;
+    let name = |key, value| {
+        String::from_utf8(value).map_err(|_| RkyvVersionedError::InvalidExtensionError(key))
+    };
+    let Some(type_name) = extensions.remove(EXTENSION_KEY_TYPE_NAME) else {
+        return Ok(None);
+    };
+    Ok(Some(EmbeddedTypeName {
+        type_name: name(EXTENSION_KEY_TYPE_NAME, type_name)?,
+        variant_name: extensions
+            .remove(EXTENSION_KEY_VARIANT_NAME)
+            .map(|variant| name(EXTENSION_KEY_VARIANT_NAME, variant))
+            .transpose()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_from_tagged_bytes, get_type_and_version_from_tagged_bytes, to_tagged_bytes,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Invoice {
+        V1(u64),
+        V2((u64, String)),
+    }
+
+    #[test]
+    fn test_embedded_type_name() {
+        assert_eq!(Invoice::TYPE_NAME, "Invoice");
+        let invoice = Invoice::V2((1200, "EUR".to_string()));
+        let bytes = to_tagged_bytes_named(&invoice, &TaggedOptions::default()).unwrap();
+        assert!(matches!(
+            access_from_tagged_bytes::<Invoice>(&bytes).unwrap(),
+            ArchivedInvoice::V2(_)
+        ));
+
+        // Tooling which doesn't know the type can still name it
+        assert_eq!(
+            get_type_and_version_from_tagged_bytes(&bytes).unwrap(),
+            (Invoice::ARCHIVE_TYPE_ID, 1)
+        );
+        assert_eq!(
+            get_type_name_from_tagged_bytes(&bytes).unwrap(),
+            Some(EmbeddedTypeName {
+                type_name: "Invoice".to_string(),
+                variant_name: Some("V2".to_string()),
+            })
+        );
+
+        let plain = to_tagged_bytes(&invoice).unwrap();
+        assert_eq!(get_type_name_from_tagged_bytes(&plain).unwrap(), None);
+    }
+}
@@ -0,0 +1,606 @@
+//! Limits on the resources spent validating untrusted input.
+//!
+//! A tagged byte array declares the length of its payload in the header, and a compressed
+//! payload declares its uncompressed length, so a malicious peer can make a reader allocate or
+//! hash far more than it intended to.  Similarly, deeply nested archives make `bytecheck`
+//! recurse deeply.  A [ValidationConfig] caps both, failing with
+//! [RkyvVersionedError::LimitExceededError] before the expensive work is done.
+//!
+//! The same configuration enforces deprecation timelines: reading a version past the sunset
+//! date set with `#[vac(sunset = "YYYY-MM-DD")]` is reported to a [VersionObserver], and
+//! rejected with [RkyvVersionedError::VersionSunsetError] in strict mode.
+//!
+//! Buffers holding more than the record, e.g. because a framing bug passed along part of the
+//! next one, are accepted by default.  [ValidationConfig::strict_trailing_bytes] rejects them
+//! with [RkyvVersionedError::TrailingBytesError] instead, while
+//! [access_prefix_from_tagged_bytes](crate::access_prefix_from_tagged_bytes) accepts them
+//! explicitly and returns how much of the buffer the record took up.
+
+use core::fmt;
+use core::num::NonZeroUsize;
+
+use rkyv::api::high::HighValidator;
+use rkyv::bytecheck::CheckBytes;
+use rkyv::rancor::Source;
+use rkyv::util::AlignedVec;
+use rkyv::validation::archive::ArchiveValidator;
+use rkyv::validation::shared::SharedValidator;
+use rkyv::validation::{ArchiveContext, Validator};
+
+#[cfg(feature = "std")]
+use crate::SystemClock;
+use crate::{
+    access_encrypted_tagged, access_encrypted_tagged_in_place, access_tagged,
+    access_tagged_with_scratch, access_tagged_with_strategy, rkyv_error, Clock, EncryptionKey,
+    RkyvVersionedError, VersionInfo, VersionedContainer,
+};
+
+/// A limit enforced by a [ValidationConfig].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationLimit {
+    /// [ValidationConfig::max_payload_len]
+    PayloadLen,
+    /// [ValidationConfig::max_depth]
+    Depth,
+}
+
+impl fmt::Display for ValidationLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationLimit::PayloadLen => write!(f, "payload length"),
+            ValidationLimit::Depth => write!(f, "nesting depth"),
+        }
+    }
+}
+
+/// Validates `payload` as a `T::Archived` with at most `max_depth` levels, returning the result
+/// and whether validation failed with no depth left.
+fn validate_payload<'a, T: VersionedContainer + 'a, E: Source>(
+    payload: &'a [u8],
+    max_depth: Option<NonZeroUsize>,
+) -> (Result<&'a T::Archived, E>, bool)
+where
+    T::Archived: rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, E>>,
+{
+    let mut validator = Validator::new(
+        ArchiveValidator::with_max_depth(payload, max_depth),
+        SharedValidator::new(),
+    );
+    let result = rkyv::api::access_with_context::<T::Archived, _, E>(payload, &mut validator);
+    if result.is_ok() || max_depth.is_none() {
+        return (result, false);
+    }
+    // Probe for depth left by descending once more
+    let range = payload.as_ptr_range();
+    // SAFETY: The validator is dropped right after, so the subtree range it records for the
+    // probe is never checked against
+    let exhausted = unsafe {
+        ArchiveContext::<rkyv::rancor::Failure>::push_subtree_range(
+            &mut validator,
+            range.start,
+            range.end,
+        )
+    }
+    .is_err();
+    (result, exhausted)
+}
+
+/// Notified of noteworthy records read with a [ValidationConfig].
+pub trait VersionObserver: Sync {
+    /// Called when a record holds a version of the container with type ID `type_id` whose
+    /// [sunset](VersionInfo::sunset) has passed, typically to log a warning.  `now` is the
+    /// Unix timestamp the sunset was checked against.
+    fn sunset_version_read(&self, type_id: u32, info: &VersionInfo, now: u64);
+}
+
+/// Limits applied while reading a tagged byte array, see the [module](self) documentation.
+///
+/// The default configuration has no limits, which is what the free functions such as
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes) use.
+#[derive(Default, Clone, Copy)]
+pub struct ValidationConfig {
+    /// The maximum length of the stored payload declared in the header, and of the payload
+    /// after decompression, in bytes.
+    pub max_payload_len: Option<usize>,
+    /// The maximum depth of nested subtrees (e.g. boxes, vectors and strings) the validator
+    /// will descend into, counting the root of the archive as the first level.
+    pub max_depth: Option<NonZeroUsize>,
+    /// Notified when a version past its sunset date is read.
+    pub observer: Option<&'static dyn VersionObserver>,
+    /// Whether reading a version past its sunset date fails with
+    /// [RkyvVersionedError::VersionSunsetError], after notifying the observer.
+    pub strict_sunset: bool,
+    /// The Unix timestamp, in seconds, to check sunset dates against instead of the system
+    /// clock.
+    pub now: Option<u64>,
+    /// The clock to check sunset dates against when [now](Self::now) isn't set, or `None`
+    /// for the system clock.
+    pub clock: Option<&'static dyn Clock>,
+    /// Whether buffers holding more than the tagged byte array fail with
+    /// [RkyvVersionedError::TrailingBytesError] rather than the trailing bytes being ignored.
+    pub strict_trailing_bytes: bool,
+    /// Whether records of [fingerprinted](crate::SchemaFingerprint) versions without a
+    /// fingerprint, e.g. written before the container was fingerprinted, are read rather than
+    /// failing with [RkyvVersionedError::SchemaMismatchError].
+    pub allow_missing_fingerprint: bool,
+}
+
+impl fmt::Debug for ValidationConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidationConfig")
+            .field("max_payload_len", &self.max_payload_len)
+            .field("max_depth", &self.max_depth)
+            .field("observer", &self.observer.is_some())
+            .field("strict_sunset", &self.strict_sunset)
+            .field("now", &self.now)
+            .field("clock", &self.clock)
+            .field("strict_trailing_bytes", &self.strict_trailing_bytes)
+            .field("allow_missing_fingerprint", &self.allow_missing_fingerprint)
+            .finish()
+    }
+}
+
+impl ValidationConfig {
+    /// Checks whether version `version_id` of `T` is past its sunset date, notifying the
+    /// observer and failing in strict mode if it is.
+    pub(crate) fn check_sunset<T: VersionedContainer>(
+        &self,
+        version_id: u32,
+    ) -> Result<(), RkyvVersionedError> {
+        if self.observer.is_none() && !self.strict_sunset {
+            return Ok(());
+        }
+        let Some(info) = T::version_info(version_id) else {
+            return Ok(());
+        };
+        let Some(sunset) = info.sunset else {
+            return Ok(());
+        };
+        let Some(now) = self.now.or_else(|| self.current_time()) else {
+            return Ok(());
+        };
+        if now < sunset {
+            return Ok(());
+        }
+
+        if let Some(observer) = self.observer {
+            observer.sunset_version_read(T::ARCHIVE_TYPE_ID, info, now);
+        }
+        match self.strict_sunset {
+            true => Err(RkyvVersionedError::VersionSunsetError(version_id, sunset)),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that a buffer of `len` bytes holds nothing after a tagged byte array of
+    /// `tagged_len` bytes, in strict mode.
+    pub(crate) fn check_trailing_bytes(
+        &self,
+        len: usize,
+        tagged_len: usize,
+    ) -> Result<(), RkyvVersionedError> {
+        match len.saturating_sub(tagged_len) {
+            trailing if self.strict_trailing_bytes && trailing > 0 => {
+                Err(RkyvVersionedError::TrailingBytesError(trailing))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The current Unix timestamp, in seconds, from the clock or the system clock.  Without the
+    /// `std` feature there is no system clock, so sunset dates are only checked against
+    /// [now](Self::now) or [clock](Self::clock).
+    fn current_time(&self) -> Option<u64> {
+        #[cfg(feature = "std")]
+        let clock = Some(self.clock.unwrap_or(&SystemClock));
+        #[cfg(not(feature = "std"))]
+        let clock = self.clock;
+        clock.map(|clock| clock.now_millis() / 1000)
+    }
+
+    /// Returns the maximum payload length, or `usize::MAX` if there is no limit.
+    pub(crate) fn payload_len_limit(&self) -> usize {
+        self.max_payload_len.unwrap_or(usize::MAX)
+    }
+
+    /// Checks the length of a payload against [ValidationConfig::max_payload_len].
+    pub(crate) fn check_payload_len(&self, len: usize) -> Result<(), RkyvVersionedError> {
+        match self.max_payload_len {
+            Some(max) if len > max => Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::PayloadLen,
+                max,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates `payload` as a `T::Archived`, descending at most
+    /// [ValidationConfig::max_depth] levels.
+    pub(crate) fn access_payload<'a, T: VersionedContainer + 'a>(
+        &self,
+        payload: &'a [u8],
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived:
+            rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, rkyv::rancor::Error>>,
+    {
+        self.access_payload_with_strategy::<T, rkyv::rancor::Error>(payload)
+    }
+
+    /// As [access_payload](Self::access_payload), validating with the error type `E`.
+    pub(crate) fn access_payload_with_strategy<'a, T: VersionedContainer + 'a, E: Source>(
+        &self,
+        payload: &'a [u8],
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, E>>,
+    {
+        let Some(max) = self.max_depth else {
+            return validate_payload::<T, E>(payload, None)
+                .0
+                .map_err(rkyv_error);
+        };
+        // rkyv fails when the remaining depth would reach zero, rather than go below it
+        let limit = max.saturating_add(1);
+        match validate_payload::<T, E>(payload, Some(limit)) {
+            (Ok(archived), _) => Ok(archived),
+            // The error rkyv raises for the limit is private, so running out of depth is told
+            // from the depth left.  An invalid payload failing at the deepest level allowed has
+            // none left either, but fails at the same point with one more level to spare
+            (Err(e), true) => {
+                match validate_payload::<T, E>(payload, Some(limit.saturating_add(1))) {
+                    (Err(_), false) => Err(rkyv_error(e)),
+                    _ => Err(RkyvVersionedError::LimitExceededError(
+                        ValidationLimit::Depth,
+                        max.get(),
+                    )),
+                }
+            }
+            (Err(e), false) => Err(rkyv_error(e)),
+        }
+    }
+
+    /// Validates and accesses a tagged byte array like
+    /// [access_from_tagged_bytes](crate::access_from_tagged_bytes), within these limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the tagged serialized data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error, which
+    /// is [RkyvVersionedError::LimitExceededError] if a limit was hit.
+    pub fn access_from_tagged_bytes<'a, T: VersionedContainer + 'a>(
+        &self,
+        buf: &'a [u8],
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived:
+            rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, rkyv::rancor::Error>>,
+    {
+        access_tagged::<T>(buf, self)
+    }
+
+    /// Validates and accesses a tagged byte array like
+    /// [access_from_tagged_bytes_with_strategy](crate::access_from_tagged_bytes_with_strategy),
+    /// within these limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the tagged serialized data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error, which
+    /// is [RkyvVersionedError::LimitExceededError] if a limit was hit.
+    pub fn access_from_tagged_bytes_with_strategy<'a, T: VersionedContainer + 'a, E: Source>(
+        &self,
+        buf: &'a [u8],
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived: rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, E>>,
+    {
+        access_tagged_with_strategy::<T, E>(buf, self)
+    }
+
+    /// Validates and accesses a tagged byte array like
+    /// [access_from_tagged_bytes_with_scratch](crate::access_from_tagged_bytes_with_scratch),
+    /// within these limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the tagged serialized data.
+    /// * `scratch` - A buffer to decompress the payload into if necessary.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error, which
+    /// is [RkyvVersionedError::LimitExceededError] if a limit was hit.
+    pub fn access_from_tagged_bytes_with_scratch<'a, T: VersionedContainer + 'a>(
+        &self,
+        buf: &'a [u8],
+        scratch: &'a mut AlignedVec,
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived:
+            rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, rkyv::rancor::Error>>,
+    {
+        access_tagged_with_scratch::<T>(buf, scratch, self)
+    }
+
+    /// Validates and accesses a tagged byte array like
+    /// [access_from_encrypted_tagged_bytes](crate::access_from_encrypted_tagged_bytes), within
+    /// these limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A reference to the byte array containing the tagged serialized data.
+    /// * `key` - The key the payload was encrypted with.
+    /// * `scratch` - A buffer to decrypt the payload into.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error, which
+    /// is [RkyvVersionedError::LimitExceededError] if a limit was hit.
+    pub fn access_from_encrypted_tagged_bytes<'a, T: VersionedContainer + 'a>(
+        &self,
+        buf: &'a [u8],
+        key: &EncryptionKey,
+        scratch: &'a mut AlignedVec,
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived:
+            rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, rkyv::rancor::Error>>,
+    {
+        access_encrypted_tagged::<T>(buf, key, scratch, self)
+    }
+
+    /// Validates and accesses a tagged byte array like
+    /// [access_from_encrypted_tagged_bytes_in_place](crate::access_from_encrypted_tagged_bytes_in_place),
+    /// within these limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - A mutable reference to the byte array containing the tagged serialized data.
+    /// * `key` - The key the payload was encrypted with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either a reference to the deserialized item or an error, which
+    /// is [RkyvVersionedError::LimitExceededError] if a limit was hit.
+    pub fn access_from_encrypted_tagged_bytes_in_place<'a, T: VersionedContainer + 'a>(
+        &self,
+        buf: &'a mut [u8],
+        key: &EncryptionKey,
+    ) -> Result<&'a T::Archived, RkyvVersionedError>
+    where
+        T::Archived:
+            rkyv::Portable + for<'b> CheckBytes<HighValidator<'b, rkyv::rancor::Error>>,
+    {
+        access_encrypted_tagged_in_place::<T>(buf, key, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_prefix_from_tagged_bytes, payload_bytes, tag_existing_payload, to_tagged_bytes,
+        VersionedArchiveContainer,
+    };
+    use rkyv::{Archive, Deserialize, Serialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Nested {
+        V1(Vec<Vec<Vec<String>>>),
+    }
+
+    fn nested_bytes() -> AlignedVec {
+        let nested = Nested::V1(vec![vec![vec!["deep".to_string(); 4]; 4]; 4]);
+        to_tagged_bytes(&nested).unwrap()
+    }
+
+    /// Limits the root and three levels of vectors, the strings are short enough to be inline
+    fn depth_config(max_depth: usize) -> ValidationConfig {
+        ValidationConfig {
+            max_depth: NonZeroUsize::new(max_depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unlimited_validation() {
+        let bytes = nested_bytes();
+        let unlimited = ValidationConfig::default();
+        assert!(unlimited.access_from_tagged_bytes::<Nested>(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_payload_len_limit() {
+        let bytes = nested_bytes();
+        let small = ValidationConfig {
+            max_payload_len: Some(64),
+            ..Default::default()
+        };
+        assert!(matches!(
+            small.access_from_tagged_bytes::<Nested>(&bytes),
+            Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::PayloadLen,
+                64
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let bytes = nested_bytes();
+        assert!(matches!(
+            depth_config(3).access_from_tagged_bytes::<Nested>(&bytes),
+            Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::Depth,
+                3
+            ))
+        ));
+        assert!(depth_config(4)
+            .access_from_tagged_bytes::<Nested>(&bytes)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_depth_limit_with_failure_strategy() {
+        // Failure carries no message, so the limit is told apart without one
+        let bytes = nested_bytes();
+        assert!(matches!(
+            depth_config(3)
+                .access_from_tagged_bytes_with_strategy::<Nested, rkyv::rancor::Failure>(
+                    &bytes
+                ),
+            Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::Depth,
+                3
+            ))
+        ));
+        assert!(depth_config(4)
+            .access_from_tagged_bytes_with_strategy::<Nested, rkyv::rancor::Failure>(&bytes)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_payload_at_depth_limit() {
+        // Corrupt an inline string at the deepest level allowed
+        let mut payload = payload_bytes(&nested_bytes()).unwrap().to_vec();
+        let at = payload.windows(4).position(|w| w == b"deep").unwrap();
+        payload[at + 3] = 0xc0;
+        let bytes = tag_existing_payload::<Nested>(0, &payload).unwrap();
+
+        for config in [depth_config(4), depth_config(5)] {
+            assert!(matches!(
+                config.access_from_tagged_bytes::<Nested>(&bytes),
+                Err(RkyvVersionedError::RkyvError(_))
+            ));
+            assert!(matches!(
+                config
+                    .access_from_tagged_bytes_with_strategy::<Nested, rkyv::rancor::Failure>(
+                        &bytes
+                    ),
+                Err(RkyvVersionedError::RkyvError(_))
+            ));
+        }
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Sunsetting {
+        #[vac(sunset = "2026-01-01")]
+        V1(u32),
+        V2(u64),
+    }
+
+    /// Counts the sunset versions read
+    struct CountingObserver(std::sync::atomic::AtomicUsize);
+
+    impl VersionObserver for CountingObserver {
+        fn sunset_version_read(&self, type_id: u32, info: &VersionInfo, _now: u64) {
+            assert_eq!(type_id, Sunsetting::ARCHIVE_TYPE_ID);
+            assert_eq!(info.name, "V1");
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_version_sunset() {
+        static OBSERVER: CountingObserver =
+            CountingObserver(std::sync::atomic::AtomicUsize::new(0));
+        // 2026-01-01T00:00:00Z
+        const SUNSET: u64 = 1_767_225_600;
+        assert_eq!(Sunsetting::VERSION_TABLE[0].sunset, Some(SUNSET));
+        assert_eq!(Sunsetting::VERSION_TABLE[1].sunset, None);
+
+        let old = to_tagged_bytes(&Sunsetting::V1(1)).unwrap();
+        let new = to_tagged_bytes(&Sunsetting::V2(2)).unwrap();
+        let mut config = ValidationConfig {
+            observer: Some(&OBSERVER),
+            now: Some(SUNSET - 1),
+            ..Default::default()
+        };
+        let read_count = || OBSERVER.0.load(std::sync::atomic::Ordering::Relaxed);
+
+        // Before the sunset nothing happens
+        config.access_from_tagged_bytes::<Sunsetting>(&old).unwrap();
+        assert_eq!(read_count(), 0);
+
+        // After it, reads are reported but still succeed
+        config.now = Some(SUNSET);
+        config.access_from_tagged_bytes::<Sunsetting>(&old).unwrap();
+        config.access_from_tagged_bytes::<Sunsetting>(&new).unwrap();
+        assert_eq!(read_count(), 1);
+
+        // Unless the config is strict
+        config.strict_sunset = true;
+        assert!(matches!(
+            config.access_from_tagged_bytes::<Sunsetting>(&old),
+            Err(RkyvVersionedError::VersionSunsetError(0, SUNSET))
+        ));
+        assert_eq!(read_count(), 2);
+        config.access_from_tagged_bytes::<Sunsetting>(&new).unwrap();
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        let nested = Nested::V1(vec![vec![vec!["tail".to_string()]]]);
+        let bytes = to_tagged_bytes(&nested).unwrap();
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[0; 5]);
+
+        // Ignored by default, rejected in strict mode
+        let lenient = ValidationConfig::default();
+        assert!(lenient.access_from_tagged_bytes::<Nested>(&padded).is_ok());
+        let strict = ValidationConfig {
+            strict_trailing_bytes: true,
+            ..Default::default()
+        };
+        assert!(strict.access_from_tagged_bytes::<Nested>(&bytes).is_ok());
+        assert!(matches!(
+            strict.access_from_tagged_bytes::<Nested>(&padded),
+            Err(RkyvVersionedError::TrailingBytesError(5))
+        ));
+        let mut scratch = AlignedVec::new();
+        assert!(matches!(
+            strict.access_from_tagged_bytes_with_scratch::<Nested>(&padded, &mut scratch),
+            Err(RkyvVersionedError::TrailingBytesError(5))
+        ));
+
+        // Or accepted explicitly, reporting where the record ends
+        let (archived, consumed) = access_prefix_from_tagged_bytes::<Nested>(&padded).unwrap();
+        assert_eq!(consumed, bytes.len());
+        let ArchivedNested::V1(outer) = archived;
+        assert_eq!(outer[0][0][0], "tail");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_decompressed_len_limit() {
+        use crate::{to_tagged_bytes_with, CompressionCodec, TaggedOptions};
+
+        let nested = Nested::V1(vec![vec![vec!["a".repeat(1024); 4]; 4]; 4]);
+        let options = TaggedOptions {
+            compression: CompressionCodec::Zstd,
+            ..Default::default()
+        };
+        let bytes = to_tagged_bytes_with(&nested, &options).unwrap();
+
+        // The stored payload fits, but it would decompress beyond the limit
+        let config = ValidationConfig {
+            max_payload_len: Some(bytes.len()),
+            ..Default::default()
+        };
+        let mut scratch = AlignedVec::new();
+        assert!(matches!(
+            config.access_from_tagged_bytes_with_scratch::<Nested>(&bytes, &mut scratch),
+            Err(RkyvVersionedError::LimitExceededError(
+                ValidationLimit::PayloadLen,
+                _
+            ))
+        ));
+    }
+}
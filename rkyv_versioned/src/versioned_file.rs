@@ -0,0 +1,655 @@
+//! A file of tagged records with a footer index for random access.
+//!
+//! [VersionedFileWriter] appends records as the length-prefixed frames of
+//! [write_tagged](crate::write_tagged) and, when [finished](VersionedFileWriter::finish),
+//! writes an index of every record followed by a fixed-size trailer:
+//!
+//! `[frame]...[index entry: offset (u64) + length (u32) + version ID (u32)]*`
+//! `[index offset (u64)][record count (u64)]["VACFIDX1"]`
+//!
+//! All integers are little-endian.  [VersionedFile] reads the trailer and index when opened,
+//! so the i-th record, its version, or the last records of the file are read with a single
+//! seek.  A file whose writer never finished, e.g. because the process crashed, has no
+//! trailer; its frames are scanned instead, up to the first incomplete or corrupt one.
+//!
+//! # Splitting
+//!
+//! A writer [with a maximum entry length](VersionedFileWriter::with_max_entry_len) splits
+//! longer records into pieces, each a tagged byte array of the same type and version whose
+//! payload is a chunk of the original record.  The continuation entries holding the later
+//! chunks are written first and mark the ID of their primary entry under
+//! [EXTENSION_KEY_CONTINUATION_OF]; the primary entry holds the first chunk and lists the
+//! IDs of its continuations under [EXTENSION_KEY_CONTINUATIONS].  [VersionedFile] joins the
+//! pieces again, so [read_record](VersionedFile::read_record) returns the original record,
+//! while other readers of the frames see the pieces as they are.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::io::{io_error, write_frame};
+use crate::{
+    get_extensions_from_tagged_bytes, read_header, split_metadata, to_tagged_bytes_with,
+    verify_payload, write_header, HeaderExtensions, RkyvVersionedError, TaggedOptions,
+    TaggedVersionedHeader, VersionedContainer, FRAME_PREFIX_SIZE, HEADER_FLAG_EXTENSIONS,
+    TAGGED_HEADER_SIZE,
+};
+
+/// The magic bytes ending a finished file.
+const FILE_MAGIC: [u8; 8] = *b"VACFIDX1";
+
+/// The size of the trailer after the index.
+const TRAILER_SIZE: usize = 24;
+
+/// The size of each index entry.
+const INDEX_ENTRY_SIZE: usize = 16;
+
+/// The extension key listing the IDs of the continuation entries of a split record, as
+/// little-endian `u64`s in order.
+pub const EXTENSION_KEY_CONTINUATIONS: u16 = 0xff07;
+
+/// The extension key marking a continuation entry of a split record, holding the ID of its
+/// primary entry as a little-endian `u64`.
+pub const EXTENSION_KEY_CONTINUATION_OF: u16 = 0xff08;
+
+/// Where a record is in a [VersionedFile], as stored in its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileIndexEntry {
+    /// The offset of the record in the file, after its frame prefix.
+    pub offset: u64,
+    /// The length of the record.
+    pub len: u32,
+    /// The version ID of the record.
+    pub version_id: u32,
+}
+
+impl FileIndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut bytes = [0u8; INDEX_ENTRY_SIZE];
+        bytes[..8].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.len.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.version_id.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (offset, rest) = bytes.split_first_chunk()?;
+        let (len, rest) = rest.split_first_chunk()?;
+        let version_id = rest.first_chunk()?;
+        Some(FileIndexEntry {
+            offset: u64::from_le_bytes(*offset),
+            len: u32::from_le_bytes(*len),
+            version_id: u32::from_le_bytes(*version_id),
+        })
+    }
+}
+
+/// Writes a [VersionedFile], see the [module](self) documentation.
+#[derive(Debug)]
+pub struct VersionedFileWriter<W> {
+    writer: W,
+    options: TaggedOptions,
+    /// The number of bytes written so far.
+    position: u64,
+    index: Vec<FileIndexEntry>,
+    max_entry_len: Option<usize>,
+}
+
+impl<W: Write> VersionedFileWriter<W> {
+    /// Wraps `writer`, which should be positioned at the start of an empty file.
+    pub fn new(writer: W) -> Self {
+        VersionedFileWriter {
+            writer,
+            options: TaggedOptions::default(),
+            position: 0,
+            index: Vec::new(),
+            max_entry_len: None,
+        }
+    }
+
+    /// Tags the records appended with `options` instead of the default [TaggedOptions].
+    pub fn with_options(mut self, options: TaggedOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Splits records longer than `max_entry_len` bytes into pieces carrying at most
+    /// `max_entry_len` bytes of the record each, see [Splitting](self#splitting).  A record can
+    /// be split into at most 8191 pieces, as the IDs of the continuations are stored in an
+    /// extension.
+    pub fn with_max_entry_len(mut self, max_entry_len: usize) -> Self {
+        self.max_entry_len = Some(max_entry_len.max(1));
+        self
+    }
+
+    /// Returns the number of entries appended so far, counting each piece of a split record.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns whether no record has been appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Serializes `item` and appends it to the file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ID of the record, i.e. the index of its primary entry in the
+    /// file, or an error if serialization or the write fails.
+    pub fn append<T>(&mut self, item: &T) -> Result<usize, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let record = to_tagged_bytes_with(item, &self.options)?;
+        self.append_record(&record)
+    }
+
+    /// Appends an already tagged byte array to the file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ID of the record, or an error if `record` is not a complete
+    /// tagged byte array, it has to be split into too many pieces
+    /// ([RkyvVersionedError::InvalidExtensionError]) or the write fails.
+    pub fn append_record(&mut self, record: &[u8]) -> Result<usize, RkyvVersionedError> {
+        let header = read_header(record)?;
+        let tagged_len = header.tagged_len()?;
+        let record = record
+            .get(..tagged_len)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        match self.max_entry_len {
+            Some(max_entry_len) if tagged_len > max_entry_len => {
+                self.append_split(&header, record, max_entry_len)
+            }
+            _ => self.append_entry(record, header.version_id),
+        }
+    }
+
+    /// Splits `record` into a primary entry and its continuations.
+    fn append_split(
+        &mut self,
+        header: &TaggedVersionedHeader,
+        record: &[u8],
+        max_entry_len: usize,
+    ) -> Result<usize, RkyvVersionedError> {
+        let mut chunks = record.chunks(max_entry_len);
+        let first = chunks.next().unwrap_or_default();
+        let continuations = self.index.len()..self.index.len() + chunks.len();
+        let primary_id = continuations.end;
+
+        // Build every piece before writing any, so a record which can't be split leaves no
+        // orphaned continuations behind
+        let mut extensions = HeaderExtensions::new();
+        let ids: Vec<u8> = continuations
+            .clone()
+            .flat_map(|id| (id as u64).to_le_bytes())
+            .collect();
+        extensions.insert(EXTENSION_KEY_CONTINUATIONS, &ids)?;
+        let primary = self.tag_piece(header, &extensions, first)?;
+        let mut extensions = HeaderExtensions::new();
+        extensions.insert(
+            EXTENSION_KEY_CONTINUATION_OF,
+            &(primary_id as u64).to_le_bytes(),
+        )?;
+        let pieces = chunks
+            .map(|chunk| self.tag_piece(header, &extensions, chunk))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for piece in &pieces {
+            self.append_entry(piece, header.version_id)?;
+        }
+        self.append_entry(&primary, header.version_id)
+    }
+
+    /// Wraps a chunk of a split record in a tagged byte array of the same type and version.
+    fn tag_piece(
+        &self,
+        header: &TaggedVersionedHeader,
+        extensions: &HeaderExtensions,
+        chunk: &[u8],
+    ) -> Result<AlignedVec, RkyvVersionedError> {
+        let mut piece = AlignedVec::with_capacity(
+            TAGGED_HEADER_SIZE
+                + extensions.encoded_len()
+                + chunk.len()
+                + self.options.digest.digest_len(),
+        );
+        piece.resize(TAGGED_HEADER_SIZE, 0);
+        extensions.encode(&mut piece)?;
+        piece.extend_from_slice(chunk);
+        let body = piece.get(TAGGED_HEADER_SIZE..).unwrap_or_default();
+        let payload_len = u32::try_from(body.len())
+            .map_err(|_| RkyvVersionedError::PayloadTooLargeError(body.len()))?;
+        let digest = self.options.digest.compute(body)?;
+        let piece_header = TaggedVersionedHeader {
+            payload_len,
+            digest: self.options.digest.id(),
+            compression: 0,
+            encryption: 0,
+            flags: HEADER_FLAG_EXTENSIONS,
+            ..*header
+        };
+        write_header(&mut piece, &piece_header)?;
+        piece.extend_from_slice(digest.as_bytes());
+        Ok(piece)
+    }
+
+    /// Writes one entry to the file and indexes it.
+    fn append_entry(
+        &mut self,
+        entry: &[u8],
+        version_id: u32,
+    ) -> Result<usize, RkyvVersionedError> {
+        let written = write_frame(entry, &mut self.writer)?;
+        self.index.push(FileIndexEntry {
+            offset: self.position + FRAME_PREFIX_SIZE as u64,
+            len: entry.len() as u32,
+            version_id,
+        });
+        self.position += written as u64;
+        Ok(self.index.len() - 1)
+    }
+
+    /// Writes the index and trailer, and returns the underlying stream.
+    ///
+    /// The stream is flushed but not synced to stable storage.
+    pub fn finish(mut self) -> Result<W, RkyvVersionedError> {
+        let mut footer =
+            Vec::with_capacity(self.index.len() * INDEX_ENTRY_SIZE + TRAILER_SIZE);
+        for entry in &self.index {
+            footer.extend_from_slice(&entry.to_bytes());
+        }
+        footer.extend_from_slice(&self.position.to_le_bytes());
+        footer.extend_from_slice(&(self.index.len() as u64).to_le_bytes());
+        footer.extend_from_slice(&FILE_MAGIC);
+        self.writer
+            .write_all(&footer)
+            .and_then(|_| self.writer.flush())
+            .map_err(io_error)?;
+        Ok(self.writer)
+    }
+}
+
+/// Random access to the records of a file written by [VersionedFileWriter], see the
+/// [module](self) documentation.
+#[derive(Debug)]
+pub struct VersionedFile<R> {
+    reader: R,
+    index: Vec<FileIndexEntry>,
+    indexed: bool,
+}
+
+impl<R: Read + Seek> VersionedFile<R> {
+    /// Opens a file, reading its index, or scanning its frames if it has no trailer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the file, or an error if the stream fails, or the trailer is
+    /// present but the index is inconsistent with it
+    /// ([RkyvVersionedError::InvalidMessageError]).
+    pub fn open(mut reader: R) -> Result<Self, RkyvVersionedError> {
+        let file_len = reader.seek(SeekFrom::End(0)).map_err(io_error)?;
+        if file_len >= TRAILER_SIZE as u64 {
+            let mut trailer = [0u8; TRAILER_SIZE];
+            reader
+                .seek(SeekFrom::End(-(TRAILER_SIZE as i64)))
+                .and_then(|_| reader.read_exact(&mut trailer))
+                .map_err(io_error)?;
+            if trailer[16..] == FILE_MAGIC {
+                let index = read_index(&mut reader, &trailer, file_len)?;
+                return Ok(VersionedFile {
+                    reader,
+                    index,
+                    indexed: true,
+                });
+            }
+        }
+        let index = scan_frames(&mut reader, file_len)?;
+        Ok(VersionedFile {
+            reader,
+            index,
+            indexed: false,
+        })
+    }
+
+    /// Returns whether the index was read from the trailer, rather than rebuilt by scanning
+    /// the frames of an unfinished file.
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Returns the number of entries in the file, counting each piece of a split record.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns whether the file holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the index entry of record `id`, e.g. to find its version without reading it.
+    pub fn entry(&self, id: usize) -> Option<&FileIndexEntry> {
+        self.index.get(id)
+    }
+
+    /// Returns the index entries of every record, in order.
+    pub fn entries(&self) -> &[FileIndexEntry] {
+        &self.index
+    }
+
+    /// Reads record `id` into an [AlignedVec], so that it can be accessed in place with e.g.
+    /// [access_from_tagged_bytes](crate::access_from_tagged_bytes).  The pieces of a split
+    /// record are joined again.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the tagged byte array, or an error if `id` is out of range
+    /// ([RkyvVersionedError::BufferTooSmallError]), it is a continuation entry or the pieces
+    /// of the record don't fit together ([RkyvVersionedError::InvalidMessageError]), or the
+    /// stream fails.
+    pub fn read_record(&mut self, id: usize) -> Result<AlignedVec, RkyvVersionedError> {
+        let entry = self.read_entry(id)?;
+        match piece_kind(&entry)? {
+            PieceKind::Whole => Ok(entry),
+            PieceKind::Primary(continuations) => self.join(id, &entry, &continuations),
+            PieceKind::Continuation(_) => Err(RkyvVersionedError::InvalidMessageError),
+        }
+    }
+
+    /// Reads the last `count` records of the file, or all of them if there are fewer, oldest
+    /// first.  Continuation entries are skipped, their records are joined from the primary
+    /// entry.
+    pub fn read_tail(&mut self, count: usize) -> Result<Vec<AlignedVec>, RkyvVersionedError> {
+        let mut tail = Vec::new();
+        for id in (0..self.index.len()).rev() {
+            if tail.len() == count {
+                break;
+            }
+            let entry = self.read_entry(id)?;
+            match piece_kind(&entry)? {
+                PieceKind::Whole => tail.push(entry),
+                PieceKind::Primary(continuations) => {
+                    tail.push(self.join(id, &entry, &continuations)?)
+                }
+                PieceKind::Continuation(_) => {}
+            }
+        }
+        tail.reverse();
+        Ok(tail)
+    }
+
+    /// Reads entry `id` as it is stored.
+    fn read_entry(&mut self, id: usize) -> Result<AlignedVec, RkyvVersionedError> {
+        let entry = *self
+            .index
+            .get(id)
+            .ok_or(RkyvVersionedError::BufferTooSmallError)?;
+        let mut record = AlignedVec::with_capacity(entry.len as usize);
+        record.resize(entry.len as usize, 0);
+        self.reader
+            .seek(SeekFrom::Start(entry.offset))
+            .and_then(|_| self.reader.read_exact(&mut record))
+            .map_err(io_error)?;
+        Ok(record)
+    }
+
+    /// Joins the chunks of the primary entry `id` and its continuations.
+    fn join(
+        &mut self,
+        id: usize,
+        primary: &[u8],
+        continuations: &[usize],
+    ) -> Result<AlignedVec, RkyvVersionedError> {
+        let mut record = AlignedVec::new();
+        record.extend_from_slice(piece_chunk(primary)?);
+        for &continuation in continuations {
+            let entry = self.read_entry(continuation)?;
+            if piece_kind(&entry)? != PieceKind::Continuation(id) {
+                return Err(RkyvVersionedError::InvalidMessageError);
+            }
+            record.extend_from_slice(piece_chunk(&entry)?);
+        }
+        if read_header(&record)?.tagged_len()? != record.len() {
+            return Err(RkyvVersionedError::InvalidMessageError);
+        }
+        Ok(record)
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// The part an entry plays in a split record.
+#[derive(Debug, PartialEq, Eq)]
+enum PieceKind {
+    /// A record which wasn't split.
+    Whole,
+    /// The primary entry of a split record, with the IDs of its continuations.
+    Primary(Vec<usize>),
+    /// A continuation entry, with the ID of its primary entry.
+    Continuation(usize),
+}
+
+/// Reads the part `entry` plays in a split record from its extensions.
+fn piece_kind(entry: &[u8]) -> Result<PieceKind, RkyvVersionedError> {
+    if read_header(entry)?.flags & HEADER_FLAG_EXTENSIONS == 0 {
+        return Ok(PieceKind::Whole);
+    }
+    let extensions = get_extensions_from_tagged_bytes(entry)?;
+    if let Some(ids) = extensions.get(EXTENSION_KEY_CONTINUATIONS) {
+        if ids.len() % 8 != 0 {
+            return Err(RkyvVersionedError::InvalidExtensionError(
+                EXTENSION_KEY_CONTINUATIONS,
+            ));
+        }
+        let ids = ids
+            .chunks_exact(8)
+            .flat_map(<[u8; 8]>::try_from)
+            .map(|id| u64::from_le_bytes(id) as usize)
+            .collect();
+        return Ok(PieceKind::Primary(ids));
+    }
+    match extensions.get(EXTENSION_KEY_CONTINUATION_OF) {
+        Some(id) => {
+            let id: [u8; 8] = id.try_into().map_err(|_| {
+                RkyvVersionedError::InvalidExtensionError(EXTENSION_KEY_CONTINUATION_OF)
+            })?;
+            Ok(PieceKind::Continuation(u64::from_le_bytes(id) as usize))
+        }
+        None => Ok(PieceKind::Whole),
+    }
+}
+
+/// Returns the chunk of a split record carried by the piece `entry`, checking its digest.
+fn piece_chunk(entry: &[u8]) -> Result<&[u8], RkyvVersionedError> {
+    let header = read_header(entry)?;
+    let body = verify_payload(entry, &header)?;
+    let (_, chunk) = split_metadata(&header, body)?;
+    Ok(chunk)
+}
+
+/// Reads the index described by `trailer` and checks it is consistent with the file.
+fn read_index(
+    reader: &mut (impl Read + Seek),
+    trailer: &[u8; TRAILER_SIZE],
+    file_len: u64,
+) -> Result<Vec<FileIndexEntry>, RkyvVersionedError> {
+    let (index_offset, rest) = trailer
+        .split_first_chunk()
+        .ok_or(RkyvVersionedError::InvalidMessageError)?;
+    let count = rest
+        .first_chunk()
+        .ok_or(RkyvVersionedError::InvalidMessageError)?;
+    let (index_offset, count) = (
+        u64::from_le_bytes(*index_offset),
+        u64::from_le_bytes(*count),
+    );
+    let index_len = count
+        .checked_mul(INDEX_ENTRY_SIZE as u64)
+        .filter(|index_len| {
+            index_offset.checked_add(*index_len) == Some(file_len - TRAILER_SIZE as u64)
+        })
+        .ok_or(RkyvVersionedError::InvalidMessageError)?;
+
+    let mut bytes = vec![0u8; index_len as usize];
+    reader
+        .seek(SeekFrom::Start(index_offset))
+        .and_then(|_| reader.read_exact(&mut bytes))
+        .map_err(io_error)?;
+    let index: Vec<_> = bytes
+        .chunks_exact(INDEX_ENTRY_SIZE)
+        .map(FileIndexEntry::from_bytes)
+        .collect::<Option<_>>()
+        .ok_or(RkyvVersionedError::InvalidMessageError)?;
+    if index
+        .iter()
+        .any(|entry| entry.offset + entry.len as u64 > index_offset)
+    {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+    Ok(index)
+}
+
+/// Rebuilds the index of an unfinished file from its frames.
+pub(crate) fn scan_frames(
+    reader: &mut (impl Read + Seek),
+    file_len: u64,
+) -> Result<Vec<FileIndexEntry>, RkyvVersionedError> {
+    let mut index = Vec::new();
+    let mut position = 0u64;
+    let mut start = [0u8; FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE];
+    reader.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    while position + start.len() as u64 <= file_len {
+        reader.read_exact(&mut start).map_err(io_error)?;
+        let Some((prefix, header)) = start.split_first_chunk() else {
+            break;
+        };
+        let Some(record_len) = parse_frame_prefix(prefix) else {
+            break;
+        };
+        let Ok(header) = read_header(header) else {
+            break;
+        };
+        let offset = position + FRAME_PREFIX_SIZE as u64;
+        if header.tagged_len().ok() != Some(record_len)
+            || offset + record_len as u64 > file_len
+        {
+            break;
+        }
+        index.push(FileIndexEntry {
+            offset,
+            len: record_len as u32,
+            version_id: header.version_id,
+        });
+        position = offset + (record_len + frame_padding(record_len)) as u64;
+        reader.seek(SeekFrom::Start(position)).map_err(io_error)?;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::io::Cursor;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(String),
+        V2(u64),
+    }
+
+    #[test]
+    fn test_versioned_file() {
+        let mut writer = VersionedFileWriter::new(Vec::new());
+        for value in 0..10u64 {
+            let id = match value % 4 {
+                0 => writer.append(&Event::V1(format!("event-{}", value))),
+                _ => writer.append(&Event::V2(value)),
+            };
+            assert_eq!(id.unwrap(), value as usize);
+        }
+        let unfinished = writer.writer.clone();
+        let bytes = writer.finish().unwrap();
+
+        let mut file = VersionedFile::open(Cursor::new(&bytes)).unwrap();
+        assert!(file.is_indexed());
+        assert_eq!(file.len(), 10);
+        assert_eq!(file.entry(4).unwrap().version_id, 0);
+        assert_eq!(file.entry(5).unwrap().version_id, 1);
+        assert_eq!(
+            file.read_record(8).unwrap().as_slice(),
+            to_tagged_bytes(&Event::V1("event-8".to_string()))
+                .unwrap()
+                .as_slice()
+        );
+        match access_from_tagged_bytes::<Event>(&file.read_record(7).unwrap()).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 7),
+            _ => panic!("Expected V2"),
+        }
+        assert!(file.read_record(10).is_err());
+
+        let tail = file.read_tail(3).unwrap();
+        assert_eq!(tail.len(), 3);
+        match access_from_tagged_bytes::<Event>(&tail[2]).unwrap() {
+            ArchivedEvent::V2(value) => assert_eq!(*value, 9),
+            _ => panic!("Expected V2"),
+        }
+        assert_eq!(file.read_tail(100).unwrap().len(), 10);
+
+        // An unfinished file, torn part way through its last frame, is scanned instead
+        let torn = &unfinished[..unfinished.len() - 20];
+        let scanned = VersionedFile::open(Cursor::new(torn)).unwrap();
+        assert!(!scanned.is_indexed());
+        assert_eq!(scanned.entries(), &file.entries()[..9]);
+
+        // A trailer inconsistent with the file is rejected
+        let mut corrupt = bytes.clone();
+        let count_offset = corrupt.len() - 16;
+        corrupt[count_offset] += 1;
+        assert!(matches!(
+            VersionedFile::open(Cursor::new(&corrupt)),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+
+        // Records over the maximum entry length are split and joined again
+        let mut writer = VersionedFileWriter::new(Vec::new()).with_max_entry_len(64);
+        let long = Event::V1("x".repeat(200));
+        assert_eq!(writer.append(&Event::V2(1)).unwrap(), 0);
+        let long_id = writer.append(&long).unwrap();
+        assert_eq!(writer.append(&Event::V2(2)).unwrap(), long_id + 1);
+        assert!(writer.len() > 4);
+        let bytes = writer.finish().unwrap();
+        let mut file = VersionedFile::open(Cursor::new(&bytes)).unwrap();
+        let record = file.read_record(long_id).unwrap();
+        assert_eq!(
+            record.as_slice(),
+            to_tagged_bytes(&long).unwrap().as_slice()
+        );
+        assert!(file
+            .entries()
+            .iter()
+            .all(|entry| entry.len as usize <= 64 + 64));
+        assert!(matches!(
+            file.read_record(1),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+        let tail = file.read_tail(2).unwrap();
+        assert_eq!(tail[0].as_slice(), record.as_slice());
+        assert_eq!(file.read_tail(10).unwrap().len(), 3);
+
+        let empty = VersionedFileWriter::new(Vec::new()).finish().unwrap();
+        assert!(VersionedFile::open(Cursor::new(&empty)).unwrap().is_empty());
+    }
+}
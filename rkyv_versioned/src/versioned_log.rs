@@ -0,0 +1,531 @@
+//! An append-only log of tagged records, split into segment files.
+//!
+//! [VersionedLog] appends records to the newest segment in a directory through a
+//! [LogWriter], so the [SyncPolicy] controls their durability, and starts a new segment once
+//! the current one reaches a configurable size.  Each segment is a stream of the
+//! length-prefixed frames of [write_tagged](crate::write_tagged), named after its sequence
+//! number, e.g. `00000000000000000003.vlog`, so old segments can be archived or deleted
+//! oldest first.
+//!
+//! [LogTailer] reads the records of a log in order, following new ones as they are appended
+//! by a writer in this or another process.  It never blocks: once it has caught up it returns
+//! `None`, and the caller polls it again later.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::framing::{frame_padding, parse_frame_prefix};
+use crate::io::io_error;
+use crate::log_writer::{LogWriter, SyncPolicy};
+use crate::versioned_file::scan_frames;
+use crate::{
+    read_header, to_tagged_bytes_with, RkyvVersionedError, TaggedOptions, VersionedContainer,
+    FRAME_PREFIX_SIZE, TAGGED_HEADER_SIZE,
+};
+
+/// The extension of segment files.
+const SEGMENT_EXTENSION: &str = "vlog";
+
+/// The path of segment `segment` of the log in `dir`.
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", segment, SEGMENT_EXTENSION))
+}
+
+/// Lists the sequence numbers of the segments in `dir`, oldest first.
+fn list_segments(dir: &Path) -> Result<Vec<u64>, RkyvVersionedError> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(segment) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok())
+        {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Returns whether the bytes from `offset` to the end of a segment, after its last complete
+/// frame, were left by a crash while appending a frame rather than corrupted: too few to hold
+/// a frame prefix and header, the start of a frame extending past the end of the segment, or
+/// zeros where the file system had allocated the frame.
+fn is_torn_tail(
+    file: &mut File,
+    offset: u64,
+    file_len: u64,
+) -> Result<bool, RkyvVersionedError> {
+    let mut start = [0u8; FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE];
+    if file_len - offset < start.len() as u64 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut start))
+        .map_err(io_error)?;
+    if let Some((prefix, header)) = start.split_first_chunk() {
+        let tagged_len = read_header(header)
+            .and_then(|header| header.tagged_len())
+            .ok();
+        if let Some(record_len) =
+            parse_frame_prefix(prefix).filter(|len| Some(*len) == tagged_len)
+        {
+            return Ok(offset + (FRAME_PREFIX_SIZE + record_len) as u64 > file_len);
+        }
+    }
+
+    let mut chunk = [0u8; 4096];
+    let mut zeros = start.iter().all(|b| *b == 0);
+    while zeros {
+        let read = file.read(&mut chunk).map_err(io_error)?;
+        if read == 0 {
+            break;
+        }
+        zeros = chunk.iter().take(read).all(|b| *b == 0);
+    }
+    Ok(zeros)
+}
+
+/// An append-only log of tagged records in a directory, see the [module](self) documentation.
+#[derive(Debug)]
+pub struct VersionedLog {
+    dir: PathBuf,
+    policy: SyncPolicy,
+    options: TaggedOptions,
+    max_segment_len: Option<u64>,
+    segment: u64,
+    segment_len: u64,
+    writer: LogWriter<File>,
+}
+
+impl VersionedLog {
+    /// Opens the log in `dir`, creating the directory if needed, and positions it after the
+    /// last complete record of the newest segment.
+    ///
+    /// A record left incomplete by a crash at the end of the newest segment is truncated, so
+    /// that the records appended next can be read back.  A corrupt frame followed by more data
+    /// isn't a torn write though, and is left in place for
+    /// [repair_file](crate::repair_file) to recover the records after it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the log, an [RkyvVersionedError::InvalidFrameError] with the
+    /// offset of the first corrupt frame of the newest segment, or an error if the directory
+    /// or segment can't be accessed.
+    pub fn open(
+        dir: impl AsRef<Path>,
+        policy: SyncPolicy,
+    ) -> Result<Self, RkyvVersionedError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let segment = list_segments(&dir)?.last().copied().unwrap_or(0);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(segment_path(&dir, segment))
+            .map_err(io_error)?;
+        let file_len = file.metadata().map_err(io_error)?.len();
+        let segment_len = match scan_frames(&mut file, file_len)?.last() {
+            Some(last) => {
+                last.offset + (last.len as usize + frame_padding(last.len as usize)) as u64
+            }
+            None => 0,
+        };
+        if segment_len < file_len && !is_torn_tail(&mut file, segment_len, file_len)? {
+            return Err(RkyvVersionedError::InvalidFrameError(segment_len as usize));
+        }
+        if segment_len != file_len {
+            file.set_len(segment_len).map_err(io_error)?;
+        }
+
+        Ok(VersionedLog {
+            writer: LogWriter::new(file, policy),
+            dir,
+            policy,
+            options: TaggedOptions::default(),
+            max_segment_len: None,
+            segment,
+            segment_len,
+        })
+    }
+
+    /// Starts a new segment once the current one holds at least `max_segment_len` bytes.
+    ///
+    /// Records are never split, so a segment exceeds the limit by up to one record.
+    pub fn with_max_segment_len(mut self, max_segment_len: u64) -> Self {
+        self.max_segment_len = Some(max_segment_len);
+        self
+    }
+
+    /// Tags the records appended with `options` instead of the default [TaggedOptions].
+    pub fn with_options(mut self, options: TaggedOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the directory holding the log.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the sequence number of the segment being appended to.
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+
+    /// Serializes `item` and appends it to the log, syncing according to the [SyncPolicy].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of bytes appended, or an error if serialization, the
+    /// write, the sync or starting a new segment fails.
+    pub fn append<T>(&mut self, item: &T) -> Result<usize, RkyvVersionedError>
+    where
+        T: VersionedContainer
+            + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+    {
+        let record = to_tagged_bytes_with(item, &self.options)?;
+        self.append_record(&record)
+    }
+
+    /// Appends an already tagged byte array to the log.
+    pub fn append_record(&mut self, record: &[u8]) -> Result<usize, RkyvVersionedError> {
+        let written = self.writer.append_record(record)?;
+        self.segment_len += written as u64;
+        if self
+            .max_segment_len
+            .is_some_and(|max| self.segment_len >= max)
+        {
+            self.rotate()?;
+        }
+        Ok(written)
+    }
+
+    /// Syncs the current segment and starts a new one.
+    pub fn rotate(&mut self) -> Result<(), RkyvVersionedError> {
+        self.writer.sync()?;
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(segment_path(&self.dir, self.segment + 1))
+            .map_err(io_error)?;
+        self.writer = LogWriter::new(file, self.policy);
+        self.segment += 1;
+        self.segment_len = 0;
+        Ok(())
+    }
+
+    /// Syncs the records appended so far, whatever the policy.
+    pub fn sync(&mut self) -> Result<(), RkyvVersionedError> {
+        self.writer.sync()
+    }
+}
+
+/// Reads the records of a [VersionedLog] in order, following new ones as they are appended.
+#[derive(Debug)]
+pub struct LogTailer {
+    dir: PathBuf,
+    segment: u64,
+    /// The current segment, once it exists.
+    file: Option<File>,
+    /// The offset of the next frame in the current segment.
+    position: u64,
+}
+
+impl LogTailer {
+    /// Creates a tailer reading the log in `dir` from its oldest segment.
+    pub fn from_start(dir: impl AsRef<Path>) -> Result<Self, RkyvVersionedError> {
+        let dir = dir.as_ref().to_path_buf();
+        let segment = list_segments(&dir)?.first().copied().unwrap_or(0);
+        Ok(LogTailer {
+            dir,
+            segment,
+            file: None,
+            position: 0,
+        })
+    }
+
+    /// Creates a tailer skipping the records already in the log in `dir`, only reading those
+    /// appended from now on.
+    pub fn from_end(dir: impl AsRef<Path>) -> Result<Self, RkyvVersionedError> {
+        let dir = dir.as_ref().to_path_buf();
+        let segment = list_segments(&dir)?.last().copied().unwrap_or(0);
+        let mut tailer = LogTailer {
+            dir,
+            segment,
+            file: None,
+            position: 0,
+        };
+        while tailer.read_frame()?.is_some() {}
+        Ok(tailer)
+    }
+
+    /// Returns the sequence number of the segment being read.
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+
+    /// Reads the next record of the log into an [AlignedVec], so that it can be accessed in
+    /// place with e.g. [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the tagged byte array, `None` if no complete record has been
+    /// appended since the last one read, or an error if a segment can't be read or holds a
+    /// corrupt frame ([RkyvVersionedError::InvalidFrameError]).
+    pub fn next_record(&mut self) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+        loop {
+            if let Some(record) = self.read_frame()? {
+                return Ok(Some(record));
+            }
+            // The writer only starts the next segment once it is done with this one, so
+            // anything left here is a torn write and can be skipped
+            let next = self.segment + 1;
+            if !segment_path(&self.dir, next).exists() {
+                return Ok(None);
+            }
+            self.segment = next;
+            self.file = None;
+            self.position = 0;
+        }
+    }
+
+    /// Reads the frame at the current position, if it is complete.
+    fn read_frame(&mut self) -> Result<Option<AlignedVec>, RkyvVersionedError> {
+        if self.file.is_none() {
+            match File::open(segment_path(&self.dir, self.segment)) {
+                Ok(file) => self.file = Some(file),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(None);
+        };
+
+        let file_len = file.metadata().map_err(io_error)?.len();
+        if self.position + FRAME_PREFIX_SIZE as u64 > file_len {
+            return Ok(None);
+        }
+        let mut prefix = [0u8; FRAME_PREFIX_SIZE];
+        file.seek(SeekFrom::Start(self.position))
+            .and_then(|_| file.read_exact(&mut prefix))
+            .map_err(io_error)?;
+        let record_len = parse_frame_prefix(&prefix).ok_or(
+            RkyvVersionedError::InvalidFrameError(self.position as usize),
+        )?;
+        let frame_len = (FRAME_PREFIX_SIZE + record_len + frame_padding(record_len)) as u64;
+        if self.position + frame_len > file_len {
+            return Ok(None);
+        }
+
+        let mut record = AlignedVec::with_capacity(record_len);
+        record.resize(record_len, 0);
+        file.read_exact(&mut record).map_err(io_error)?;
+        self.position += frame_len;
+        Ok(Some(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::{Archive, Deserialize, Serialize};
+    use std::io::Write;
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum Event {
+        V1(u64),
+    }
+
+    fn value_of(record: &AlignedVec) -> u64 {
+        match access_from_tagged_bytes::<Event>(record).unwrap() {
+            ArchivedEvent::V1(value) => value.to_native(),
+        }
+    }
+
+    /// Returns an empty directory for the log of the test `name`.
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rkyv_versioned_log_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// The number of records written by [write_log].
+    const RECORDS: u64 = 3;
+
+    /// Writes the values `0..RECORDS` to a single segment log in `dir`, returning the path of
+    /// the segment and its contents.
+    fn write_log(dir: &Path) -> (PathBuf, Vec<u8>) {
+        let mut log = VersionedLog::open(dir, SyncPolicy::EveryEntry).unwrap();
+        for value in 0..RECORDS {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        let path = segment_path(dir, log.segment());
+        drop(log);
+        let contents = fs::read(&path).unwrap();
+        (path, contents)
+    }
+
+    /// Appends `tail` to the segment at `path`, checking that reopening the log truncates it.
+    fn assert_tail_truncated(dir: &Path, path: &Path, tail: &[u8]) {
+        let contents = fs::read(path).unwrap();
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(tail).unwrap();
+        drop(file);
+
+        let mut log = VersionedLog::open(dir, SyncPolicy::EveryEntry).unwrap();
+        assert_eq!(fs::read(path).unwrap(), contents);
+        log.append(&Event::V1(u64::MAX)).unwrap();
+        drop(log);
+
+        // Records appended after the truncation can be read back
+        let mut reread = LogTailer::from_start(dir).unwrap();
+        let mut values = Vec::new();
+        while let Some(record) = reread.next_record().unwrap() {
+            values.push(value_of(&record));
+        }
+        let expected: Vec<_> = (0..RECORDS).chain([u64::MAX]).collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_tailer_reads_appended_records() {
+        let dir = log_dir("append");
+        let mut log = VersionedLog::open(&dir, SyncPolicy::EveryEntries(2)).unwrap();
+        let mut tailer = LogTailer::from_start(&dir).unwrap();
+        assert!(tailer.next_record().unwrap().is_none());
+
+        for value in 0..3 {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        for value in 0..3 {
+            assert_eq!(value_of(&tailer.next_record().unwrap().unwrap()), value);
+        }
+        assert!(tailer.next_record().unwrap().is_none());
+
+        log.append(&Event::V1(3)).unwrap();
+        assert_eq!(value_of(&tailer.next_record().unwrap().unwrap()), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tailer_follows_segments() {
+        let dir = log_dir("segments");
+        let mut log = VersionedLog::open(&dir, SyncPolicy::EveryEntries(2))
+            .unwrap()
+            .with_max_segment_len(128);
+        let mut tailer = LogTailer::from_start(&dir).unwrap();
+        for value in 0..3 {
+            log.append(&Event::V1(value)).unwrap();
+        }
+
+        // Only the records appended after a tailer is created from the end are read by it
+        let mut late = LogTailer::from_end(&dir).unwrap();
+        for value in 3..10 {
+            log.append(&Event::V1(value)).unwrap();
+        }
+        assert!(log.segment() > 0);
+        for value in 0..3 {
+            assert_eq!(value_of(&tailer.next_record().unwrap().unwrap()), value);
+        }
+        for value in 3..10 {
+            assert_eq!(value_of(&tailer.next_record().unwrap().unwrap()), value);
+            assert_eq!(value_of(&late.next_record().unwrap().unwrap()), value);
+        }
+        assert!(tailer.next_record().unwrap().is_none());
+        assert!(late.next_record().unwrap().is_none());
+
+        // Reopening carries on in the newest segment
+        let segment = log.segment();
+        drop(log);
+        let mut log = VersionedLog::open(&dir, SyncPolicy::EveryEntry).unwrap();
+        assert_eq!(log.segment(), segment);
+        log.append(&Event::V1(10)).unwrap();
+        assert_eq!(value_of(&tailer.next_record().unwrap().unwrap()), 10);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_prefix_is_truncated() {
+        let dir = log_dir("torn_prefix");
+        let (path, _) = write_log(&dir);
+        assert_tail_truncated(&dir, &path, &[0x30, 0, 0, 0, 0, 0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_frame_is_truncated() {
+        // A frame whose header made it to disk but not the rest
+        let dir = log_dir("torn_frame");
+        let (path, contents) = write_log(&dir);
+        assert_tail_truncated(
+            &dir,
+            &path,
+            &contents[..FRAME_PREFIX_SIZE + TAGGED_HEADER_SIZE + 4],
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_zeroed_tail_is_truncated() {
+        // Space allocated for a frame that was never written, longer than the frame start
+        // checked first and than a single read
+        let dir = log_dir("zeroed_tail");
+        let (path, _) = write_log(&dir);
+        assert_tail_truncated(&dir, &path, &[0; 5000]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_data_after_zeros_is_reported() {
+        let dir = log_dir("data_after_zeros");
+        let (path, contents) = write_log(&dir);
+        let mut tail = vec![0; 5000];
+        tail[4500] = 1;
+        let mut corrupt = contents.clone();
+        corrupt.extend_from_slice(&tail);
+        fs::write(&path, &corrupt).unwrap();
+
+        assert!(matches!(
+            VersionedLog::open(&dir, SyncPolicy::EveryEntry),
+            Err(RkyvVersionedError::InvalidFrameError(offset)) if offset == contents.len()
+        ));
+        assert_eq!(fs::read(&path).unwrap(), corrupt);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_frame_is_reported() {
+        // Corruption before the last frame is reported, leaving the segment as it was
+        let dir = log_dir("corrupt");
+        let (path, contents) = write_log(&dir);
+        let mut corrupt = contents.clone();
+        corrupt[4] = 0xff;
+        fs::write(&path, &corrupt).unwrap();
+
+        assert!(matches!(
+            VersionedLog::open(&dir, SyncPolicy::EveryEntry),
+            Err(RkyvVersionedError::InvalidFrameError(0))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), corrupt);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
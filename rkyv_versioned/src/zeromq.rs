@@ -0,0 +1,211 @@
+//! Publish/subscribe adapters for ZeroMQ sockets, enabled with the `zmq` feature.
+//!
+//! Tagged byte arrays are sent as two-frame messages: a topic frame followed by the tagged
+//! byte array itself.  The topic is the little-endian `type_id` followed by the little-endian
+//! `version_id`, so subscribers can use ZeroMQ's prefix filtering to receive every version of
+//! a container ([subscribe]) or a single version only ([subscribe_version]).
+
+use rkyv::api::high::HighSerializer;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::Serialize;
+
+use crate::{
+    get_type_and_version_from_tagged_bytes, to_tagged_bytes_with, RkyvVersionedError,
+    TaggedOptions, VersionedContainer,
+};
+
+/// Returns the topic prefix matching every version of `T`.
+pub fn topic_for<T: VersionedContainer>() -> [u8; 4] {
+    T::ARCHIVE_TYPE_ID.to_le_bytes()
+}
+
+/// Returns the topic of a specific version of `T`.
+pub fn topic_for_version<T: VersionedContainer>(version_id: u32) -> [u8; 8] {
+    topic(T::ARCHIVE_TYPE_ID, version_id)
+}
+
+fn topic(type_id: u32, version_id: u32) -> [u8; 8] {
+    let mut topic = [0u8; 8];
+    topic[..4].copy_from_slice(&type_id.to_le_bytes());
+    topic[4..].copy_from_slice(&version_id.to_le_bytes());
+    topic
+}
+
+fn transport_error(e: ::zmq::Error) -> RkyvVersionedError {
+    RkyvVersionedError::TransportError(Box::new(e))
+}
+
+/// Subscribes a `SUB` socket to every version of `T`.
+pub fn subscribe<T: VersionedContainer>(
+    socket: &::zmq::Socket,
+) -> Result<(), RkyvVersionedError> {
+    socket
+        .set_subscribe(&topic_for::<T>())
+        .map_err(transport_error)
+}
+
+/// Subscribes a `SUB` socket to a single version of `T`.
+pub fn subscribe_version<T: VersionedContainer>(
+    socket: &::zmq::Socket,
+    version_id: u32,
+) -> Result<(), RkyvVersionedError> {
+    socket
+        .set_subscribe(&topic_for_version::<T>(version_id))
+        .map_err(transport_error)
+}
+
+/// Serializes `item` and publishes it on `socket` under its topic.
+pub fn publish<T>(
+    socket: &::zmq::Socket,
+    item: &T,
+    options: &TaggedOptions,
+) -> Result<(), RkyvVersionedError>
+where
+    T: VersionedContainer
+        + for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rkyv::rancor::Error>>,
+{
+    let bytes = to_tagged_bytes_with(item, options)?;
+    publish_tagged(socket, &bytes)
+}
+
+/// Publishes an already tagged byte array on `socket`, deriving the topic from its header.
+pub fn publish_tagged(
+    socket: &::zmq::Socket,
+    tagged: &[u8],
+) -> Result<(), RkyvVersionedError> {
+    let (type_id, version_id) = get_type_and_version_from_tagged_bytes(tagged)?;
+    socket
+        .send_multipart([&topic(type_id, version_id)[..], tagged], 0)
+        .map_err(transport_error)
+}
+
+/// Receives a message published by [publish] or [publish_tagged], copying the tagged byte
+/// array into an aligned buffer so it can be accessed with
+/// [access_from_tagged_bytes](crate::access_from_tagged_bytes).
+///
+/// Messages with the wrong number of frames, or whose topic does not match the header of the
+/// tagged byte array, are rejected with [RkyvVersionedError::InvalidMessageError].
+pub fn receive_tagged(socket: &::zmq::Socket) -> Result<AlignedVec, RkyvVersionedError> {
+    let frames = socket.recv_multipart(0).map_err(transport_error)?;
+    let [topic_frame, tagged] = frames.as_slice() else {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    };
+
+    let (type_id, version_id) = get_type_and_version_from_tagged_bytes(tagged)?;
+    if topic_frame.as_slice() != topic(type_id, version_id) {
+        return Err(RkyvVersionedError::InvalidMessageError);
+    }
+
+    let mut bytes = AlignedVec::with_capacity(tagged.len());
+    bytes.extend_from_slice(tagged);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_from_tagged_bytes, to_tagged_bytes, VersionedArchiveContainer};
+    use rkyv::with::InlineAsBox;
+    use rkyv::{Archive, Deserialize};
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Quote {
+        pub symbol: String,
+        pub price: u64,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize)]
+    struct Trade {
+        pub symbol: String,
+        pub quantity: u32,
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum QuoteContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Quote),
+    }
+
+    #[derive(Debug, Archive, Serialize, Deserialize, VersionedArchiveContainer)]
+    enum TradeContainer<'a> {
+        V1(#[rkyv(with=InlineAsBox)] &'a Trade),
+    }
+
+    #[test]
+    fn test_zmq_pub_sub_filters_by_type() {
+        let context = ::zmq::Context::new();
+        let publisher = context.socket(::zmq::PUB).unwrap();
+        publisher.bind("inproc://test_zmq_pub_sub").unwrap();
+        let subscriber = context.socket(::zmq::SUB).unwrap();
+        subscriber.connect("inproc://test_zmq_pub_sub").unwrap();
+        subscriber.set_rcvtimeo(100).unwrap();
+        subscribe::<QuoteContainer>(&subscriber).unwrap();
+
+        let quote = Quote {
+            symbol: "RKYV".to_owned(),
+            price: 100,
+        };
+        let trade = Trade {
+            symbol: "RKYV".to_owned(),
+            quantity: 5,
+        };
+
+        // Subscriptions propagate asynchronously, so keep publishing until one arrives
+        let mut received = None;
+        for _ in 0..50 {
+            publish(
+                &publisher,
+                &TradeContainer::V1(&trade),
+                &TaggedOptions::default(),
+            )
+            .unwrap();
+            publish(
+                &publisher,
+                &QuoteContainer::V1(&quote),
+                &TaggedOptions::default(),
+            )
+            .unwrap();
+            if let Ok(bytes) = receive_tagged(&subscriber) {
+                received = Some(bytes);
+                break;
+            }
+        }
+
+        let bytes = received.expect("no message received");
+        match access_from_tagged_bytes::<QuoteContainer>(&bytes).unwrap() {
+            ArchivedQuoteContainer::V1(quote) => {
+                assert_eq!(quote.symbol, "RKYV");
+                assert_eq!(quote.price, 100);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zmq_rejects_mismatched_topic() {
+        let context = ::zmq::Context::new();
+        let sender = context.socket(::zmq::PAIR).unwrap();
+        sender.bind("inproc://test_zmq_mismatched").unwrap();
+        let receiver = context.socket(::zmq::PAIR).unwrap();
+        receiver.connect("inproc://test_zmq_mismatched").unwrap();
+
+        let quote = Quote {
+            symbol: "RKYV".to_owned(),
+            price: 100,
+        };
+        let tagged = to_tagged_bytes(&QuoteContainer::V1(&quote)).unwrap();
+
+        sender
+            .send_multipart([&topic_for_version::<TradeContainer>(0)[..], &tagged], 0)
+            .unwrap();
+        assert!(matches!(
+            receive_tagged(&receiver),
+            Err(RkyvVersionedError::InvalidMessageError)
+        ));
+
+        publish_tagged(&sender, &tagged).unwrap();
+        assert_eq!(
+            receive_tagged(&receiver).unwrap().as_slice(),
+            tagged.as_slice()
+        );
+    }
+}
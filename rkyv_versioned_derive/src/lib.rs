@@ -1,19 +1,63 @@
 use proc_macro2::TokenStream;
-use quote::quote;
-use syn::{Data, DataEnum, DeriveInput, Fields, Generics, Ident};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    Attribute, Data, DataEnum, DeriveInput, Field, Fields, Generics, Ident, LitStr, Path, Type,
+};
 
 /// Derive macro for automatically implementing VersionedArchiveContainer for an enum.
 ///
 /// See the `VersionedContainer` trait and the example in the `rkyv_versioned` crate for more
 /// details.
-#[proc_macro_derive(VersionedArchiveContainer)]
+///
+/// The behaviour can be customized with `#[vac(...)]` attributes on the enum:
+/// - `#[vac(compare(PartialEq))]`: Implements `PartialEq<Container>` for `ArchivedContainer`
+///   by comparing the archived and owned values of matching variants, so each archived
+///   variant type must implement `PartialEq` against its owned type (e.g. through
+///   `#[rkyv(compare(PartialEq))]`).  This replaces `#[rkyv(compare(PartialEq))]` on the
+///   container itself, which can't compare variants wrapped with `InlineAsBox`.
+/// - `#[vac(proptest)]`: Implements `proptest::arbitrary::Arbitrary` for the container,
+///   generating every version with equal probability from the `Arbitrary` implementations of
+///   the variant types.  Requires the `proptest` feature of `rkyv_versioned` and owned
+///   (non-reference) variants.
+/// - `#[vac(debug_json)]`: Implements `DebugJson` for the container, rendering the archived
+///   value of any version as JSON by deserializing it and serializing the variant type with
+///   `serde`, for `to_debug_json`.  Requires the `debug_json` feature of `rkyv_versioned`,
+///   variant types implementing `serde::Serialize`, and reference variants to be wrapped with
+///   `InlineAsBox`.
+/// - `#[vac(fingerprint)]`: Records the `SchemaFingerprint` of the type of every variant in
+///   the `VERSION_TABLE`, which is stored in the records of each version and checked when
+///   reading them.  Every variant type must implement `SchemaFingerprint`, usually with
+///   `#[derive(SchemaFingerprint)]`.
+/// - `#[vac(constructors)]`: Generates a constructor per version named after the variant in
+///   snake case, e.g. `Container::v2(&value)` for `V2`, and `Container::latest(&value)` for
+///   the last variant, taking the declared type of the variant, so call sites needn't spell
+///   out the variants of containers with `InlineAsBox` references.
+/// - `#[vac(from)]`: Implements `From<T>` for the container for the declared type `T` of
+///   every variant, e.g. `From<&'a TestStructV1> for TestContainer<'a>`, so generic code can
+///   take `impl Into<Container>`.  Every variant must then have a distinct type.
+/// - `#[vac(crate = "my_facade::rkyv_versioned")]`: References `rkyv_versioned` through the
+///   given path in the generated code, for crates which re-export or rename it.
+///
+/// Variants can be marked with `#[vac(deprecated)]`, which is recorded in the generated
+/// `VERSION_TABLE` so that tooling can flag versions which shouldn't be written anymore, and
+/// with `#[vac(sunset = "2026-01-01")]`, after which reading the version is reported to the
+/// observer of a `ValidationConfig` (or rejected in its strict mode).
+#[proc_macro_derive(VersionedArchiveContainer, attributes(vac))]
 pub fn derive_versioned_archive_container(
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let input: DeriveInput = syn::parse(input).unwrap();
+    let input: DeriveInput = match syn::parse(input) {
+        Ok(input) => input,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let options = match ContainerOptions::parse(&input.attrs) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     let result = match input.data {
-        Data::Enum(data_enum) => generate(input.ident, data_enum, input.generics),
+        Data::Enum(data_enum) => generate(input.ident, data_enum, input.generics, &options),
         _ => {
             quote! { compile_error!("#[derive(VersionedArchiveContainer)] is only defined for enums") }
         }
@@ -22,13 +66,274 @@ pub fn derive_versioned_archive_container(
     result.into()
 }
 
-fn generate(enum_name: Ident, data_enum: DataEnum, generics: Generics) -> TokenStream {
+/// Derive macro implementing `SchemaFingerprint` for a struct or enum, from the fingerprints
+/// of its fields in order, for containers deriving with `#[vac(fingerprint)]`.
+///
+/// Field and type names don't contribute, since renaming them doesn't change the archived
+/// layout, but `#[rkyv(...)]` attributes on fields, such as `with` wrappers, do.  Every field
+/// type must implement `SchemaFingerprint`, and type parameters are required to.  The path
+/// to `rkyv_versioned` can be set with `#[vac(crate = "...")]` as for containers.
+#[proc_macro_derive(SchemaFingerprint, attributes(vac))]
+pub fn derive_schema_fingerprint(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = match syn::parse(input) {
+        Ok(input) => input,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let options = match ContainerOptions::parse(&input.attrs) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let krate = match &options.crate_path {
+        Some(path) => quote! { #path },
+        None => quote! { ::rkyv_versioned },
+    };
+
+    let fingerprint = match &input.data {
+        Data::Struct(data) => fields_fingerprint(&krate, "struct", &data.fields),
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| fields_fingerprint(&krate, "variant", &variant.fields));
+            quote! { #krate::combine_fingerprints("enum", &[#(#variants),*]) }
+        }
+        Data::Union(_) => {
+            return quote! { compile_error!("#[derive(SchemaFingerprint)] is not defined for unions"); }
+                .into()
+        }
+    };
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param
+            .bounds
+            .push(syn::parse_quote!(#krate::SchemaFingerprint));
+    }
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics #krate::SchemaFingerprint for #name #type_generics #where_clause {
+            const FINGERPRINT: u32 = #fingerprint;
+        }
+    }
+    .into()
+}
+
+/// Generates the fingerprint of the fields of a struct or enum variant, from the fingerprint
+/// and any `#[rkyv(...)]` attributes of each field
+fn fields_fingerprint(krate: &TokenStream, kind: &str, fields: &Fields) -> TokenStream {
+    let crc32_path = quote! { #krate::const_crc32::crc32 };
+    let parts = fields.iter().map(|field| {
+        let field_type = &field.ty;
+        let attributes = field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("rkyv"))
+            .map(|attr| attr.meta.to_token_stream().to_string())
+            .collect::<String>();
+        quote! {
+            #krate::combine_fingerprints(
+                "field",
+                &[<#field_type as #krate::SchemaFingerprint>::FINGERPRINT, #crc32_path(#attributes.as_bytes())],
+            )
+        }
+    });
+    quote! { #krate::combine_fingerprints(#kind, &[#(#parts),*]) }
+}
+
+/// Options set through `#[vac(...)]` attributes on the container
+#[derive(Default)]
+struct ContainerOptions {
+    compare_partial_eq: bool,
+    proptest: bool,
+    debug_json: bool,
+    fingerprint: bool,
+    constructors: bool,
+    from: bool,
+    crate_path: Option<Path>,
+}
+
+impl ContainerOptions {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut options = ContainerOptions::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("vac")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("compare") {
+                    meta.parse_nested_meta(|compare| {
+                        if compare.path.is_ident("PartialEq") {
+                            options.compare_partial_eq = true;
+                            Ok(())
+                        } else {
+                            Err(compare.error("only `PartialEq` comparisons are supported"))
+                        }
+                    })
+                } else if meta.path.is_ident("proptest") {
+                    options.proptest = true;
+                    Ok(())
+                } else if meta.path.is_ident("debug_json") {
+                    options.debug_json = true;
+                    Ok(())
+                } else if meta.path.is_ident("fingerprint") {
+                    options.fingerprint = true;
+                    Ok(())
+                } else if meta.path.is_ident("constructors") {
+                    options.constructors = true;
+                    Ok(())
+                } else if meta.path.is_ident("from") {
+                    options.from = true;
+                    Ok(())
+                } else if meta.path.is_ident("crate") {
+                    let path: LitStr = meta.value()?.parse()?;
+                    options.crate_path = Some(path.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported vac attribute"))
+                }
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// Options set through `#[vac(...)]` attributes on a variant
+#[derive(Default)]
+struct VariantOptions {
+    deprecated: bool,
+    /// The sunset date as a Unix timestamp, in seconds
+    sunset: Option<u64>,
+}
+
+impl VariantOptions {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut options = VariantOptions::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("vac")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("deprecated") {
+                    options.deprecated = true;
+                    Ok(())
+                } else if meta.path.is_ident("sunset") {
+                    let date: LitStr = meta.value()?.parse()?;
+                    let timestamp = parse_date(&date.value()).ok_or_else(|| {
+                        syn::Error::new(date.span(), "expected a date formatted as YYYY-MM-DD")
+                    })?;
+                    options.sunset = Some(timestamp);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported vac variant attribute"))
+                }
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date into the Unix timestamp of its start, in UTC
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (i64, i64, i64) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month[month as usize - 1]
+    {
+        return None;
+    }
+
+    // Days since the epoch, from Howard Hinnant's days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Some(days as u64 * 86400)
+}
+
+/// Converts a variant name such as `V2` or `FooBar` to the snake case name of its constructor
+fn constructor_name(variant: &Ident) -> Ident {
+    let mut name = String::new();
+    for (i, c) in variant.to_string().chars().enumerate() {
+        if c.is_uppercase() && i > 0 && !name.ends_with('_') {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+    format_ident!("{}", name)
+}
+
+/// Returns whether a variant field is archived with `InlineAsBox`
+fn is_inline_as_box(field: &Field) -> bool {
+    field.attrs.iter().any(|attr| {
+        attr.path().is_ident("rkyv")
+            && attr
+                .meta
+                .to_token_stream()
+                .to_string()
+                .contains("InlineAsBox")
+    })
+}
+
+/// Generates the comparison of an archived variant field against its owned counterpart, both
+/// bound by reference as `archived` and `owned`
+fn field_comparison(field: &Field) -> TokenStream {
+    match (&field.ty, is_inline_as_box(field)) {
+        (Type::Reference(_), true) => quote! { archived.get() == *owned },
+        (Type::Reference(_), false) => quote! { archived == *owned },
+        _ => quote! { archived == owned },
+    }
+}
+
+fn generate(
+    enum_name: Ident,
+    data_enum: DataEnum,
+    generics: Generics,
+    options: &ContainerOptions,
+) -> TokenStream {
     let string_name = enum_name.to_string();
+    let archived_name = format_ident!("Archived{}", enum_name);
+
+    // Fully qualified so that neither the trait nor const_crc32 needs to be in scope
+    let krate = match &options.crate_path {
+        Some(path) => quote! { #path },
+        None => quote! { ::rkyv_versioned },
+    };
+    let trait_path = quote! { #krate::VersionedContainer };
+    let crc32_path = quote! { #krate::const_crc32::crc32 };
     let mut error_messages = quote! {};
 
     // Parse the enum variants
     let mut valid_versions: Vec<TokenStream> = vec![];
     let mut match_branches = quote! {};
+    let mut compare_branches = quote! {};
+    let mut strategies: Vec<TokenStream> = vec![];
+    let mut debug_json_branches = quote! {};
+    let mut version_table: Vec<TokenStream> = vec![];
+    let mut constructors = quote! {};
+    let mut latest_constructor = quote! {};
+    let mut from_impls = quote! {};
+    let mut from_types: Vec<String> = vec![];
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
     for (variant_index, variant) in data_enum.variants.iter().enumerate() {
         // Cache this for error messages
         let current_field_debug_name = format!("{}::{}", enum_name, variant.ident);
@@ -49,6 +354,143 @@ fn generate(enum_name: Ident, data_enum: DataEnum, generics: Generics) -> TokenS
                 match_branches.extend(quote! {
                     #enum_name::#branch_name(_) => #variant_index_as_u32,
                 });
+
+                let field = &fields.unnamed[0];
+                let variant_options = match VariantOptions::parse(&variant.attrs) {
+                    Ok(variant_options) => variant_options,
+                    Err(e) => {
+                        error_messages.extend(e.to_compile_error());
+                        VariantOptions::default()
+                    }
+                };
+                let deprecated = variant_options.deprecated;
+                let sunset = match variant_options.sunset {
+                    Some(timestamp) => quote! { ::core::option::Option::Some(#timestamp) },
+                    None => quote! { ::core::option::Option::None },
+                };
+                let variant_name = branch_name.to_string();
+                // Only the declared type is visible here, not its fields
+                let schema = format!("{}({})", variant_name, field.ty.to_token_stream());
+                let fingerprint = match (options.fingerprint, &field.ty) {
+                    (false, _) => quote! { ::core::option::Option::None },
+                    // References have the fingerprint of what they refer to
+                    (true, Type::Reference(reference)) => {
+                        let field_type = &reference.elem;
+                        quote! {
+                            ::core::option::Option::Some(
+                                <#field_type as #krate::SchemaFingerprint>::FINGERPRINT
+                            )
+                        }
+                    }
+                    (true, field_type) => quote! {
+                        ::core::option::Option::Some(
+                            <#field_type as #krate::SchemaFingerprint>::FINGERPRINT
+                        )
+                    },
+                };
+                version_table.push(quote! {
+                    #krate::VersionInfo {
+                        version_id: #variant_index_as_u32,
+                        name: #variant_name,
+                        schema_hash: #crc32_path(#schema.as_bytes()),
+                        fingerprint: #fingerprint,
+                        deprecated: #deprecated,
+                        sunset: #sunset,
+                    }
+                });
+
+                if options.proptest {
+                    if let Type::Reference(_) = field.ty {
+                        let error_string = format!("#[vac(proptest)] requires owned variants, found a reference in {}", current_field_debug_name);
+                        error_messages.extend(quote! {
+                            compile_error!(#error_string);
+                        });
+                    }
+                    let field_type = &field.ty;
+                    strategies.push(quote! {
+                        #krate::proptest::arbitrary::any::<#field_type>()
+                            .prop_map(#enum_name::#branch_name)
+                    });
+                }
+
+                if options.debug_json {
+                    // Deserialize the referenced value of InlineAsBox variants
+                    let rendering = match (&field.ty, is_inline_as_box(field)) {
+                        (Type::Reference(reference), true) => {
+                            let field_type = &reference.elem;
+                            quote! {
+                                #krate::archived_variant_to_json::<_, #field_type>(archived.get())
+                            }
+                        }
+                        (Type::Reference(_), false) => {
+                            let error_string = format!("#[vac(debug_json)] requires reference variants to be wrapped with InlineAsBox, found {}", current_field_debug_name);
+                            error_messages.extend(quote! {
+                                compile_error!(#error_string);
+                            });
+                            quote! { unreachable!() }
+                        }
+                        (field_type, _) => quote! {
+                            #krate::archived_variant_to_json::<_, #field_type>(archived)
+                        },
+                    };
+                    debug_json_branches.extend(quote! {
+                        #archived_name::#branch_name(archived) => #rendering,
+                    });
+                }
+
+                if options.constructors {
+                    let constructor = constructor_name(branch_name);
+                    if constructor == "latest" {
+                        let error_string = format!("#[vac(constructors)] generates `latest` for the last variant, rename {}", current_field_debug_name);
+                        error_messages.extend(quote! {
+                            compile_error!(#error_string);
+                        });
+                    }
+                    let field_type = &field.ty;
+                    let doc =
+                        format!("Creates the `{}` version of the container.", variant_name);
+                    constructors.extend(quote! {
+                        #[doc = #doc]
+                        pub fn #constructor(value: #field_type) -> Self {
+                            #enum_name::#branch_name(value)
+                        }
+                    });
+                    latest_constructor = quote! {
+                        /// Creates the latest version of the container.
+                        pub fn latest(value: #field_type) -> Self {
+                            #enum_name::#branch_name(value)
+                        }
+                    };
+                }
+
+                if options.from {
+                    let field_type = &field.ty;
+                    let type_string = field_type.to_token_stream().to_string();
+                    if from_types.contains(&type_string) {
+                        let error_string = format!(
+                            "#[vac(from)] requires a distinct type per variant, {} repeats {}",
+                            current_field_debug_name, type_string
+                        );
+                        error_messages.extend(quote! {
+                            compile_error!(#error_string);
+                        });
+                    } else {
+                        from_types.push(type_string);
+                        from_impls.extend(quote! {
+                            #[automatically_derived]
+                            impl #impl_generics ::core::convert::From<#field_type> for #enum_name #type_generics #where_clause {
+                                fn from(value: #field_type) -> Self {
+                                    #enum_name::#branch_name(value)
+                                }
+                            }
+                        });
+                    }
+                }
+
+                let comparison = field_comparison(field);
+                compare_branches.extend(quote! {
+                    (#archived_name::#branch_name(archived), #enum_name::#branch_name(owned)) => #comparison,
+                });
             }
         } else {
             let error_string = format!(
@@ -71,13 +513,82 @@ fn generate(enum_name: Ident, data_enum: DataEnum, generics: Generics) -> TokenS
         _ => quote! {<#(#lifetime_params),*>},
     };
 
+    let compare_impl = match options.compare_partial_eq {
+        true => quote! {
+            #[automatically_derived]
+            impl ::core::cmp::PartialEq<#enum_name #lifetime_decl> for #archived_name #lifetime_decl {
+                fn eq(&self, other: &#enum_name #lifetime_decl) -> bool {
+                    match (self, other) {
+                        #compare_branches
+                        #[allow(unreachable_patterns)]
+                        _ => false,
+                    }
+                }
+            }
+        },
+        false => quote! {},
+    };
+
+    let proptest_impl = match options.proptest {
+        true => quote! {
+            #[automatically_derived]
+            impl #krate::proptest::arbitrary::Arbitrary for #enum_name {
+                type Parameters = ();
+                type Strategy = #krate::proptest::strategy::BoxedStrategy<Self>;
+
+                fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+                    use #krate::proptest::strategy::Strategy;
+                    #krate::proptest::prop_oneof![#(#strategies),*].boxed()
+                }
+            }
+        },
+        false => quote! {},
+    };
+
+    let debug_json_impl = match options.debug_json {
+        true => quote! {
+            #[automatically_derived]
+            impl #krate::DebugJson for #enum_name #lifetime_decl {
+                fn archived_to_debug_json(
+                    archived: &Self::Archived,
+                ) -> ::core::result::Result<#krate::serde_json::Value, #krate::RkyvVersionedError> {
+                    match archived {
+                        #debug_json_branches
+                    }
+                }
+            }
+        },
+        false => quote! {},
+    };
+
+    let constructors_impl = match options.constructors {
+        true => quote! {
+            #[automatically_derived]
+            #[allow(dead_code)]
+            impl #impl_generics #enum_name #type_generics #where_clause {
+                #constructors
+                #latest_constructor
+            }
+        },
+        false => quote! {},
+    };
+
     quote! {
         #error_messages
+        #compare_impl
+        #proptest_impl
+        #debug_json_impl
+        #constructors_impl
+        #from_impls
 
         #[automatically_derived]
         // Automatically derived implementation of VersionedContainer for #enum_name
-        impl VersionedContainer for #enum_name #lifetime_decl {
-            const ARCHIVE_TYPE_ID : u32 = const_crc32::crc32(#string_name.as_bytes());
+        impl #trait_path for #enum_name #lifetime_decl {
+            const ARCHIVE_TYPE_ID : u32 = #crc32_path(#string_name.as_bytes());
+
+            const TYPE_NAME : &'static str = #string_name;
+
+            const VERSION_TABLE : &'static [#krate::VersionInfo] = &[#(#version_table),*];
 
             fn get_entry_version_id(&self) -> u32 {
                 match self {